@@ -1,174 +1,1495 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     net::SocketAddr,
     path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        mpsc, Arc, Mutex, RwLock,
     },
+    time::{Duration, Instant},
 };
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
     response::{Html, IntoResponse, Response},
     routing::get,
     Json, Router,
 };
-use serde::Deserialize;
-use tokio::fs;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::{fs, sync::Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
 
 use crate::{
-    config::CaptureConfig,
-    db::{CaptureRecord, Db},
-    error::AppResult,
+    api_error::{self, ApiError, ApiJson, ApiQuery, ApiResult},
+    cache::{self, CacheUsage, SweepReport},
+    capture::{CaptureEngine, SelfTestCheck},
+    codec::{self, ImageFormat},
+    config::{self, CaptureConfig},
+    cors::{self, CorsPolicy},
+    crypto,
+    db::{CaptureFilter, CaptureRecord, ChangeEntry, Db, MaintenanceOp, MaintenanceReport},
+    error::{AppError, AppResult},
+    expose::{self, ApiExpose, ExposePolicy},
+    feed,
+    focus::{self, WindowEvent, WindowInfo},
+    hooks,
+    memory::{CollectionSize, MemoryUsage},
+    ocr::OcrBackend,
+    overlay,
+    passphrase_backoff::PassphraseBackoff,
+    schedule::Schedule,
+    story::{self, Story},
+    search::{SearchHit, SearchMode, SearchOrder, SearchScope, Suggestion},
+    thumbnail_cache::ThumbnailCache,
+    timeline,
+    viz::{self, HeatmapScheme},
 };
 
 #[derive(Clone)]
 pub struct ApiState {
     pub db_path: PathBuf,
-    pub config: CaptureConfig,
+    /// Replaced as a whole by `POST /setup`; read it through `config()`.
+    pub config: Arc<RwLock<CaptureConfig>>,
+    /// Where the config is stored; `POST /setup` only writes it while no
+    /// file exists there.
+    pub config_path: PathBuf,
     pub search_index_path: PathBuf,
     pub pause_flag: Arc<AtomicBool>,
+    pub focus_backend: &'static str,
+    /// Feeds work to the capture engine's event loop.
+    pub events: mpsc::Sender<WindowEvent>,
+    /// Cancellation flags of bursts still in progress, keyed by group id.
+    pub bursts: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    /// Whether the battery profile is currently applied.
+    pub on_battery: Arc<AtomicBool>,
+    /// Bounds concurrent image and thumbnail reads to
+    /// `max_concurrent_image_reads`; further requests wait their turn.
+    pub image_reads: Arc<Semaphore>,
+    /// Rendered thumbnails, bounded by `thumb_cache_mb`.
+    pub thumbnails: Arc<ThumbnailCache>,
+    /// Lockouts after wrong passphrases to `/captures/:id/original`.
+    pub passphrase_backoff: Arc<PassphraseBackoff>,
 }
 
-#[derive(Debug, Deserialize)]
+impl ApiState {
+    /// Snapshot of the current config.
+    pub fn config(&self) -> CaptureConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Database connection for one request, able to open sealed text.
+    pub fn db(&self) -> AppResult<Db> {
+        let config = self.config();
+        let mut db = Db::new(&self.db_path)?;
+        db.set_text_key(config.original_passphrase.as_deref(), config.encrypt_text)?;
+        Ok(db)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ListParams {
     pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Return the pre-pagination bare JSON array instead of the envelope.
+    #[serde(default)]
+    pub bare: bool,
+    /// Only captures with this event type, e.g. "focus" or a snapshot tag.
+    pub event_type: Option<String>,
+    /// Only captures taken this way, e.g. "monitor_fallback".
+    pub capture_method: Option<String>,
+    /// Only captures taken while the mic / camera was (or wasn't) in use.
+    pub mic_active: Option<bool>,
+    pub cam_active: Option<bool>,
+    /// Only captures from this virtual desktop, as stored in `workspace`.
+    pub workspace: Option<String>,
+    /// Only captures whose OCR or accessibility text did (or didn't) look
+    /// like source code.
+    pub contains_code: Option<bool>,
+    /// Only captures of this app, by bundle identifier or executable path.
+    pub app_id: Option<String>,
+    /// Only the frames of this burst, as returned by `/control/burst`.
+    pub capture_group_id: Option<String>,
+    /// Only captures with at least this `activity_level`, 0 to 3.
+    pub min_activity: Option<u8>,
+    /// Also list captures flagged as blank frames.
+    #[serde(default)]
+    pub include_blank: bool,
+    /// Only captures taken at or after `from` and before `to`, each as
+    /// epoch milliseconds or RFC 3339.
+    #[serde(default, deserialize_with = "query_time")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "query_time")]
+    pub to: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// A time query parameter given as Unix epoch milliseconds or RFC 3339,
+/// e.g. `1735689600000` or `2025-01-01T00:00:00Z`.
+fn query_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let Some(raw) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let parsed = match raw.parse::<i64>() {
+        Ok(ms) => DateTime::from_timestamp_millis(ms),
+        Err(_) => DateTime::parse_from_rfc3339(&raw)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+    };
+    parsed.map(Some).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "invalid time {raw:?}, expected epoch milliseconds or RFC 3339"
+        ))
+    })
+}
+
+/// `from`/`to` as a half-open range in epoch ms, refusing an empty one.
+fn time_range(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> ApiResult<(Option<i64>, Option<i64>)> {
+    let (from, to) = (
+        from.map(|t| t.timestamp_millis()),
+        to.map(|t| t.timestamp_millis()),
+    );
+    if let (Some(from), Some(to)) = (from, to)
+        && to <= from
+    {
+        return Err(ApiError::bad_request("range is empty"));
+    }
+    Ok((from, to))
+}
+
+/// `offset` of a paged request. Offsets past `u32::MAX` are refused
+/// rather than wrapped into some other page.
+fn page_offset(offset: Option<usize>) -> ApiResult<usize> {
+    let offset = offset.unwrap_or(0);
+    u32::try_from(offset).map_err(|_| ApiError::bad_request("offset is too large"))?;
+    Ok(offset)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FeedParams {
+    pub limit: Option<usize>,
+    pub event_type: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SearchParams {
     pub q: String,
+    /// `words` (default), `phrase`, `substring` or `fuzzy`.
+    #[serde(default)]
+    pub mode: SearchMode,
+    /// Shorthand for `mode=fuzzy`.
+    #[serde(default)]
+    pub fuzzy: bool,
     pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Only captures taken at or after `from` and before `to`, as for
+    /// `/captures`.
+    #[serde(default, deserialize_with = "query_time")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "query_time")]
+    pub to: Option<DateTime<Utc>>,
+    /// `newest` (default) or `oldest` first.
+    #[serde(default)]
+    pub order: SearchOrder,
+    #[serde(default)]
+    pub bare: bool,
+    /// Include filesystem paths in hits, for trusted local tooling.
+    #[serde(default)]
+    pub include_paths: bool,
 }
 
-pub async fn serve(addr: SocketAddr, state: ApiState) -> AppResult<()> {
-    let app = Router::new()
+/// Shorter queries get no suggestions; they would match nearly everything.
+const SUGGEST_MIN_CHARS: usize = 2;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SuggestParams {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LatestByAppParams {
+    /// Comma-separated app names; all apps when absent.
+    pub apps: Option<String>,
+}
+
+/// Newest capture of one app, as served by `/captures/latest_by_app`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppLatest {
+    pub app: String,
+    pub capture: Option<CaptureSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<T>,
+}
+
+impl<T: Serialize> Page<T> {
+    fn respond(self, bare: bool) -> Response {
+        respond_page(self.total, bare, self, |page| page.items)
+    }
+}
+
+/// `/search` results: a `Page` whose total says it counts matches.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub total_matches: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<SearchHit>,
+}
+
+impl SearchPage {
+    fn respond(self, bare: bool) -> Response {
+        respond_page(self.total_matches, bare, self, |page| page.items)
+    }
+}
+
+/// Envelope response, or the bare array of its `items` when `bare` is
+/// set. Both carry the total in `X-Total-Count`.
+fn respond_page<P: Serialize, T: Serialize>(
+    total: usize,
+    bare: bool,
+    page: P,
+    items: impl FnOnce(P) -> Vec<T>,
+) -> Response {
+    let total = [("x-total-count", total.to_string())];
+    if bare {
+        (total, Json(items(page))).into_response()
+    } else {
+        (total, Json(page)).into_response()
+    }
+}
+
+pub fn router(state: ApiState) -> Router {
+    Router::new()
         .route("/captures", get(list_captures))
+        .route("/captures/latest_by_app", get(latest_by_app))
         .route("/captures/:id", get(get_capture))
         .route("/captures/:id/image", get(get_image))
+        .route("/captures/:id/thumbnail", get(get_thumbnail))
+        .route("/captures/:id/original", get(get_original))
+        .route("/captures/:id/reprocess", axum::routing::post(reprocess_capture))
         .route("/config", get(get_config))
+        .route("/config/schema", get(get_config_schema))
+        .route("/setup", get(get_setup).post(post_setup))
+        .route("/feed.atom", get(get_feed))
         .route("/search", get(search_captures))
+        .route("/search/suggest", get(suggest_search))
+        .route("/status", get(get_status))
+        .route("/gaps", get(get_gaps))
+        .route("/changes", get(get_changes))
+        .route("/timeline/strip", get(get_timeline_strip))
+        .route("/stats/heatmap.png", get(get_heatmap))
+        .route("/highlights", get(get_highlights))
+        .route("/stories", get(get_stories))
+        .route("/stories/:story_id/captures", get(get_story_captures))
+        .route("/debug/windows", get(debug_windows))
+        .route("/debug/memory", get(debug_memory))
+        .route("/metrics", get(get_metrics))
         .route("/control/pause", axum::routing::post(pause))
         .route("/control/resume", axum::routing::post(resume))
         .route("/control/erase", axum::routing::post(erase_recent))
+        .route("/control/snapshot", axum::routing::post(snapshot))
+        .route("/control/burst", axum::routing::post(start_burst))
+        .route("/control/burst/:group_id", axum::routing::delete(cancel_burst))
+        .route("/admin/vacuum", axum::routing::post(admin_vacuum))
+        .route("/admin/analyze", axum::routing::post(admin_analyze))
+        .route("/admin/integrity", get(admin_integrity))
+        .route("/admin/clear_cache", axum::routing::post(admin_clear_cache))
         .route("/", get(index_page))
-        .with_state(state);
+        .fallback(api_error::route_not_found)
+        .layer(middleware::from_fn(api_error::request_id))
+        .with_state(state)
+}
 
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .map_err(|e| crate::error::AppError::Capture(e.to_string()))?;
+/// axum's own body limit, kept outside `proxy` mode.
+const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Binds `addr`, or a free port on the same interface when another
+/// instance already holds it.
+pub async fn bind(addr: SocketAddr) -> AppResult<tokio::net::TcpListener> {
+    match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => Ok(listener),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            eprintln!("API address {addr} is in use, picking a free port");
+            Ok(tokio::net::TcpListener::bind(SocketAddr::new(addr.ip(), 0)).await?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-    axum::serve(listener, app)
+pub async fn serve(listener: tokio::net::TcpListener, state: ApiState) -> AppResult<()> {
+    let config = state.config();
+    let cors = CorsPolicy::new(&config.api_allowed_origins);
+    let policy = ExposePolicy::new(&config);
+    if cors.allows_any() && !policy.has_token() {
+        eprintln!(
+            "api_allowed_origins contains \"*\" and the API has no authentication: \
+             any website open in your browser can read your captures"
+        );
+    }
+    let body_limit = match policy.mode() {
+        ApiExpose::Proxy => DefaultBodyLimit::max(config.api_max_body_bytes),
+        ApiExpose::Localhost | ApiExpose::Lan => DefaultBodyLimit::max(DEFAULT_BODY_LIMIT),
+    };
+    // CORS goes outermost so preflights, which never carry the token, are
+    // answered before the token check.
+    let app = router(state)
+        .layer(body_limit)
+        .layer(middleware::from_fn_with_state(Arc::new(policy), expose::guard))
+        .layer(middleware::from_fn_with_state(Arc::new(cors), cors::apply));
+
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
-        .map_err(|e| crate::error::AppError::Capture(e.to_string()))?;
+        .map_err(|e| AppError::Capture(e.to_string()))?;
 
     Ok(())
 }
 
 async fn list_captures(
     State(state): State<ApiState>,
-    Query(params): Query<ListParams>,
-) -> Response {
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> ApiResult<Response> {
     let limit = params.limit.unwrap_or(50).clamp(1, 500);
-    match Db::new(&state.db_path)
-        .and_then(|db| db.list_recent(limit))
-        .map(|rows| rows.into_iter().map(CaptureSummary::from).collect::<Vec<_>>())
-    {
-        Ok(list) => Json(list).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("error listing captures: {e}"),
-        )
-            .into_response(),
+    let offset = page_offset(params.offset)?;
+    let (from, to) = time_range(params.from, params.to)?;
+    let filter = CaptureFilter {
+        from,
+        to,
+        event_type: params.event_type.as_deref(),
+        capture_method: params.capture_method.as_deref(),
+        mic_active: params.mic_active,
+        cam_active: params.cam_active,
+        workspace: params.workspace.as_deref(),
+        contains_code: params.contains_code,
+        app_id: params.app_id.as_deref(),
+        capture_group_id: params.capture_group_id.as_deref(),
+        min_activity: params.min_activity,
+        include_blank: params.include_blank,
+    };
+    let db = state.db()?;
+    let page = Page {
+        total: db.count_recent(&filter)?,
+        offset,
+        limit,
+        items: db
+            .list_recent(limit, offset, &filter)?
+            .into_iter()
+            .map(CaptureSummary::from)
+            .collect(),
+    };
+    Ok(page.respond(params.bare))
+}
+
+/// The latest captures as an Atom feed for feed readers. Links are made
+/// absolute with the request's `Host`, since readers resolve them
+/// outside the page.
+async fn get_feed(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<FeedParams>,
+) -> ApiResult<Response> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    let filter = CaptureFilter {
+        event_type: params.event_type.as_deref(),
+        ..CaptureFilter::default()
+    };
+    let records = state.db()?.list_recent(limit, 0, &filter)?;
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost");
+    let xml = feed::atom(&records, &format!("http://{host}"));
+    Ok(([(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")], xml).into_response())
+}
+
+/// Most apps `/captures/latest_by_app` reports on, asked for or not.
+const LATEST_BY_APP_MAX: usize = 50;
+
+/// The newest capture of each app in `apps`, in the order given, with a
+/// null capture for apps never seen; without `apps`, of the most recently
+/// seen apps.
+async fn latest_by_app(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<LatestByAppParams>,
+) -> ApiResult<Json<Vec<AppLatest>>> {
+    let db = state.db()?;
+    let Some(apps) = params.apps else {
+        let latest = db.latest_of_each_app(LATEST_BY_APP_MAX)?;
+        return Ok(Json(
+            latest
+                .into_iter()
+                .map(|record| AppLatest {
+                    app: record.app_name.clone().unwrap_or_default(),
+                    capture: Some(CaptureSummary::from(record)),
+                })
+                .collect(),
+        ));
+    };
+    let apps: Vec<String> = apps
+        .split(',')
+        .map(str::trim)
+        .filter(|app| !app.is_empty())
+        .map(str::to_string)
+        .collect();
+    if apps.len() > LATEST_BY_APP_MAX {
+        return Err(ApiError::bad_request(format!(
+            "at most {LATEST_BY_APP_MAX} apps can be asked for"
+        )));
+    }
+    let latest = db.latest_by_apps(&apps)?;
+    Ok(Json(
+        apps.into_iter()
+            .zip(latest)
+            .map(|(app, record)| AppLatest {
+                app,
+                capture: record.map(CaptureSummary::from),
+            })
+            .collect(),
+    ))
+}
+
+async fn get_capture(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<CaptureSummary>> {
+    let record = state.db()?
+        .get_capture(&id)?
+        .ok_or_else(|| ApiError::not_found("capture not found"))?;
+    Ok(Json(CaptureSummary::from(record)))
+}
+
+async fn get_config(State(state): State<ApiState>) -> Json<serde_json::Value> {
+    Json(state.config().to_redacted_json())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigFieldSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub description: String,
+    pub default: serde_json::Value,
+    pub current: serde_json::Value,
+    pub differs_from_default: bool,
+    /// Settings are read once at startup, so this is false for all of them
+    /// until some can be changed on a running daemon.
+    pub runtime_modifiable: bool,
+    pub sensitive: bool,
+}
+
+/// Every config field with its type, default, and current value.
+async fn get_config_schema(State(state): State<ApiState>) -> Json<Vec<ConfigFieldSchema>> {
+    let defaults = CaptureConfig::default().to_redacted_json();
+    let current = state.config().to_redacted_json();
+    let value_of = |json: &serde_json::Value, name: &str| {
+        json.get(name).cloned().unwrap_or(serde_json::Value::Null)
+    };
+    let fields = config::CONFIG_FIELDS
+        .iter()
+        .map(|field| {
+            let default = value_of(&defaults, field.name);
+            let current = value_of(&current, field.name);
+            ConfigFieldSchema {
+                name: field.name.to_string(),
+                kind: field.kind.to_string(),
+                description: field.description.to_string(),
+                differs_from_default: default != current,
+                default,
+                current,
+                runtime_modifiable: false,
+                sensitive: field.sensitive,
+            }
+        })
+        .collect();
+    Json(fields)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub paused: bool,
+    /// Whether the time falls inside the `schedule`; event captures are
+    /// skipped outside it.
+    pub on_schedule: bool,
+    pub focus_backend: String,
+    pub power_profile: String,
+    /// Disk used under `cache_dir` and when it was last swept.
+    #[serde(default)]
+    pub cache: CacheUsage,
+    /// `post_capture_hook` runs that failed, timed out or were skipped
+    /// since the daemon started.
+    #[serde(default)]
+    pub hook_failures: u64,
+}
+
+async fn get_status(State(state): State<ApiState>) -> ApiResult<Json<Status>> {
+    let on_battery = state.on_battery.load(Ordering::Relaxed);
+    let cache = tokio::task::spawn_blocking(cache::usage).await??;
+    Ok(Json(Status {
+        paused: state.pause_flag.load(Ordering::Relaxed),
+        on_schedule: Schedule::new(&state.config().schedule).is_ok_and(|s| s.is_active()),
+        focus_backend: state.focus_backend.to_string(),
+        power_profile: if on_battery { "battery" } else { "default" }.to_string(),
+        cache,
+        hook_failures: hooks::failures(),
+    }))
+}
+
+/// Live window list for diagnosing capture targeting. Only served when
+/// `debug_endpoints` is enabled, since titles can be sensitive.
+async fn debug_windows(State(state): State<ApiState>) -> ApiResult<Json<Vec<WindowInfo>>> {
+    if !state.config().debug_endpoints {
+        return Err(ApiError::not_found("no such route"));
+    }
+    let windows = tokio::task::spawn_blocking(focus::list_windows)
+        .await?
+        .map_err(|e| ApiError::unavailable(format!("cannot list windows: {e}")))?;
+    Ok(Json(windows))
+}
+
+/// Sizes of the daemon's long-lived collections, gathered by the capture
+/// loop. Gated like `/debug/windows`.
+async fn debug_memory(State(state): State<ApiState>) -> ApiResult<Json<MemoryUsage>> {
+    if !state.config().debug_endpoints {
+        return Err(ApiError::not_found("no such route"));
+    }
+    let (reply_tx, reply_rx) = mpsc::channel();
+    state
+        .events
+        .send(WindowEvent::MemoryUsage { reply: reply_tx })
+        .map_err(|_| ApiError::unavailable("capture loop is not running"))?;
+    let mut usage = tokio::task::spawn_blocking(move || reply_rx.recv())
+        .await?
+        .map_err(|_| ApiError::unavailable("capture loop dropped memory request"))?;
+    usage.bursts = CollectionSize {
+        len: state.bursts.lock().unwrap().len(),
+        cap: None,
+    };
+    usage.thumbnail_cache = state.thumbnails.stats();
+    Ok(Json(usage))
+}
+
+/// Thumbnail cache counters in the Prometheus text format.
+async fn get_metrics(State(state): State<ApiState>) -> Response {
+    let cache = state.thumbnails.stats();
+    let metrics = [
+        ("thumbnail_cache_hits_total", "counter", "Thumbnails served from memory.", cache.hits),
+        ("thumbnail_cache_misses_total", "counter", "Thumbnails not in memory.", cache.misses),
+        ("thumbnail_cache_entries", "gauge", "Thumbnails held in memory.", cache.entries as u64),
+        ("thumbnail_cache_bytes", "gauge", "Bytes held in memory.", cache.bytes as u64),
+    ];
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        body.push_str(&format!(
+            "# HELP veea_{name} {help}\n# TYPE veea_{name} {kind}\nveea_{name} {value}\n"
+        ));
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GapParams {
+    /// A single day in `storage_timezone`, `YYYY-MM-DD`. Alternative to
+    /// `from`/`to`.
+    pub date: Option<String>,
+    #[serde(default, deserialize_with = "query_time")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "query_time")]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(alias = "min_gap_minutes")]
+    pub min_minutes: Option<i64>,
+}
+
+async fn get_gaps(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<GapParams>,
+) -> ApiResult<Json<Vec<timeline::Gap>>> {
+    let now = Utc::now().timestamp_millis();
+    let (from, to) = match (&params.date, params.from) {
+        (Some(date), None) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| ApiError::bad_request("date must be YYYY-MM-DD"))?;
+            let (start, end) = state.config().storage_timezone.day_bounds(date);
+            (start.timestamp_millis(), end.timestamp_millis())
+        }
+        (None, Some(from)) => (
+            from.timestamp_millis(),
+            params.to.map(|t| t.timestamp_millis()).unwrap_or(now),
+        ),
+        _ => return Err(ApiError::bad_request("pass either date or from (and optionally to)")),
+    };
+    // Don't report the part of the range that hasn't happened yet.
+    let to = to.min(now);
+    if to <= from {
+        return Err(ApiError::bad_request("range is empty"));
     }
+    let min_gap_ms = params.min_minutes.unwrap_or(10).max(1) * 60_000;
+
+    let gaps = state.db()?.find_gaps(from, to, min_gap_ms)?;
+    Ok(Json(gaps))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesParams {
+    /// Last `seq` already seen; 0 reads from the start of the feed.
+    #[serde(default)]
+    pub since_seq: i64,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangesPage {
+    pub changes: Vec<ChangeEntry>,
+    /// Seq to pass as `since_seq` next: the last entry's, or `since_seq`
+    /// itself when nothing is newer.
+    pub max_seq: i64,
+    /// More entries follow `max_seq`.
+    pub more: bool,
+}
+
+/// Captures inserted, deleted or reprocessed after `since_seq`, oldest
+/// first. Entries are pruned after `changes_retention_days`; a reader
+/// that fell behind the pruned ones gets `410 changes_pruned` and has to
+/// resync from `/captures`.
+async fn get_changes(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<ChangesParams>,
+) -> ApiResult<Json<ChangesPage>> {
+    let limit = params.limit.unwrap_or(500).clamp(1, 500);
+    let db = state.db()?;
+    let mut changes = db.list_changes(params.since_seq, limit + 1)?;
+    // Read after listing: pruning only moves forward, so a prune racing
+    // the read above still shows up here.
+    let pruned = db.pruned_change_seq()?;
+    if params.since_seq < pruned {
+        return Err(ApiError::new(
+            StatusCode::GONE,
+            "changes_pruned",
+            format!(
+                "changes up to seq {pruned} have been pruned; resync and continue from seq {}",
+                db.max_change_seq()?
+            ),
+        ));
+    }
+    let more = changes.len() > limit;
+    changes.truncate(limit);
+    let max_seq = changes.last().map_or(params.since_seq, |change| change.seq);
+    Ok(Json(ChangesPage {
+        changes,
+        max_seq,
+        more,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StripParams {
+    /// A single day in `storage_timezone`, `YYYY-MM-DD`.
+    pub date: String,
+    pub bucket_minutes: Option<i64>,
 }
 
-async fn get_capture(State(state): State<ApiState>, Path(id): Path<String>) -> Response {
-    match Db::new(&state.db_path).and_then(|db| db.get_capture(&id)) {
-        Ok(Some(record)) => Json(CaptureSummary::from(record)).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("error fetching capture: {e}"),
-        )
-            .into_response(),
+async fn get_timeline_strip(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<StripParams>,
+) -> ApiResult<Json<Vec<timeline::StripBucket>>> {
+    let date = NaiveDate::parse_from_str(&params.date, "%Y-%m-%d")
+        .map_err(|_| ApiError::bad_request("date must be YYYY-MM-DD"))?;
+    let (start, end) = state.config().storage_timezone.day_bounds(date);
+    let (from, to) = (start.timestamp_millis(), end.timestamp_millis());
+    // Don't pad the strip with buckets that haven't happened yet.
+    let to = to.min(Utc::now().timestamp_millis());
+    if to <= from {
+        return Err(ApiError::bad_request("range is empty"));
     }
+    let bucket_ms = params.bucket_minutes.unwrap_or(10).clamp(1, 24 * 60) * 60_000;
+
+    let strip = state.db()?.timeline_strip(from, to, bucket_ms)?;
+    Ok(Json(strip))
 }
 
-async fn get_config(State(state): State<ApiState>) -> Response {
-    Json(state.config).into_response()
+#[derive(Debug, Deserialize)]
+pub struct HeatmapParams {
+    /// Defaults to the current year in `storage_timezone`.
+    pub year: Option<i32>,
+    /// Side of one day's square in pixels.
+    pub cell: Option<u32>,
+    #[serde(default)]
+    pub scheme: HeatmapScheme,
+}
+
+/// Calendar heatmap PNG of a year's captures per day, for embedding where
+/// only images can be shown.
+async fn get_heatmap(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<HeatmapParams>,
+) -> ApiResult<Response> {
+    let tz = state.config().storage_timezone;
+    let this_year = tz.date_of(Utc::now()).year();
+    let year = params.year.unwrap_or(this_year);
+    if !(1970..=this_year).contains(&year) {
+        return Err(ApiError::bad_request(format!(
+            "year must be between 1970 and {this_year}"
+        )));
+    }
+    let cell = params.cell.unwrap_or(12).clamp(4, 48);
+    let first = NaiveDate::from_ymd_opt(year, 1, 1)
+        .ok_or_else(|| ApiError::bad_request("year out of range"))?;
+    let next = NaiveDate::from_ymd_opt(year + 1, 1, 1)
+        .ok_or_else(|| ApiError::bad_request("year out of range"))?;
+    let (from, to) = (tz.day_bounds(first).0, tz.day_bounds(next).0);
+
+    let counts = state
+        .db()?
+        .daily_counts(from.timestamp_millis(), to.timestamp_millis(), tz)?;
+    let png = tokio::task::spawn_blocking(move || -> ApiResult<Vec<u8>> {
+        let image = viz::heatmap(year, &counts, cell, params.scheme)
+            .ok_or_else(|| ApiError::bad_request("year out of range"))?;
+        Ok(codec::encode(&image, ImageFormat::Png)?)
+    })
+    .await??;
+    Ok(([("content-type", "image/png")], png).into_response())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightPeriod {
+    Hour,
+    #[default]
+    Day,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HighlightParams {
+    /// Defaults to seven days before `to`.
+    #[serde(default, deserialize_with = "query_time")]
+    pub from: Option<DateTime<Utc>>,
+    /// Defaults to now.
+    #[serde(default, deserialize_with = "query_time")]
+    pub to: Option<DateTime<Utc>>,
+    /// Captures per period.
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub period: HighlightPeriod,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Highlights {
+    /// `YYYY-MM-DD`, or `YYYY-MM-DDTHH:00` for hours, in `storage_timezone`.
+    pub period: String,
+    /// Highest importance first.
+    pub captures: Vec<CaptureSummary>,
+}
+
+/// The top-scored captures of each day or hour in the range, oldest
+/// period first.
+async fn get_highlights(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<HighlightParams>,
+) -> ApiResult<Json<Vec<Highlights>>> {
+    let to = params.to.unwrap_or_else(Utc::now);
+    let from = params.from.unwrap_or(to - chrono::Duration::days(7));
+    if to <= from {
+        return Err(ApiError::bad_request("range is empty"));
+    }
+    let limit = params.limit.unwrap_or(5).clamp(1, 50);
+    let format = match params.period {
+        HighlightPeriod::Day => "%Y-%m-%d",
+        HighlightPeriod::Hour => "%Y-%m-%dT%H:00",
+    };
+
+    let captures =
+        state.db()?.scored_captures(from.timestamp_millis(), to.timestamp_millis(), None)?;
+    // Captures arrive best first, so each period keeps its first `limit`.
+    let timezone = state.config().storage_timezone;
+    let mut periods: BTreeMap<String, Vec<CaptureSummary>> = BTreeMap::new();
+    for record in captures {
+        let period = timezone
+            .local_time(record.ts)
+            .format(format)
+            .to_string();
+        let top = periods.entry(period).or_default();
+        if top.len() < limit {
+            top.push(CaptureSummary::from(record));
+        }
+    }
+    Ok(Json(
+        periods
+            .into_iter()
+            .map(|(period, captures)| Highlights { period, captures })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StoryParams {
+    pub title: String,
+    /// Also match titles that are merely similar, by trigram overlap.
+    #[serde(default)]
+    pub fuzzy: bool,
+    /// Lowest trigram similarity a fuzzy match needs, 0 to 1.
+    pub min_similarity: Option<f64>,
+    pub limit: Option<usize>,
+}
+
+/// Stories of the document named `title`: the exact one, or with `fuzzy`
+/// every similar enough one, best match first.
+async fn get_stories(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<StoryParams>,
+) -> ApiResult<Json<Vec<Story>>> {
+    let key = story::title_key(&params.title, None)
+        .ok_or_else(|| ApiError::bad_request("title must not be empty"))?;
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    let min_similarity = params.min_similarity.unwrap_or(0.5).clamp(0.0, 1.0);
+    let tz = state.config().storage_timezone;
+    let db = state.db()?;
+
+    let matches = if params.fuzzy {
+        let mut scored: Vec<(String, f64)> = db
+            .title_keys()?
+            .into_iter()
+            .map(|k| {
+                let score = story::similarity(&key, &k);
+                (k, score)
+            })
+            .filter(|(_, score)| *score >= min_similarity)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+    } else {
+        vec![(key, 1.0)]
+    };
+
+    let mut stories = Vec::with_capacity(matches.len());
+    for (key, score) in matches {
+        if let Some(mut story) = db.story(&key, tz)? {
+            story.similarity = score;
+            stories.push(story);
+        }
+    }
+    Ok(Json(stories))
+}
+
+/// Frames of one story, oldest first.
+async fn get_story_captures(
+    State(state): State<ApiState>,
+    Path(story_id): Path<String>,
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> ApiResult<Response> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    let offset = page_offset(params.offset)?;
+    let db = state.db()?;
+    let key = db
+        .story_key(&story_id)?
+        .ok_or_else(|| ApiError::not_found("story not found"))?;
+    let page = Page {
+        total: db.count_story(&key)?,
+        offset,
+        limit,
+        items: db
+            .list_story(&key, limit, offset)?
+            .into_iter()
+            .map(CaptureSummary::from)
+            .collect(),
+    };
+    Ok(page.respond(params.bare))
 }
 
 async fn search_captures(
     State(state): State<ApiState>,
-    Query(params): Query<SearchParams>,
-) -> Response {
+    ApiQuery(params): ApiQuery<SearchParams>,
+) -> ApiResult<Response> {
     let limit = params.limit.unwrap_or(20).clamp(1, 200);
-    match crate::search::SearchIndex::new(&state.search_index_path)
-        .and_then(|index| index.search(&params.q, limit))
-    {
-        Ok(results) => Json(results).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("search error: {e}"),
-        )
-            .into_response(),
-    }
-}
-
-async fn get_image(State(state): State<ApiState>, Path(id): Path<String>) -> Response {
-    match Db::new(&state.db_path).and_then(|db| db.get_capture(&id)) {
-        Ok(Some(record)) => match fs::read(record.path).await {
-            Ok(bytes) => (
-                StatusCode::OK,
-                [("content-type", "image/png")],
-                bytes,
-            )
-                .into_response(),
-            Err(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("read image failed: {e}"),
-            )
-                .into_response(),
-        },
-        Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("error fetching capture: {e}"),
-        )
-            .into_response(),
-    }
-}
-
-async fn pause(State(state): State<ApiState>) -> Response {
-    state.pause_flag.store(true, Ordering::Relaxed);
-    (StatusCode::OK, "paused").into_response()
-}
-
-async fn resume(State(state): State<ApiState>) -> Response {
-    state.pause_flag.store(false, Ordering::Relaxed);
-    (StatusCode::OK, "resumed").into_response()
+    let offset = page_offset(params.offset)?;
+    let index = crate::search::SearchIndex::new(&state.search_index_path)?
+        .with_text_key(state.db()?.shared_text_key());
+    let mode = if params.fuzzy { SearchMode::Fuzzy } else { params.mode };
+    let (from, to) = time_range(params.from, params.to)?;
+    let scope = SearchScope {
+        from,
+        to,
+        order: params.order,
+    };
+    let mut items = index.search(&params.q, mode, scope, limit, offset)?;
+    if !params.include_paths {
+        for hit in &mut items {
+            hit.path = None;
+        }
+    }
+    let page = SearchPage {
+        total_matches: index.count(&params.q, mode, scope)?,
+        offset,
+        limit,
+        items,
+    };
+    Ok(page.respond(params.bare))
+}
+
+/// Window titles and app names completing `q`, for search-as-you-type.
+async fn suggest_search(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<SuggestParams>,
+) -> ApiResult<Json<Vec<Suggestion>>> {
+    let limit = params.limit.unwrap_or(10).clamp(1, 50);
+    if params.q.trim().chars().count() < SUGGEST_MIN_CHARS {
+        return Ok(Json(Vec::new()));
+    }
+    Ok(Json(state.db()?.suggest(&params.q, limit)?))
 }
 
 #[derive(Debug, Deserialize)]
+pub struct ImageParams {
+    /// Draw the `overlay` label on the served copy; defaults to
+    /// `overlay.enabled`.
+    pub overlay: Option<bool>,
+}
+
+/// Serves the stored image as is when the client lists its type in
+/// `Accept`, and as PNG otherwise, so browsers without JPEG XL support
+/// still get a picture. With the overlay the image is always PNG.
+async fn get_image(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ApiQuery(params): ApiQuery<ImageParams>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let _permit = state.image_reads.acquire().await.map_err(ApiError::internal)?;
+    let record = state.db()?
+        .get_capture(&id)?
+        .ok_or_else(|| ApiError::not_found("capture not found"))?;
+    let codec = record.codec;
+    let config = state.config();
+    let capture_dir = config.capture_dir.clone();
+    let read = record.clone();
+    let bytes = match tokio::task::spawn_blocking(move || read.read_image(&capture_dir)).await? {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ApiError::not_found("image file missing"));
+        }
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    if params.overlay.unwrap_or(config.overlay.enabled) {
+        let overlay = config.overlay;
+        let timezone = config.storage_timezone;
+        let png = tokio::task::spawn_blocking(move || {
+            let mut image = codec::decode(&bytes, codec)?;
+            overlay::apply(&mut image, &record, &overlay, timezone);
+            codec::encode(&image, ImageFormat::Png)
+        })
+        .await??;
+        return Ok(([("content-type", "image/png")], png).into_response());
+    }
+    let accepted = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains(codec.content_type()));
+    if codec == ImageFormat::Png || accepted {
+        return Ok(([("content-type", codec.content_type())], bytes).into_response());
+    }
+    let png = tokio::task::spawn_blocking(move || codec::to_png(bytes, codec)).await??;
+    Ok(([("content-type", "image/png")], png).into_response())
+}
+
+/// Header carrying `original_passphrase` to `/captures/:id/original`.
+pub const PASSPHRASE_HEADER: &str = "x-veea-passphrase";
+
+/// Decrypted unredacted image of a redacted capture. Requires
+/// `X-Veea-Passphrase: <original_passphrase>`, apart from `Authorization`,
+/// which carries the `api_token`. Repeated wrong passphrases get 429
+/// until the lockout of `passphrase_backoff` passes.
+async fn get_original(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let _permit = state.image_reads.acquire().await.map_err(ApiError::internal)?;
+    let passphrase = headers
+        .get(PASSPHRASE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| ApiError::unauthorized("passphrase required"))?
+        .to_string();
+    if let Err(wait) = state.passphrase_backoff.check(Instant::now()) {
+        return Ok(too_many_attempts(wait));
+    }
+    let record = state.db()?
+        .get_capture(&id)?
+        .ok_or_else(|| ApiError::not_found("capture not found"))?;
+    let original = record
+        .original_path
+        .ok_or_else(|| ApiError::not_found("capture has no stored original"))?;
+    let data = match fs::read(&original).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ApiError::not_found("original file missing"));
+        }
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let bytes = tokio::task::spawn_blocking(move || crypto::decrypt(&passphrase, &data)).await?;
+    let Some(bytes) = bytes else {
+        state.passphrase_backoff.failed(Instant::now());
+        return Err(ApiError::unauthorized("wrong passphrase"));
+    };
+    state.passphrase_backoff.succeeded();
+    Ok(([("content-type", "image/png")], bytes).into_response())
+}
+
+/// 429 while `ApiState::passphrase_backoff` is locked out, with
+/// `Retry-After` in whole seconds.
+fn too_many_attempts(wait: Duration) -> Response {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let error = ApiError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        "too_many_attempts",
+        "too many wrong passphrases, try again later",
+    );
+    ([(header::RETRY_AFTER, secs.to_string())], error).into_response()
+}
+
+/// Longest edge of images served by `/captures/:id/thumbnail`.
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// `ApiState::thumbnails` key of a capture's thumbnail.
+fn thumbnail_key(id: &str) -> String {
+    format!("{id}@{THUMBNAIL_SIZE}")
+}
+
+/// Serves from `ApiState::thumbnails` when the image file is unchanged,
+/// and answers `If-None-Match` with 304. Captures erased through the API
+/// leave the cache at once; ones deleted elsewhere are never found again,
+/// so their thumbnails age out.
+async fn get_thumbnail(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let record = state.db()?
+        .get_capture(&id)?
+        .ok_or_else(|| ApiError::not_found("capture not found"))?;
+    let capture_dir = state.config().capture_dir.clone();
+    // A packed image is never rewritten in place, so its capture time
+    // versions it as well as a file's mtime would.
+    let mtime = match &record.pack {
+        Some(_) => std::time::SystemTime::from(record.ts),
+        None => {
+            let path = record
+                .resolve_path(&capture_dir)
+                .ok_or_else(|| ApiError::not_found("image file missing"))?;
+            fs::metadata(&path)
+                .await
+                .and_then(|m| m.modified())
+                .map_err(ApiError::internal)?
+        }
+    };
+    let version = mtime
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let etag = format!("\"{id}-{THUMBNAIL_SIZE}-{version}\"");
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "private, no-cache".to_string()),
+    ];
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let key = thumbnail_key(&id);
+    let bytes = match state.thumbnails.get(&key, mtime) {
+        Some(bytes) => bytes,
+        None => {
+            let _permit = state.image_reads.acquire().await.map_err(ApiError::internal)?;
+            let bytes = tokio::task::spawn_blocking(move || -> AppResult<Vec<u8>> {
+                let bytes = record.read_image(&capture_dir)?;
+                let image = xcap::image::DynamicImage::from(codec::decode(&bytes, record.codec)?);
+                let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+                codec::encode(&thumbnail.to_rgba8(), ImageFormat::Png)
+            })
+            .await??;
+            let bytes = Bytes::from(bytes);
+            state.thumbnails.insert(key, mtime, bytes.clone());
+            bytes
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "image/png".to_string())], cache_headers, bytes).into_response())
+}
+
+async fn pause(State(state): State<ApiState>) -> &'static str {
+    if !state.pause_flag.swap(true, Ordering::Relaxed) {
+        let _ = state.db().and_then(|db| db.insert_daemon_event("pause"));
+    }
+    "paused"
+}
+
+async fn resume(State(state): State<ApiState>) -> &'static str {
+    if state.pause_flag.swap(false, Ordering::Relaxed) {
+        let _ = state.db().and_then(|db| db.insert_daemon_event("resume"));
+    }
+    "resumed"
+}
+
+/// Rejects a destructive request that wasn't explicitly confirmed, unless
+/// `require_delete_confirm` is off.
+fn require_confirm(state: &ApiState, confirm: bool) -> ApiResult<()> {
+    if confirm || !state.config().require_delete_confirm {
+        return Ok(());
+    }
+    Err(ApiError::new(
+        StatusCode::BAD_REQUEST,
+        "confirmation_required",
+        "this permanently deletes captures; repeat the request with confirm=true",
+    ))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct EraseParams {
     pub minutes: Option<i64>,
+    /// Required while `require_delete_confirm` is on.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EraseResponse {
+    pub deleted: usize,
 }
 
 async fn erase_recent(
     State(state): State<ApiState>,
-    Query(params): Query<EraseParams>,
-) -> Response {
+    ApiQuery(params): ApiQuery<EraseParams>,
+) -> ApiResult<Json<EraseResponse>> {
+    require_confirm(&state, params.confirm)?;
     let minutes = params.minutes.unwrap_or(5).clamp(1, 240);
-    match Db::new(&state.db_path).and_then(|db| db.delete_recent(minutes)) {
-        Ok(count) => Json(serde_json::json!({ "deleted": count })).into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("erase failed: {e}"),
-        )
-            .into_response(),
+    let deleted = state.db()?.delete_recent(minutes, &state.config().capture_dir)?;
+    for id in &deleted {
+        state.thumbnails.remove(&thumbnail_key(id));
+    }
+    Ok(Json(EraseResponse {
+        deleted: deleted.len(),
+    }))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotParams {
+    pub label: Option<String>,
+    /// Index or name of one monitor, or "composite" for all of them.
+    pub monitor: Option<String>,
+    /// Defaults to `snapshot_event_type`.
+    pub event_type: Option<String>,
+}
+
+async fn snapshot(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<SnapshotParams>,
+) -> ApiResult<Json<CaptureSummary>> {
+    let label = params.label.unwrap_or_else(|| state.config().snapshot_label.clone());
+    let event_type = params
+        .event_type
+        .unwrap_or_else(|| state.config().snapshot_event_type.clone());
+    let record = tokio::task::spawn_blocking(move || {
+        let db = state.db()?;
+        let mut engine = CaptureEngine::new(state.config(), db, state.pause_flag)?;
+        engine.snapshot_png(&label, params.monitor.as_deref(), &event_type)
+    })
+    .await??;
+    Ok(Json(CaptureSummary::from(record)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BurstParams {
+    #[serde(alias = "count")]
+    pub frames: Option<u32>,
+    pub interval_ms: Option<u64>,
+    pub label: Option<String>,
+}
+
+/// Schedules `frames` snapshots `interval_ms` apart and returns their shared
+/// group id right away; frames are fed to the engine as they come due.
+/// Bursts skip the rate limit, so only one runs at a time to keep
+/// `max_burst_frames` a real cap.
+async fn start_burst(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<BurstParams>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let frames = params.frames.unwrap_or(10);
+    if frames == 0 || frames > state.config().max_burst_frames {
+        return Err(ApiError::bad_request(format!(
+            "frames must be between 1 and {}",
+            state.config().max_burst_frames
+        )));
+    }
+    let interval = Duration::from_millis(params.interval_ms.unwrap_or(300).clamp(50, 60_000));
+    let label = params.label.unwrap_or_else(|| "burst".to_string());
+    let group_id = Uuid::new_v4().to_string();
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let mut bursts = state.bursts.lock().unwrap();
+        if let Some(running) = bursts.keys().next() {
+            return Err(ApiError::conflict(format!("burst {running} is still running")));
+        }
+        bursts.insert(group_id.clone(), cancelled.clone());
+    }
+
+    let task_group_id = group_id.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        for _ in 0..frames {
+            ticker.tick().await;
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            let frame = WindowEvent::BurstFrame {
+                label: label.clone(),
+                group_id: task_group_id.clone(),
+            };
+            if state.events.send(frame).is_err() {
+                break;
+            }
+        }
+        state.bursts.lock().unwrap().remove(&task_group_id);
+    });
+
+    Ok(Json(serde_json::json!({
+        "capture_group_id": group_id,
+        "frames": frames,
+        "interval_ms": interval.as_millis() as u64,
+    })))
+}
+
+async fn cancel_burst(
+    State(state): State<ApiState>,
+    Path(group_id): Path<String>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let cancelled = state
+        .bursts
+        .lock()
+        .unwrap()
+        .remove(&group_id)
+        .ok_or_else(|| ApiError::not_found("no running burst with that id"))?;
+    cancelled.store(true, Ordering::Relaxed);
+    Ok(Json(serde_json::json!({ "cancelled": group_id })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReprocessParams {
+    /// Re-run OCR; on by default. Off only re-checks the image.
+    pub ocr: Option<bool>,
+}
+
+/// Re-reads one capture's image and re-runs OCR on it through the capture
+/// loop, which owns the OCR backend and the writing connection.
+async fn reprocess_capture(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+    ApiQuery(params): ApiQuery<ReprocessParams>,
+) -> ApiResult<Json<CaptureSummary>> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    state
+        .events
+        .send(WindowEvent::Reprocess {
+            id,
+            ocr: params.ocr.unwrap_or(true),
+            reply: reply_tx,
+        })
+        .map_err(|_| ApiError::unavailable("capture loop is not running"))?;
+    let record = tokio::task::spawn_blocking(move || reply_rx.recv())
+        .await?
+        .map_err(|_| ApiError::unavailable("capture loop dropped reprocess request"))??
+        .ok_or_else(|| ApiError::not_found("capture not found"))?;
+    Ok(Json(CaptureSummary::from(record)))
+}
+
+/// Shortest periodic interval setup accepts; 0 still turns it off.
+const SETUP_MIN_INTERVAL_MS: u64 = 1000;
+const SETUP_MAX_EXCLUSIONS: usize = 100;
+
+/// Settings chosen on the first-run setup page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetupForm {
+    pub capture_dir: PathBuf,
+    pub capture_interval_ms: u64,
+    #[serde(default)]
+    pub exclude_titles: Vec<String>,
+    #[serde(default)]
+    pub exclude_apps: Vec<String>,
+    pub enable_search_index: bool,
+    pub ocr_backend: OcrBackend,
+}
+
+impl SetupForm {
+    fn from_config(config: &CaptureConfig) -> Self {
+        Self {
+            capture_dir: config.capture_dir.clone(),
+            capture_interval_ms: config.capture_interval_ms,
+            exclude_titles: config.exclude_titles.clone(),
+            exclude_apps: config.exclude_apps.clone(),
+            enable_search_index: config.enable_search_index,
+            ocr_backend: config.ocr_backend,
+        }
+    }
+
+    /// Checks the form and writes it over `config`.
+    fn apply(self, config: &mut CaptureConfig) -> Result<(), String> {
+        if !self.capture_dir.is_absolute() {
+            return Err("capture_dir must be an absolute path".into());
+        }
+        if self.capture_interval_ms != 0 && self.capture_interval_ms < SETUP_MIN_INTERVAL_MS {
+            return Err(format!(
+                "capture_interval_ms must be 0 or at least {SETUP_MIN_INTERVAL_MS}"
+            ));
+        }
+        if cfg!(not(target_os = "macos")) && self.ocr_backend == OcrBackend::AppleVision {
+            return Err("AppleVision OCR is only available on macOS".into());
+        }
+        let clean = |list: Vec<String>, name: &str| -> Result<Vec<String>, String> {
+            let list: Vec<String> = list
+                .iter()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if list.len() > SETUP_MAX_EXCLUSIONS {
+                return Err(format!("{name} takes at most {SETUP_MAX_EXCLUSIONS} entries"));
+            }
+            Ok(list)
+        };
+        config.exclude_titles = clean(self.exclude_titles, "exclude_titles")?;
+        config.exclude_apps = clean(self.exclude_apps, "exclude_apps")?;
+        config.capture_dir = self.capture_dir;
+        config.capture_interval_ms = self.capture_interval_ms;
+        config.enable_search_index = self.enable_search_index;
+        config.ocr_backend = self.ocr_backend;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetupStatus {
+    /// No config file exists yet, so `POST /setup` is open.
+    pub pending: bool,
+    /// Current values, which are the defaults while setup is pending.
+    pub current: SetupForm,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetupResult {
+    pub self_test: Vec<SelfTestCheck>,
+}
+
+async fn get_setup(State(state): State<ApiState>) -> Json<SetupStatus> {
+    Json(SetupStatus {
+        pending: !state.config_path.exists(),
+        current: SetupForm::from_config(&state.config()),
+    })
+}
+
+/// First-run setup: validates the form, writes it as the config file,
+/// hands it to the capture loop and returns the self-test run against it.
+/// Capturing, paused until now, starts. Refused with 409 once a config
+/// file exists, so the endpoint can't rewrite settings later.
+async fn post_setup(
+    State(state): State<ApiState>,
+    ApiJson(form): ApiJson<SetupForm>,
+) -> ApiResult<Json<SetupResult>> {
+    let already_done = || ApiError::conflict("setup has already been completed");
+    if state.config_path.exists() {
+        return Err(already_done());
+    }
+    let mut config = state.config();
+    form.apply(&mut config).map_err(ApiError::bad_request)?;
+    cache::check_dir(&config, &state.config_path).map_err(|e| {
+        eprintln!("Setup refused: {e}");
+        ApiError::bad_request("capture_dir must not be inside cache_dir")
+    })?;
+    fs::create_dir_all(&config.capture_dir).await.map_err(|e| {
+        eprintln!("Setup cannot create {}: {e}", config.capture_dir.display());
+        ApiError::bad_request(format!("cannot create capture_dir: {e}"))
+    })?;
+    match config.save_new(&state.config_path) {
+        Err(AppError::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(already_done());
+        }
+        result => result?,
     }
+    *state.config.write().unwrap() = config.clone();
+
+    let (reply_tx, reply_rx) = mpsc::channel();
+    state
+        .events
+        .send(WindowEvent::ApplyConfig {
+            config: Box::new(config),
+            reply: reply_tx,
+        })
+        .map_err(|_| ApiError::unavailable("capture loop is not running"))?;
+    let self_test = tokio::task::spawn_blocking(move || reply_rx.recv())
+        .await?
+        .map_err(|_| ApiError::unavailable("capture loop dropped setup request"))??;
+    state.pause_flag.store(false, Ordering::Relaxed);
+    Ok(Json(SetupResult { self_test }))
+}
+
+async fn admin_vacuum(State(state): State<ApiState>) -> ApiResult<Json<MaintenanceReport>> {
+    run_maintenance(&state, MaintenanceOp::Vacuum).await
+}
+
+async fn admin_analyze(State(state): State<ApiState>) -> ApiResult<Json<MaintenanceReport>> {
+    run_maintenance(&state, MaintenanceOp::Analyze).await
+}
+
+/// Hands `op` to the capture loop, which owns the writing connection, and
+/// waits for the report. Captures arriving meanwhile wait in its queue.
+async fn run_maintenance(state: &ApiState, op: MaintenanceOp) -> ApiResult<Json<MaintenanceReport>> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    state
+        .events
+        .send(WindowEvent::Maintenance {
+            op,
+            reply: Some(reply_tx),
+        })
+        .map_err(|_| ApiError::unavailable("capture loop is not running"))?;
+    let report = tokio::task::spawn_blocking(move || reply_rx.recv())
+        .await?
+        .map_err(|_| ApiError::unavailable("capture loop dropped maintenance request"))??;
+    Ok(Json(report))
+}
+
+/// Empties the subsystem folders of `cache_dir`. Files in use are left to
+/// be deleted once their holder is done with them, and counted as `in_use`.
+async fn admin_clear_cache() -> ApiResult<Json<SweepReport>> {
+    let report = tokio::task::spawn_blocking(cache::clear).await??;
+    Ok(Json(report))
+}
+
+/// Streams `PRAGMA integrity_check` output line by line as plain text.
+async fn admin_integrity(State(state): State<ApiState>) -> ApiResult<Response> {
+    let db = state.db()?;
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<String>>(16);
+    tokio::task::spawn_blocking(move || {
+        let result = db.integrity_check(|line| tx.blocking_send(Ok(format!("{line}\n"))).is_ok());
+        if let Err(e) = result {
+            eprintln!("Integrity check failed: {e}");
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+    let body = Body::from_stream(ReceiverStream::new(rx));
+    Ok(([("content-type", "text/plain; charset=utf-8")], body).into_response())
 }
 
 async fn index_page() -> Html<&'static str> {
@@ -183,31 +1504,77 @@ async fn index_page() -> Html<&'static str> {
       .card { border: 1px solid #ccc; padding: 8px; border-radius: 6px; }
       img { max-width: 100%; }
       .controls { margin-bottom: 12px; display: flex; gap: 8px; }
+      #setup { display: none; max-width: 520px; border: 1px solid #ccc; padding: 12px; border-radius: 6px; }
+      #setup label { display: block; margin-bottom: 8px; }
+      #setup input[type=text], #setup input[type=number], #setup textarea { width: 100%; }
     </style>
   </head>
   <body>
     <h1>Veea Timeline</h1>
     <div class="controls">
-      <input id="searchBox" placeholder="Search title/app" />
+      <input id="searchBox" placeholder="Search title/app" list="suggestions" autocomplete="off" />
+      <datalist id="suggestions"></datalist>
       <button onclick="doSearch()">Search</button>
       <button onclick="loadCaptures()">Refresh</button>
       <button onclick="togglePause()" id="pauseBtn">Pause</button>
     </div>
+    <form id="setup" onsubmit="submitSetup(event)">
+      <h2>Setup</h2>
+      <p>Capturing is paused until these settings are saved.</p>
+      <label>Capture folder <input type="text" name="capture_dir" required /></label>
+      <label>Periodic capture interval, ms (0 = off) <input type="number" name="capture_interval_ms" min="0" /></label>
+      <label>Excluded window titles, one per line <textarea name="exclude_titles" rows="3"></textarea></label>
+      <label>Excluded apps, one per line <textarea name="exclude_apps" rows="3"></textarea></label>
+      <label><input type="checkbox" name="enable_search_index" /> Full-text search index</label>
+      <label>OCR
+        <select name="ocr_backend">
+          <option value="None">None</option>
+          <option value="Tesseract">Tesseract</option>
+          <option value="AppleVision">Apple Vision (macOS)</option>
+        </select>
+      </label>
+      <div id="setupError"></div>
+      <ul id="selfTest"></ul>
+      <button type="submit" id="setupSave">Save and start</button>
+      <button type="button" id="setupDone" style="display: none" onclick="finishSetup()">Finish</button>
+    </form>
     <div id="status"></div>
     <div class="grid" id="grid"></div>
     <script>
       let paused = false;
       async function loadCaptures() {
         const res = await fetch('/captures?limit=40');
-        const data = await res.json();
-        render(data);
+        const page = await res.json();
+        render(page);
       }
       async function doSearch() {
         const q = document.getElementById('searchBox').value;
         if (!q) return loadCaptures();
         const res = await fetch('/search?q=' + encodeURIComponent(q));
-        const data = await res.json();
-        render(data);
+        const page = await res.json();
+        render(page);
+      }
+      let suggestTimer;
+      document.getElementById('searchBox').addEventListener('input', (e) => {
+        clearTimeout(suggestTimer);
+        const q = e.target.value;
+        suggestTimer = setTimeout(() => suggest(q), 150);
+      });
+      document.getElementById('searchBox').addEventListener('keydown', (e) => {
+        if (e.key === 'Enter') doSearch();
+      });
+      async function suggest(q) {
+        const list = document.getElementById('suggestions');
+        if (q.trim().length < 2) { list.innerHTML = ''; return; }
+        const res = await fetch('/search/suggest?limit=10&q=' + encodeURIComponent(q));
+        const items = await res.json();
+        list.innerHTML = '';
+        for (const item of items) {
+          const option = document.createElement('option');
+          option.value = item.value;
+          option.label = item.kind + ' · ' + item.count;
+          list.appendChild(option);
+        }
       }
       async function togglePause() {
         paused = !paused;
@@ -215,7 +1582,8 @@ async fn index_page() -> Html<&'static str> {
         await fetch(endpoint, { method: 'POST' });
         document.getElementById('pauseBtn').innerText = paused ? 'Resume' : 'Pause';
       }
-      function render(list) {
+      function render(page) {
+        const list = page.items;
         const grid = document.getElementById('grid');
         grid.innerHTML = '';
         for (const item of list) {
@@ -223,15 +1591,68 @@ async fn index_page() -> Html<&'static str> {
           div.className = 'card';
           div.innerHTML = `
             <div>${new Date(item.ts).toLocaleString()}</div>
-            <div><strong>${item.event_type}</strong></div>
+            <div><strong title="${item.trigger_detail || ''}">${item.event_type}</strong></div>
             <div>${item.window_title || ''}</div>
             <img src="/captures/${item.id}/image" />
           `;
           grid.appendChild(div);
         }
-        document.getElementById('status').innerText = list.length + ' items';
+        const first = list.length ? page.offset + 1 : 0;
+        document.getElementById('status').innerText =
+          'showing ' + first + '–' + (page.offset + list.length) + ' of ' + (page.total ?? page.total_matches);
+      }
+      async function checkSetup() {
+        const res = await fetch('/setup');
+        const setup = await res.json();
+        if (!setup.pending) return loadCaptures();
+        const form = document.getElementById('setup');
+        const cur = setup.current;
+        form.capture_dir.value = cur.capture_dir;
+        form.capture_interval_ms.value = cur.capture_interval_ms;
+        form.exclude_titles.value = cur.exclude_titles.join('\n');
+        form.exclude_apps.value = cur.exclude_apps.join('\n');
+        form.enable_search_index.checked = cur.enable_search_index;
+        form.ocr_backend.value = cur.ocr_backend;
+        form.style.display = 'block';
+      }
+      async function submitSetup(e) {
+        e.preventDefault();
+        const form = e.target;
+        const lines = (v) => v.split('\n').map((s) => s.trim()).filter((s) => s);
+        const body = {
+          capture_dir: form.capture_dir.value,
+          capture_interval_ms: Number(form.capture_interval_ms.value),
+          exclude_titles: lines(form.exclude_titles.value),
+          exclude_apps: lines(form.exclude_apps.value),
+          enable_search_index: form.enable_search_index.checked,
+          ocr_backend: form.ocr_backend.value,
+        };
+        const res = await fetch('/setup', {
+          method: 'POST',
+          headers: { 'content-type': 'application/json' },
+          body: JSON.stringify(body),
+        });
+        const out = await res.json();
+        if (!res.ok) {
+          document.getElementById('setupError').innerText = out.error ? out.error.message : res.statusText;
+          return;
+        }
+        document.getElementById('setupError').innerText = '';
+        const list = document.getElementById('selfTest');
+        list.innerHTML = '';
+        for (const check of out.self_test) {
+          const li = document.createElement('li');
+          li.innerText = (check.ok ? '✓ ' : '✗ ') + check.name + ': ' + check.detail;
+          list.appendChild(li);
+        }
+        document.getElementById('setupSave').style.display = 'none';
+        document.getElementById('setupDone').style.display = '';
+      }
+      function finishSetup() {
+        document.getElementById('setup').style.display = 'none';
+        loadCaptures();
       }
-      loadCaptures();
+      checkSetup();
     </script>
   </body>
 </html>
@@ -239,17 +1660,34 @@ async fn index_page() -> Html<&'static str> {
     Html(HTML)
 }
 
-#[derive(serde::Serialize)]
-struct CaptureSummary {
-    id: String,
-    ts: i64,
-    window_title: Option<String>,
-    app_name: Option<String>,
-    event_type: String,
-    path: String,
-    width: Option<u32>,
-    height: Option<u32>,
-    monitor: Option<String>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureSummary {
+    pub id: String,
+    pub ts: i64,
+    pub window_title: Option<String>,
+    pub app_name: Option<String>,
+    pub app_id: Option<String>,
+    pub event_type: String,
+    pub path: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub monitor: Option<String>,
+    pub capture_group_id: Option<String>,
+    pub capture_method: Option<String>,
+    pub mic_active: Option<bool>,
+    pub cam_active: Option<bool>,
+    pub codec: ImageFormat,
+    pub blank: bool,
+    pub importance: Option<f64>,
+    pub trigger_path: Option<String>,
+    pub doc_path: Option<String>,
+    pub workspace: Option<String>,
+    pub color_profile: Option<String>,
+    pub keys_per_min: Option<u32>,
+    pub clicks_per_min: Option<u32>,
+    pub activity_level: Option<u8>,
+    pub trigger_detail: Option<String>,
+    pub contains_code: Option<bool>,
 }
 
 impl From<CaptureRecord> for CaptureSummary {
@@ -259,11 +1697,158 @@ impl From<CaptureRecord> for CaptureSummary {
             ts: record.ts.timestamp_millis(),
             window_title: record.window_title,
             app_name: record.app_name,
+            app_id: record.app_id,
             event_type: record.event_type,
             path: record.path,
             width: record.width,
             height: record.height,
             monitor: record.monitor,
+            capture_group_id: record.capture_group_id,
+            capture_method: record.capture_method,
+            mic_active: record.mic_active,
+            cam_active: record.cam_active,
+            codec: record.codec,
+            blank: record.blank,
+            importance: record.importance,
+            trigger_path: record.trigger_path,
+            doc_path: record.doc_path,
+            workspace: record.workspace,
+            color_profile: record.color_profile,
+            keys_per_min: record.keys_per_min,
+            clicks_per_min: record.clicks_per_min,
+            activity_level: record.activity_level,
+            trigger_detail: record.trigger_detail,
+            contains_code: record.contains_code,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use serde_json::Value;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Router over a fresh database in a scratch directory, holding one
+    /// capture whose image file is `image` when given.
+    fn app(image: Option<&[u8]>) -> (TempDir, Router) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CaptureConfig {
+            capture_dir: dir.path().join("captures"),
+            db_path: dir.path().join("veea.db"),
+            ..Default::default()
+        };
+        std::fs::create_dir_all(&config.capture_dir).unwrap();
+        let mut record = CaptureRecord::sample("c1", Utc::now());
+        record.path = config.capture_dir.join("c1.png").display().to_string();
+        if let Some(bytes) = image {
+            std::fs::write(&record.path, bytes).unwrap();
+        }
+        Db::new(&config.db_path)
+            .unwrap()
+            .insert_capture(&record)
+            .unwrap();
+
+        let (events, _) = mpsc::channel();
+        let state = ApiState {
+            db_path: config.db_path.clone(),
+            config_path: dir.path().join("config.toml"),
+            search_index_path: dir.path().join("index"),
+            pause_flag: Arc::new(AtomicBool::new(false)),
+            focus_backend: "none",
+            events,
+            bursts: Default::default(),
+            on_battery: Arc::new(AtomicBool::new(false)),
+            image_reads: Arc::new(Semaphore::new(1)),
+            thumbnails: Arc::new(ThumbnailCache::new(0)),
+            passphrase_backoff: Default::default(),
+            config: Arc::new(RwLock::new(config)),
+        };
+        (dir, router(state))
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    /// Checks `body` is an error of `code` that names no file under `dir`.
+    fn assert_error(dir: &TempDir, body: &str, code: &str) {
+        let json: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["error"]["code"], code, "{body}");
+        assert!(json["error"]["message"].is_string(), "{body}");
+        assert!(!body.contains(&*dir.path().to_string_lossy()), "{body}");
+        assert!(!body.contains("c1.png"), "{body}");
+    }
+
+    #[tokio::test]
+    async fn missing_image_is_not_found_without_its_path() {
+        let (dir, app) = app(None);
+        for uri in ["/captures/c1/image", "/captures/c1/thumbnail"] {
+            let (status, body) = send(app.clone(), get(uri)).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            assert_error(&dir, &body, "not_found");
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupt_image_is_unprocessable_without_its_path() {
+        let (dir, app) = app(Some(b"not a png"));
+        let (status, body) = send(app, get("/captures/c1/thumbnail")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_error(&dir, &body, "image_unreadable");
+    }
+
+    #[tokio::test]
+    async fn setup_refusal_does_not_name_the_capture_dir() {
+        let (dir, app) = app(None);
+        // A file where a parent directory should be.
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        let form = serde_json::json!({
+            "capture_dir": blocker.join("captures"),
+            "capture_interval_ms": 60_000,
+            "enable_search_index": false,
+            "ocr_backend": "None",
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/setup")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(form.to_string()))
+            .unwrap();
+        let (status, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_error(&dir, &body, "bad_request");
+    }
+
+    #[tokio::test]
+    async fn bad_queries_are_json_errors() {
+        let (dir, app) = app(None);
+        for (uri, status) in [
+            ("/captures?limit=many", StatusCode::BAD_REQUEST),
+            ("/captures/missing", StatusCode::NOT_FOUND),
+            ("/no/such/route", StatusCode::NOT_FOUND),
+        ] {
+            let (got, body) = send(app.clone(), get(uri)).await;
+            assert_eq!(got, status, "{uri}: {body}");
+            let json: Value = serde_json::from_str(&body).unwrap();
+            assert!(json["error"]["code"].is_string(), "{uri}: {body}");
+            assert!(
+                !body.contains(&*dir.path().to_string_lossy()),
+                "{uri}: {body}"
+            );
         }
     }
 }
@@ -0,0 +1,345 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::{request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Error returned by API handlers, rendered as
+/// `{"error": {"code", "message", "request_id"}}`.
+///
+/// Messages for 4xx responses are written for the client; 5xx responses
+/// get a generic message and the detail only goes to stderr, so internal
+/// paths never leave the process.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    pub fn unavailable(detail: impl std::fmt::Display) -> Self {
+        eprintln!("API backend unavailable: {detail}");
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "backend_unavailable",
+            "backend temporarily unavailable",
+        )
+    }
+
+    pub fn internal(detail: impl std::fmt::Display) -> Self {
+        eprintln!("API internal error: {detail}");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "internal server error",
+        )
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::MonitorNotFound(name) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "monitor_not_found",
+                format!("monitor not found: {name}"),
+            ),
+            AppError::Paused => Self::new(StatusCode::CONFLICT, "paused", "capture is paused"),
+            AppError::InvalidEventType(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_event_type", err.to_string())
+            }
+            AppError::Db(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseBusy
+                        | rusqlite::ErrorCode::DatabaseLocked
+                        | rusqlite::ErrorCode::CannotOpen
+                ) =>
+            {
+                Self::unavailable(err)
+            }
+            AppError::ImageUnreadable(_) => {
+                // The detail names the file, so it only goes to the log.
+                eprintln!("API image unreadable: {err}");
+                Self::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "image_unreadable",
+                    "stored image could not be decoded",
+                )
+            }
+            AppError::Capture(_) => Self::unavailable(err),
+            AppError::LowDiskSpace { .. } => {
+                Self::new(StatusCode::INSUFFICIENT_STORAGE, "low_disk_space", err.to_string())
+            }
+            other => Self::internal(other),
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for ApiError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(Clone::clone).ok();
+        let body = serde_json::json!({
+            "error": {
+                "code": self.code,
+                "message": self.message,
+                "request_id": request_id,
+            }
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// `Query` extractor whose rejection is an `ApiError` instead of plain text.
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Query::<T>::from_request_parts(parts, state)
+            .await
+            .map(|Query(value)| ApiQuery(value))
+            .map_err(|rejection| ApiError::bad_request(rejection.body_text()))
+    }
+}
+
+/// `Json` body extractor whose rejection is an `ApiError` instead of plain
+/// text.
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(request, state)
+            .await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|rejection| ApiError::bad_request(rejection.body_text()))
+    }
+}
+
+/// Tags every request with an id, echoed in the `x-request-id` header and
+/// in any error body produced while handling it.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+pub async fn route_not_found() -> ApiError {
+    ApiError::not_found("no such route")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::get};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn read(response: Response) -> (StatusCode, Value) {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    async fn render(err: impl Into<ApiError>) -> (StatusCode, Value) {
+        read(err.into().into_response()).await
+    }
+
+    fn busy() -> AppError {
+        AppError::Db(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn error_body_has_code_message_and_request_id() {
+        let (status, body) = render(ApiError::not_found("capture not found")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({"error": {"code": "not_found", "message": "capture not found", "request_id": null}})
+        );
+
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                ApiError::bad_request("range is empty").into_response()
+            })
+            .await;
+        let (_, body) = read(response).await;
+        assert_eq!(body["error"]["request_id"], "req-1");
+    }
+
+    #[tokio::test]
+    async fn app_errors_map_to_status_codes() {
+        let cases = [
+            (AppError::Paused, StatusCode::CONFLICT, "paused"),
+            (
+                AppError::InvalidEventType("Focus!".into()),
+                StatusCode::BAD_REQUEST,
+                "invalid_event_type",
+            ),
+            (
+                AppError::MonitorNotFound("HDMI-9".into()),
+                StatusCode::BAD_REQUEST,
+                "monitor_not_found",
+            ),
+            (
+                busy(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "backend_unavailable",
+            ),
+            (
+                AppError::Capture("no display".into()),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "backend_unavailable",
+            ),
+            (
+                AppError::ImageUnreadable("bad".into()),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "image_unreadable",
+            ),
+            (
+                AppError::LowDiskSpace {
+                    available: 1,
+                    required: 2,
+                },
+                StatusCode::INSUFFICIENT_STORAGE,
+                "low_disk_space",
+            ),
+            (
+                AppError::Channel("gone".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal",
+            ),
+        ];
+        for (err, status, code) in cases {
+            let (got, body) = render(err).await;
+            assert_eq!((got, body["error"]["code"].as_str()), (status, Some(code)));
+        }
+    }
+
+    #[tokio::test]
+    async fn error_bodies_never_name_files() {
+        let path = "/home/someone/.local/share/veea/captures/2024/06/05/shot.png";
+        let errors = [
+            AppError::ImageUnreadable(format!("{path}: invalid PNG signature")),
+            AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{path}: permission denied"),
+            )),
+            AppError::Relocate(format!("{path} is missing")),
+            AppError::InstanceLocked(path.to_string()),
+        ];
+        for err in errors {
+            let (status, body) = render(err).await;
+            let message = body["error"]["message"].as_str().unwrap();
+            assert!(!message.contains("/home"), "{status}: {message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn request_id_is_echoed_or_generated() {
+        let app = Router::new()
+            .route("/fail", get(|| async { ApiError::conflict("busy") }))
+            .layer(middleware::from_fn(request_id));
+        let request = |id: Option<&str>| {
+            let mut request = Request::builder().uri("/fail");
+            if let Some(id) = id {
+                request = request.header(REQUEST_ID_HEADER, id);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = app.clone().oneshot(request(Some("abc-123"))).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+        let (status, body) = read(response).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["error"]["request_id"], "abc-123");
+
+        // Ids too long to be reasonable are replaced.
+        let long = "x".repeat(65);
+        for id in [None, Some(""), Some(long.as_str())] {
+            let response = app.clone().oneshot(request(id)).await.unwrap();
+            let header = response.headers()[REQUEST_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert!(Uuid::parse_str(&header).is_ok(), "{header}");
+            let (_, body) = read(response).await;
+            assert_eq!(body["error"]["request_id"], header.as_str());
+        }
+    }
+}
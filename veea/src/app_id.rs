@@ -0,0 +1,179 @@
+/// Stable identifier of the focused window's app: the bundle identifier
+/// on macOS (`com.apple.Safari`), the executable path elsewhere. Unlike
+/// the display name it is neither localized nor shared by look-alike
+/// apps, so exclusion rules can rely on it.
+pub fn focused() -> Option<String> {
+    platform_focused()
+}
+
+/// Lowercased names an app can be listed under in `exclude_apps` and
+/// trigger rules: its display name, its id, and for an executable path
+/// the file name with and without its extension.
+pub fn aliases(app_name: Option<&str>, app_id: Option<&str>) -> Vec<String> {
+    let mut aliases: Vec<String> = app_name.into_iter().map(str::to_lowercase).collect();
+    if let Some(id) = app_id {
+        aliases.push(id.to_lowercase());
+        let path = std::path::Path::new(id);
+        if path.components().count() > 1 {
+            let parts = [path.file_name(), path.file_stem()];
+            aliases.extend(
+                parts
+                    .into_iter()
+                    .flatten()
+                    .map(|p| p.to_string_lossy().to_lowercase()),
+            );
+        }
+    }
+    aliases.sort();
+    aliases.dedup();
+    aliases
+}
+
+#[cfg(target_os = "linux")]
+fn platform_focused() -> Option<String> {
+    use xcb::x;
+
+    xcb::atoms_struct! {
+        struct Atoms {
+            net_active_window => b"_NET_ACTIVE_WINDOW",
+            net_wm_pid => b"_NET_WM_PID",
+        }
+    }
+
+    let (conn, screen_num) = xcb::Connection::connect(None).ok()?;
+    let root = conn.get_setup().roots().nth(screen_num as usize)?.root();
+    let atoms = Atoms::intern_all(&conn).ok()?;
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: atoms.net_active_window,
+        r#type: x::ATOM_WINDOW,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let active = *conn
+        .wait_for_reply(cookie)
+        .ok()?
+        .value::<x::Window>()
+        .first()?;
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: active,
+        property: atoms.net_wm_pid,
+        r#type: x::ATOM_CARDINAL,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let pid = *conn.wait_for_reply(cookie).ok()?.value::<u32>().first()?;
+
+    // Another user's process can't be resolved to its executable, but its
+    // name is still readable.
+    let proc = std::path::PathBuf::from(format!("/proc/{pid}"));
+    match std::fs::read_link(proc.join("exe")) {
+        Ok(exe) => Some(exe.to_string_lossy().into_owned()),
+        Err(_) => std::fs::read_to_string(proc.join("comm"))
+            .ok()
+            .map(|comm| comm.trim().to_string())
+            .filter(|comm| !comm.is_empty()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_focused() -> Option<String> {
+    use std::ffi::{c_char, c_void, CStr};
+
+    // NSWorkspace's frontmost app is only kept current by a main run loop,
+    // which the daemon doesn't run, so the pid comes from the window list
+    // the same way the focused window capture finds its window.
+    let pid = xcap::Window::all().ok()?.into_iter().find_map(|window| {
+        let visible = !window.is_minimized().ok()?;
+        let titled = window.title().is_ok_and(|t| !t.is_empty());
+        (visible && titled).then(|| window.pid().ok()).flatten()
+    })?;
+
+    #[link(name = "AppKit", kind = "framework")]
+    unsafe extern "C" {}
+    #[link(name = "objc")]
+    unsafe extern "C" {
+        fn objc_getClass(name: *const c_char) -> *mut c_void;
+        fn sel_registerName(name: *const c_char) -> *mut c_void;
+        fn objc_msgSend();
+        fn objc_autoreleasePoolPush() -> *mut c_void;
+        fn objc_autoreleasePoolPop(pool: *mut c_void);
+    }
+
+    // SAFETY: each message is sent with the argument and return types of
+    // the method it names; a nil receiver answers nil. The autorelease pool
+    // frees the returned objects, and the string is copied before it goes.
+    unsafe {
+        let send: unsafe extern "C" fn(*mut c_void, *mut c_void) -> *mut c_void =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let send_pid: unsafe extern "C" fn(*mut c_void, *mut c_void, i32) -> *mut c_void =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let sel = |name: &CStr| sel_registerName(name.as_ptr());
+
+        let pool = objc_autoreleasePoolPush();
+        let app = send_pid(
+            objc_getClass(c"NSRunningApplication".as_ptr()),
+            sel(c"runningApplicationWithProcessIdentifier:"),
+            pid as i32,
+        );
+        // Unbundled programs have no identifier; their path stands in.
+        let mut id = send(app, sel(c"bundleIdentifier"));
+        if id.is_null() {
+            id = send(send(app, sel(c"executableURL")), sel(c"path"));
+        }
+        let utf8 = send(id, sel(c"UTF8String")) as *const c_char;
+        let id = (!utf8.is_null()).then(|| CStr::from_ptr(utf8).to_string_lossy().into_owned());
+        objc_autoreleasePoolPop(pool);
+        id
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_focused() -> Option<String> {
+    use std::ffi::c_void;
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetForegroundWindow() -> *mut c_void;
+        fn GetWindowThreadProcessId(hwnd: *mut c_void, pid: *mut u32) -> u32;
+    }
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn QueryFullProcessImageNameW(
+            process: *mut c_void,
+            flags: u32,
+            name: *mut u16,
+            len: *mut u32,
+        ) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    // SAFETY: the process handle is closed before returning, and `name`
+    // has room for the `len` characters the call may write.
+    unsafe {
+        let window = GetForegroundWindow();
+        let mut pid = 0;
+        if window.is_null() || GetWindowThreadProcessId(window, &mut pid) == 0 {
+            return None;
+        }
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return None;
+        }
+        let mut name = [0u16; 1024];
+        let mut len = name.len() as u32;
+        let found = QueryFullProcessImageNameW(process, 0, name.as_mut_ptr(), &mut len) != 0;
+        CloseHandle(process);
+        found.then(|| String::from_utf16_lossy(&name[..len as usize]))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_focused() -> Option<String> {
+    None
+}
@@ -0,0 +1,565 @@
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::focus;
+
+/// Whether this platform can read window text from its accessibility tree.
+pub const SUPPORTED: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// How much of the accessibility tree a walk may cover.
+#[derive(Debug, Clone, Copy)]
+pub struct AxLimits {
+    pub timeout: Duration,
+    /// Levels below the window that are visited.
+    pub max_depth: usize,
+    /// Characters kept; the walk stops once it has this many.
+    pub max_chars: usize,
+}
+
+/// Reads the text of the focused window's accessibility elements on a
+/// helper thread, alongside the screenshot. Like `DocProbe`, it gives up
+/// at the deadline: an app that is slow to answer only costs the text.
+/// The text is dropped unless the focused window is the one the capture
+/// is for, both before and after the walk.
+pub struct AxProbe {
+    rx: mpsc::Receiver<Option<String>>,
+    deadline: Instant,
+}
+
+impl AxProbe {
+    /// Starts a walk of the focused window, expected to be titled
+    /// `window_title`.
+    pub fn start(limits: AxLimits, window_title: &str) -> Self {
+        let deadline = Instant::now() + limits.timeout;
+        let (tx, rx) = mpsc::channel();
+        let expected = window_title.to_string();
+        thread::spawn(move || {
+            let _ = tx.send(platform_window_text(limits, deadline, &expected));
+        });
+        Self { rx, deadline }
+    }
+
+    /// The text, waiting at most until the probe's deadline.
+    pub fn wait(self) -> Option<String> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        self.rx.recv_timeout(left).ok().flatten()
+    }
+}
+
+/// The focused window as seen before or after a walk: its app's process
+/// id and its title as the platform reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FocusedKey {
+    pid: u32,
+    title: String,
+}
+
+/// The walked text, if focus was on the event's window before the walk
+/// and hadn't moved by the end of it. Titles are compared the way the
+/// focus watchers clean them.
+#[cfg_attr(not(any(target_os = "macos", target_os = "windows")), allow(dead_code))]
+fn kept(
+    expected_title: &str,
+    before: Option<&FocusedKey>,
+    after: Option<&FocusedKey>,
+    text: Option<String>,
+) -> Option<String> {
+    let before = before?;
+    if focus::sanitize_title(&before.title) != expected_title || after != Some(before) {
+        return None;
+    }
+    text
+}
+
+/// Element text gathered by a walk, one element per line, cut off at the
+/// character limit or the deadline.
+#[cfg_attr(not(any(target_os = "macos", target_os = "windows")), allow(dead_code))]
+struct Collected {
+    text: String,
+    chars: usize,
+    limits: AxLimits,
+    deadline: Instant,
+}
+
+#[cfg_attr(not(any(target_os = "macos", target_os = "windows")), allow(dead_code))]
+impl Collected {
+    fn new(limits: AxLimits, deadline: Instant) -> Self {
+        Self {
+            text: String::new(),
+            chars: 0,
+            limits,
+            deadline,
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.chars >= self.limits.max_chars || Instant::now() >= self.deadline
+    }
+
+    /// Adds `text` unless it is blank or repeats the previous line, as
+    /// labels often do between an element and its child.
+    fn push(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() || self.text.lines().next_back() == Some(text) {
+            return;
+        }
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        let room = self.limits.max_chars.saturating_sub(self.chars);
+        let kept: String = text.chars().take(room).collect();
+        self.chars += kept.chars().count() + 1;
+        self.text.push_str(&kept);
+    }
+
+    fn finish(self) -> Option<String> {
+        (!self.text.is_empty()).then_some(self.text)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_window_text(limits: AxLimits, deadline: Instant, expected: &str) -> Option<String> {
+    use std::ffi::{c_char, c_void};
+
+    type CFTypeRef = *const c_void;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    unsafe extern "C" {
+        fn AXUIElementCreateSystemWide() -> CFTypeRef;
+        fn AXUIElementCopyAttributeValue(
+            element: CFTypeRef,
+            attribute: CFTypeRef,
+            value: *mut CFTypeRef,
+        ) -> i32;
+        fn AXUIElementSetMessagingTimeout(element: CFTypeRef, seconds: f32) -> i32;
+        fn AXUIElementGetPid(element: CFTypeRef, pid: *mut i32) -> i32;
+    }
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFStringCreateWithBytes(
+            alloc: CFTypeRef,
+            bytes: *const u8,
+            len: isize,
+            encoding: u32,
+            external: u8,
+        ) -> CFTypeRef;
+        fn CFGetTypeID(cf: CFTypeRef) -> usize;
+        fn CFStringGetTypeID() -> usize;
+        fn CFArrayGetTypeID() -> usize;
+        fn CFStringGetLength(string: CFTypeRef) -> isize;
+        fn CFStringGetMaximumSizeForEncoding(len: isize, encoding: u32) -> isize;
+        fn CFStringGetCString(
+            string: CFTypeRef,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+        fn CFArrayGetCount(array: CFTypeRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CFTypeRef, index: isize) -> CFTypeRef;
+        fn CFRelease(cf: CFTypeRef);
+    }
+
+    const UTF8: u32 = 0x0800_0100;
+
+    /// Attribute names, created once per walk.
+    struct Names {
+        focused_app: CFTypeRef,
+        focused_window: CFTypeRef,
+        children: CFTypeRef,
+        text: [CFTypeRef; 3],
+    }
+
+    unsafe fn cf_string(text: &str) -> CFTypeRef {
+        unsafe {
+            CFStringCreateWithBytes(
+                std::ptr::null(),
+                text.as_ptr(),
+                text.len() as isize,
+                UTF8,
+                0,
+            )
+        }
+    }
+
+    /// `element`'s `name` attribute, owned by the caller.
+    unsafe fn attribute(element: CFTypeRef, name: CFTypeRef) -> Option<CFTypeRef> {
+        let mut value: CFTypeRef = std::ptr::null();
+        let status = unsafe { AXUIElementCopyAttributeValue(element, name, &mut value) };
+        (status == 0 && !value.is_null()).then_some(value)
+    }
+
+    unsafe fn to_string(value: CFTypeRef) -> Option<String> {
+        unsafe {
+            if CFGetTypeID(value) != CFStringGetTypeID() {
+                return None;
+            }
+            let size = CFStringGetMaximumSizeForEncoding(CFStringGetLength(value), UTF8) + 1;
+            let mut buffer = vec![0u8; size as usize];
+            if CFStringGetCString(value, buffer.as_mut_ptr().cast(), size, UTF8) == 0 {
+                return None;
+            }
+            let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+            buffer.truncate(end);
+            String::from_utf8(buffer).ok()
+        }
+    }
+
+    /// The focused window, owned by the caller, and what identifies it.
+    unsafe fn focused(system: CFTypeRef, names: &Names) -> Option<(CFTypeRef, FocusedKey)> {
+        unsafe {
+            let app = attribute(system, names.focused_app)?;
+            let mut pid = 0;
+            let window = if AXUIElementGetPid(app, &mut pid) == 0 {
+                attribute(app, names.focused_window)
+            } else {
+                None
+            };
+            CFRelease(app);
+            let window = window?;
+            let title = attribute(window, names.text[0]).and_then(|value| {
+                let title = to_string(value);
+                CFRelease(value);
+                title
+            });
+            let key = FocusedKey {
+                pid: pid as u32,
+                title: title.unwrap_or_default(),
+            };
+            Some((window, key))
+        }
+    }
+
+    unsafe fn visit(element: CFTypeRef, depth: usize, names: &Names, out: &mut Collected) {
+        if out.done() {
+            return;
+        }
+        unsafe {
+            for name in names.text {
+                if let Some(value) = attribute(element, name) {
+                    if let Some(text) = to_string(value) {
+                        out.push(&text);
+                    }
+                    CFRelease(value);
+                }
+            }
+            if depth >= out.limits.max_depth {
+                return;
+            }
+            let Some(children) = attribute(element, names.children) else {
+                return;
+            };
+            if CFGetTypeID(children) == CFArrayGetTypeID() {
+                for i in 0..CFArrayGetCount(children) {
+                    if out.done() {
+                        break;
+                    }
+                    visit(CFArrayGetValueAtIndex(children, i), depth + 1, names, out);
+                }
+            }
+            CFRelease(children);
+        }
+    }
+
+    // SAFETY: every object copied or created here is released once, and
+    // array elements are only borrowed while their array is held. The
+    // messaging timeout on the system-wide element bounds each call, so a
+    // hung app can't hold the walk much past the deadline.
+    unsafe {
+        let names = Names {
+            focused_app: cf_string("AXFocusedApplication"),
+            focused_window: cf_string("AXFocusedWindow"),
+            children: cf_string("AXChildren"),
+            text: [
+                cf_string("AXTitle"),
+                cf_string("AXValue"),
+                cf_string("AXDescription"),
+            ],
+        };
+        let system = AXUIElementCreateSystemWide();
+        AXUIElementSetMessagingTimeout(system, limits.timeout.as_secs_f32());
+        let mut out = Collected::new(limits, deadline);
+        let before = focused(system, &names).map(|(window, key)| {
+            if focus::sanitize_title(&key.title) == expected {
+                visit(window, 0, &names, &mut out);
+            }
+            CFRelease(window);
+            key
+        });
+        let after = focused(system, &names).map(|(window, key)| {
+            CFRelease(window);
+            key
+        });
+        CFRelease(system);
+        for name in [names.focused_app, names.focused_window, names.children]
+            .into_iter()
+            .chain(names.text)
+        {
+            CFRelease(name);
+        }
+        kept(expected, before.as_ref(), after.as_ref(), out.finish())
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_window_text(limits: AxLimits, deadline: Instant, expected: &str) -> Option<String> {
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    struct Guid {
+        data1: u32,
+        data2: u16,
+        data3: u16,
+        data4: [u8; 8],
+    }
+
+    type Object = *mut c_void;
+    type Release = unsafe extern "system" fn(this: Object) -> u32;
+    type ElementFromHandle =
+        unsafe extern "system" fn(this: Object, hwnd: *mut c_void, element: *mut Object) -> i32;
+    type GetWalker = unsafe extern "system" fn(this: Object, walker: *mut Object) -> i32;
+    type WalkStep =
+        unsafe extern "system" fn(this: Object, element: Object, found: *mut Object) -> i32;
+    type GetName = unsafe extern "system" fn(this: Object, name: *mut *mut u16) -> i32;
+
+    // Vtable slots of the methods used, counting IUnknown's three.
+    const RELEASE: usize = 2;
+    const AUTOMATION_ELEMENT_FROM_HANDLE: usize = 6;
+    const AUTOMATION_CONTROL_VIEW_WALKER: usize = 14;
+    const WALKER_FIRST_CHILD: usize = 4;
+    const WALKER_NEXT_SIBLING: usize = 6;
+    const ELEMENT_CURRENT_NAME: usize = 23;
+
+    const CLSID_CUI_AUTOMATION: Guid = Guid {
+        data1: 0xFF48_DBA4,
+        data2: 0x60EF,
+        data3: 0x4201,
+        data4: [0xAA, 0x87, 0x54, 0x10, 0x3E, 0xEF, 0x59, 0x4E],
+    };
+    const IID_IUI_AUTOMATION: Guid = Guid {
+        data1: 0x30CB_E57D,
+        data2: 0xD9D0,
+        data3: 0x452A,
+        data4: [0xAB, 0x13, 0x7A, 0xC5, 0xAC, 0x48, 0x25, 0xEE],
+    };
+    const COINIT_MULTITHREADED: u32 = 0;
+    const CLSCTX_INPROC_SERVER: u32 = 0x1;
+
+    #[link(name = "ole32")]
+    unsafe extern "system" {
+        fn CoInitializeEx(reserved: *mut c_void, coinit: u32) -> i32;
+        fn CoUninitialize();
+        fn CoCreateInstance(
+            clsid: *const Guid,
+            outer: *mut c_void,
+            context: u32,
+            iid: *const Guid,
+            object: *mut Object,
+        ) -> i32;
+    }
+    #[link(name = "oleaut32")]
+    unsafe extern "system" {
+        fn SysStringLen(string: *mut u16) -> u32;
+        fn SysFreeString(string: *mut u16);
+    }
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetForegroundWindow() -> *mut c_void;
+        fn GetWindowThreadProcessId(hwnd: *mut c_void, pid: *mut u32) -> u32;
+        fn GetWindowTextW(hwnd: *mut c_void, text: *mut u16, max: i32) -> i32;
+    }
+
+    /// The foreground window and what identifies it.
+    unsafe fn focused() -> Option<(*mut c_void, FocusedKey)> {
+        unsafe {
+            let window = GetForegroundWindow();
+            if window.is_null() {
+                return None;
+            }
+            let mut pid = 0;
+            GetWindowThreadProcessId(window, &mut pid);
+            let mut buffer = [0u16; 512];
+            let len = GetWindowTextW(window, buffer.as_mut_ptr(), buffer.len() as i32);
+            let title = String::from_utf16_lossy(&buffer[..len.max(0) as usize]);
+            Some((window, FocusedKey { pid, title }))
+        }
+    }
+
+    /// Method `slot` of COM object `object`, typed as `F`.
+    unsafe fn method<F: Copy>(object: Object, slot: usize) -> F {
+        unsafe {
+            let vtbl = *(object as *const *const usize);
+            std::mem::transmute_copy(&*vtbl.add(slot))
+        }
+    }
+
+    unsafe fn release(object: Object) {
+        unsafe { method::<Release>(object, RELEASE)(object) };
+    }
+
+    unsafe fn visit(walker: Object, element: Object, depth: usize, out: &mut Collected) {
+        if out.done() {
+            return;
+        }
+        unsafe {
+            let mut name: *mut u16 = std::ptr::null_mut();
+            if method::<GetName>(element, ELEMENT_CURRENT_NAME)(element, &mut name) >= 0
+                && !name.is_null()
+            {
+                let units = std::slice::from_raw_parts(name, SysStringLen(name) as usize);
+                out.push(&String::from_utf16_lossy(units));
+                SysFreeString(name);
+            }
+            if depth >= out.limits.max_depth {
+                return;
+            }
+            let first_child = method::<WalkStep>(walker, WALKER_FIRST_CHILD);
+            let next_sibling = method::<WalkStep>(walker, WALKER_NEXT_SIBLING);
+            let mut child: Object = std::ptr::null_mut();
+            if first_child(walker, element, &mut child) < 0 {
+                return;
+            }
+            while !child.is_null() {
+                if out.done() {
+                    release(child);
+                    break;
+                }
+                visit(walker, child, depth + 1, out);
+                let mut next: Object = std::ptr::null_mut();
+                let ok = next_sibling(walker, child, &mut next) >= 0;
+                release(child);
+                child = if ok { next } else { std::ptr::null_mut() };
+            }
+        }
+    }
+
+    // SAFETY: COM is initialised for the walk and balanced afterwards;
+    // every interface pointer returned to us is released exactly once, and
+    // the vtable slots match the UIAutomation interface definitions.
+    unsafe {
+        let initialized = CoInitializeEx(std::ptr::null_mut(), COINIT_MULTITHREADED) >= 0;
+        let mut out = Collected::new(limits, deadline);
+        let mut automation: Object = std::ptr::null_mut();
+        let created = CoCreateInstance(
+            &CLSID_CUI_AUTOMATION,
+            std::ptr::null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &IID_IUI_AUTOMATION,
+            &mut automation,
+        ) >= 0
+            && !automation.is_null();
+        let before = focused();
+        if let Some((window, key)) = before.as_ref().filter(|_| created)
+            && focus::sanitize_title(&key.title) == expected
+        {
+            let window = *window;
+            let mut root: Object = std::ptr::null_mut();
+            let mut walker: Object = std::ptr::null_mut();
+            let from_handle =
+                method::<ElementFromHandle>(automation, AUTOMATION_ELEMENT_FROM_HANDLE);
+            let control_view = method::<GetWalker>(automation, AUTOMATION_CONTROL_VIEW_WALKER);
+            if from_handle(automation, window, &mut root) >= 0 && !root.is_null() {
+                if control_view(automation, &mut walker) >= 0 && !walker.is_null() {
+                    visit(walker, root, 0, &mut out);
+                    release(walker);
+                }
+                release(root);
+            }
+        }
+        if created {
+            release(automation);
+        }
+        if initialized {
+            CoUninitialize();
+        }
+        let before = before.map(|(_, key)| key);
+        let after = focused().map(|(_, key)| key);
+        kept(expected, before.as_ref(), after.as_ref(), out.finish())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn platform_window_text(_limits: AxLimits, _deadline: Instant, _expected: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(pid: u32, title: &str) -> FocusedKey {
+        FocusedKey {
+            pid,
+            title: title.to_string(),
+        }
+    }
+
+    fn text() -> Option<String> {
+        Some("Inbox\nCompose".to_string())
+    }
+
+    #[test]
+    fn text_is_kept_for_the_event_window() {
+        let mail = key(7, "Inbox");
+        assert_eq!(kept("Inbox", Some(&mail), Some(&mail), text()), text());
+        // Titles are compared as the watchers clean them.
+        let raw = key(7, " Inbox\0\t");
+        assert_eq!(kept("Inbox", Some(&raw), Some(&raw), text()), text());
+    }
+
+    #[test]
+    fn text_of_another_window_is_dropped() {
+        let chat = key(9, "general");
+        assert_eq!(kept("Inbox", Some(&chat), Some(&chat), text()), None);
+        assert_eq!(kept("Inbox", None, None, text()), None);
+    }
+
+    #[test]
+    fn focus_moving_during_the_walk_drops_the_text() {
+        let mail = key(7, "Inbox");
+        assert_eq!(
+            kept("Inbox", Some(&mail), Some(&key(9, "Inbox")), text()),
+            None
+        );
+        assert_eq!(
+            kept("Inbox", Some(&mail), Some(&key(7, "Drafts")), text()),
+            None
+        );
+        assert_eq!(kept("Inbox", Some(&mail), None, text()), None);
+    }
+
+    fn collected(max_chars: usize) -> Collected {
+        let limits = AxLimits {
+            timeout: Duration::from_secs(60),
+            max_depth: 4,
+            max_chars,
+        };
+        Collected::new(limits, Instant::now() + limits.timeout)
+    }
+
+    #[test]
+    fn collected_skips_blank_and_repeated_lines() {
+        let mut out = collected(100);
+        for line in ["Inbox", "  Inbox ", "", "Compose", "Inbox"] {
+            out.push(line);
+        }
+        assert_eq!(out.finish().as_deref(), Some("Inbox\nCompose\nInbox"));
+        assert_eq!(collected(100).finish(), None);
+    }
+
+    #[test]
+    fn collected_stops_at_the_character_limit() {
+        let mut out = collected(8);
+        out.push("Inbox");
+        assert!(!out.done());
+        out.push("Compose");
+        assert!(out.done());
+        assert_eq!(out.finish().as_deref(), Some("Inbox\nCo"));
+
+        let mut late = collected(100);
+        late.deadline = Instant::now();
+        assert!(late.done());
+    }
+}
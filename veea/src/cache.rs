@@ -0,0 +1,390 @@
+//! Scratch files the daemon can always rebuild, kept under `cache_dir`
+//! with one folder per subsystem, e.g. `cache/ocr`. A sweep at startup
+//! and then daily drops entries older than `cache_ttl_hours` and, oldest
+//! first, whatever exceeds `cache_max_mb`; `/admin/clear_cache` drops
+//! everything. Only the subsystem folders are swept, so other files in
+//! `cache_dir` are never touched, and neither is anything under
+//! `capture_dir`; loading the config refuses a `cache_dir` holding the
+//! database, the config or the captures. Files still open through a
+//! `CacheFile` are left for their holder to delete.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    config::{self, CaptureConfig},
+    error::{AppError, AppResult},
+};
+
+/// Part of the daemon owning a folder of the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Images handed to command-line OCR tools.
+    Ocr,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 1] = [Subsystem::Ocr];
+
+    fn dir_name(self) -> &'static str {
+        match self {
+            Subsystem::Ocr => "ocr",
+        }
+    }
+}
+
+/// Size of the cache for `/status`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheUsage {
+    pub total_bytes: u64,
+    /// Bytes per subsystem folder.
+    pub subsystems: BTreeMap<String, u64>,
+    pub last_sweep: Option<DateTime<Utc>>,
+}
+
+/// What a sweep or `/admin/clear_cache` removed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+    /// Files skipped because they are still open; their holder deletes
+    /// them when done.
+    pub in_use: usize,
+}
+
+struct State {
+    root: Option<PathBuf>,
+    capture_dir: Option<PathBuf>,
+    open: BTreeSet<PathBuf>,
+    last_sweep: Option<DateTime<Utc>>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    root: None,
+    capture_dir: None,
+    open: BTreeSet::new(),
+    last_sweep: None,
+});
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Points the cache at `config.cache_dir`. Until called, the default
+/// location is used.
+pub fn init(config: &CaptureConfig) {
+    let mut state = state();
+    state.root = Some(config.cache_dir.clone());
+    state.capture_dir = Some(config.capture_dir.clone());
+}
+
+fn root() -> PathBuf {
+    state()
+        .root
+        .clone()
+        .unwrap_or_else(|| config::default_data_dir().join("cache"))
+}
+
+/// A cache file in use. Sweeps skip it, and it is deleted on drop.
+#[derive(Debug)]
+pub struct CacheFile {
+    path: PathBuf,
+}
+
+impl CacheFile {
+    /// Reserves a fresh `.<extension>` file name in `subsystem`'s folder;
+    /// the caller creates the file.
+    pub fn new(subsystem: Subsystem, extension: &str) -> io::Result<Self> {
+        let dir = root().join(subsystem.dir_name());
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.{extension}", Uuid::new_v4()));
+        state().open.insert(path.clone());
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CacheFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        state().open.remove(&self.path);
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// Every file in the subsystem folders under `root`, except those under
+/// `capture_dir`.
+fn entries(root: &Path, capture_dir: Option<&Path>) -> io::Result<Vec<Entry>> {
+    let mut found = Vec::new();
+    let mut pending: Vec<PathBuf> = Subsystem::ALL
+        .iter()
+        .map(|subsystem| root.join(subsystem.dir_name()))
+        .filter(|dir| dir.is_dir())
+        .collect();
+    while let Some(dir) = pending.pop() {
+        if capture_dir.is_some_and(|captures| dir.starts_with(captures)) {
+            continue;
+        }
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                pending.push(entry.path());
+            } else {
+                found.push(Entry {
+                    path: entry.path(),
+                    len: meta.len(),
+                    modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Removes entries older than `ttl` and then, oldest first, as many as
+/// it takes to get under `max_bytes`. Zero turns either limit off.
+pub fn sweep(ttl: Duration, max_bytes: u64) -> AppResult<SweepReport> {
+    remove(|entries| {
+        let mut total: u64 = entries.iter().map(|e| e.len).sum();
+        entries
+            .iter()
+            .map(|e| {
+                let expired = !ttl.is_zero() && e.modified.elapsed().is_ok_and(|age| age > ttl);
+                let over = max_bytes > 0 && total > max_bytes;
+                if expired || over {
+                    total -= e.len;
+                }
+                expired || over
+            })
+            .collect()
+    })
+}
+
+/// Removes everything not currently open.
+pub fn clear() -> AppResult<SweepReport> {
+    remove(|entries| vec![true; entries.len()])
+}
+
+/// Sweep with the limits of `config`.
+pub fn sweep_with(config: &CaptureConfig) -> AppResult<SweepReport> {
+    let ttl = Duration::from_secs(u64::from(config.cache_ttl_hours) * 60 * 60);
+    sweep(ttl, config.cache_max_mb * 1024 * 1024)
+}
+
+/// Deletes the entries `choose` picks, given them oldest first. Open
+/// files count towards the size but are never deleted.
+fn remove(choose: impl FnOnce(&[Entry]) -> Vec<bool>) -> AppResult<SweepReport> {
+    let root = root();
+    let capture_dir = state().capture_dir.clone();
+    let mut entries = entries(&root, capture_dir.as_deref())?;
+    entries.sort_by_key(|e| e.modified);
+    let chosen = choose(&entries);
+    let mut report = SweepReport::default();
+    for (entry, chosen) in entries.iter().zip(chosen) {
+        if !chosen {
+            continue;
+        }
+        if state().open.contains(&entry.path) {
+            report.in_use += 1;
+            continue;
+        }
+        match fs::remove_file(&entry.path) {
+            Ok(()) => {
+                report.removed += 1;
+                report.freed_bytes += entry.len;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    state().last_sweep = Some(Utc::now());
+    Ok(report)
+}
+
+/// Refuses a `cache_dir` that is, or holds, the database, the config at
+/// `config_path` or `capture_dir`, as sweeps would delete from it.
+pub fn check_dir(config: &CaptureConfig, config_path: &Path) -> AppResult<()> {
+    let cache_dir = config.cache_dir.as_path();
+    let kept = [
+        ("db_path", config.db_path.as_path()),
+        ("the config file", config_path),
+        ("capture_dir", config.capture_dir.as_path()),
+    ];
+    match kept.iter().find(|(_, path)| path.starts_with(cache_dir)) {
+        Some((name, path)) => Err(AppError::Config(format!(
+            "cache_dir {} holds {name} ({}); point it at a folder of its own",
+            cache_dir.display(),
+            path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Current size of the cache, per subsystem folder.
+pub fn usage() -> AppResult<CacheUsage> {
+    let root = root();
+    let (capture_dir, last_sweep) = {
+        let state = state();
+        (state.capture_dir.clone(), state.last_sweep)
+    };
+    let mut usage = CacheUsage {
+        last_sweep,
+        ..Default::default()
+    };
+    for entry in entries(&root, capture_dir.as_deref())? {
+        usage.total_bytes += entry.len;
+        if let Ok(rel) = entry.path.strip_prefix(&root)
+            && let Some(first) = rel.components().next()
+            && rel.components().nth(1).is_some()
+        {
+            *usage
+                .subsystems
+                .entry(first.as_os_str().to_string_lossy().into_owned())
+                .or_default() += entry.len;
+        }
+    }
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The cache state is global, so its tests take turns.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    /// Points the cache at a fresh data folder, held until the guard
+    /// drops.
+    fn scratch() -> (std::sync::MutexGuard<'static, ()>, tempfile::TempDir) {
+        let guard = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = tempfile::tempdir().unwrap();
+        init(&CaptureConfig {
+            cache_dir: dir.path().join("cache"),
+            capture_dir: dir.path().join("captures"),
+            ..Default::default()
+        });
+        (guard, dir)
+    }
+
+    /// Writes `len` bytes to `rel` under the cache, last modified `age`
+    /// ago.
+    fn file(dir: &Path, rel: &str, len: usize, age: Duration) -> PathBuf {
+        let path = dir.join("cache").join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![0; len]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn sweep_drops_expired_files() {
+        let (_guard, dir) = scratch();
+        let old = file(dir.path(), "ocr/old.png", 10, 3 * HOUR);
+        let new = file(dir.path(), "ocr/new.png", 10, HOUR);
+
+        let report = sweep(2 * HOUR, 0).unwrap();
+        assert_eq!((report.removed, report.freed_bytes), (1, 10));
+        assert!(!old.exists());
+        assert!(new.exists());
+    }
+
+    #[test]
+    fn sweep_drops_oldest_first_until_under_the_limit() {
+        let (_guard, dir) = scratch();
+        let oldest = file(dir.path(), "ocr/a.png", 300, 3 * HOUR);
+        let older = file(dir.path(), "ocr/b.png", 300, 2 * HOUR);
+        let newest = file(dir.path(), "ocr/c.png", 300, HOUR);
+
+        let report = sweep(Duration::ZERO, 700).unwrap();
+        assert_eq!((report.removed, report.freed_bytes), (1, 300));
+        assert!(!oldest.exists());
+        assert!(older.exists());
+
+        let report = sweep(Duration::ZERO, 300).unwrap();
+        assert_eq!(report.removed, 1);
+        assert!(!older.exists());
+        assert!(newest.exists());
+        assert_eq!(usage().unwrap().total_bytes, 300);
+    }
+
+    #[test]
+    fn open_files_are_left_to_their_holder() {
+        let (_guard, dir) = scratch();
+        let stale = file(dir.path(), "ocr/stale.png", 10, 3 * HOUR);
+        let open = CacheFile::new(Subsystem::Ocr, "png").unwrap();
+        fs::write(open.path(), b"in use").unwrap();
+
+        let report = clear().unwrap();
+        assert_eq!((report.removed, report.in_use), (1, 1));
+        assert!(!stale.exists());
+        assert!(open.path().exists());
+
+        let path = open.path().to_path_buf();
+        drop(open);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn only_subsystem_folders_are_swept() {
+        let (_guard, dir) = scratch();
+        let db = file(dir.path(), "veea.db", 10, 3 * HOUR);
+        let notes = file(dir.path(), "notes/todo.txt", 10, 3 * HOUR);
+        let scratch = file(dir.path(), "ocr/page.png", 10, 3 * HOUR);
+
+        let report = clear().unwrap();
+        assert_eq!(report.removed, 1);
+        assert!(!scratch.exists());
+        assert!(db.exists() && notes.exists());
+        assert_eq!(usage().unwrap().total_bytes, 0);
+    }
+
+    #[test]
+    fn cache_dir_holding_kept_files_is_refused() {
+        let data = PathBuf::from("/home/me/.local/share/veea");
+        let config = |cache_dir: PathBuf| CaptureConfig {
+            cache_dir,
+            db_path: data.join("veea.db"),
+            capture_dir: data.join("captures"),
+            ..Default::default()
+        };
+        let config_path = PathBuf::from("/home/me/.config/veea/config.toml");
+
+        check_dir(&config(data.join("cache")), &config_path).unwrap();
+        for (cache_dir, named) in [
+            (data.clone(), "db_path"),
+            (PathBuf::from("/home/me"), "db_path"),
+            (PathBuf::from("/home/me/.config"), "the config file"),
+            (data.join("captures"), "capture_dir"),
+        ] {
+            let error = check_dir(&config(cache_dir), &config_path)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(named), "{error}");
+        }
+    }
+}
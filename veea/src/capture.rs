@@ -1,28 +1,361 @@
-use std::{collections::VecDeque, fs, path::PathBuf};
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use xcap::{Monitor, Window};
 
 use crate::{
+    app_id,
+    ax_text::{self, AxLimits, AxProbe},
+    clipboard,
+    code_text,
+    codec::{self, ImageFormat, PngCompression},
+    color_profile::{ColorProfile, ProfileCache},
     config::CaptureConfig,
-    db::{CaptureRecord, Db},
+    crypto,
+    cursor,
+    db::{CaptureRecord, Db, MaintenanceOp, MaintenanceReport},
+    devices::{DeviceState, DeviceStateCache},
+    disk::FreeSpaceCache,
+    document::{self, DocProbe},
     error::{AppError, AppResult},
+    focus,
+    hooks::PostCaptureHook,
+    importance::TitleChurn,
+    incremental_ocr::IncrementalOcr,
+    input_activity::{self, InputCounts},
+    memory::{BoundedMap, CollectionSize, MemoryUsage},
+    ocr::{self, Ocr, OcrBackend, OcrLine, OcrResult},
+    quality::ImageStats,
+    schedule::Schedule,
     search::SearchIndex,
+    workspace,
+    writer::ImageWriter,
 };
 
+/// Lowercase title fragments of Veea's own web UI (page title and the
+/// bare address browsers show before it loads), skipped when `skip_self`
+/// is on so the timeline doesn't fill up with pictures of itself.
+const SELF_TITLE_PATTERNS: &[&str] = &["veea timeline", "127.0.0.1:8787", "localhost:8787"];
+
+/// Bytes of a title used in a capture's filename, well under the usual
+/// 255-byte name limit; the full title is stored in the database.
+const FILENAME_TITLE_BYTES: usize = 120;
+
 fn normalized(filename: &str) -> String {
-    filename.replace(['|', '\\', ':', '/', '<', '>', '"', '?', '*'], "_")
+    let mut out = String::new();
+    for c in filename.chars() {
+        if out.len() + c.len_utf8() > FILENAME_TITLE_BYTES {
+            break;
+        }
+        out.push(match c {
+            '|' | '\\' | ':' | '/' | '<' | '>' | '"' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        });
+    }
+    out
+}
+
+/// Caller-chosen event types are rendered by the timeline page and used
+/// as filter values, so keep them short and plain.
+pub fn validate_event_type(event_type: &str) -> AppResult<()> {
+    let valid = (1..=32).contains(&event_type.len())
+        && event_type
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidEventType(event_type.to_string()))
+    }
+}
+
+fn log_ocr(ocr: &dyn Ocr, result: AppResult<OcrResult>) -> Option<OcrResult> {
+    match result {
+        Ok(result) => Some(result).filter(|r| !r.text.is_empty()),
+        Err(e) => {
+            eprintln!("OCR ({}) failed: {}", ocr.name(), e);
+            None
+        }
+    }
+}
+
+/// Text and line positions as stored on a `CaptureRecord`.
+fn split_ocr(result: Option<OcrResult>) -> (Option<String>, Vec<OcrLine>) {
+    result.map_or((None, Vec::new()), |r| (Some(r.text), r.lines))
+}
+
+/// Encodes `image` as `format` and writes it with `write_atomic`.
+pub(crate) fn save_image(
+    image: &xcap::image::RgbaImage,
+    path: &Path,
+    format: ImageFormat,
+    compression: PngCompression,
+    icc: Option<&[u8]>,
+) -> AppResult<()> {
+    write_atomic(path, &codec::encode_tagged(image, format, compression, icc)?)
+}
+
+/// Suffix of the hidden temp files `write_atomic` renames into place.
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// Writes to a temp file beside `path` and renames it into place, so a
+/// crash mid-write never leaves a truncated file at the final path. The
+/// data is synced before the rename and, on Unix, the directory after it,
+/// so the new name can't survive a power loss without its contents.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> AppResult<()> {
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(TEMP_SUFFIX);
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = write_synced(&tmp_path, bytes).and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = fs::File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Whether `path` is a `write_atomic` temp file. Those are never stored
+/// in the database; one still on disk is left over from a crash.
+pub fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.') && n.ends_with(TEMP_SUFFIX))
+}
+
+/// Deletes temp files under `dir` last modified over `min_age` ago, so
+/// writes still in progress are left alone. Returns how many went.
+pub fn remove_temp_files(dir: &Path, min_age: std::time::Duration) -> std::io::Result<usize> {
+    let mut removed = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                pending.push(path);
+            } else if is_temp_file(&path)
+                && meta.modified()?.elapsed().is_ok_and(|age| age > min_age)
+            {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Coarse pixelation: scale down by `REDACT_BLOCK` and back up with
+/// nearest-neighbour sampling, leaving nothing legible.
+fn pixelate(image: &xcap::image::RgbaImage) -> xcap::image::RgbaImage {
+    use xcap::image::imageops::{resize, FilterType};
+    const REDACT_BLOCK: u32 = 24;
+    let (w, h) = image.dimensions();
+    let small = resize(
+        image,
+        (w / REDACT_BLOCK).max(1),
+        (h / REDACT_BLOCK).max(1),
+        FilterType::Triangle,
+    );
+    resize(&small, w, h, FilterType::Nearest)
+}
+
+/// `monitor` of captures stitched from every display, and the snapshot
+/// selector that asks for one.
+pub const COMPOSITE_MONITOR: &str = "composite";
+/// Share of the per-minute budget in use past which a capture's
+/// `trigger_detail` notes how close it came to the rate limit.
+const RATE_LIMIT_NEAR: f64 = 0.8;
+/// Largest composite built, about 256 MiB of RGBA.
+const MAX_COMPOSITE_PIXELS: i64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureTarget {
+    /// Whatever window currently has focus, trying each stage of
+    /// `capture_strategy` in turn.
+    Focused,
+    /// Only the window whose title matches.
+    ByTitle,
+}
+
+/// One way of getting a capture's image, listed in `capture_strategy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureStage {
+    FocusedWindow,
+    /// The window whose title matches the event's.
+    TitleMatch,
+    /// The whole monitor, or all of them with `composite_monitors`.
+    Monitor,
+}
+
+impl CaptureStage {
+    fn method(self) -> CaptureMethod {
+        match self {
+            CaptureStage::FocusedWindow => CaptureMethod::FocusedWindow,
+            CaptureStage::TitleMatch => CaptureMethod::TitleMatch,
+            CaptureStage::Monitor => CaptureMethod::MonitorFallback,
+        }
+    }
+}
+
+/// Stages a capture tries, in order, until one yields an image. Never
+/// empty and without repeats; config files listing otherwise fail to load.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<CaptureStage>", into = "Vec<CaptureStage>")]
+pub struct CaptureStrategy(Vec<CaptureStage>);
+
+impl CaptureStrategy {
+    pub fn stages(&self) -> &[CaptureStage] {
+        &self.0
+    }
+}
+
+impl Default for CaptureStrategy {
+    fn default() -> Self {
+        Self(vec![
+            CaptureStage::FocusedWindow,
+            CaptureStage::TitleMatch,
+            CaptureStage::Monitor,
+        ])
+    }
+}
+
+impl TryFrom<Vec<CaptureStage>> for CaptureStrategy {
+    type Error = String;
+
+    fn try_from(stages: Vec<CaptureStage>) -> Result<Self, Self::Error> {
+        if stages.is_empty() {
+            return Err("capture_strategy needs at least one stage".to_string());
+        }
+        if (1..stages.len()).any(|i| stages[..i].contains(&stages[i])) {
+            return Err("capture_strategy lists a stage twice".to_string());
+        }
+        Ok(Self(stages))
+    }
+}
+
+impl From<CaptureStrategy> for Vec<CaptureStage> {
+    fn from(strategy: CaptureStrategy) -> Self {
+        strategy.0
+    }
+}
+
+/// What led to a capture, stored alongside it.
+#[derive(Debug, Clone, Copy, Default)]
+struct EventContext<'a> {
+    /// Title the window showed before the current one.
+    prev_title: Option<&'a str>,
+    /// File whose appearance triggered an "fs" capture.
+    trigger_path: Option<&'a Path>,
+}
+
+/// How a capture's image was obtained, stored as `capture_method`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMethod {
+    FocusedWindow,
+    /// A window found by its title after focus lookup failed or was skipped.
+    TitleMatch,
+    /// The whole primary monitor, when no window could be captured.
+    MonitorFallback,
+    /// A monitor picked by the caller of a snapshot.
+    Region,
+}
+
+impl CaptureMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CaptureMethod::FocusedWindow => "focused_window",
+            CaptureMethod::TitleMatch => "title_match",
+            CaptureMethod::MonitorFallback => "monitor_fallback",
+            CaptureMethod::Region => "region",
+        }
+    }
+}
+
+/// Where captures get their pixels. The engine reads the real screen
+/// through xcap unless `CaptureEngine::with_screen` hands it another
+/// source, such as a generated one for soak runs.
+pub trait ScreenSource: Send {
+    /// The focused window, if it could be captured.
+    fn focused_window(&self) -> Option<xcap::image::RgbaImage>;
+    /// The window titled `title`, if it could be captured.
+    fn window(&self, title: &str) -> Option<xcap::image::RgbaImage>;
+    /// A whole monitor and its name.
+    fn monitor(&self) -> AppResult<(xcap::image::RgbaImage, Option<String>)>;
 }
 
 pub struct CaptureEngine {
+    /// Effective settings: `base_config` with any active power profile applied.
     config: CaptureConfig,
+    base_config: CaptureConfig,
     db: Db,
     recent_captures: VecDeque<DateTime<Utc>>,
     search: Option<SearchIndex>,
     paused: Arc<AtomicBool>,
+    session_locked: bool,
+    displays_asleep: bool,
+    /// Priority apps already captured on first focus, least recently
+    /// focused dropped first.
+    priority_seen: BoundedMap<String, ()>,
+    /// Set when a sensitive window was seen; the next capture before this
+    /// instant is suppressed too.
+    sensitive_until: Option<DateTime<Utc>>,
+    /// Event captures before this instant are dropped; set on wake.
+    wake_hold_until: Option<DateTime<Utc>>,
+    /// Built from the base config; skipped while the effective config has
+    /// OCR turned off.
+    ocr: Option<Box<dyn Ocr>>,
+    /// Format new captures are saved in, see `codec::writable`.
+    image_format: ImageFormat,
+    /// Recent title-change captures, feeding the importance score.
+    title_churn: TitleChurn,
+    /// Last frame read and its lines, for `incremental_ocr`.
+    ocr_base: IncrementalOcr,
+    /// Whether `config` has the battery profile applied.
+    on_battery: bool,
+    free_space: FreeSpaceCache,
+    devices: DeviceStateCache,
+    color_profiles: ProfileCache,
+    schedule: Schedule,
+    /// The last `schedule` check found capture outside its windows.
+    off_schedule: bool,
+    /// Saves event captures and inserts their records off this thread.
+    writer: ImageWriter,
+    /// Replaces the real screen when set.
+    screen: Option<Box<dyn ScreenSource>>,
+}
+
+/// One step of `CaptureEngine::self_test`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
 }
 
 impl CaptureEngine {
@@ -37,33 +370,286 @@ impl CaptureEngine {
             None
         };
 
+        let ocr = ocr::select_backend(config.ocr_backend);
+        let image_format = codec::writable(config.image_format);
+        let priority_seen = BoundedMap::new(config.memory.priority_seen);
+        let writer = ImageWriter::new(db.reopen()?, &config);
+        let schedule = Schedule::new(&config.schedule)?;
+        if config.track_input_counts || config.track_activity_level {
+            input_activity::start();
+        }
+
         Ok(Self {
+            ocr,
+            base_config: config.clone(),
             config,
             db,
             recent_captures: VecDeque::new(),
             search,
             paused,
+            session_locked: false,
+            displays_asleep: false,
+            priority_seen,
+            sensitive_until: None,
+            wake_hold_until: None,
+            image_format,
+            title_churn: TitleChurn::default(),
+            ocr_base: IncrementalOcr::default(),
+            on_battery: false,
+            free_space: FreeSpaceCache::default(),
+            devices: DeviceStateCache::default(),
+            color_profiles: ProfileCache::default(),
+            schedule,
+            off_schedule: false,
+            writer,
+            screen: None,
         })
     }
 
+    /// Takes captures from `screen` instead of the real screen.
+    pub fn with_screen(mut self, screen: impl ScreenSource + 'static) -> Self {
+        self.screen = Some(Box::new(screen));
+        self
+    }
+
     pub fn db_path(&self) -> PathBuf {
         self.db.connection_path()
     }
 
-    /// Capture a single snapshot and store as PNG.
-    pub fn snapshot_png(&mut self, label: &str) -> AppResult<PathBuf> {
+    /// Sizes of the engine's own collections; the caller adds the rest.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            recent_captures: CollectionSize {
+                len: self.recent_captures.len(),
+                cap: Some(self.config.max_captures_per_minute as usize)
+                    .filter(|&limit| limit > 0),
+            },
+            priority_seen: CollectionSize::of(&self.priority_seen),
+            ..MemoryUsage::default()
+        }
+    }
+
+    /// Suspends event captures while the session is locked, independently of
+    /// the user-controlled pause flag.
+    pub fn set_session_locked(&mut self, locked: bool) {
+        self.session_locked = locked;
+        let _ = self
+            .db
+            .insert_daemon_event(if locked { "lock" } else { "unlock" });
+    }
+
+    /// Suspends event captures while the displays are asleep, which would
+    /// otherwise leave a black monitor-fallback frame every interval.
+    pub fn set_displays_asleep(&mut self, asleep: bool) {
+        self.displays_asleep = asleep;
+        // Displays may have been swapped while off.
+        self.color_profiles.clear();
+        let _ = self
+            .db
+            .insert_daemon_event(if asleep { "display_sleep" } else { "display_wake" });
+    }
+
+    /// Holds event captures for `post_wake_delay_ms` after the machine
+    /// wakes, since the first frames tend to be blank or stale.
+    pub fn set_woke(&mut self) {
+        let delay = chrono::Duration::milliseconds(self.config.post_wake_delay_ms as i64);
+        self.wake_hold_until = Some(Utc::now() + delay);
+        self.color_profiles.clear();
+        let _ = self.db.insert_daemon_event("wake");
+    }
+
+    /// Switches between the base config and its battery overrides, taking
+    /// effect from the next capture.
+    pub fn set_on_battery(&mut self, on_battery: bool) {
+        self.on_battery = on_battery;
+        self.apply_power_profile();
+        let _ = self
+            .db
+            .insert_daemon_event(if on_battery { "on_battery" } else { "on_ac" });
+    }
+
+    fn apply_power_profile(&mut self) {
+        self.config = match self.base_config.battery_overrides().filter(|_| self.on_battery) {
+            Some(profile) => self.base_config.with_profile(&profile),
+            None => self.base_config.clone(),
+        };
+    }
+
+    /// Replaces the base config, rebuilding what `new` derives from it;
+    /// used by `POST /setup`. Takes effect from the next capture.
+    pub fn apply_config(&mut self, config: CaptureConfig) -> AppResult<()> {
+        let schedule = Schedule::new(&config.schedule)?;
+        self.db
+            .set_text_key(config.original_passphrase.as_deref(), config.encrypt_text)?;
+        // Replacing the writer waits for the old one's queue to drain.
+        self.writer = ImageWriter::new(self.db.reopen()?, &config);
+        self.search = if config.enable_search_index {
+            Some(SearchIndex::new(&config.search_index_path)?)
+        } else {
+            None
+        };
+        self.ocr = ocr::select_backend(config.ocr_backend);
+        self.ocr_base = IncrementalOcr::default();
+        self.image_format = codec::writable(config.image_format);
+        self.free_space.clear();
+        self.schedule = schedule;
+        if config.track_input_counts || config.track_activity_level {
+            input_activity::start();
+        }
+        self.base_config = config;
+        self.apply_power_profile();
+        Ok(())
+    }
+
+    /// Checks each step a capture depends on, for the setup page.
+    pub fn self_test(&self) -> Vec<SelfTestCheck> {
+        let mut checks = Vec::new();
+        let mut check = |name: &str, result: Result<String, String>| {
+            let (ok, detail) = match result {
+                Ok(detail) => (true, detail),
+                Err(detail) => (false, detail),
+            };
+            checks.push(SelfTestCheck { name: name.to_string(), ok, detail });
+        };
+
+        check(
+            "list_windows",
+            focus::list_windows()
+                .map(|w| format!("{} windows", w.len()))
+                .map_err(|e| e.to_string()),
+        );
+        check(
+            "focused_window",
+            self.capture_focused_window()
+                .map(|img| format!("{}x{}", img.width(), img.height()))
+                .ok_or_else(|| "could not capture the focused window".to_string()),
+        );
+        check(
+            "monitor",
+            self.capture_monitor_fallback()
+                .map(|(img, name)| {
+                    format!("{}x{} from {}", img.width(), img.height(), name.as_deref().unwrap_or("unknown"))
+                })
+                .map_err(|e| e.to_string()),
+        );
+        let probe = self.config.capture_dir.join(format!(".veea-probe-{}", Uuid::new_v4()));
+        check(
+            "capture_dir",
+            fs::create_dir_all(&self.config.capture_dir)
+                .map_err(AppError::from)
+                .and_then(|_| write_atomic(&probe, b"probe"))
+                .map(|_| {
+                    let _ = fs::remove_file(&probe);
+                    format!("{} is writable", self.config.capture_dir.display())
+                })
+                .map_err(|e| e.to_string()),
+        );
+        let ocr = match (self.config.ocr_backend, &self.ocr) {
+            (OcrBackend::None, _) => Ok("off".to_string()),
+            (_, None) => Err("the OCR backend is not available here".to_string()),
+            (_, Some(ocr)) => ocr
+                .extract(&xcap::image::RgbaImage::from_pixel(64, 32, xcap::image::Rgba([255; 4])))
+                .map(|_| format!("{} runs", ocr.name()))
+                .map_err(|e| e.to_string()),
+        };
+        check("ocr", ocr);
+        checks
+    }
+
+    /// Decodes capture `id`'s stored image again, refreshing its size, and
+    /// with `ocr` re-runs the configured OCR backend over it. Thumbnails
+    /// are rendered from the image on request, so a readable image is all
+    /// they need. Redacted captures keep their empty text, and a failed
+    /// OCR run leaves the text from before.
+    pub fn reprocess(&self, id: &str, ocr: bool) -> AppResult<Option<CaptureRecord>> {
+        let Some(mut record) = self.db.get_capture(id)? else {
+            return Ok(None);
+        };
+        let bytes = record
+            .read_image(&self.config.capture_dir)
+            .map_err(|e| AppError::ImageUnreadable(format!("{}: {e}", record.path)))?;
+        let image = codec::decode(&bytes, record.codec)?;
+        record.width = Some(image.width());
+        record.height = Some(image.height());
+        if ocr
+            && record.original_path.is_none()
+            && let Some(backend) = self.ocr.as_deref()
+        {
+            match backend.extract(&image) {
+                Err(e) => {
+                    log_ocr(backend, Err(e));
+                }
+                result => {
+                    (record.ocr_text, record.ocr_lines) = split_ocr(log_ocr(backend, result));
+                    record.contains_code =
+                        code_text::classify(record.ocr_text.as_deref(), record.ax_text.as_deref());
+                }
+            }
+        }
+        self.db.update_reprocessed(&record)?;
+        Ok(Some(record))
+    }
+
+    pub fn run_maintenance(&self, op: MaintenanceOp) -> AppResult<MaintenanceReport> {
+        self.db.run_maintenance(op)
+    }
+
+    /// Interval periodic captures should currently run at.
+    pub fn capture_interval_ms(&self) -> u64 {
+        self.config.capture_interval_ms
+    }
+
+    /// Captures the monitor `monitor` names, by index or name, or else the
+    /// first one, and stores it in the configured codec as `event_type`,
+    /// e.g. "bug" or "receipt", so on-demand captures stand apart in the
+    /// timeline.
+    pub fn snapshot_png(
+        &mut self,
+        label: &str,
+        monitor: Option<&str>,
+        event_type: &str,
+    ) -> AppResult<CaptureRecord> {
+        validate_event_type(event_type)?;
+        self.snapshot(label, monitor, None, event_type)
+    }
+
+    /// One frame of a burst; frames share `group_id` and skip the rate limit,
+    /// which bursts are capped separately from.
+    pub fn snapshot_burst_frame(&mut self, label: &str, group_id: &str) -> AppResult<CaptureRecord> {
+        self.snapshot(label, None, Some(group_id), "burst")
+    }
+
+    fn snapshot(
+        &mut self,
+        label: &str,
+        monitor: Option<&str>,
+        group_id: Option<&str>,
+        event_type: &str,
+    ) -> AppResult<CaptureRecord> {
         if self.paused.load(Ordering::Relaxed) {
-            return Err(AppError::Capture("capture paused".to_string()));
+            return Err(AppError::Paused);
         }
 
+        self.ensure_free_space(event_type)?;
+
+        // Labels come from API callers, so clean them like window titles.
+        let label = &focus::sanitize_title(label);
         let now = Utc::now();
-        let id = Uuid::new_v4().to_string();
+        let id = self.config.capture_id_version.new_id();
         let safe_label = normalized(label);
         let date_dir = self.date_dir(now);
         fs::create_dir_all(&date_dir)?;
-        let filename = date_dir.join(format!("snapshot_{}_{}.png", safe_label, id));
+        let filename = date_dir.join(format!(
+            "snapshot_{}_{}.{}",
+            safe_label,
+            id,
+            self.image_format.extension()
+        ));
 
-        let (image, monitor_label) = self.capture_monitor_fallback()?;
+        let ((image, monitor_label), method) = match monitor {
+            Some(selector) => (self.capture_selected_monitor(selector)?, CaptureMethod::Region),
+            None => (self.capture_monitor_fallback()?, CaptureMethod::MonitorFallback),
+        };
         let width = image.width();
         let height = image.height();
 
@@ -74,29 +660,65 @@ impl CaptureEngine {
             )));
         }
 
-        image
-            .save(&filename)
-            .map_err(|e| AppError::Capture(e.to_string()))?;
+        let color_profile = self.color_profile(None, monitor_label.as_deref());
+        let color_profile_name = color_profile.as_ref().map(|p| p.name.clone());
+        let icc = self.embedded_icc(color_profile);
+        let compression = self.config.png_compression;
+        save_image(&image, &filename, self.image_format, compression, icc.as_deref())?;
+        let (ocr_text, ocr_lines) = split_ocr(self.recognize(None, &image));
+        // Snapshots were asked for explicitly, so a blank one is kept.
+        let blank = ImageStats::of(&image).is_blank();
+        let importance = self.importance(event_type, ocr_text.as_deref(), blank, now);
+        let devices = self.device_state();
+        let input = self.input_counts();
 
         let record = CaptureRecord {
             id: id.clone(),
             ts: now,
             window_title: Some(label.to_string()),
             app_name: None,
-            event_type: "snapshot".to_string(),
+            app_id: None,
+            event_type: event_type.to_string(),
             path: filename.to_string_lossy().to_string(),
             width: Some(width),
             height: Some(height),
             monitor: monitor_label,
             hash: None,
+            capture_group_id: group_id.map(str::to_string),
+            rel_path: self.relative_path(&filename),
+            clipboard_text: self.clipboard_text(self.focus_redacted()),
+            prev_title: None,
+            original_path: None,
+            contains_code: code_text::classify(ocr_text.as_deref(), None),
+            ocr_text,
+            ax_text: None,
+            ocr_lines,
+            capture_method: Some(method.as_str().to_string()),
+            mic_active: devices.mic_active,
+            cam_active: devices.cam_active,
+            codec: self.image_format,
+            blank,
+            importance: Some(importance),
+            trigger_path: None,
+            doc_path: None,
+            workspace: self.workspace(),
+            color_profile: color_profile_name,
+            keys_per_min: input.map(|counts| counts.keys),
+            clicks_per_min: input.map(|counts| counts.clicks),
+            activity_level: self.activity_level(),
+            trigger_detail: None,
+            pack: None,
         };
 
         self.db.insert_capture(&record)?;
         if let Some(index) = &self.search {
-            let _ = index.add_capture(&record, None);
+            let _ = index.add_capture(&record, record.ocr_text.as_deref());
+        }
+        if let Some(hook) = PostCaptureHook::from_config(&self.config) {
+            hook.run(&record);
         }
 
-        Ok(filename)
+        Ok(record)
     }
 
     /// Test function to verify capture is working
@@ -105,19 +727,15 @@ impl CaptureEngine {
         
         // Test 1: List windows
         println!("Test 1: Listing windows...");
-        match Window::all() {
+        match focus::list_windows() {
             Ok(windows) => {
-                let mut count = 0;
-                for window in windows {
-                    count += 1;
-                    if let Ok(title) = window.title() {
-                        if !title.is_empty() {
-                            let minimized = window.is_minimized().unwrap_or(false);
-                            println!("  Window {}: '{}' (minimized: {})", count, title, minimized);
-                        }
+                for (i, window) in windows.iter().enumerate() {
+                    if let Some(title) = window.title.as_deref().filter(|t| !t.is_empty()) {
+                        let minimized = window.minimized.unwrap_or(false);
+                        println!("  Window {}: '{}' (minimized: {})", i + 1, title, minimized);
                     }
                 }
-                println!("Found {} total windows", count);
+                println!("Found {} total windows", windows.len());
             }
             Err(e) => {
                 eprintln!("ERROR: Failed to list windows: {:?}", e);
@@ -149,70 +767,244 @@ impl CaptureEngine {
         Ok(())
     }
 
-    pub fn capture_event(&mut self, window_title: &str, event_type: &str) -> AppResult<()> {
+    pub fn capture_event(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+        prev_title: Option<&str>,
+        event_type: &str,
+    ) -> AppResult<()> {
+        let context = EventContext { prev_title, ..EventContext::default() };
+        self.capture(window_title, app_name, event_type, false, CaptureTarget::Focused, context)
+            .map(|_| ())
+    }
+
+    /// Captures the focused window because `path` appeared in one of the
+    /// `watch_paths` folders.
+    pub fn capture_fs_event(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+        path: &Path,
+    ) -> AppResult<()> {
+        let context = EventContext { trigger_path: Some(path), ..EventContext::default() };
+        self.capture(window_title, app_name, "fs", false, CaptureTarget::Focused, context)
+            .map(|_| ())
+    }
+
+    /// Captures the first focus of a `priority_apps` entry in this session,
+    /// bypassing the rate limit. Returns whether the window qualified, so
+    /// the caller can skip its regular focus capture.
+    pub fn capture_priority_focus(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+    ) -> AppResult<bool> {
+        let Some(key) = self.priority_key(window_title, app_name) else {
+            return Ok(false);
+        };
+        if self.priority_seen.contains_key(&key) {
+            return Ok(false);
+        }
+        let context = EventContext::default();
+        if self.capture(window_title, app_name, "priority_focus", true, CaptureTarget::Focused, context)? {
+            self.priority_seen.insert(key, ());
+        }
+        Ok(true)
+    }
+
+    /// Follow-up capture once a priority app had time to finish loading.
+    pub fn capture_priority_settle(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+    ) -> AppResult<()> {
+        let context = EventContext::default();
+        self.capture(window_title, app_name, "priority_focus", true, CaptureTarget::Focused, context)
+            .map(|_| ())
+    }
+
+    /// Captures the window that just lost focus, located by its title, so
+    /// the "before" side of a context switch is kept. Never falls back to
+    /// the monitor since that would show the newly focused window.
+    pub fn capture_previous_window(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+    ) -> AppResult<()> {
+        let context = EventContext::default();
+        self.capture(window_title, app_name, "focus_prev", false, CaptureTarget::ByTitle, context)
+            .map(|_| ())
+    }
+
+    fn priority_key(&self, window_title: &str, app_name: Option<&str>) -> Option<String> {
+        let lower_title = window_title.to_lowercase();
+        self.config
+            .priority_apps
+            .iter()
+            .map(|p| p.to_lowercase())
+            .find(|p| match app_name {
+                Some(app) => app.to_lowercase() == *p,
+                None => lower_title.contains(p.as_str()),
+            })
+    }
+
+    /// Returns `Ok(true)` when a capture was stored and `Ok(false)` when it
+    /// was skipped by pause, lock, display sleep, or exclusion rules.
+    fn capture(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+        event_type: &str,
+        bypass_rate_limit: bool,
+        target: CaptureTarget,
+        context: EventContext<'_>,
+    ) -> AppResult<bool> {
         if self.paused.load(Ordering::Relaxed) {
             println!("Capture paused, skipping event for '{}'", window_title);
-            return Ok(());
+            return Ok(false);
+        }
+
+        if self.session_locked || self.displays_asleep || !self.on_schedule() {
+            return Ok(false);
         }
 
-        if self.should_skip(window_title) {
+        // Facts about how this capture was decided, stored as its
+        // `trigger_detail`.
+        let mut detail = Vec::new();
+        if let Some(until) = self.wake_hold_until {
+            if Utc::now() < until {
+                return Ok(false);
+            }
+            self.wake_hold_until = None;
+            detail.push("first_after_wake".to_string());
+        }
+
+        if let Some(detail) = self.sensitive_skip(window_title, app_name) {
+            println!("Sensitive window, skipping capture ({detail})");
+            let _ = self.db.insert_attempt(event_type, "skipped_sensitive", Some(detail));
+            return Ok(false);
+        }
+
+        // Only the focused window's app can be asked for its id.
+        let app_id = matches!(target, CaptureTarget::Focused)
+            .then(app_id::focused)
+            .flatten();
+        if self.should_skip(window_title, app_name, app_id.as_deref()) {
             println!("Window '{}' is in exclude list, skipping", window_title);
-            return Ok(());
+            return Ok(false);
         }
 
-        if !self.consume_rate_limit() {
-            return Err(AppError::Capture(format!(
-                "capture rate exceeded ({} per minute)",
-                self.config.max_captures_per_minute
-            )));
+        if !bypass_rate_limit && !self.consume_rate_limit(event_type) {
+            return Err(AppError::RateLimited(self.config.max_captures_per_minute));
+        }
+        if bypass_rate_limit {
+            detail.push("rate_limit=bypassed".to_string());
+        } else if let Some(limit) = self.rate_limit(event_type) {
+            let used = self.recent_captures.len();
+            if used as f64 >= limit as f64 * RATE_LIMIT_NEAR {
+                detail.push(format!("rate_limit={used}/{limit}"));
+            }
+        }
+        if !self.config.include_apps.is_empty() || !self.config.include_titles.is_empty() {
+            detail.push("allowlisted".to_string());
         }
+        self.ensure_free_space(event_type)?;
         
         println!("Attempting to capture window '{}' (event: {})", window_title, event_type);
 
         let now = Utc::now();
-        let id = Uuid::new_v4().to_string();
+        let id = self.config.capture_id_version.new_id();
         let safe_title = normalized(window_title);
         let date_dir = self.date_dir(now);
         fs::create_dir_all(&date_dir)?;
-        let filename = date_dir.join(format!("{event_type}_{safe_title}_{id}.png"));
-
-        // Try to capture focused window first (more reliable)
-        let (image, monitor_label) = match self.capture_focused_window() {
-            Some(img) => {
-                let w = img.width();
-                let h = img.height();
-                if w == 0 || h == 0 {
-                    eprintln!("Warning: captured image has zero dimensions ({}x{})", w, h);
-                } else {
-                    println!("Captured focused window: {}x{}", w, h);
+        let extension = self.image_format.extension();
+        let filename = date_dir.join(format!("{event_type}_{safe_title}_{id}.{extension}"));
+
+        // Runs alongside the screenshot. Only the focused window's document
+        // can be asked for, so captures by title go without.
+        let doc_probe = (self.config.capture_doc_path
+            && document::SUPPORTED
+            && matches!(target, CaptureTarget::Focused))
+        .then(|| {
+            DocProbe::start(std::time::Duration::from_millis(self.config.doc_path_timeout_ms))
+        });
+        // Excluded and sensitive windows never get here; redacted ones are
+        // not walked either, as their text must stay out of search.
+        let ax_probe = (self.config.capture_ax_text
+            && ax_text::SUPPORTED
+            && matches!(target, CaptureTarget::Focused)
+            && !self.should_redact(window_title, app_name))
+        .then(|| {
+            let limits = AxLimits {
+                timeout: std::time::Duration::from_millis(self.config.ax_text_timeout_ms),
+                max_depth: self.config.ax_text_max_depth,
+                max_chars: self.config.ax_text_max_chars,
+            };
+            AxProbe::start(limits, window_title)
+        });
+
+        // Walk `capture_strategy` until a stage yields an image. A window
+        // that just lost focus can only be found by its title; the focused
+        // window or the monitor would show something else.
+        let mut tried = Vec::new();
+        let mut monitor_error = None;
+        let mut captured = None;
+        for &stage in self.config.capture_strategy.stages() {
+            let usable = match stage {
+                CaptureStage::TitleMatch => true,
+                CaptureStage::FocusedWindow => target == CaptureTarget::Focused,
+                CaptureStage::Monitor => {
+                    target == CaptureTarget::Focused && self.config.allow_monitor_fallback
                 }
-                (img, None)
+            };
+            if !usable {
+                continue;
             }
-            None => {
-                // Fallback to searching by title
-                match self.capture_window_image(window_title) {
-                    Some(img) => {
-                        let w = img.width();
-                        let h = img.height();
-                        if w == 0 || h == 0 {
-                            eprintln!("Warning: captured image has zero dimensions ({}x{})", w, h);
-                        } else {
-                            println!("Captured window '{}': {}x{}", window_title, w, h);
-                        }
-                        (img, None)
-                    }
-                    None if self.config.allow_monitor_fallback => {
-                        println!("Window capture failed for '{}', using monitor fallback", window_title);
-                        self.capture_monitor_fallback()?
-                    }
-                    None => {
-                        return Err(AppError::Capture(format!(
-                            "no window matched title '{window_title}' and monitor fallback disabled"
-                        )))
-                    }
+            tried.push(stage.method());
+            let image = match stage {
+                CaptureStage::FocusedWindow => self.capture_focused_window().map(|img| (img, None)),
+                CaptureStage::TitleMatch => {
+                    self.capture_window_image(window_title).map(|img| (img, None))
                 }
+                CaptureStage::Monitor => {
+                    println!("Capturing the monitor for '{}'", window_title);
+                    self.capture_monitor_fallback()
+                        .map_err(|e| monitor_error = Some(e))
+                        .ok()
+                }
+            };
+            if let Some((img, label)) = image {
+                captured = Some((img, label, stage.method()));
+                break;
             }
+        }
+        let Some((image, monitor_label, method)) = captured else {
+            self.record_failure(event_type, &tried);
+            return Err(monitor_error.unwrap_or_else(|| {
+                AppError::Capture(format!(
+                    "no capture_strategy stage could capture '{window_title}'"
+                ))
+            }));
         };
+        // Stages tried in order, ending with the one that worked.
+        let chain: Vec<_> = tried.iter().map(|m| m.as_str()).collect();
+        detail.insert(0, format!("strategy={}", chain.join(">")));
+        if image.width() == 0 || image.height() == 0 {
+            eprintln!(
+                "Warning: captured image has zero dimensions ({}x{})",
+                image.width(),
+                image.height()
+            );
+        } else {
+            println!(
+                "Captured '{}' by {}: {}x{}",
+                window_title,
+                method.as_str(),
+                image.width(),
+                image.height()
+            );
+        }
 
         let width = image.width();
         let height = image.height();
@@ -224,50 +1016,354 @@ impl CaptureEngine {
             )));
         }
         
-        image.save(&filename).map_err(|e| AppError::Capture(e.to_string()))?;
-        println!("Saved screenshot: {} ({}x{})", filename.display(), width, height);
+        let blank = ImageStats::of(&image).is_blank();
+        if blank && self.config.drop_blank_captures {
+            println!("Capture of '{}' is a blank frame, dropping it", window_title);
+            let _ = self.db.insert_attempt(event_type, "dropped_blank", None);
+            return Ok(false);
+        }
+
+        // Redacted captures skip OCR and the clipboard so their text can't
+        // leak through search.
+        let redacted = self.should_redact(window_title, app_name);
+        let (image, original_path, (ocr_text, ocr_lines)) =
+            if redacted {
+                detail.push("redacted".to_string());
+                (pixelate(&image), self.store_original(&image, &filename)?, (None, Vec::new()))
+            } else {
+                let ocr = split_ocr(self.recognize(app_name, &image));
+                (image, None, ocr)
+            };
+
+        if event_type == "title" {
+            self.title_churn.record(now);
+        }
+        let importance = self.importance(event_type, ocr_text.as_deref(), blank, now);
+        let devices = self.device_state();
+        let input = self.input_counts();
+        let ax_text = ax_probe.and_then(AxProbe::wait);
+        let color_profile = self.color_profile(Some(window_title), monitor_label.as_deref());
+        let color_profile_name = color_profile.as_ref().map(|p| p.name.clone());
+        let icc = self.embedded_icc(color_profile);
 
         let record = CaptureRecord {
             id: id.clone(),
             ts: now,
             window_title: Some(window_title.to_string()),
-            app_name: None,
+            app_name: app_name.map(str::to_string),
+            app_id,
             event_type: event_type.to_string(),
             path: filename.to_string_lossy().to_string(),
             width: Some(width),
             height: Some(height),
             monitor: monitor_label,
             hash: None,
+            capture_group_id: None,
+            rel_path: self.relative_path(&filename),
+            clipboard_text: self.clipboard_text(redacted),
+            prev_title: context.prev_title.map(str::to_string),
+            original_path,
+            contains_code: code_text::classify(ocr_text.as_deref(), ax_text.as_deref()),
+            ocr_text,
+            ax_text,
+            ocr_lines,
+            capture_method: Some(method.as_str().to_string()),
+            mic_active: devices.mic_active,
+            cam_active: devices.cam_active,
+            codec: self.image_format,
+            blank,
+            importance: Some(importance),
+            trigger_path: context.trigger_path.map(|p| p.to_string_lossy().into_owned()),
+            doc_path: doc_probe.and_then(DocProbe::wait),
+            workspace: self.workspace(),
+            color_profile: color_profile_name,
+            keys_per_min: input.map(|counts| counts.keys),
+            clicks_per_min: input.map(|counts| counts.clicks),
+            activity_level: self.activity_level(),
+            trigger_detail: Some(detail.join("; ")),
+            pack: None,
         };
 
-        self.db.insert_capture(&record)?;
         if let Some(index) = &self.search {
-            let _ = index.add_capture(&record, None);
+            let _ = index.add_capture(&record, record.ocr_text.as_deref());
         }
-        Ok(())
+        // The record is inserted once the image is on disk.
+        let compression = self.config.png_compression;
+        self.writer
+            .submit(image, filename, self.image_format, compression, icc, record)?;
+        Ok(true)
+    }
+
+    /// Whether `schedule` allows event captures now. Entering and leaving
+    /// the schedule is recorded as daemon events, so the gaps it causes
+    /// are explained on the timeline.
+    fn on_schedule(&mut self) -> bool {
+        let active = self.schedule.is_active();
+        if active == self.off_schedule {
+            self.off_schedule = !active;
+            if active {
+                println!("Capture schedule started, resuming capture");
+            } else {
+                println!("Outside the capture schedule, pausing capture");
+            }
+            let _ = self
+                .db
+                .insert_daemon_event(if active { "schedule_resume" } else { "schedule_pause" });
+        }
+        active
+    }
+
+    /// Refuses a capture while free space is below `min_free_bytes`, so a
+    /// full disk shows up as a skipped attempt rather than truncated files.
+    /// An unknown amount of free space lets the capture through.
+    fn ensure_free_space(&mut self, event_type: &str) -> AppResult<()> {
+        let required = self.config.min_free_bytes;
+        if required == 0 {
+            return Ok(());
+        }
+        match self.free_space.available(&self.config.capture_dir) {
+            Some(available) if available < required => {
+                let detail = format!("{available} bytes free");
+                let _ = self.db.insert_attempt(event_type, "skipped_low_disk", Some(&detail));
+                Err(AppError::LowDiskSpace { available, required })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Logs a capture that found no image, with the methods tried in order.
+    fn record_failure(&self, event_type: &str, tried: &[CaptureMethod]) {
+        let chain: Vec<_> = tried.iter().map(|m| m.as_str()).collect();
+        let _ = self
+            .db
+            .insert_attempt(event_type, "failed", Some(&chain.join(">")));
     }
 
     fn date_dir(&self, ts: DateTime<Utc>) -> PathBuf {
+        let date = self.config.storage_timezone.date_of(ts);
         self.config
             .capture_dir
-            .join(format!("{:04}", ts.year()))
-            .join(format!("{:02}", ts.month()))
-            .join(format!("{:02}", ts.day()))
+            .join(format!("{:04}", date.year()))
+            .join(format!("{:02}", date.month()))
+            .join(format!("{:02}", date.day()))
     }
 
-    fn should_skip(&self, window_title: &str) -> bool {
-        let lower_title = window_title.to_lowercase();
+    /// Why a capture of this window must be suppressed as sensitive, if it
+    /// must: it matches `sensitive_title_patterns`, or it is the first
+    /// capture after such a window within the cool-off.
+    fn sensitive_skip(
+        &mut self,
+        window_title: &str,
+        app_name: Option<&str>,
+    ) -> Option<&'static str> {
+        let now = Utc::now();
+        let title = window_title.to_lowercase();
+        let app = app_name.map(str::to_lowercase).unwrap_or_default();
+        let matched = self
+            .config
+            .sensitive_title_patterns
+            .iter()
+            .map(|p| p.to_lowercase())
+            .any(|p| title.contains(&p) || app.contains(&p));
+        if matched {
+            let cooloff = chrono::Duration::milliseconds(self.config.sensitive_cooloff_ms as i64);
+            self.sensitive_until = Some(now + cooloff);
+            return Some("pattern");
+        }
+        match self.sensitive_until.take() {
+            Some(until) if now < until => Some("cooloff"),
+            _ => None,
+        }
+    }
+
+    /// Reads the text of a new capture of `app_name`, only re-reading
+    /// what changed since the last one when `incremental_ocr` is on.
+    fn recognize(
+        &mut self,
+        app_name: Option<&str>,
+        image: &xcap::image::RgbaImage,
+    ) -> Option<OcrResult> {
+        if self.config.ocr_backend == OcrBackend::None {
+            return None;
+        }
+        let ocr = self.ocr.as_deref()?;
+        let result = if self.config.incremental_ocr {
+            self.ocr_base.recognize(ocr, app_name, image)
+        } else {
+            ocr.extract(image)
+        };
+        log_ocr(ocr, result)
+    }
+
+    fn should_redact(&self, window_title: &str, app_name: Option<&str>) -> bool {
+        let title = window_title.to_lowercase();
+        let app = app_name.map(str::to_lowercase);
+        self.config.redact_titles.iter().map(|p| p.to_lowercase()).any(|p| {
+            title.contains(&p) || app.as_deref() == Some(p.as_str())
+        })
+    }
+
+    /// Whether the focused window is one `redact_titles` covers. Snapshots
+    /// aren't redacted, but keep that window's clipboard out all the same.
+    fn focus_redacted(&self) -> bool {
+        self.config.capture_clipboard
+            && focus::get_focused_window()
+                .is_some_and(|w| self.should_redact(&w.title, w.app_name.as_deref()))
+    }
+
+    /// Keeps the unredacted image, encrypted, next to the served file when
+    /// `redact_keep_original` is on. Returns where it was written.
+    fn store_original(
+        &self,
+        image: &xcap::image::RgbaImage,
+        served_path: &Path,
+    ) -> AppResult<Option<String>> {
+        if !self.config.redact_keep_original {
+            return Ok(None);
+        }
+        let Some(passphrase) = self.config.original_passphrase.as_deref() else {
+            eprintln!("redact_keep_original needs original_passphrase; discarding original");
+            return Ok(None);
+        };
+        let mut name = served_path.as_os_str().to_owned();
+        name.push(".orig.enc");
+        let path = PathBuf::from(name);
+        write_atomic(&path, &crypto::encrypt(passphrase, &codec::encode(image, ImageFormat::Png)?))?;
+        Ok(Some(path.to_string_lossy().to_string()))
+    }
+
+    /// The clipboard, unless the capture was `redacted`: what was copied
+    /// likely came from the window redaction hides.
+    fn clipboard_text(&self, redacted: bool) -> Option<String> {
+        if !self.config.capture_clipboard || redacted {
+            return None;
+        }
+        clipboard::read_text(self.config.clipboard_max_bytes)
+    }
+
+    /// Heuristic score of a capture, see `ImportanceWeights`. Blank frames
+    /// score 0 so they never make the highlights.
+    fn importance(
+        &mut self,
+        event_type: &str,
+        ocr_text: Option<&str>,
+        blank: bool,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        if blank {
+            return 0.0;
+        }
+        let churn = self.title_churn.level(now);
+        let ocr_chars = ocr_text.map_or(0, |t| t.chars().count());
+        self.config.importance.score(event_type, churn, ocr_chars)
+    }
+
+    fn device_state(&mut self) -> DeviceState {
+        if !self.config.record_device_state {
+            return DeviceState::default();
+        }
+        self.devices.current()
+    }
+
+    fn input_counts(&self) -> Option<InputCounts> {
+        (self.config.track_input_counts && input_activity::SUPPORTED)
+            .then(input_activity::last_minute)
+    }
+
+    fn activity_level(&self) -> Option<u8> {
+        (self.config.track_activity_level && input_activity::SUPPORTED)
+            .then(|| input_activity::activity_level(input_activity::last_minute()))
+    }
+
+    fn workspace(&self) -> Option<String> {
+        self.config.record_workspace.then(workspace::current).flatten()
+    }
+
+    /// Colour profile of the display a capture came from: the monitor
+    /// named `monitor_label`, or the one showing `window_title` for window
+    /// captures. Composites span displays that may differ, so get none.
+    fn color_profile(
+        &mut self,
+        window_title: Option<&str>,
+        monitor_label: Option<&str>,
+    ) -> Option<ColorProfile> {
+        if !self.config.record_color_profile {
+            return None;
+        }
+        match monitor_label {
+            Some(COMPOSITE_MONITOR) => None,
+            Some(name) => self.color_profiles.of_monitor_named(name),
+            None => window_title.and_then(|title| self.color_profiles.of_window(title)),
+        }
+    }
+
+    /// The profile's ICC data to embed in the saved image, if wanted.
+    fn embedded_icc(&self, profile: Option<ColorProfile>) -> Option<Vec<u8>> {
         self.config
+            .embed_icc_profile
+            .then(|| profile.and_then(|profile| profile.icc))
+            .flatten()
+    }
+
+    fn relative_path(&self, path: &Path) -> Option<String> {
+        path.strip_prefix(&self.config.capture_dir)
+            .ok()
+            .map(|rel| rel.to_string_lossy().to_string())
+    }
+
+    /// Exclusion rules shared by every trigger: Veea's own UI, title
+    /// fragments in `exclude_titles`, and apps in `exclude_apps`, matched
+    /// case-insensitively as whole names or ids, see `app_id::aliases`.
+    /// With `include_apps` or `include_titles` set, anything matching
+    /// neither is skipped too; exclusions are checked first and win.
+    fn should_skip(
+        &self,
+        window_title: &str,
+        app_name: Option<&str>,
+        app_id: Option<&str>,
+    ) -> bool {
+        let lower_title = window_title.to_lowercase();
+        if self.config.skip_self && SELF_TITLE_PATTERNS.iter().any(|p| lower_title.contains(p)) {
+            return true;
+        }
+        let aliases = app_id::aliases(app_name, app_id);
+        if self
+            .config
+            .exclude_apps
+            .iter()
+            .any(|a| aliases.contains(&a.to_lowercase()))
+        {
+            return true;
+        }
+        if self
+            .config
             .exclude_titles
             .iter()
             .any(|p| lower_title.contains(&p.to_lowercase()))
+        {
+            return true;
+        }
+        let (include_apps, include_titles) =
+            (&self.config.include_apps, &self.config.include_titles);
+        if include_apps.is_empty() && include_titles.is_empty() {
+            return false;
+        }
+        let included = include_apps
+            .iter()
+            .any(|a| aliases.contains(&a.to_lowercase()))
+            || include_titles
+                .iter()
+                .any(|p| lower_title.contains(&p.to_lowercase()));
+        !included
     }
 
-    fn consume_rate_limit(&mut self) -> bool {
-        let limit = self.config.max_captures_per_minute as usize;
-        if limit == 0 {
+    /// Takes a slot of the per-minute budget for a capture of `event_type`.
+    /// Focus and title captures may use all of it; the others stop short
+    /// of the `rate_limit_focus_reserve` share.
+    fn consume_rate_limit(&mut self, event_type: &str) -> bool {
+        let Some(limit) = self.rate_limit(event_type) else {
             return true;
-        }
+        };
         let now = Utc::now();
         while let Some(front) = self.recent_captures.front() {
             if (*front + chrono::Duration::seconds(60)) < now {
@@ -283,7 +1379,23 @@ impl CaptureEngine {
         true
     }
 
+    /// Captures of `event_type` allowed per minute, `None` when unlimited.
+    fn rate_limit(&self, event_type: &str) -> Option<usize> {
+        let limit = self.config.max_captures_per_minute as usize;
+        if limit == 0 {
+            return None;
+        }
+        if matches!(event_type, "focus" | "title") {
+            return Some(limit);
+        }
+        let reserve = self.config.rate_limit_focus_reserve.clamp(0.0, 1.0);
+        Some(limit - (limit as f64 * reserve).round() as usize)
+    }
+
     fn capture_focused_window(&self) -> Option<xcap::image::RgbaImage> {
+        if let Some(screen) = &self.screen {
+            return screen.focused_window();
+        }
         // On macOS, Window::all() typically returns windows in z-order,
         // so the first visible, non-minimized window should be the focused one
         let windows = match Window::all() {
@@ -349,6 +1461,9 @@ impl CaptureEngine {
     }
 
     fn capture_window_image(&self, window_title: &str) -> Option<xcap::image::RgbaImage> {
+        if let Some(screen) = &self.screen {
+            return screen.window(window_title);
+        }
         if let Ok(windows) = Window::all() {
             // First, try to find the focused window by title
             for window in windows {
@@ -383,6 +1498,9 @@ impl CaptureEngine {
     }
 
     fn capture_monitor_fallback(&self) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
+        if let Some(screen) = &self.screen {
+            return screen.monitor();
+        }
         let monitors = match Monitor::all() {
             Ok(m) => m,
             Err(e) => {
@@ -398,8 +1516,127 @@ impl CaptureEngine {
         if monitors.is_empty() {
             return Err(AppError::Capture("no monitors available".to_string()));
         }
-        
-        let monitor = &monitors[0];
+        if self.config.composite_monitors {
+            return Self::capture_composite(&monitors);
+        }
+        if self.config.capture_cursor_monitor
+            && let Some(monitor) = Self::monitor_under_cursor(&monitors)
+        {
+            return Self::capture_monitor(monitor);
+        }
+
+        Self::capture_monitor(&monitors[0])
+    }
+
+    /// The monitor whose bounds contain the mouse pointer, if the pointer
+    /// can be located.
+    fn monitor_under_cursor(monitors: &[Monitor]) -> Option<&Monitor> {
+        let (cx, cy) = cursor::position()?;
+        monitors.iter().find(|m| {
+            let (Ok(x), Ok(y), Ok(width), Ok(height)) = (m.x(), m.y(), m.width(), m.height())
+            else {
+                return false;
+            };
+            let (cx, cy) = (cx as i64, cy as i64);
+            cx >= x as i64
+                && cx < x as i64 + width as i64
+                && cy >= y as i64
+                && cy < y as i64 + height as i64
+        })
+    }
+
+    /// Capture a specific display, chosen by index into `Monitor::all()` or
+    /// by name, or all of them with `COMPOSITE_MONITOR`.
+    fn capture_selected_monitor(
+        &self,
+        selector: &str,
+    ) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
+        let monitors = Monitor::all()
+            .map_err(|e| AppError::Capture(format!("Failed to get monitors: {:?}", e)))?;
+        if selector == COMPOSITE_MONITOR {
+            return Self::capture_composite(&monitors);
+        }
+
+        let monitor = match selector.parse::<usize>() {
+            Ok(index) => monitors.get(index),
+            Err(_) => monitors
+                .iter()
+                .find(|m| m.name().map(|name| name == selector).unwrap_or(false)),
+        }
+        .ok_or_else(|| AppError::MonitorNotFound(selector.to_string()))?;
+
+        Self::capture_monitor(monitor)
+    }
+
+    /// Every monitor stitched into one image, each placed by its position
+    /// in the desktop layout. Monitors at a lower scale factor are scaled
+    /// up to the highest one so the layout keeps its proportions; areas no
+    /// monitor covers stay black. Monitors that fail to capture are left
+    /// out.
+    fn capture_composite(monitors: &[Monitor]) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
+        use xcap::image::{imageops, Rgba, RgbaImage};
+
+        struct Placed {
+            image: RgbaImage,
+            x: i32,
+            y: i32,
+            width: u32,
+            height: u32,
+        }
+
+        let mut placed = Vec::new();
+        for monitor in monitors {
+            let geometry = (monitor.x(), monitor.y(), monitor.width(), monitor.height());
+            let (Ok(x), Ok(y), Ok(width), Ok(height)) = geometry else {
+                eprintln!("Monitor geometry unavailable, leaving it out of the composite");
+                continue;
+            };
+            match Self::capture_monitor(monitor) {
+                Ok((image, _)) if width > 0 && height > 0 => {
+                    placed.push(Placed { image, x, y, width, height })
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Leaving monitor out of the composite: {}", e),
+            }
+        }
+        if placed.is_empty() {
+            return Err(AppError::Capture("no monitor could be captured".to_string()));
+        }
+
+        // Captured pixels per layout unit; layouts are in logical points
+        // on some platforms and in pixels on others.
+        let scale = placed
+            .iter()
+            .map(|p| p.image.width() as f64 / p.width as f64)
+            .fold(1.0, f64::max);
+        let min_x = placed.iter().map(|p| p.x).min().unwrap_or(0);
+        let min_y = placed.iter().map(|p| p.y).min().unwrap_or(0);
+        let to_px = |v: i64| (v as f64 * scale).round() as i64;
+        let right = placed.iter().map(|p| to_px(p.x as i64 - min_x as i64 + p.width as i64)).max();
+        let bottom = placed.iter().map(|p| to_px(p.y as i64 - min_y as i64 + p.height as i64)).max();
+        let (canvas_w, canvas_h) = (right.unwrap_or(0), bottom.unwrap_or(0));
+        if canvas_w * canvas_h > MAX_COMPOSITE_PIXELS {
+            return Err(AppError::Capture(format!(
+                "composite of {canvas_w}x{canvas_h} is too large"
+            )));
+        }
+
+        let mut canvas = RgbaImage::from_pixel(canvas_w as u32, canvas_h as u32, Rgba([0, 0, 0, 255]));
+        for p in placed {
+            let (w, h) = (to_px(p.width as i64) as u32, to_px(p.height as i64) as u32);
+            let image = if p.image.dimensions() == (w, h) {
+                p.image
+            } else {
+                imageops::resize(&p.image, w, h, imageops::FilterType::Triangle)
+            };
+            let (x, y) = (to_px((p.x - min_x) as i64), to_px((p.y - min_y) as i64));
+            imageops::replace(&mut canvas, &image, x, y);
+        }
+        println!("Composite captured: {}x{}", canvas.width(), canvas.height());
+        Ok((canvas, Some(COMPOSITE_MONITOR.to_string())))
+    }
+
+    fn capture_monitor(monitor: &Monitor) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
         let monitor_name = monitor.name().ok();
         
         let image = match monitor.capture_image() {
@@ -423,8 +1660,477 @@ impl CaptureEngine {
                 w, h
             )));
         }
-        println!("Monitor fallback captured: {}x{} from '{}'", w, h, 
+        println!("Monitor captured: {}x{} from '{}'", w, h, 
             monitor_name.as_deref().unwrap_or("unknown"));
         Ok((image, monitor_name))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(names(dir.path()), ["shot.png"]);
+    }
+
+    #[test]
+    fn interrupted_write_keeps_the_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shot.png");
+        fs::write(&path, b"old").unwrap();
+        // The temp file can't be created while a directory holds its name.
+        let blocker = dir.path().join(".shot.png.tmp");
+        fs::create_dir(&blocker).unwrap();
+        assert!(write_atomic(&path, b"new").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        fs::remove_dir(&blocker).unwrap();
+        assert_eq!(names(dir.path()), ["shot.png"]);
+    }
+
+    #[test]
+    fn failed_rename_leaves_no_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        // A non-empty directory at the final path makes the rename fail
+        // after the temp file was fully written.
+        let path = dir.path().join("shot.png");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("keep"), b"").unwrap();
+        assert!(write_atomic(&path, b"data").is_err());
+        assert!(path.is_dir());
+        assert_eq!(names(dir.path()), ["shot.png"]);
+    }
+
+    #[test]
+    fn failed_save_leaves_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("shot.png");
+        let image = xcap::image::RgbaImage::new(4, 4);
+        let compression = PngCompression::Default;
+        assert!(save_image(&image, &path, ImageFormat::Png, compression, None).is_err());
+        assert!(names(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn leftover_temp_files_are_swept() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = dir.path().join("2024").join("05");
+        fs::create_dir_all(&day).unwrap();
+        fs::write(day.join(".shot.png.tmp"), b"trunc").unwrap();
+        fs::write(day.join("shot.png"), b"whole").unwrap();
+        assert!(is_temp_file(&day.join(".shot.png.tmp")));
+        assert!(!is_temp_file(&day.join("shot.png")));
+        assert_eq!(
+            remove_temp_files(dir.path(), std::time::Duration::ZERO).unwrap(),
+            1
+        );
+        assert_eq!(names(&day), ["shot.png"]);
+    }
+
+    /// A gradient for every window, so no capture counts as blank.
+    struct GeneratedScreen;
+
+    impl GeneratedScreen {
+        fn frame() -> xcap::image::RgbaImage {
+            xcap::image::RgbaImage::from_fn(16, 16, |x, y| {
+                xcap::image::Rgba([x as u8 * 16, y as u8 * 16, 128, 255])
+            })
+        }
+    }
+
+    impl ScreenSource for GeneratedScreen {
+        fn focused_window(&self) -> Option<xcap::image::RgbaImage> {
+            Some(Self::frame())
+        }
+
+        fn window(&self, _title: &str) -> Option<xcap::image::RgbaImage> {
+            Some(Self::frame())
+        }
+
+        fn monitor(&self) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
+            Ok((Self::frame(), Some("generated".to_string())))
+        }
+    }
+
+    fn test_config(dir: &Path) -> CaptureConfig {
+        CaptureConfig {
+            capture_dir: dir.join("captures"),
+            db_path: dir.join("veea.db"),
+            enable_search_index: false,
+            ocr_backend: OcrBackend::None,
+            image_format: ImageFormat::Png,
+            ..Default::default()
+        }
+    }
+
+    fn engine(config: CaptureConfig) -> CaptureEngine {
+        let db = Db::new(&config.db_path).unwrap();
+        CaptureEngine::new(config, db, Arc::new(AtomicBool::new(false)))
+            .unwrap()
+            .with_screen(GeneratedScreen)
+    }
+
+    /// OCR that recognizes `Some` text, or fails with `None`.
+    struct FixedOcr(Option<&'static str>);
+
+    impl Ocr for FixedOcr {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn extract(&self, _img: &xcap::image::RgbaImage) -> AppResult<OcrResult> {
+            match self.0 {
+                Some(text) => Ok(OcrResult {
+                    text: text.to_string(),
+                    lines: Vec::new(),
+                }),
+                None => Err(AppError::Capture("recognizer crashed".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn failed_reprocess_keeps_the_old_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine(test_config(dir.path()));
+        fs::create_dir_all(dir.path().join("captures")).unwrap();
+        let mut record = CaptureRecord::sample("a", Utc::now());
+        record.path = dir
+            .path()
+            .join("captures/a.png")
+            .to_string_lossy()
+            .into_owned();
+        let png = codec::encode(&GeneratedScreen::frame(), ImageFormat::Png).unwrap();
+        fs::write(&record.path, png).unwrap();
+        record.ocr_text = Some("quarterly report".to_string());
+        engine.db.insert_capture(&record).unwrap();
+
+        engine.ocr = Some(Box::new(FixedOcr(None)));
+        let reprocessed = engine.reprocess("a", true).unwrap().unwrap();
+        assert_eq!(reprocessed.ocr_text.as_deref(), Some("quarterly report"));
+        assert_eq!(
+            (reprocessed.width, reprocessed.height),
+            (Some(16), Some(16))
+        );
+        let stored = engine.db.get_capture("a").unwrap().unwrap();
+        assert_eq!(stored.ocr_text.as_deref(), Some("quarterly report"));
+
+        engine.ocr = Some(Box::new(FixedOcr(Some("fn main() {}"))));
+        engine.reprocess("a", true).unwrap();
+        let stored = engine.db.get_capture("a").unwrap().unwrap();
+        assert_eq!(stored.ocr_text.as_deref(), Some("fn main() {}"));
+    }
+
+    /// Whether a capture of the window titled `title` was stored.
+    fn captured(engine: &mut CaptureEngine, title: &str, app: Option<&str>) -> bool {
+        engine
+            .capture(
+                title,
+                app,
+                "focus",
+                true,
+                CaptureTarget::ByTitle,
+                EventContext::default(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn excluded_app_is_never_captured() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine(CaptureConfig {
+            exclude_apps: vec!["Slack".to_string()],
+            ..test_config(dir.path())
+        });
+        assert!(!captured(&mut engine, "general", Some("Slack")));
+        assert!(!captured(&mut engine, "general", Some("SLACK")));
+        assert!(captured(
+            &mut engine,
+            "general",
+            Some("Slackware Installer")
+        ));
+        assert!(captured(&mut engine, "general", None));
+
+        // Listed by what the app id path amounts to.
+        assert!(engine.should_skip("general", None, Some("/usr/lib/slack/slack")));
+        assert!(engine.should_skip("general", Some("Chat"), Some("/opt/Slack/slack.exe")));
+        assert!(!engine.should_skip("general", Some("Chat"), Some("/opt/chat/chat")));
+    }
+
+    #[test]
+    fn excluded_app_listed_by_id_is_never_captured() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine(CaptureConfig {
+            exclude_apps: vec!["com.tinyspeck.slackmacgap".to_string()],
+            ..test_config(dir.path())
+        });
+        assert!(engine.should_skip("general", Some("Slack"), Some("com.tinyspeck.slackmacgap")));
+        assert!(engine.should_skip("general", None, Some("COM.tinyspeck.SlackMacGap")));
+        assert!(!engine.should_skip("general", Some("Slack"), None));
+    }
+
+    #[test]
+    fn allowlist_captures_only_what_it_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine(CaptureConfig {
+            include_apps: vec!["Code".to_string()],
+            include_titles: vec!["Jira".to_string()],
+            ..test_config(dir.path())
+        });
+        assert!(captured(&mut engine, "main.rs", Some("code")));
+        assert!(captured(&mut engine, "PROJ-12 - jira", Some("Firefox")));
+        assert!(!captured(&mut engine, "Inbox", Some("Firefox")));
+        assert!(!captured(&mut engine, "Inbox", None));
+        assert!(!engine.should_skip("main.rs", None, Some("/usr/share/code/code")));
+    }
+
+    #[test]
+    fn exclusion_wins_over_the_allowlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine(CaptureConfig {
+            include_apps: vec!["Firefox".to_string()],
+            include_titles: vec!["Jira".to_string()],
+            exclude_apps: vec!["Firefox".to_string()],
+            exclude_titles: vec!["Private".to_string()],
+            ..test_config(dir.path())
+        });
+        assert!(!captured(&mut engine, "Docs", Some("Firefox")));
+        assert!(!captured(&mut engine, "PROJ-12 - Jira", Some("Firefox")));
+        assert!(!captured(&mut engine, "Jira (Private)", Some("Chrome")));
+        assert!(captured(&mut engine, "PROJ-12 - Jira", Some("Chrome")));
+    }
+
+    /// Weeks of window switching compressed into a million events: every
+    /// one is a new window, most are turned away by the rate limit, and
+    /// every thousandth is the first focus of one of 100 priority apps,
+    /// which always captures. Run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "soak test, takes a while"]
+    fn million_events_keep_collections_bounded() {
+        const EVENTS: usize = 1_000_000;
+        const RATE_LIMIT: u32 = 60;
+        const PRIORITY_SEEN: usize = 16;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = CaptureConfig {
+            capture_dir: dir.path().join("captures"),
+            db_path: dir.path().join("veea.db"),
+            enable_search_index: false,
+            ocr_backend: OcrBackend::None,
+            image_format: ImageFormat::Png,
+            max_captures_per_minute: RATE_LIMIT,
+            priority_apps: (0..100).map(|n| format!("app{n}")).collect(),
+            memory: crate::config::MemoryLimits {
+                priority_seen: PRIORITY_SEEN,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let db = Db::new(&config.db_path).unwrap();
+        let mut engine = CaptureEngine::new(config, db, Arc::new(AtomicBool::new(false)))
+            .unwrap()
+            .with_screen(GeneratedScreen);
+
+        let started = std::time::Instant::now();
+        let mut stored = 0;
+        for n in 0..EVENTS {
+            let title = format!("Window {n}");
+            if n % 1000 == 0 {
+                let app = format!("app{}", n / 1000 % 100);
+                engine.capture_priority_focus(&title, Some(&app)).unwrap();
+            } else if engine
+                .capture_previous_window(&title, Some("editor"))
+                .is_ok()
+            {
+                stored += 1;
+            }
+            if n % 10_000 == 0 {
+                let usage = engine.memory_usage();
+                assert!(
+                    usage.recent_captures.len <= RATE_LIMIT as usize,
+                    "{usage:?}"
+                );
+                assert!(usage.priority_seen.len <= PRIORITY_SEEN, "{usage:?}");
+            }
+        }
+
+        let usage = engine.memory_usage();
+        assert_eq!(usage.recent_captures.cap, Some(RATE_LIMIT as usize));
+        assert_eq!(usage.priority_seen.len, PRIORITY_SEEN);
+        // The rate limit, not the event count, decided how many got through.
+        let minutes = started.elapsed().as_secs() as usize / 60 + 1;
+        assert!(
+            stored <= RATE_LIMIT as usize * minutes,
+            "{stored} captures stored"
+        );
+    }
+
+    #[test]
+    fn strategy_needs_distinct_stages() {
+        use CaptureStage::*;
+        assert!(CaptureStrategy::try_from(vec![]).is_err());
+        assert!(CaptureStrategy::try_from(vec![Monitor, TitleMatch, Monitor]).is_err());
+        let strategy = CaptureStrategy::try_from(vec![Monitor, FocusedWindow]).unwrap();
+        assert_eq!(strategy.stages(), [Monitor, FocusedWindow]);
+
+        let parse = |toml: &str| toml::from_str::<CaptureConfig>(toml).map(|c| c.capture_strategy);
+        assert!(parse("capture_strategy = []").is_err());
+        assert!(parse(r#"capture_strategy = ["title_match", "title_match"]"#).is_err());
+        assert!(parse(r#"capture_strategy = ["window"]"#).is_err());
+        assert_eq!(parse("").unwrap(), CaptureStrategy::default());
+        let strategy = parse(r#"capture_strategy = ["title_match", "monitor"]"#).unwrap();
+        assert_eq!(strategy.stages(), [TitleMatch, Monitor]);
+    }
+
+    /// A screen where only some ways of capturing work.
+    struct PartialScreen {
+        focused: bool,
+        window: bool,
+        monitor: bool,
+    }
+
+    impl ScreenSource for PartialScreen {
+        fn focused_window(&self) -> Option<xcap::image::RgbaImage> {
+            self.focused.then(GeneratedScreen::frame)
+        }
+
+        fn window(&self, _title: &str) -> Option<xcap::image::RgbaImage> {
+            self.window.then(GeneratedScreen::frame)
+        }
+
+        fn monitor(&self) -> AppResult<(xcap::image::RgbaImage, Option<String>)> {
+            match self.monitor {
+                true => Ok((GeneratedScreen::frame(), Some("generated".to_string()))),
+                false => Err(AppError::MonitorNotFound("generated".to_string())),
+            }
+        }
+    }
+
+    /// Captures a window on `screen` by `stages`, returning the stages
+    /// tried in order and the method that worked, if any did.
+    fn strategy_run(
+        stages: Vec<CaptureStage>,
+        screen: PartialScreen,
+        target: CaptureTarget,
+        allow_monitor_fallback: bool,
+    ) -> (String, Option<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CaptureConfig {
+            capture_strategy: stages.try_into().unwrap(),
+            allow_monitor_fallback,
+            ..test_config(dir.path())
+        };
+        let db = Db::new(&config.db_path).unwrap();
+        let mut engine = CaptureEngine::new(config.clone(), db, Arc::new(AtomicBool::new(false)))
+            .unwrap()
+            .with_screen(screen);
+        let captured = engine.capture("main.rs", None, "focus", true, target, Default::default());
+        // Dropping the engine finishes its queued write.
+        drop(engine);
+        let db = Db::new(&config.db_path).unwrap();
+        let records = db.list_recent(10, 0, &Default::default()).unwrap();
+        if let Some(record) = records.first() {
+            assert!(captured.unwrap());
+            let detail = record.trigger_detail.as_deref().unwrap();
+            let chain = detail.split("; ").next().unwrap().strip_prefix("strategy=");
+            return (chain.unwrap().to_string(), record.capture_method.clone());
+        }
+        assert!(captured.is_err());
+        // A failure is logged with the stages it tried.
+        let conn = rusqlite::Connection::open(&config.db_path).unwrap();
+        let select = "SELECT outcome, detail FROM capture_attempts";
+        let (outcome, chain): (String, String) = conn
+            .query_row(select, [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(outcome, "failed");
+        (chain, None)
+    }
+
+    #[test]
+    fn strategy_falls_back_in_the_order_listed() {
+        use CaptureStage::*;
+        let all = || vec![FocusedWindow, TitleMatch, Monitor];
+        let screen = |focused, window, monitor| PartialScreen {
+            focused,
+            window,
+            monitor,
+        };
+        let run = |stages, screen| strategy_run(stages, screen, CaptureTarget::Focused, true);
+        let (chain, method) = run(all(), screen(true, true, true));
+        assert_eq!(chain, "focused_window");
+        assert_eq!(method.as_deref(), Some("focused_window"));
+        let (chain, method) = run(all(), screen(false, false, true));
+        assert_eq!(chain, "focused_window>title_match>monitor_fallback");
+        assert_eq!(method.as_deref(), Some("monitor_fallback"));
+        let (chain, method) = run(vec![Monitor, FocusedWindow], screen(true, true, false));
+        assert_eq!(chain, "monitor_fallback>focused_window");
+        assert_eq!(method.as_deref(), Some("focused_window"));
+
+        let (chain, method) = run(all(), screen(false, false, false));
+        assert_eq!(chain, "focused_window>title_match>monitor_fallback");
+        assert_eq!(method, None);
+    }
+
+    #[test]
+    fn strategy_skips_stages_that_would_show_another_window() {
+        use CaptureStage::*;
+        let all = || vec![FocusedWindow, TitleMatch, Monitor];
+        let screen = || PartialScreen {
+            focused: true,
+            window: false,
+            monitor: true,
+        };
+        // A window that lost focus is only found by its title.
+        let (chain, method) = strategy_run(all(), screen(), CaptureTarget::ByTitle, true);
+        assert_eq!((chain.as_str(), method), ("title_match", None));
+
+        let screen = PartialScreen {
+            focused: false,
+            window: false,
+            monitor: true,
+        };
+        let (chain, method) = strategy_run(all(), screen, CaptureTarget::Focused, false);
+        assert_eq!(
+            (chain.as_str(), method),
+            ("focused_window>title_match", None)
+        );
+    }
+
+    #[test]
+    fn battery_aware_encodes_faster_until_back_on_ac() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine(CaptureConfig {
+            battery_aware: true,
+            ocr_backend: OcrBackend::Tesseract,
+            ..test_config(dir.path())
+        });
+        engine.set_on_battery(true);
+        assert_eq!(engine.config.png_compression, PngCompression::Fast);
+        assert_eq!(engine.config.ocr_backend, OcrBackend::None);
+        engine.set_on_battery(false);
+        assert_eq!(engine.config.png_compression, PngCompression::Default);
+        assert_eq!(engine.config.ocr_backend, OcrBackend::Tesseract);
+
+        // Fast compression is still lossless.
+        let path = dir.path().join("fast.png");
+        let image = GeneratedScreen::frame();
+        save_image(&image, &path, ImageFormat::Png, PngCompression::Fast, None).unwrap();
+        let stored = codec::decode(&fs::read(&path).unwrap(), ImageFormat::Png).unwrap();
+        assert_eq!(stored, image);
+    }
+}
@@ -0,0 +1,144 @@
+//! `capture_delay_ms`: focus and title captures wait this long so the
+//! window can finish rendering. Only one capture is pending at a time. A
+//! focus change to another window replaces it. A title change of the same
+//! window folds into it, so the capture shows the window's latest state
+//! once, at the time first scheduled.
+
+use std::time::{Duration, Instant};
+
+/// A focus or title capture waiting for its delay to pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingCapture {
+    pub window_id: u32,
+    pub window_title: String,
+    pub app_name: Option<String>,
+    pub prev_title: Option<String>,
+    pub event_type: &'static str,
+}
+
+/// Holds the pending capture. Times are passed in rather than read, so
+/// the scheduling can be tested without waiting.
+#[derive(Debug)]
+pub struct CaptureDelay {
+    delay: Duration,
+    pending: Option<(Instant, PendingCapture)>,
+}
+
+impl CaptureDelay {
+    pub fn new(delay_ms: u64) -> Self {
+        Self {
+            delay: Duration::from_millis(delay_ms),
+            pending: None,
+        }
+    }
+
+    /// Applies a reloaded `capture_delay_ms`. A capture already pending
+    /// keeps its time.
+    pub fn set_delay(&mut self, delay_ms: u64) {
+        self.delay = Duration::from_millis(delay_ms);
+    }
+
+    /// Whether captures wait at all; with no delay they're taken at once
+    /// and nothing is scheduled.
+    pub fn is_set(&self) -> bool {
+        !self.delay.is_zero()
+    }
+
+    /// Drops the pending capture if it was for a window other than the
+    /// one now focused.
+    pub fn focus_moved(&mut self, window_id: u32) {
+        if self.pending.as_ref().is_some_and(|(_, pending)| pending.window_id != window_id) {
+            self.pending = None;
+        }
+    }
+
+    /// Schedules `capture`, returning how long until it's due, or `None`
+    /// when it was folded into a capture already pending for its window.
+    pub fn schedule(&mut self, capture: PendingCapture, now: Instant) -> Option<Duration> {
+        self.focus_moved(capture.window_id);
+        if let Some((_, pending)) = &mut self.pending {
+            pending.window_title = capture.window_title;
+            pending.app_name = capture.app_name;
+            return None;
+        }
+        self.pending = Some((now + self.delay, capture));
+        Some(self.delay)
+    }
+
+    /// The pending capture, once it's due at `now`.
+    pub fn take_due(&mut self, now: Instant) -> Option<PendingCapture> {
+        let (due, _) = self.pending.as_ref()?;
+        if now < *due {
+            return None;
+        }
+        self.pending.take().map(|(_, capture)| capture)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(window_id: u32, title: &str, event_type: &'static str) -> PendingCapture {
+        PendingCapture {
+            window_id,
+            window_title: title.to_string(),
+            app_name: None,
+            prev_title: None,
+            event_type,
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn capture_waits_for_the_delay() {
+        let start = Instant::now();
+        let mut delay = CaptureDelay::new(150);
+        assert!(delay.is_set());
+        assert_eq!(delay.schedule(capture(1, "Inbox", "focus"), start), Some(ms(150)));
+        assert_eq!(delay.take_due(start + ms(149)), None);
+        assert_eq!(delay.take_due(start + ms(150)), Some(capture(1, "Inbox", "focus")));
+        assert_eq!(delay.take_due(start + ms(300)), None);
+    }
+
+    #[test]
+    fn focus_on_another_window_replaces_the_pending_capture() {
+        let start = Instant::now();
+        let mut delay = CaptureDelay::new(150);
+        delay.schedule(capture(1, "Inbox", "focus"), start);
+        assert_eq!(delay.schedule(capture(2, "main.rs", "focus"), start + ms(100)), Some(ms(150)));
+        // The first capture's wake-up finds nothing due yet.
+        assert_eq!(delay.take_due(start + ms(150)), None);
+        assert_eq!(delay.take_due(start + ms(250)), Some(capture(2, "main.rs", "focus")));
+
+        // A focus change that isn't captured still cancels.
+        delay.schedule(capture(1, "Inbox", "focus"), start + ms(300));
+        delay.focus_moved(2);
+        assert_eq!(delay.take_due(start + ms(500)), None);
+    }
+
+    #[test]
+    fn title_change_of_the_same_window_folds_in() {
+        let start = Instant::now();
+        let mut delay = CaptureDelay::new(150);
+        delay.schedule(capture(1, "Loading…", "focus"), start);
+        delay.focus_moved(1);
+        assert_eq!(delay.schedule(capture(1, "Inbox (3)", "title"), start + ms(100)), None);
+        assert_eq!(delay.take_due(start + ms(150)), Some(capture(1, "Inbox (3)", "focus")));
+    }
+
+    #[test]
+    fn reload_changes_the_delay_of_later_captures() {
+        let start = Instant::now();
+        let mut delay = CaptureDelay::new(150);
+        delay.schedule(capture(1, "Inbox", "focus"), start);
+        delay.set_delay(500);
+        assert_eq!(delay.take_due(start + ms(150)), Some(capture(1, "Inbox", "focus")));
+        assert_eq!(delay.schedule(capture(1, "Inbox", "title"), start + ms(200)), Some(ms(500)));
+        delay.set_delay(0);
+        assert!(!delay.is_set());
+    }
+}
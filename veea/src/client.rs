@@ -0,0 +1,255 @@
+use axum::{
+    body::Bytes,
+    http::{header, Method, Request},
+};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    api::{
+        CaptureSummary, EraseParams, EraseResponse, ListParams, Page, SearchPage,
+        SearchParams, SnapshotParams, Status,
+    },
+    config,
+};
+
+/// Where `veea` serves its API unless told otherwise.
+pub const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8787";
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("request failed: {0}")]
+    Transport(String),
+
+    /// Structured error returned by the server.
+    #[error("{status} {code}: {message}")]
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("invalid response: {0}")]
+    Decode(String),
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    code: String,
+    message: String,
+    request_id: Option<String>,
+}
+
+/// Typed client for the HTTP API, using the same request and response
+/// types as the server.
+#[derive(Clone)]
+pub struct VeeaClient {
+    base_url: String,
+    token: Option<String>,
+    http: Client<HttpConnector, Empty<Bytes>>,
+}
+
+impl VeeaClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            http: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    /// Sends `token` with every request, for daemons with `api_token` set.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Client for the current user's daemon, found through the address
+    /// file it writes next to its config, or at the default address, with
+    /// the config's `api_token`.
+    pub fn local() -> Self {
+        let config_path = config::default_config_path();
+        let client = match config::read_api_addr(&config_path) {
+            Some(addr) => Self::new(format!("http://{addr}")),
+            None => Self::new(DEFAULT_BASE_URL),
+        };
+        let token = config::CaptureConfig::load_or_default(&config_path)
+            .ok()
+            .and_then(|config| config.api_token);
+        match token {
+            Some(token) => client.with_token(token.trim()),
+            None => client,
+        }
+    }
+
+    pub async fn list_captures(
+        &self,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> ClientResult<Page<CaptureSummary>> {
+        let params = ListParams {
+            limit,
+            offset,
+            bare: false,
+            event_type: None,
+            capture_method: None,
+            mic_active: None,
+            cam_active: None,
+            workspace: None,
+            contains_code: None,
+            app_id: None,
+            capture_group_id: None,
+            min_activity: None,
+            include_blank: false,
+            from: None,
+            to: None,
+        };
+        self.json(Method::GET, "/captures", Some(&params)).await
+    }
+
+    pub async fn get_capture(&self, id: &str) -> ClientResult<CaptureSummary> {
+        self.json(Method::GET, &format!("/captures/{id}"), None::<&()>)
+            .await
+    }
+
+    pub async fn get_image_bytes(&self, id: &str) -> ClientResult<Vec<u8>> {
+        let bytes = self
+            .send(Method::GET, &format!("/captures/{id}/image"), None::<&()>)
+            .await?;
+        Ok(bytes.to_vec())
+    }
+
+    pub async fn search(
+        &self,
+        q: &str,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> ClientResult<SearchPage> {
+        let params = SearchParams {
+            q: q.to_string(),
+            limit,
+            offset,
+            ..Default::default()
+        };
+        self.json(Method::GET, "/search", Some(&params)).await
+    }
+
+    pub async fn pause(&self) -> ClientResult<()> {
+        self.send(Method::POST, "/control/pause", None::<&()>).await?;
+        Ok(())
+    }
+
+    pub async fn resume(&self) -> ClientResult<()> {
+        self.send(Method::POST, "/control/resume", None::<&()>).await?;
+        Ok(())
+    }
+
+    /// Deletes captures from the last `minutes` (server default 5) and
+    /// returns how many were removed. Calling this is the confirmation.
+    pub async fn erase(&self, minutes: Option<i64>) -> ClientResult<usize> {
+        let params = EraseParams {
+            minutes,
+            confirm: true,
+        };
+        let response: EraseResponse = self
+            .json(Method::POST, "/control/erase", Some(&params))
+            .await?;
+        Ok(response.deleted)
+    }
+
+    pub async fn snapshot(
+        &self,
+        label: Option<&str>,
+        monitor: Option<&str>,
+    ) -> ClientResult<CaptureSummary> {
+        let params = SnapshotParams {
+            label: label.map(str::to_string),
+            monitor: monitor.map(str::to_string),
+            event_type: None,
+        };
+        self.json(Method::POST, "/control/snapshot", Some(&params))
+            .await
+    }
+
+    pub async fn status(&self) -> ClientResult<Status> {
+        self.json(Method::GET, "/status", None::<&()>).await
+    }
+
+    async fn json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&impl Serialize>,
+    ) -> ClientResult<T> {
+        let bytes = self.send(method, path, query).await?;
+        serde_json::from_slice(&bytes).map_err(|e| ClientError::Decode(e.to_string()))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&impl Serialize>,
+    ) -> ClientResult<Bytes> {
+        let mut uri = format!("{}{}", self.base_url, path);
+        if let Some(query) = query {
+            let encoded = serde_urlencoded::to_string(query)
+                .map_err(|e| ClientError::Transport(e.to_string()))?;
+            if !encoded.is_empty() {
+                uri.push('?');
+                uri.push_str(&encoded);
+            }
+        }
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = &self.token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request
+            .body(Empty::new())
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        let response = self
+            .http
+            .request(request)
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?
+            .to_bytes();
+
+        if status.is_success() {
+            return Ok(body);
+        }
+        match serde_json::from_slice::<ErrorEnvelope>(&body) {
+            Ok(ErrorEnvelope { error }) => Err(ClientError::Api {
+                status: status.as_u16(),
+                code: error.code,
+                message: error.message,
+                request_id: error.request_id,
+            }),
+            Err(_) => Err(ClientError::Api {
+                status: status.as_u16(),
+                code: "unknown".to_string(),
+                message: String::from_utf8_lossy(&body).to_string(),
+                request_id: None,
+            }),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+/// Current clipboard text, capped at `max_bytes` (cut on a char boundary).
+/// Only text is ever read; `None` when the clipboard is empty, holds
+/// something else, or can't be accessed.
+#[cfg(feature = "clipboard")]
+pub fn read_text(max_bytes: usize) -> Option<String> {
+    let mut text = arboard::Clipboard::new().ok()?.get_text().ok()?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    Some(text)
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn read_text(_max_bytes: usize) -> Option<String> {
+    None
+}
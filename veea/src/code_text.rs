@@ -0,0 +1,154 @@
+//! Guesses whether OCR or accessibility text shows source code, for the
+//! `contains_code` flag. Plain heuristics rather than a model: code is dense in brackets
+//! and operators, its lines end in `;`, `{` or `:`, and it is full of
+//! keywords, while prose is mostly words and spaces.
+
+/// Lines judged together; a screen is code if any such run of lines is,
+/// so an editor pane still counts next to menus and a file tree.
+const BLOCK_LINES: usize = 12;
+/// Fewer non-empty lines than this are never judged code.
+const MIN_LINES: usize = 3;
+/// Signals a block must show to count as code.
+const MIN_SIGNALS: usize = 3;
+
+const KEYWORDS: &[&str] = &[
+    "async", "await", "break", "case", "catch", "class", "const", "def", "elif", "else", "enum",
+    "export", "extends", "fn", "for", "func", "function", "if", "impl", "import", "include",
+    "int", "interface", "lambda", "let", "match", "mut", "nil", "null", "package", "private",
+    "pub", "public", "return", "self", "static", "struct", "switch", "this", "throw", "trait",
+    "try", "typeof", "use", "var", "void", "while", "yield",
+];
+
+const OPERATORS: &[&str] = &["==", "!=", "=>", "->", "::", "&&", "||", "+=", "</", "/>"];
+
+/// A capture's `contains_code`: whether its OCR or accessibility text
+/// reads as code, or `None` when it has neither.
+pub fn classify(ocr_text: Option<&str>, ax_text: Option<&str>) -> Option<bool> {
+    if ocr_text.is_none() && ax_text.is_none() {
+        return None;
+    }
+    Some([ocr_text, ax_text].into_iter().flatten().any(contains_code))
+}
+
+/// Whether some part of `text` reads as source code.
+pub fn contains_code(text: &str) -> bool {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() < MIN_LINES {
+        return false;
+    }
+    block_starts(lines.len())
+        .any(|start| signals(&lines[start..(start + BLOCK_LINES).min(lines.len())]) >= MIN_SIGNALS)
+}
+
+/// Where the blocks of `lines` lines start: every half block, and always
+/// one ending on the last line, so code at the bottom of a screen isn't
+/// cut short.
+fn block_starts(lines: usize) -> impl Iterator<Item = usize> {
+    let last = lines.saturating_sub(BLOCK_LINES);
+    (0..last)
+        .step_by(BLOCK_LINES / 2)
+        .chain(std::iter::once(last))
+}
+
+/// How many of the code signals `lines` show.
+fn signals(lines: &[&str]) -> usize {
+    let chars = lines
+        .iter()
+        .flat_map(|l| l.chars())
+        .filter(|c| !c.is_whitespace())
+        .count()
+        .max(1);
+    let symbols = lines
+        .iter()
+        .flat_map(|l| l.chars())
+        .filter(|c| "{}()[];=<>".contains(*c))
+        .count();
+    let code_endings = lines
+        .iter()
+        .filter(|l| l.trim_end().ends_with([';', '{', '}', ':', ')', ',']))
+        .count();
+    let indented = lines
+        .iter()
+        .filter(|l| l.starts_with("  ") || l.starts_with('\t'))
+        .count();
+    let words: Vec<&str> = lines
+        .iter()
+        .flat_map(|l| l.split(|c: char| !c.is_alphanumeric() && c != '_'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let keywords = words.iter().filter(|w| KEYWORDS.contains(w)).count();
+    let operators: usize = lines
+        .iter()
+        .map(|l| OPERATORS.iter().filter(|op| l.contains(*op)).count())
+        .sum();
+
+    let n = lines.len() as f64;
+    [
+        symbols as f64 / chars as f64 > 0.06,
+        code_endings as f64 / n > 0.4,
+        indented as f64 / n > 0.3,
+        keywords as f64 / words.len().max(1) as f64 > 0.08,
+        operators as f64 / n > 0.25,
+    ]
+    .into_iter()
+    .filter(|&signal| signal)
+    .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "fn main() {
+    let total = items.iter().map(|i| i.len()).sum::<usize>();
+    if total == 0 {
+        return;
+    }
+    println!(\"{total}\");
+}";
+
+    const PROSE: &str = "Minutes of the weekly meeting
+We went over the launch plan and agreed on dates
+Marketing will send the announcement on Monday
+Support asked for a week of notice before any change
+The next meeting is moved to Thursday afternoon";
+
+    fn prose_lines(n: usize) -> String {
+        PROSE.lines().cycle().take(n).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn tells_code_from_prose() {
+        assert!(contains_code(CODE));
+        assert!(!contains_code(PROSE));
+        assert!(!contains_code("fn a() {}\nfn b() {}"));
+    }
+
+    #[test]
+    fn blocks_always_reach_the_last_line() {
+        assert_eq!(block_starts(3).collect::<Vec<_>>(), [0]);
+        assert_eq!(block_starts(12).collect::<Vec<_>>(), [0]);
+        assert_eq!(block_starts(13).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(block_starts(20).collect::<Vec<_>>(), [0, 6, 8]);
+        assert_eq!(block_starts(24).collect::<Vec<_>>(), [0, 6, 12]);
+    }
+
+    #[test]
+    fn code_below_a_page_of_prose_is_found() {
+        // Stepping by half a block alone, the code's last lines would
+        // fall outside every block or share one mostly with prose.
+        for before in [13, 17, 20, 25] {
+            let text = format!("{}\n{CODE}", prose_lines(before));
+            assert!(contains_code(&text), "{before} lines of prose first");
+        }
+    }
+
+    #[test]
+    fn either_text_can_show_code() {
+        assert_eq!(classify(None, None), None);
+        assert_eq!(classify(Some(PROSE), None), Some(false));
+        assert_eq!(classify(Some(PROSE), Some(CODE)), Some(true));
+        assert_eq!(classify(None, Some(CODE)), Some(true));
+        assert_eq!(classify(Some(CODE), Some(PROSE)), Some(true));
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use xcap::image::{ImageFormat as EncodedFormat, RgbaImage};
+
+use crate::{
+    capture::write_atomic,
+    error::{AppError, AppResult},
+};
+
+/// Whether this build can read and write JPEG XL (the `jxl` feature).
+pub const JXL_AVAILABLE: bool = cfg!(feature = "jxl");
+
+/// File format of stored captures. Each capture records its own, so an
+/// archive can mix formats after `image_format` changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    /// Lossless JPEG XL, typically well under the PNG's size.
+    Jxl,
+}
+
+impl ImageFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jxl => "jxl",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        self.as_str()
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jxl => "image/jxl",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(ImageFormat::Png),
+            "jxl" => Ok(ImageFormat::Jxl),
+            other => Err(format!("unknown image format '{other}'")),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How hard PNG encoding works at shrinking the file. The pixels are
+/// lossless either way; `fast` trades larger files for less CPU, which
+/// the `battery_aware` profile uses. JPEG XL has no such setting here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngCompression {
+    #[default]
+    Default,
+    Fast,
+}
+
+/// The format new captures are written in: `format`, or PNG when this
+/// build can't write it.
+pub fn writable(format: ImageFormat) -> ImageFormat {
+    if format == ImageFormat::Jxl && !JXL_AVAILABLE {
+        eprintln!("JPEG XL storage needs the `jxl` feature; saving captures as PNG");
+        return ImageFormat::Png;
+    }
+    format
+}
+
+pub fn encode(image: &RgbaImage, format: ImageFormat) -> AppResult<Vec<u8>> {
+    encode_tagged(image, format, PngCompression::Default, None)
+}
+
+/// `encode` with the display's ICC profile embedded, so viewers show the
+/// colours as the screen did. Only PNGs carry it; JPEG XL files are
+/// written without.
+pub fn encode_tagged(
+    image: &RgbaImage,
+    format: ImageFormat,
+    compression: PngCompression,
+    icc: Option<&[u8]>,
+) -> AppResult<Vec<u8>> {
+    use xcap::image::{
+        codecs::png::{CompressionType, FilterType, PngEncoder},
+        ExtendedColorType, ImageEncoder,
+    };
+
+    match format {
+        ImageFormat::Png => {
+            let mut out = Vec::new();
+            let mut encoder = match compression {
+                PngCompression::Default => PngEncoder::new(&mut out),
+                PngCompression::Fast => PngEncoder::new_with_quality(
+                    &mut out,
+                    CompressionType::Fast,
+                    FilterType::Adaptive,
+                ),
+            };
+            if let Some(icc) = icc {
+                encoder
+                    .set_icc_profile(icc.to_vec())
+                    .map_err(|e| AppError::Capture(e.to_string()))?;
+            }
+            encoder
+                .write_image(image.as_raw(), image.width(), image.height(), ExtendedColorType::Rgba8)
+                .map_err(|e| AppError::Capture(e.to_string()))?;
+            Ok(out)
+        }
+        ImageFormat::Jxl => jxl::encode(image),
+    }
+}
+
+pub fn decode(bytes: &[u8], format: ImageFormat) -> AppResult<RgbaImage> {
+    match format {
+        ImageFormat::Png => xcap::image::load_from_memory_with_format(bytes, EncodedFormat::Png)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| AppError::ImageUnreadable(e.to_string())),
+        ImageFormat::Jxl => jxl::decode(bytes),
+    }
+}
+
+/// Reads and decodes the capture file at `path`.
+pub fn open(path: &Path, format: ImageFormat) -> AppResult<RgbaImage> {
+    let bytes = std::fs::read(path)?;
+    decode(&bytes, format).map_err(|e| match e {
+        AppError::ImageUnreadable(msg) => {
+            AppError::ImageUnreadable(format!("{}: {}", path.display(), msg))
+        }
+        other => other,
+    })
+}
+
+/// `bytes` of a `format` image as PNG, for clients that can't show it.
+pub fn to_png(bytes: Vec<u8>, format: ImageFormat) -> AppResult<Vec<u8>> {
+    match format {
+        ImageFormat::Png => Ok(bytes),
+        other => encode(&decode(&bytes, other)?, ImageFormat::Png),
+    }
+}
+
+/// Writes a JPEG XL copy of the PNG at `png_path` beside it and returns
+/// the new path. The copy is decoded again and must hash to the same
+/// pixels as the PNG; the PNG itself is left for the caller to delete.
+pub fn recompress_lossless(png_path: &Path) -> AppResult<PathBuf> {
+    let image = open(png_path, ImageFormat::Png)?;
+    let encoded = encode(&image, ImageFormat::Jxl)?;
+    let roundtrip = decode(&encoded, ImageFormat::Jxl)?;
+    if image.dimensions() != roundtrip.dimensions()
+        || Sha256::digest(image.as_raw()) != Sha256::digest(roundtrip.as_raw())
+    {
+        return Err(AppError::Capture(format!(
+            "{}: JPEG XL copy does not match the original pixels",
+            png_path.display()
+        )));
+    }
+    let jxl_path = png_path.with_extension(ImageFormat::Jxl.extension());
+    write_atomic(&jxl_path, &encoded)?;
+    Ok(jxl_path)
+}
+
+#[cfg(feature = "jxl")]
+mod jxl {
+    use xcap::image::RgbaImage;
+    use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+    use zune_jpegxl::JxlSimpleEncoder;
+
+    use crate::error::{AppError, AppResult};
+
+    pub fn encode(image: &RgbaImage) -> AppResult<Vec<u8>> {
+        let options = EncoderOptions::new(
+            image.width() as usize,
+            image.height() as usize,
+            ColorSpace::RGBA,
+            BitDepth::Eight,
+        );
+        JxlSimpleEncoder::new(image.as_raw(), options)
+            .encode()
+            .map_err(|e| AppError::Capture(format!("JPEG XL encoding failed: {e:?}")))
+    }
+
+    pub fn decode(bytes: &[u8]) -> AppResult<RgbaImage> {
+        let unreadable = |e: &dyn std::fmt::Display| AppError::ImageUnreadable(e.to_string());
+        let image = jxl_oxide::JxlImage::builder()
+            .read(std::io::Cursor::new(bytes))
+            .map_err(|e| unreadable(&e))?;
+        let render = image.render_frame(0).map_err(|e| unreadable(&e))?;
+        let frame = render.image_all_channels();
+        let channels = frame.channels();
+        let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let mut rgba = Vec::with_capacity(frame.width() * frame.height() * 4);
+        for pixel in frame.buf().chunks_exact(channels) {
+            let (rgb, alpha) = match channels {
+                1 => ([pixel[0]; 3], 1.0),
+                2 => ([pixel[0]; 3], pixel[1]),
+                3 => ([pixel[0], pixel[1], pixel[2]], 1.0),
+                _ => ([pixel[0], pixel[1], pixel[2]], pixel[3]),
+            };
+            rgba.extend(rgb.map(to_u8));
+            rgba.push(to_u8(alpha));
+        }
+        RgbaImage::from_raw(frame.width() as u32, frame.height() as u32, rgba)
+            .ok_or_else(|| AppError::ImageUnreadable("JPEG XL frame has the wrong size".into()))
+    }
+}
+
+#[cfg(not(feature = "jxl"))]
+mod jxl {
+    use xcap::image::RgbaImage;
+
+    use crate::error::{AppError, AppResult};
+
+    pub fn encode(_image: &RgbaImage) -> AppResult<Vec<u8>> {
+        Err(AppError::Capture("built without the `jxl` feature".into()))
+    }
+
+    pub fn decode(_bytes: &[u8]) -> AppResult<RgbaImage> {
+        Err(AppError::ImageUnreadable("built without the `jxl` feature".into()))
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use xcap::{Monitor, Window};
+
+/// How long a display's profile is reused before it's read again, so a
+/// profile switched in the OS settings, or another display plugged in
+/// under the same name, shows up within a minute.
+const PROFILE_TTL: Duration = Duration::from_secs(60);
+
+/// ICC colour profile the OS applies to a display, e.g. "Color LCD" or
+/// "Display P3". Captures hold the framebuffer's values, which only read
+/// as the intended colours through this profile.
+#[derive(Debug, Clone)]
+pub struct ColorProfile {
+    /// The profile's description, or its file name when it has none.
+    pub name: String,
+    /// The profile itself, when the platform hands it out.
+    pub icc: Option<Vec<u8>>,
+}
+
+/// Profile of the display showing the window titled `title`.
+pub fn of_window(title: &str) -> Option<ColorProfile> {
+    let window = Window::all()
+        .ok()?
+        .into_iter()
+        .find(|window| window.title().is_ok_and(|t| t == title))?;
+    of_monitor(&window.current_monitor().ok()?)
+}
+
+/// Profile of the display called `name`, as stored in `monitor`.
+pub fn of_monitor_named(name: &str) -> Option<ColorProfile> {
+    let monitor = Monitor::all()
+        .ok()?
+        .into_iter()
+        .find(|monitor| monitor.name().is_ok_and(|n| n == name))?;
+    of_monitor(&monitor)
+}
+
+pub fn of_monitor(monitor: &Monitor) -> Option<ColorProfile> {
+    platform_profile(monitor)
+}
+
+/// Recent profiles by display name, so captures don't each list the
+/// monitors and ask the OS for the profile again. Holds one entry per
+/// display seen.
+#[derive(Debug, Default)]
+pub struct ProfileCache {
+    profiles: HashMap<String, (Instant, Option<ColorProfile>)>,
+}
+
+impl ProfileCache {
+    /// `of_monitor_named`, cached.
+    pub fn of_monitor_named(&mut self, name: &str) -> Option<ColorProfile> {
+        self.get(name, Instant::now(), || of_monitor_named(name))
+    }
+
+    /// `of_window`, cached by the display the window is on. Finding that
+    /// display still lists the windows.
+    pub fn of_window(&mut self, title: &str) -> Option<ColorProfile> {
+        let window = Window::all()
+            .ok()?
+            .into_iter()
+            .find(|window| window.title().is_ok_and(|t| t == title))?;
+        let monitor = window.current_monitor().ok()?;
+        let name = monitor.name().ok()?;
+        self.get(&name, Instant::now(), || of_monitor(&monitor))
+    }
+
+    /// Forgets every profile, e.g. after the displays changed.
+    pub fn clear(&mut self) {
+        self.profiles.clear();
+    }
+
+    /// The profile of display `name` as of `now`, from `read` when none
+    /// was read in the last `PROFILE_TTL`.
+    fn get(
+        &mut self,
+        name: &str,
+        now: Instant,
+        read: impl FnOnce() -> Option<ColorProfile>,
+    ) -> Option<ColorProfile> {
+        match self.profiles.get(name) {
+            Some((at, profile)) if now.duration_since(*at) < PROFILE_TTL => profile.clone(),
+            _ => {
+                let profile = read();
+                self.profiles.insert(name.to_string(), (now, profile.clone()));
+                profile
+            }
+        }
+    }
+}
+
+/// Reads the `desc` tag of an ICC profile: an ASCII `desc` record in v2
+/// profiles, a `mluc` record of UTF-16 translations in v4 ones, of which
+/// the first is taken.
+pub fn icc_description(icc: &[u8]) -> Option<String> {
+    let u32_at = |at: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(icc.get(at..at + 4)?.try_into().ok()?))
+    };
+    let tags = u32_at(128)? as usize;
+    let (offset, size) = (0..tags.min(256)).find_map(|i| {
+        let entry = 132 + i * 12;
+        (icc.get(entry..entry + 4)? == b"desc")
+            .then(|| Some((u32_at(entry + 4)? as usize, u32_at(entry + 8)? as usize)))
+            .flatten()
+    })?;
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+    let text = match tag.get(..4)? {
+        b"desc" => {
+            let len = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            let ascii = tag.get(12..12 + len)?;
+            String::from_utf8_lossy(ascii).into_owned()
+        }
+        b"mluc" => {
+            let at = |at: usize| -> Option<usize> {
+                Some(u32::from_be_bytes(tag.get(at..at + 4)?.try_into().ok()?) as usize)
+            };
+            if at(8)? == 0 {
+                return None;
+            }
+            let (len, start) = (at(20)?, at(24)?);
+            let units: Vec<u16> = tag
+                .get(start..start.checked_add(len)?)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// X11 colour managers such as colord publish each display's profile on
+/// the root window, as `_ICC_PROFILE` for the first and `_ICC_PROFILE_n`
+/// for the n-th after it.
+#[cfg(target_os = "linux")]
+fn platform_profile(monitor: &Monitor) -> Option<ColorProfile> {
+    use xcb::x;
+
+    let id = monitor.id().ok()?;
+    let index = Monitor::all()
+        .ok()?
+        .iter()
+        .position(|m| m.id().is_ok_and(|m| m == id))?;
+    let name = match index {
+        0 => "_ICC_PROFILE".to_string(),
+        n => format!("_ICC_PROFILE_{n}"),
+    };
+
+    let (conn, screen_num) = xcb::Connection::connect(None).ok()?;
+    let root = conn.get_setup().roots().nth(screen_num as usize)?.root();
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name: name.as_bytes(),
+    });
+    let atom = conn.wait_for_reply(cookie).ok()?.atom();
+    if atom == x::ATOM_NONE {
+        return None;
+    }
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: atom,
+        r#type: x::ATOM_ANY,
+        long_offset: 0,
+        long_length: u32::MAX / 4,
+    });
+    let icc = conn.wait_for_reply(cookie).ok()?.value::<u8>().to_vec();
+    Some(ColorProfile {
+        name: icc_description(&icc)?,
+        icc: Some(icc),
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn platform_profile(monitor: &Monitor) -> Option<ColorProfile> {
+    use std::ffi::c_void;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGDisplayCopyColorSpace(display: u32) -> *const c_void;
+        fn CGColorSpaceCopyICCData(space: *const c_void) -> *const c_void;
+    }
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFDataGetLength(data: *const c_void) -> isize;
+        fn CFDataGetBytePtr(data: *const c_void) -> *const u8;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    // The monitor id is its CGDirectDisplayID.
+    let display = monitor.id().ok()?;
+    // SAFETY: both copies are released, and the bytes are copied out
+    // before the data owning them goes.
+    let icc = unsafe {
+        let space = CGDisplayCopyColorSpace(display);
+        if space.is_null() {
+            return None;
+        }
+        let data = CGColorSpaceCopyICCData(space);
+        CFRelease(space);
+        if data.is_null() {
+            return None;
+        }
+        let len = CFDataGetLength(data).max(0) as usize;
+        let icc = std::slice::from_raw_parts(CFDataGetBytePtr(data), len).to_vec();
+        CFRelease(data);
+        icc
+    };
+    Some(ColorProfile {
+        name: icc_description(&icc)?,
+        icc: Some(icc),
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn platform_profile(monitor: &Monitor) -> Option<ColorProfile> {
+    use std::{ffi::c_void, path::PathBuf, ptr::null};
+
+    #[repr(C)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[repr(C)]
+    struct MonitorInfoEx {
+        size: u32,
+        monitor: [i32; 4],
+        work: [i32; 4],
+        flags: u32,
+        device: [u16; 32],
+    }
+
+    const MONITOR_DEFAULTTONULL: u32 = 0;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn MonitorFromPoint(point: Point, flags: u32) -> *mut c_void;
+        fn GetMonitorInfoW(monitor: *mut c_void, info: *mut MonitorInfoEx) -> i32;
+    }
+    #[link(name = "gdi32")]
+    unsafe extern "system" {
+        fn CreateDCW(
+            driver: *const u16,
+            device: *const u16,
+            port: *const u16,
+            mode: *const c_void,
+        ) -> *mut c_void;
+        fn GetICMProfileW(dc: *mut c_void, len: *mut u32, name: *mut u16) -> i32;
+        fn DeleteDC(dc: *mut c_void) -> i32;
+    }
+
+    let center = Point {
+        x: monitor.x().ok()? + monitor.width().ok()? as i32 / 2,
+        y: monitor.y().ok()? + monitor.height().ok()? as i32 / 2,
+    };
+    // SAFETY: `info` is sized for the extended struct the call fills, the
+    // device name it returns is NUL-terminated, and the DC is deleted
+    // before returning.
+    let path = unsafe {
+        let handle = MonitorFromPoint(center, MONITOR_DEFAULTTONULL);
+        if handle.is_null() {
+            return None;
+        }
+        let mut info: MonitorInfoEx = std::mem::zeroed();
+        info.size = size_of::<MonitorInfoEx>() as u32;
+        if GetMonitorInfoW(handle, &mut info) == 0 {
+            return None;
+        }
+        let dc = CreateDCW(info.device.as_ptr(), info.device.as_ptr(), null(), null());
+        if dc.is_null() {
+            return None;
+        }
+        let mut name = [0u16; 260];
+        let mut len = name.len() as u32;
+        let found = GetICMProfileW(dc, &mut len, name.as_mut_ptr()) != 0;
+        DeleteDC(dc);
+        if !found {
+            return None;
+        }
+        let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        PathBuf::from(String::from_utf16_lossy(&name[..end]))
+    };
+    let icc = std::fs::read(&path).ok();
+    let name = icc
+        .as_deref()
+        .and_then(icc_description)
+        .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()))?;
+    Some(ColorProfile { name, icc })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_profile(_monitor: &Monitor) -> Option<ColorProfile> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile whose tag table holds just `tag`, under `signature`.
+    fn profile(signature: &[u8; 4], tag: &[u8]) -> Vec<u8> {
+        let mut icc = vec![0u8; 128];
+        icc.extend(1u32.to_be_bytes());
+        icc.extend(signature);
+        icc.extend(144u32.to_be_bytes());
+        icc.extend((tag.len() as u32).to_be_bytes());
+        icc.extend(tag);
+        icc
+    }
+
+    fn desc(text: &[u8]) -> Vec<u8> {
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend((text.len() as u32).to_be_bytes());
+        tag.extend(text);
+        tag
+    }
+
+    fn mluc(records: &[&str]) -> Vec<u8> {
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        tag.extend((records.len() as u32).to_be_bytes());
+        tag.extend(12u32.to_be_bytes());
+        let mut strings = Vec::new();
+        for text in records {
+            let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            let start = 16 + 12 * records.len() + strings.len();
+            tag.extend(b"enUS");
+            tag.extend((units.len() as u32).to_be_bytes());
+            tag.extend((start as u32).to_be_bytes());
+            strings.extend(units);
+        }
+        tag.extend(strings);
+        tag
+    }
+
+    #[test]
+    fn reads_v2_ascii_descriptions() {
+        let icc = profile(b"desc", &desc(b"Color LCD\0"));
+        assert_eq!(icc_description(&icc).as_deref(), Some("Color LCD"));
+    }
+
+    #[test]
+    fn reads_the_first_v4_translation() {
+        let icc = profile(b"desc", &mluc(&["Display P3", "Affichage P3"]));
+        assert_eq!(icc_description(&icc).as_deref(), Some("Display P3"));
+        assert_eq!(icc_description(&profile(b"desc", &mluc(&[]))), None);
+    }
+
+    #[test]
+    fn rejects_missing_blank_and_truncated_descriptions() {
+        assert_eq!(
+            icc_description(&profile(b"cprt", &desc(b"(c) Apple"))),
+            None
+        );
+        assert_eq!(icc_description(&profile(b"desc", &desc(b" \0\0"))), None);
+        assert_eq!(icc_description(&profile(b"desc", b"XYZ \0\0\0\0")), None);
+        let icc = profile(b"desc", &desc(b"Color LCD"));
+        for len in [0, 100, 131, 140, icc.len() - 1] {
+            assert_eq!(icc_description(&icc[..len]), None, "{len} bytes");
+        }
+        // A tag table claiming more entries than the data holds.
+        let mut icc = profile(b"desc", &desc(b"Color LCD"));
+        icc[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+        icc.truncate(144);
+        assert_eq!(icc_description(&icc), None);
+    }
+
+    #[test]
+    fn profiles_are_reused_for_a_while() {
+        let mut cache = ProfileCache::default();
+        let start = Instant::now();
+        let mut reads = 0;
+        let mut read = |name: &str| {
+            reads += 1;
+            Some(ColorProfile {
+                name: name.to_string(),
+                icc: None,
+            })
+        };
+        cache.get("eDP-1", start, || read("Color LCD"));
+        let later = start + PROFILE_TTL - Duration::from_secs(1);
+        let reused = cache.get("eDP-1", later, || read("other"));
+        assert_eq!(reused.map(|p| p.name).as_deref(), Some("Color LCD"));
+        cache.get("HDMI-1", later, || read("Dell"));
+        let stale = cache.get("eDP-1", start + PROFILE_TTL, || read("Display P3"));
+        assert_eq!(stale.map(|p| p.name).as_deref(), Some("Display P3"));
+        cache.clear();
+        cache.get("HDMI-1", later, || read("Dell"));
+        assert_eq!(reads, 4);
+    }
+}
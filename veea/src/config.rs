@@ -1,62 +1,757 @@
 use std::{
     fs,
+    io::Write,
+    net::SocketAddr,
     path::{Path, PathBuf},
 };
 
+use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
-use crate::error::AppResult;
+use crate::{
+    cache,
+    capture::CaptureStrategy,
+    codec::{ImageFormat, PngCompression}, error::AppResult, expose::ApiExpose, ids::IdVersion,
+    importance::ImportanceWeights,
+    ocr::OcrBackend, overlay::OverlayConfig, pack::StorageMode, schedule::Schedule,
+    timezone::StorageTimezone,
+};
+
+/// Config location of the original layout, relative to the working
+/// directory. Still used when present so existing setups keep working.
+pub const LEGACY_CONFIG_PATH: &str = "data/config.toml";
+
+/// Per-user data directory (e.g. `~/.local/share/veea`), or `data/` when
+/// the platform doesn't have one.
+pub fn default_data_dir() -> PathBuf {
+    ProjectDirs::from("", "", "veea")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("data"))
+}
+
+/// `data/config.toml` if the working directory has one, otherwise
+/// `config.toml` in the per-user data directory, so users sharing a
+/// machine don't share a config.
+pub fn default_config_path() -> PathBuf {
+    let legacy = PathBuf::from(LEGACY_CONFIG_PATH);
+    if legacy.exists() {
+        return legacy;
+    }
+    default_data_dir().join("config.toml")
+}
+
+/// File next to the config where the daemon records the address its API
+/// actually bound, which differs from `api_addr` when that port was busy.
+pub fn api_addr_file(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("api_addr")
+}
 
-pub const DEFAULT_CONFIG_PATH: &str = "data/config.toml";
+/// Address of the running instance that uses `config_path`, if any.
+pub fn read_api_addr(config_path: &Path) -> Option<SocketAddr> {
+    fs::read_to_string(api_addr_file(config_path))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct CaptureConfig {
     pub capture_dir: PathBuf,
+    /// Captures are skipped while the disk holding `capture_dir` has less
+    /// free space than this; 0 turns the check off.
+    pub min_free_bytes: u64,
     pub db_path: PathBuf,
-    pub capture_on_focus: bool,
-    pub capture_on_title_change: bool,
+    /// Legacy switches, folded into `triggers` on load.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_on_focus: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_on_title_change: Option<bool>,
     pub capture_interval_ms: u64,
     pub max_captures_per_minute: u32,
+    /// Share of `max_captures_per_minute` only focus and title captures may
+    /// use, so interval and other captures are refused first when busy.
+    pub rate_limit_focus_reserve: f64,
+    /// `false` skips the `monitor` stage of `capture_strategy`; kept for
+    /// configs written before the strategy existed.
     pub allow_monitor_fallback: bool,
+    /// Ways of capturing tried in order until one works: `focused_window`,
+    /// `title_match` and `monitor`. Captures of a window that lost focus
+    /// only use `title_match`.
+    pub capture_strategy: CaptureStrategy,
+    /// Monitor captures stitch every display into one image, laid out as
+    /// on the desktop, instead of taking the primary one.
+    pub composite_monitors: bool,
+    /// Monitor captures take the display under the mouse pointer instead
+    /// of the first one. `composite_monitors` wins when both are set.
+    pub capture_cursor_monitor: bool,
     pub exclude_titles: Vec<String>,
     pub exclude_apps: Vec<String>,
+    /// Allowlist: when either is non-empty, only windows of one of these
+    /// apps or with one of these title fragments are captured. The
+    /// exclusions still win over a match.
+    pub include_apps: Vec<String>,
+    pub include_titles: Vec<String>,
     pub search_index_path: PathBuf,
     pub enable_search_index: bool,
+    pub pause_when_locked: bool,
+    /// Skip event captures while the displays are asleep; off for kiosks
+    /// whose screens blank but should still be recorded.
+    pub pause_when_displays_off: bool,
+    pub priority_apps: Vec<String>,
+    pub priority_settle_ms: u64,
+    /// Wait this long after a focus or title change before capturing, so
+    /// windows that are still drawing aren't caught half-rendered. Focus
+    /// moving to another window meanwhile replaces the pending capture; a
+    /// title change of the same window only updates it. 150 suits most
+    /// Electron apps.
+    pub capture_delay_ms: u64,
+    /// Share of the title, 0 to 1, that has to change for a title change
+    /// to be captured, as edit distance over the longer title. 0.15 skips
+    /// counters and unsaved markers flipping while keeping most document
+    /// and page switches; 0 captures every change.
+    pub title_min_change: f64,
+    /// Command run through the shell after each stored capture, with the
+    /// capture in `VEEA_CAPTURE_ID`, `VEEA_PATH`, `VEEA_TITLE`, `VEEA_APP`,
+    /// `VEEA_TS` and `VEEA_EVENT_TYPE`, and in packs mode
+    /// `VEEA_PACK_PATH`, `VEEA_PACK_OFFSET` and `VEEA_PACK_LEN`, see
+    /// `hooks`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_capture_hook: Option<String>,
+    /// A hook still running after this long is killed.
+    pub hook_timeout_ms: u64,
+    /// Hooks running at once; captures arriving while this many run get
+    /// no hook.
+    pub hook_max_running: usize,
+    pub capture_previous_on_focus: bool,
+    pub debug_endpoints: bool,
+    /// Refuse requests that permanently delete captures, such as
+    /// `/control/erase`, unless they pass `confirm=true`, so a resubmitted
+    /// form or repeated command can't destroy history by accident.
+    pub require_delete_confirm: bool,
+    pub max_burst_frames: u32,
+    pub skip_self: bool,
+    /// Overrides applied while running on battery. Power state is only
+    /// monitored when this or `battery_aware` is set.
+    pub battery_profile: Option<PowerProfile>,
+    /// Throttle capture on battery even without a `battery_profile`, using
+    /// a built-in one (half the rate, twice the interval, no OCR, fast PNG
+    /// compression).
+    pub battery_aware: bool,
+    /// Run capture, encoding and OCR at a lower OS priority so they don't
+    /// compete with foreground work.
+    pub low_priority: bool,
+    /// Store the clipboard's text with each capture, up to
+    /// `clipboard_max_bytes`.
+    pub capture_clipboard: bool,
+    pub clipboard_max_bytes: usize,
+    /// Raise a desktop notification when a capture fails.
+    pub notify_on_error: bool,
+    pub notifications: Notifications,
+    /// Record the file the focused window shows, where the platform can
+    /// tell (macOS, via Accessibility).
+    pub capture_doc_path: bool,
+    /// How long a capture waits for the document path before storing
+    /// none.
+    pub doc_path_timeout_ms: u64,
+    /// Store the text of the focused window's accessibility elements for
+    /// search, a cheap stand-in for OCR (macOS and Windows).
+    pub capture_ax_text: bool,
+    /// How long a capture waits for the accessibility walk before storing
+    /// what it has not got.
+    pub ax_text_timeout_ms: u64,
+    /// Levels of the accessibility tree walked below the window.
+    pub ax_text_max_depth: usize,
+    /// Characters of accessibility text kept per capture.
+    pub ax_text_max_chars: usize,
+    /// Write yesterday's `veea report` to `report_dir` once a day.
+    pub daily_report: bool,
+    pub report_dir: PathBuf,
+    /// Days entries stay in the `changes` feed; 0 keeps them forever.
+    pub changes_retention_days: u32,
+    pub triggers: Triggers,
+    /// Local weekday and time windows in which event captures are taken;
+    /// outside all of them capture pauses by itself. Empty means always.
+    pub schedule: Vec<ScheduleWindow>,
+    /// Title fragments or app names whose captures are pixelated before
+    /// being saved.
+    pub redact_titles: Vec<String>,
+    /// Also keep the unredacted image, encrypted with `original_passphrase`
+    /// and only served by `/captures/:id/original`.
+    pub redact_keep_original: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_passphrase: Option<String>,
+    /// Store OCR and clipboard text encrypted with a key derived from
+    /// `original_passphrase`. Searching that text then needs the
+    /// passphrase configured; without it the text reads as missing.
+    pub encrypt_text: bool,
+    /// Case-insensitive title/app fragments that never get captured. A
+    /// match also suppresses the next capture within `sensitive_cooloff_ms`.
+    pub sensitive_title_patterns: Vec<String>,
+    pub sensitive_cooloff_ms: u64,
+    /// Capture when the focused window is moved or resized noticeably,
+    /// once it has stayed put for `geometry_debounce_ms`.
+    pub capture_on_geometry_change: bool,
+    pub geometry_debounce_ms: u64,
+    /// Folders whose new or changed files trigger an "fs" capture, e.g.
+    /// the downloads folder. Subfolders aren't watched.
+    pub watch_paths: Vec<PathBuf>,
+    pub fs_debounce_ms: u64,
+    /// Capture the focused window when another app posts a desktop
+    /// notification, where the platform lets Veea see them (Linux).
+    pub capture_on_notification: bool,
+    pub ocr_backend: OcrBackend,
+    /// Only OCR the rows that changed since the previous capture of the
+    /// same app, carrying the rest of its text forward. Off by default.
+    pub incremental_ocr: bool,
+    pub memory: MemoryLimits,
+    /// Zone whose midnight starts a new capture folder and `/gaps` day.
+    /// Only affects new captures; existing files stay where they are.
+    pub storage_timezone: StorageTimezone,
+    /// Whether event captures get a file each, go into hourly packs or
+    /// share one file per distinct image; `veea pack` and `veea unpack`
+    /// convert existing captures.
+    pub storage_mode: StorageMode,
+    /// Label and event type of manual snapshots that don't name their own.
+    pub snapshot_label: String,
+    pub snapshot_event_type: String,
+    /// Preferred API address; a free port on the same interface is used
+    /// when this one is taken.
+    pub api_addr: SocketAddr,
+    /// Event captures are dropped for this long after a wake from sleep,
+    /// while the screen is still redrawing.
+    pub post_wake_delay_ms: u64,
+    /// Take one capture once `post_wake_delay_ms` has passed after a wake.
+    pub capture_after_wake: bool,
+    /// Store whether the mic and camera were in use with each capture.
+    pub record_device_state: bool,
+    /// Store how many keys were pressed and buttons clicked in the minute
+    /// before each capture, never which. Needs the `input_counts` feature.
+    pub track_input_counts: bool,
+    /// Store a 0-3 `activity_level` from the same input rate, to tell
+    /// typing from reading without keeping the counts. Needs the
+    /// `input_counts` feature.
+    pub track_activity_level: bool,
+    /// Store the virtual desktop the window was on, where the platform
+    /// exposes it.
+    pub record_workspace: bool,
+    /// Store the name of the display's colour profile, to tell whether a
+    /// captured colour can be trusted. Off by default, as reading it lists
+    /// the displays; profiles are reused for a minute per display.
+    pub record_color_profile: bool,
+    /// Also embed that profile in saved PNGs. Adds a few KiB per capture.
+    pub embed_icc_profile: bool,
+    /// Format of new captures; `jxl` needs the `jxl` feature.
+    pub image_format: ImageFormat,
+    pub png_compression: PngCompression,
+    /// UUID version of new capture ids. Configs from before the option
+    /// existed keep v4.
+    #[serde(default = "IdVersion::legacy")]
+    pub capture_id_version: IdVersion,
+    /// Image, thumbnail and original requests served at once; the rest
+    /// queue, keeping memory flat when a page loads many full images.
+    pub max_concurrent_image_reads: usize,
+    /// Discard captures that come out as one flat colour instead of
+    /// storing them flagged `blank`.
+    pub drop_blank_captures: bool,
+    /// Weights of the score `/highlights` ranks captures by.
+    pub importance: ImportanceWeights,
+    /// Browser origins, like `http://localhost:3000`, allowed to call the
+    /// API. Empty means same-origin only; `"*"` allows any site.
+    pub api_allowed_origins: Vec<String>,
+    /// Who may reach the API, see `expose`. Checked at startup together
+    /// with `api_addr` and `api_token`.
+    pub api_expose: ApiExpose,
+    /// Required on every API request when set, as `Authorization: Bearer
+    /// <token>` or as the basic auth password. Needed to serve other
+    /// machines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_token: Option<String>,
+    /// Largest request body accepted in `proxy` mode.
+    pub api_max_body_bytes: usize,
+    /// Memory for rendered thumbnails, in MiB; 0 turns the cache off.
+    pub thumb_cache_mb: usize,
+    /// Scratch files such as OCR input, one folder per subsystem, see
+    /// `cache`.
+    pub cache_dir: PathBuf,
+    /// Cache files older than this go in the next sweep; 0 keeps them
+    /// until `cache_max_mb` is reached.
+    pub cache_ttl_hours: u32,
+    /// Disk the cache may use, in MiB, before the oldest files go; 0 for
+    /// no limit.
+    pub cache_max_mb: u64,
+    /// Label burned into images served with `?overlay=true`, see
+    /// `overlay::apply`.
+    pub overlay: OverlayConfig,
+}
+
+/// Shown instead of the value of a `sensitive` setting.
+pub const REDACTED: &str = "[redacted]";
+
+/// Documentation of one `CaptureConfig` field, served by `/config/schema`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigField {
+    pub name: &'static str,
+    /// TOML shape of the value, e.g. `"u64"` or `"string[]"`.
+    pub kind: &'static str,
+    pub description: &'static str,
+    /// Secrets that the API never returns.
+    pub sensitive: bool,
+}
+
+/// Builds `CONFIG_FIELDS` and a destructuring of `CaptureConfig` that
+/// stops compiling when a field is added without an entry here.
+macro_rules! config_fields {
+    ($($name:ident: $kind:literal $(, $sensitive:ident)? => $description:literal;)*) => {
+        pub const CONFIG_FIELDS: &[ConfigField] = &[$(ConfigField {
+            name: stringify!($name),
+            kind: $kind,
+            description: $description,
+            sensitive: config_fields!(@sensitive $($sensitive)?),
+        }),*];
+
+        #[allow(dead_code)]
+        fn every_field_is_documented(config: CaptureConfig) {
+            let CaptureConfig { $($name: _),* } = config;
+        }
+    };
+    (@sensitive sensitive) => { true };
+    (@sensitive) => { false };
+}
+
+config_fields! {
+    capture_dir: "path" => "Directory captures are written to, one folder per day.";
+    min_free_bytes: "u64" => "Skip captures while the capture disk has less free space; 0 disables.";
+    db_path: "path" => "SQLite database holding the capture index.";
+    capture_on_focus: "bool?" => "Legacy switch for triggers.focus.enabled.";
+    capture_on_title_change: "bool?" => "Legacy switch for triggers.title.enabled.";
+    capture_interval_ms: "u64" => "Periodic capture interval; 0 disables it.";
+    max_captures_per_minute: "u32" => "Rate limit across all triggers; 0 means unlimited.";
+    rate_limit_focus_reserve: "f64" => "Share of the rate limit kept for focus and title captures, 0 to 1.";
+    allow_monitor_fallback: "bool" => "Capture the whole monitor when the window can't be captured.";
+    capture_strategy: "string[]" => "Capture stages tried in order: focused_window, title_match, monitor.";
+    composite_monitors: "bool" => "Stitch all monitors into one image for monitor captures.";
+    capture_cursor_monitor: "bool" => "Monitor captures take the display under the mouse pointer.";
+    exclude_titles: "string[]" => "Title fragments that are never captured.";
+    exclude_apps: "string[]" => "App names that are never captured.";
+    include_apps: "string[]" => "Apps to capture; when this or include_titles is set, nothing else is.";
+    include_titles: "string[]" => "Title fragments to capture; when this or include_apps is set, nothing else is.";
+    search_index_path: "path" => "Database searched by /search.";
+    enable_search_index: "bool" => "Index new captures for search.";
+    pause_when_locked: "bool" => "Pause capturing while the screen is locked.";
+    pause_when_displays_off: "bool" => "Pause capturing while the displays are asleep.";
+    priority_apps: "string[]" => "Apps captured on first focus, bypassing the rate limit.";
+    priority_settle_ms: "u64" => "Wait before capturing a priority app.";
+    capture_delay_ms: "u64" => "Wait after a focus or title change before capturing; 0 captures at once.";
+    title_min_change: "f64" => "Share of a title, 0 to 1, that must change for a title capture; 0 captures all.";
+    post_capture_hook: "string?" => "Shell command run after each capture, given it in VEEA_* environment variables.";
+    hook_timeout_ms: "u64" => "Kill a post_capture_hook still running after this long.";
+    hook_max_running: "usize" => "post_capture_hook runs at once; further captures skip the hook.";
+    capture_previous_on_focus: "bool" => "Also capture the previously focused window on a focus change.";
+    debug_endpoints: "bool" => "Serve the /debug routes.";
+    require_delete_confirm: "bool" => "Destructive endpoints like /control/erase need confirm=true.";
+    max_burst_frames: "u32" => "Upper bound on frames per burst.";
+    skip_self: "bool" => "Don't capture Veea's own web UI.";
+    battery_profile: "table?" => "Overrides applied while on battery.";
+    battery_aware: "bool" => "Throttle on battery with a built-in profile.";
+    low_priority: "bool" => "Capture and run OCR at a lower OS priority.";
+    capture_clipboard: "bool" => "Store clipboard text with each capture.";
+    clipboard_max_bytes: "usize" => "Longest clipboard text stored.";
+    notify_on_error: "bool" => "Show a desktop notification when a capture fails.";
+    notifications: "table" => "Notifications for lost permission, low disk space and repeated failures.";
+    capture_doc_path: "bool" => "Record the focused window's document path where available.";
+    doc_path_timeout_ms: "u64" => "Longest wait for the document path lookup.";
+    capture_ax_text: "bool" => "Store the focused window's accessibility text for search (macOS, Windows).";
+    ax_text_timeout_ms: "u64" => "Longest the accessibility text walk may take.";
+    ax_text_max_depth: "usize" => "Levels of the accessibility tree walked below the window.";
+    ax_text_max_chars: "usize" => "Characters of accessibility text kept per capture.";
+    daily_report: "bool" => "Write the previous day's HTML report once a day.";
+    report_dir: "path" => "Directory daily reports are written to.";
+    changes_retention_days: "u32" => "Days entries stay in the /changes feed; 0 keeps them forever.";
+    triggers: "table" => "Per-trigger rules for focus, title and interval captures.";
+    schedule: "table[]" => "Weekday and time windows when capture runs; empty means always.";
+    redact_titles: "string[]" => "Title or app fragments whose captures are pixelated.";
+    redact_keep_original: "bool" => "Keep an encrypted unredacted copy of redacted captures.";
+    original_passphrase: "string?", sensitive => "Passphrase encrypting unredacted originals.";
+    encrypt_text: "bool" => "Encrypt stored OCR and clipboard text with original_passphrase.";
+    sensitive_title_patterns: "string[]" => "Fragments that are never captured and start a cooloff.";
+    sensitive_cooloff_ms: "u64" => "Captures suppressed after a sensitive match.";
+    capture_on_geometry_change: "bool" => "Capture when the focused window moves or resizes.";
+    geometry_debounce_ms: "u64" => "How long a window must stay put before a geometry capture.";
+    watch_paths: "path[]" => "Folders whose new or changed files trigger a capture.";
+    fs_debounce_ms: "u64" => "File events within this long of the first make one capture.";
+    capture_on_notification: "bool" => "Capture when another app posts a desktop notification (Linux only).";
+    ocr_backend: "string" => "Tesseract, AppleVision, or None.";
+    incremental_ocr: "bool" => "Only OCR the part of the screen that changed.";
+    memory: "table" => "Caps on in-memory caches.";
+    storage_timezone: "string" => "\"local\" or an IANA zone deciding capture days.";
+    storage_mode: "string" => "files, packs to append captures to one archive per hour, or content_addressed to store identical images once.";
+    snapshot_label: "string" => "Label of snapshots that don't name one.";
+    snapshot_event_type: "string" => "Event type of snapshots that don't name one.";
+    api_addr: "socket address" => "Preferred API address; a free port is used when busy.";
+    post_wake_delay_ms: "u64" => "Captures dropped for this long after a wake from sleep.";
+    capture_after_wake: "bool" => "Take one capture once the post-wake delay has passed.";
+    record_device_state: "bool" => "Store whether the mic and camera were in use.";
+    track_input_counts: "bool" => "Store key press and click counts of the minute before each capture.";
+    track_activity_level: "bool" => "Store a 0-3 input activity level of the minute before each capture.";
+    record_workspace: "bool" => "Store the virtual desktop or Space of each capture.";
+    record_color_profile: "bool" => "Store the name of the display's colour profile.";
+    embed_icc_profile: "bool" => "Embed the display's ICC profile in saved PNGs.";
+    image_format: "string" => "png, or jxl for lossless JPEG XL (needs the jxl feature).";
+    png_compression: "string" => "default, or fast for less CPU and larger PNGs.";
+    capture_id_version: "string" => "v7 for time-ordered capture ids, or v4 for random ones.";
+    max_concurrent_image_reads: "usize" => "Image requests served at once; others queue.";
+    drop_blank_captures: "bool" => "Discard all-black or all-white captures instead of flagging them.";
+    importance: "table" => "Weights of the importance score used by /highlights.";
+    api_allowed_origins: "string[]" => "Browser origins allowed to call the API; \"*\" for any.";
+    api_expose: "string" => "localhost, lan (needs api_token), or proxy for serving behind a reverse proxy.";
+    api_token: "string?", sensitive => "Bearer token every API request must carry; required beyond localhost.";
+    api_max_body_bytes: "usize" => "Largest request body accepted in proxy mode.";
+    thumb_cache_mb: "usize" => "MiB of rendered thumbnails kept in memory; 0 disables.";
+    cache_dir: "path" => "Directory of scratch files, swept at startup and daily.";
+    cache_ttl_hours: "u32" => "Age at which cache files are swept; 0 disables.";
+    cache_max_mb: "u64" => "MiB the cache may use before the oldest files are swept; 0 disables.";
+    overlay: "table" => "Timestamp and title label drawn on served copies of captures.";
+}
+
+/// Desktop notifications for daemon trouble that silently stops captures,
+/// see `notify::ErrorNotifier`. Each kind is shown at most once an hour.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    pub enabled: bool,
+    /// Capture failed with a permission error, e.g. screen recording
+    /// access revoked by an OS update.
+    pub permission_lost: bool,
+    /// Captures skipped for `min_free_bytes`.
+    pub disk_full: bool,
+    /// `failure_threshold` failed captures within ten minutes.
+    pub capture_failures: bool,
+    pub failure_threshold: u32,
+    /// Local time windows like "22:00-07:00" in which nothing is shown,
+    /// including `notify_on_error` notifications.
+    pub quiet_hours: Vec<String>,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            permission_lost: true,
+            disk_full: true,
+            capture_failures: true,
+            failure_threshold: 5,
+            quiet_hours: vec![],
+        }
+    }
+}
+
+/// Caps on the daemon's in-memory caches, which would otherwise grow with
+/// every window seen over weeks of uptime.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryLimits {
+    /// Windows whose recent titles are remembered.
+    pub title_history_windows: usize,
+    /// Titles remembered per window.
+    pub title_history_len: usize,
+    /// Priority apps remembered as already captured this session.
+    pub priority_seen: usize,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            title_history_windows: 64,
+            title_history_len: 8,
+            priority_seen: 256,
+        }
+    }
+}
+
+/// Per-trigger capture rules, see `trigger::TriggerPolicy`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Triggers {
+    pub focus: TriggerRule,
+    pub title: TriggerRule,
+    pub interval: TriggerRule,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerRule {
+    pub enabled: bool,
+    /// Only fire for these apps; empty means any app.
+    pub apps: Vec<String>,
+    pub exclude_apps: Vec<String>,
+    /// Only fire when the title contains one of these; empty means any.
+    pub titles: Vec<String>,
+    pub exclude_titles: Vec<String>,
+    /// Local time windows like "09:00-17:30" or "22:00-24:00"; hours past
+    /// midnight count towards the day the window starts. Empty means all
+    /// day.
+    pub hours: Vec<String>,
+    /// Three-letter day names ("mon", "tue", ...); empty means every day.
+    pub days: Vec<String>,
+}
+
+impl Default for TriggerRule {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            apps: vec![],
+            exclude_apps: vec![],
+            titles: vec![],
+            exclude_titles: vec![],
+            hours: vec![],
+            days: vec![],
+        }
+    }
+}
+
+/// One `schedule` entry, e.g. weekdays from "09:00-17:30".
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleWindow {
+    /// Three-letter day names ("mon", "tue", ...); empty means every day.
+    pub days: Vec<String>,
+    /// Local time windows like "09:00-17:30" or "22:00-24:00"; hours past
+    /// midnight count towards the day the window starts. Empty means all
+    /// day.
+    pub hours: Vec<String>,
+}
+
+/// Settings that replace their `CaptureConfig` counterparts while a power
+/// profile is active. Unset fields keep the base value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerProfile {
+    pub capture_interval_ms: Option<u64>,
+    pub max_captures_per_minute: Option<u32>,
+    /// `false` turns OCR off while the profile is active.
+    pub ocr: Option<bool>,
+    pub png_compression: Option<PngCompression>,
 }
 
 impl Default for CaptureConfig {
     fn default() -> Self {
+        let data_dir = default_data_dir();
         Self {
-            capture_dir: PathBuf::from("data/captures"),
-            db_path: PathBuf::from("data/index.db"),
-            capture_on_focus: true,
-            capture_on_title_change: true,
+            capture_dir: data_dir.join("captures"),
+            min_free_bytes: 500 * 1024 * 1024,
+            db_path: data_dir.join("index.db"),
+            capture_on_focus: None,
+            capture_on_title_change: None,
             capture_interval_ms: 0,
             max_captures_per_minute: 20,
+            rate_limit_focus_reserve: 0.25,
             allow_monitor_fallback: true,
+            capture_strategy: CaptureStrategy::default(),
+            composite_monitors: false,
+            capture_cursor_monitor: false,
             exclude_titles: vec![],
             exclude_apps: vec![],
-            search_index_path: PathBuf::from("data/index.db"),
+            include_apps: vec![],
+            include_titles: vec![],
+            search_index_path: data_dir.join("index.db"),
             enable_search_index: true,
+            pause_when_locked: true,
+            pause_when_displays_off: true,
+            priority_apps: vec![],
+            priority_settle_ms: 0,
+            capture_delay_ms: 0,
+            title_min_change: 0.0,
+            post_capture_hook: None,
+            hook_timeout_ms: 30_000,
+            hook_max_running: 2,
+            capture_previous_on_focus: false,
+            debug_endpoints: false,
+            require_delete_confirm: true,
+            max_burst_frames: 30,
+            skip_self: true,
+            battery_profile: None,
+            battery_aware: false,
+            low_priority: false,
+            capture_clipboard: false,
+            clipboard_max_bytes: 4096,
+            notify_on_error: false,
+            notifications: Notifications::default(),
+            capture_doc_path: false,
+            doc_path_timeout_ms: 250,
+            capture_ax_text: false,
+            ax_text_timeout_ms: 300,
+            ax_text_max_depth: 16,
+            ax_text_max_chars: 20_000,
+            daily_report: false,
+            report_dir: data_dir.join("reports"),
+            changes_retention_days: 30,
+            triggers: Triggers::default(),
+            schedule: vec![],
+            redact_titles: vec![],
+            redact_keep_original: false,
+            original_passphrase: None,
+            encrypt_text: false,
+            sensitive_title_patterns: vec![
+                "password".to_string(),
+                "1Password".to_string(),
+                "Bitwarden".to_string(),
+                "Keychain Access".to_string(),
+                "sudo".to_string(),
+            ],
+            sensitive_cooloff_ms: 3000,
+            capture_on_geometry_change: false,
+            geometry_debounce_ms: 1000,
+            watch_paths: vec![],
+            fs_debounce_ms: 2000,
+            capture_on_notification: false,
+            ocr_backend: OcrBackend::None,
+            incremental_ocr: false,
+            memory: MemoryLimits::default(),
+            storage_timezone: StorageTimezone::Local,
+            storage_mode: StorageMode::Files,
+            snapshot_label: "manual".to_string(),
+            snapshot_event_type: "snapshot".to_string(),
+            api_addr: SocketAddr::from(([127, 0, 0, 1], 8787)),
+            post_wake_delay_ms: 3000,
+            capture_after_wake: false,
+            record_device_state: false,
+            track_input_counts: false,
+            track_activity_level: false,
+            record_workspace: true,
+            record_color_profile: false,
+            embed_icc_profile: false,
+            image_format: ImageFormat::Png,
+            png_compression: PngCompression::Default,
+            capture_id_version: IdVersion::V7,
+            max_concurrent_image_reads: 4,
+            drop_blank_captures: false,
+            importance: ImportanceWeights::default(),
+            api_allowed_origins: vec![],
+            api_expose: ApiExpose::Localhost,
+            api_token: None,
+            api_max_body_bytes: 1024 * 1024,
+            thumb_cache_mb: 64,
+            cache_dir: data_dir.join("cache"),
+            cache_ttl_hours: 24,
+            cache_max_mb: 512,
+            overlay: OverlayConfig::default(),
         }
     }
 }
 
 impl CaptureConfig {
+    /// Maps the old `capture_on_*` booleans onto their trigger rules.
+    fn fold_legacy_triggers(&mut self) {
+        if let Some(enabled) = self.capture_on_focus.take() {
+            self.triggers.focus.enabled = enabled;
+        }
+        if let Some(enabled) = self.capture_on_title_change.take() {
+            self.triggers.title.enabled = enabled;
+        }
+    }
+
+    /// Overrides to apply while on battery: `battery_profile` if set,
+    /// otherwise the built-in throttle when `battery_aware` is on.
+    pub fn battery_overrides(&self) -> Option<PowerProfile> {
+        if let Some(profile) = &self.battery_profile {
+            return Some(profile.clone());
+        }
+        self.battery_aware.then(|| PowerProfile {
+            capture_interval_ms: (self.capture_interval_ms > 0)
+                .then(|| self.capture_interval_ms.saturating_mul(2)),
+            max_captures_per_minute: (self.max_captures_per_minute > 0)
+                .then(|| (self.max_captures_per_minute / 2).max(1)),
+            ocr: Some(false),
+            png_compression: Some(PngCompression::Fast),
+        })
+    }
+
+    /// The config as JSON with every `sensitive` field that is set
+    /// replaced by `REDACTED`, for returning over the API.
+    pub fn to_redacted_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            for field in CONFIG_FIELDS.iter().filter(|f| f.sensitive) {
+                if let Some(v) = fields.get_mut(field.name)
+                    && !v.is_null()
+                {
+                    *v = REDACTED.into();
+                }
+            }
+        }
+        value
+    }
+
+    /// The config with `profile`'s overrides applied on top.
+    pub fn with_profile(&self, profile: &PowerProfile) -> Self {
+        let mut config = self.clone();
+        if let Some(interval) = profile.capture_interval_ms {
+            config.capture_interval_ms = interval;
+        }
+        if let Some(limit) = profile.max_captures_per_minute {
+            config.max_captures_per_minute = limit;
+        }
+        if profile.ocr == Some(false) {
+            config.ocr_backend = OcrBackend::None;
+        }
+        if let Some(compression) = profile.png_compression {
+            config.png_compression = compression;
+        }
+        config
+    }
+
     pub fn load_or_init(path: &Path) -> AppResult<Self> {
         if path.exists() {
-            let raw = fs::read_to_string(path)?;
-            let parsed: CaptureConfig = toml::from_str(&raw)?;
-            return Ok(parsed);
+            return Self::load(path);
         }
 
         let default = CaptureConfig::default();
+        default.save(path)?;
+        Ok(default)
+    }
+
+    /// The config at `path`, or the defaults without writing them, so a
+    /// first run can still go through `POST /setup`.
+    pub fn load_or_default(path: &Path) -> AppResult<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+        Ok(CaptureConfig::default())
+    }
+
+    fn load(path: &Path) -> AppResult<Self> {
+        let raw = fs::read_to_string(path)?;
+        let mut parsed: CaptureConfig = toml::from_str(&raw)?;
+        parsed.fold_legacy_triggers();
+        cache::check_dir(&parsed, path)?;
+        Schedule::new(&parsed.schedule)?;
+        Ok(parsed)
+    }
+
+    pub fn save(&self, path: &Path) -> AppResult<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let encoded = toml::to_string_pretty(&default)?;
+        let encoded = toml::to_string_pretty(self)?;
         fs::write(path, encoded)?;
-        Ok(default)
+        Ok(())
+    }
+
+    /// Writes the config to `path` only if no file exists there yet;
+    /// fails with `AlreadyExists` otherwise, even when racing another
+    /// writer.
+    pub fn save_new(&self, path: &Path) -> AppResult<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let encoded = toml::to_string_pretty(self)?;
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+        file.write_all(encoded.as_bytes())?;
+        Ok(())
     }
 }
@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api_error::REQUEST_ID_HEADER;
+
+const ALLOWED_METHODS: &str = "GET, POST, DELETE, PATCH, OPTIONS";
+const ALLOWED_HEADERS: &str = "authorization, content-type, x-request-id, x-veea-passphrase";
+/// How long browsers may cache a preflight answer, in seconds.
+const PREFLIGHT_MAX_AGE: &str = "600";
+
+/// Origins other than the API's own that may call it from a browser,
+/// from `api_allowed_origins`. Empty means same-origin only.
+#[derive(Debug, Clone, Default)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    any: bool,
+}
+
+impl CorsPolicy {
+    pub fn new(origins: &[String]) -> Self {
+        Self {
+            origins: origins
+                .iter()
+                .map(|o| o.trim().trim_end_matches('/').to_string())
+                .filter(|o| !o.is_empty() && o != "*")
+                .collect(),
+            any: origins.iter().any(|o| o.trim() == "*"),
+        }
+    }
+
+    pub fn allows_any(&self) -> bool {
+        self.any
+    }
+
+    /// Value for `Access-Control-Allow-Origin` when `origin` may call in.
+    fn allow_origin(&self, origin: &str) -> Option<HeaderValue> {
+        if self.any {
+            return Some(HeaderValue::from_static("*"));
+        }
+        self.origins
+            .iter()
+            .any(|o| o.eq_ignore_ascii_case(origin))
+            .then(|| HeaderValue::from_str(origin).ok())
+            .flatten()
+    }
+}
+
+/// Answers preflight requests from allowed origins and adds CORS headers
+/// to their actual requests. Requests from other origins pass through
+/// without them, so the browser keeps blocking those.
+pub async fn apply(State(policy): State<Arc<CorsPolicy>>, request: Request, next: Next) -> Response {
+    let allowed = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|origin| policy.allow_origin(origin));
+    let Some(allow_origin) = allowed else {
+        return next.run(request).await;
+    };
+
+    let preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOWED_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOWED_HEADERS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static(PREFLIGHT_MAX_AGE),
+        );
+        response
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(REQUEST_ID_HEADER),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(origins: &[&str]) -> Router {
+        let origins: Vec<String> = origins.iter().map(|o| o.to_string()).collect();
+        let policy = Arc::new(CorsPolicy::new(&origins));
+        Router::new()
+            .route("/status", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(policy, apply))
+    }
+
+    async fn send(app: Router, method: Method, origin: Option<&str>, preflight: bool) -> Response {
+        let mut request = Request::builder().method(method).uri("/status");
+        if let Some(origin) = origin {
+            request = request.header(header::ORIGIN, origin);
+        }
+        if preflight {
+            request = request.header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET");
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn allowed_origin(response: &Response) -> Option<&str> {
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn preflight_from_an_allowed_origin_is_answered() {
+        let app = app(&["https://dash.example"]);
+        let response = send(app, Method::OPTIONS, Some("https://dash.example"), true).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(allowed_origin(&response), Some("https://dash.example"));
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            ALLOWED_METHODS
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            ALLOWED_HEADERS
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], PREFLIGHT_MAX_AGE);
+        assert_eq!(headers[header::VARY], "origin");
+    }
+
+    #[tokio::test]
+    async fn request_from_an_allowed_origin_gets_headers() {
+        let app = app(&["https://dash.example"]);
+        let response = send(app, Method::GET, Some("https://dash.example"), false).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(allowed_origin(&response), Some("https://dash.example"));
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            REQUEST_ID_HEADER
+        );
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_METHODS)
+        );
+    }
+
+    #[tokio::test]
+    async fn other_origins_get_no_headers() {
+        let app = app(&["https://dash.example"]);
+        for origin in [
+            Some("https://evil.example"),
+            Some("https://dash.example.evil"),
+            Some("http://dash.example"),
+            None,
+        ] {
+            let response = send(app.clone(), Method::GET, origin, false).await;
+            assert_eq!(response.status(), StatusCode::OK, "{origin:?}");
+            assert_eq!(allowed_origin(&response), None, "{origin:?}");
+            assert!(!response.headers().contains_key(header::VARY), "{origin:?}");
+
+            // Not answered here, so the router's own 405 stands.
+            let response = send(app.clone(), Method::OPTIONS, origin, true).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{origin:?}"
+            );
+            assert_eq!(allowed_origin(&response), None, "{origin:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_origins_are_normalized() {
+        let app = app(&[" https://Dash.example/ ", ""]);
+        let response = send(app, Method::GET, Some("https://dash.example"), false).await;
+        assert_eq!(allowed_origin(&response), Some("https://dash.example"));
+    }
+
+    #[tokio::test]
+    async fn wildcard_allows_every_origin() {
+        let app = app(&["*"]);
+        for origin in ["https://a.example", "null"] {
+            let response = send(app.clone(), Method::GET, Some(origin), false).await;
+            assert_eq!(allowed_origin(&response), Some("*"), "{origin}");
+        }
+        assert!(CorsPolicy::new(&["*".to_string()]).allows_any());
+        assert!(!CorsPolicy::new(&["https://a.example".to_string()]).allows_any());
+    }
+
+    #[tokio::test]
+    async fn options_without_a_request_method_is_not_a_preflight() {
+        let app = app(&["https://dash.example"]);
+        let response = send(app, Method::OPTIONS, Some("https://dash.example"), false).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(allowed_origin(&response), Some("https://dash.example"));
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_MAX_AGE)
+        );
+    }
+}
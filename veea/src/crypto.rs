@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use sha2::{Digest, Sha256};
+
+use crate::base64;
+
+const MAGIC: &[u8] = b"VEEA1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase`, as
+/// `MAGIC | salt | nonce | ciphertext+tag`.
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Vec<u8> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("in-memory encryption cannot fail");
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    out
+}
+
+/// Reverses `encrypt`. `None` when the data is malformed or the passphrase
+/// is wrong, which the AEAD tag can't tell apart.
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let rest = data.strip_prefix(MAGIC)?;
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return None;
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// Marks a text column value sealed by `TextKey`, with the format version.
+const SEALED_PREFIX: &str = "veea-sealed:1:";
+pub const TEXT_SALT_LEN: usize = SALT_LEN;
+
+/// The last derived `TextKey`, by a digest of passphrase and salt. Every
+/// API request opens its own `Db`, and PBKDF2 is deliberately slow.
+static TEXT_KEY_CACHE: Mutex<Option<([u8; 32], Arc<TextKey>)>> = Mutex::new(None);
+
+/// Key for sealing text columns, derived from the image passphrase and a
+/// per-database salt.
+pub struct TextKey {
+    key: Key,
+}
+
+impl TextKey {
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Arc<Self> {
+        let mut digest = Sha256::new();
+        digest.update((passphrase.len() as u64).to_le_bytes());
+        digest.update(passphrase.as_bytes());
+        digest.update(salt);
+        let id: [u8; 32] = digest.finalize().into();
+
+        let mut cache = TEXT_KEY_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((cached, key)) = cache.as_ref()
+            && *cached == id
+        {
+            return key.clone();
+        }
+        let key = Arc::new(Self {
+            key: derive_key(passphrase, salt),
+        });
+        *cache = Some((id, key.clone()));
+        key
+    }
+
+    /// `text` as `SEALED_PREFIX` followed by base64 of `nonce | ciphertext+tag`.
+    pub fn seal(&self, text: &str) -> String {
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, text.as_bytes())
+            .expect("in-memory encryption cannot fail");
+        let mut data = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        format!("{SEALED_PREFIX}{}", base64::encode(&data))
+    }
+
+    /// Plaintext of a value that may have been sealed. Unsealed values
+    /// pass through; `None` when a sealed one doesn't open with this key.
+    pub fn open(&self, text: &str) -> Option<String> {
+        let Some(encoded) = text.strip_prefix(SEALED_PREFIX) else {
+            return Some(text.to_string());
+        };
+        let data = base64::decode(encoded)?;
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+pub fn is_sealed(text: &str) -> bool {
+    text.starts_with(SEALED_PREFIX)
+}
+
+/// Plaintext of a text column value. Without a key, sealed values read
+/// as missing rather than as ciphertext.
+pub fn open_text(text: Option<String>, key: Option<&TextKey>) -> Option<String> {
+    let text = text?;
+    match key {
+        Some(key) => key.open(&text),
+        None if is_sealed(&text) => None,
+        None => Some(text),
+    }
+}
+
+/// Fresh random salt for `TextKey::derive`.
+pub fn text_salt() -> [u8; TEXT_SALT_LEN] {
+    let mut salt = [0u8; TEXT_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_text_opens_with_its_key() {
+        let salt = text_salt();
+        let key = TextKey::derive("correct horse", &salt);
+        let sealed = key.seal("quarterly report");
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("quarterly"));
+        // A fresh nonce each time.
+        assert_ne!(key.seal("quarterly report"), sealed);
+        assert_eq!(key.open(&sealed).as_deref(), Some("quarterly report"));
+        assert_eq!(key.open("plain").as_deref(), Some("plain"));
+        assert_eq!(open_text(Some(sealed), None), None);
+    }
+
+    #[test]
+    fn sealed_text_refuses_another_key() {
+        let salt = text_salt();
+        let sealed = TextKey::derive("correct horse", &salt).seal("quarterly report");
+        assert_eq!(TextKey::derive("wrong horse", &salt).open(&sealed), None);
+        // Same passphrase, another database's salt.
+        assert_eq!(
+            TextKey::derive("correct horse", &text_salt()).open(&sealed),
+            None
+        );
+    }
+
+    #[test]
+    fn tampered_text_does_not_open() {
+        let key = TextKey::derive("correct horse", &text_salt());
+        let sealed = key.seal("quarterly report");
+        let data = base64::decode(sealed.strip_prefix(SEALED_PREFIX).unwrap()).unwrap();
+        for i in [0, NONCE_LEN, data.len() - 1] {
+            let mut tampered = data.clone();
+            tampered[i] ^= 1;
+            let tampered = format!("{SEALED_PREFIX}{}", base64::encode(&tampered));
+            assert_eq!(key.open(&tampered), None, "byte {i} flipped");
+        }
+        let truncated = format!("{SEALED_PREFIX}{}", base64::encode(&data[..NONCE_LEN - 1]));
+        assert_eq!(key.open(&truncated), None);
+        assert_eq!(key.open(&format!("{SEALED_PREFIX}not base64!")), None);
+    }
+}
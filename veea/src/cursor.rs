@@ -0,0 +1,82 @@
+/// Mouse position in desktop coordinates, the same space monitor
+/// positions are reported in, or `None` when it can't be queried (e.g.
+/// on Wayland without XWayland).
+pub fn position() -> Option<(i32, i32)> {
+    platform_position()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_position() -> Option<(i32, i32)> {
+    use xcb::x;
+
+    let (conn, screen_num) = xcb::Connection::connect(None).ok()?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)?
+        .root();
+    let cookie = conn.send_request(&x::QueryPointer { window: root });
+    let reply = conn.wait_for_reply(cookie).ok()?;
+    Some((reply.root_x() as i32, reply.root_y() as i32))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_position() -> Option<(i32, i32)> {
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGEventCreate(source: *const c_void) -> *mut c_void;
+        fn CGEventGetLocation(event: *mut c_void) -> CGPoint;
+    }
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFRelease(cf: *const c_void);
+    }
+
+    // A fresh event carries the current cursor location, in global
+    // display points with the origin at the main display's top left.
+    // SAFETY: a null source is allowed; the event is released once read.
+    unsafe {
+        let event = CGEventCreate(std::ptr::null());
+        if event.is_null() {
+            return None;
+        }
+        let point = CGEventGetLocation(event);
+        CFRelease(event);
+        Some((point.x.floor() as i32, point.y.floor() as i32))
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_position() -> Option<(i32, i32)> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetCursorPos(point: *mut Point) -> i32;
+    }
+
+    let mut point = Point::default();
+    // SAFETY: `point` is a valid, writable POINT for the call.
+    if unsafe { GetCursorPos(&mut point) } == 0 {
+        return None;
+    }
+    Some((point.x, point.y))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_position() -> Option<(i32, i32)> {
+    None
+}
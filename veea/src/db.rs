@@ -1,10 +1,39 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
-use chrono::{DateTime, Utc};
-use rusqlite::{params, Connection};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{
+    named_params, params, params_from_iter, types::Value, Connection, OptionalExtension, Row,
+};
 use chrono::Duration;
 
-use crate::error::AppResult;
+use crate::{
+    codec::ImageFormat,
+    config::CaptureConfig,
+    crypto::{self, TextKey},
+    error::{AppError, AppResult},
+    input_activity::InputCounts,
+    objects,
+    ocr::OcrLine,
+    pack::PackSlot,
+    search::{self, Suggestion, SuggestionKind},
+    story::{self, DayCount, Story},
+    timeline::{self, Gap, StripBucket},
+    timezone::StorageTimezone,
+};
+
+/// Version of the schema `init` leaves behind, stored in `meta` as
+/// `schema_version`. Bump it when a change would break older binaries
+/// reading the database, so they refuse it instead of misreading it.
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Columns every version of the `captures` table has had; a table
+/// without them belongs to some other program.
+const CORE_CAPTURE_COLUMNS: &[&str] = &["id", "ts", "event_type", "path"];
 
 #[derive(Debug, Clone)]
 pub struct CaptureRecord {
@@ -12,17 +41,611 @@ pub struct CaptureRecord {
     pub ts: DateTime<Utc>,
     pub window_title: Option<String>,
     pub app_name: Option<String>,
+    /// Bundle identifier or executable path of the app, see `app_id`.
+    pub app_id: Option<String>,
     pub event_type: String,
     pub path: String,
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub monitor: Option<String>,
     pub hash: Option<String>,
+    pub capture_group_id: Option<String>,
+    /// `path` relative to `capture_dir`, so the data directory can move.
+    pub rel_path: Option<String>,
+    pub clipboard_text: Option<String>,
+    /// Title the window showed before `window_title`, when known.
+    pub prev_title: Option<String>,
+    /// Encrypted unredacted image, when the served one was redacted.
+    pub original_path: Option<String>,
+    pub ocr_text: Option<String>,
+    /// Text of the window's accessibility elements, see `ax_text`.
+    pub ax_text: Option<String>,
+    /// `ocr_text` or `ax_text` reads as source code, see `code_text`;
+    /// `None` with neither.
+    pub contains_code: Option<bool>,
+    /// Where each line of `ocr_text` was found in the image, when the OCR
+    /// backend reports positions.
+    pub ocr_lines: Vec<OcrLine>,
+    /// `CaptureMethod` that produced the image, e.g. "monitor_fallback".
+    pub capture_method: Option<String>,
+    /// Mic and camera in use at capture time; `None` when not recorded or
+    /// not queryable.
+    pub mic_active: Option<bool>,
+    pub cam_active: Option<bool>,
+    /// Format of the file at `path`.
+    pub codec: ImageFormat,
+    /// The image is one flat colour, see `quality::ImageStats::is_blank`.
+    pub blank: bool,
+    /// Heuristic score from `ImportanceWeights`; `None` for captures taken
+    /// before scoring existed.
+    pub importance: Option<f64>,
+    /// File whose creation in a `watch_paths` folder triggered an "fs"
+    /// capture.
+    pub trigger_path: Option<String>,
+    /// File shown by the window, when `capture_doc_path` could find it.
+    pub doc_path: Option<String>,
+    /// Virtual desktop the window was on, see `workspace::current`.
+    pub workspace: Option<String>,
+    /// Name of the display's colour profile, see `color_profile`.
+    pub color_profile: Option<String>,
+    /// Key presses and clicks in the minute before the capture, see
+    /// `input_activity`; `None` when not tracked.
+    pub keys_per_min: Option<u32>,
+    pub clicks_per_min: Option<u32>,
+    /// 0 (no input) to 3 (typing) over the same minute, see
+    /// `input_activity::activity_level`.
+    pub activity_level: Option<u8>,
+    /// How the capture was decided beyond its `event_type`, as
+    /// `;`-separated facts, e.g. `strategy=focused_window>monitor;
+    /// rate_limit=17/20`. `None` for snapshots and older captures.
+    pub trigger_detail: Option<String>,
+    /// Where the image is when it was stored in a pack rather than at
+    /// `path`, see `pack`.
+    pub pack: Option<PackSlot>,
+}
+
+impl CaptureRecord {
+    /// Reads the stored image bytes from its pack slot, or from the file
+    /// `resolve_path` finds.
+    pub fn read_image(&self, capture_dir: &Path) -> std::io::Result<Vec<u8>> {
+        if let Some(slot) = &self.pack {
+            return slot.read(capture_dir);
+        }
+        let path = self.resolve_path(capture_dir).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is missing", self.path),
+            )
+        })?;
+        std::fs::read(path)
+    }
+
+    /// Whether the image is still on disk, in its file or its pack.
+    pub fn image_exists(&self, capture_dir: &Path) -> bool {
+        match &self.pack {
+            Some(slot) => capture_dir.join(&slot.path).exists(),
+            None => self.resolve_path(capture_dir).is_some(),
+        }
+    }
+
+    /// Locates the image on disk: the stored path if it still exists,
+    /// otherwise the same file under the current `capture_dir`.
+    pub fn resolve_path(&self, capture_dir: &Path) -> Option<PathBuf> {
+        let stored = PathBuf::from(&self.path);
+        if stored.exists() {
+            return Some(stored);
+        }
+        let relative = match &self.rel_path {
+            Some(rel) => PathBuf::from(rel),
+            None => date_relative(&stored)?,
+        };
+        Some(capture_dir.join(relative)).filter(|p| p.exists())
+    }
+}
+
+#[cfg(test)]
+impl CaptureRecord {
+    /// A "focus" capture of `id` at `ts` with only the required fields
+    /// set, for tests to adjust.
+    pub(crate) fn sample(id: &str, ts: DateTime<Utc>) -> Self {
+        Self {
+            id: id.to_string(),
+            ts,
+            window_title: Some("Sample window".to_string()),
+            app_name: Some("sample".to_string()),
+            app_id: None,
+            event_type: "focus".to_string(),
+            path: format!("{id}.png"),
+            width: Some(4),
+            height: Some(4),
+            monitor: None,
+            hash: None,
+            capture_group_id: None,
+            rel_path: Some(format!("{id}.png")),
+            clipboard_text: None,
+            prev_title: None,
+            original_path: None,
+            ocr_text: None,
+            ax_text: None,
+            contains_code: None,
+            ocr_lines: Vec::new(),
+            capture_method: None,
+            mic_active: None,
+            cam_active: None,
+            codec: ImageFormat::Png,
+            blank: false,
+            importance: None,
+            trigger_path: None,
+            doc_path: None,
+            workspace: None,
+            color_profile: None,
+            keys_per_min: None,
+            clicks_per_min: None,
+            activity_level: None,
+            trigger_detail: None,
+            pack: None,
+        }
+    }
+}
+
+/// Path of a capture relative to `capture_dir`, falling back to its
+/// trailing `YYYY/MM/DD/file` components when it lives elsewhere.
+pub fn relative_capture_path(path: &Path, capture_dir: &Path) -> Option<PathBuf> {
+    match path.strip_prefix(capture_dir) {
+        Ok(rel) => Some(rel.to_path_buf()),
+        Err(_) => date_relative(path),
+    }
+}
+
+fn date_relative(path: &Path) -> Option<PathBuf> {
+    let components: Vec<_> = path.components().collect();
+    let tail = components.get(components.len().checked_sub(4)?..)?;
+    Some(tail.iter().collect())
+}
+
+const LIVE_CAPTURES: &str = "deleted = 0";
+
+/// Distinct values each suggestion query returns before ranking.
+const SUGGEST_CANDIDATES: i64 = 200;
+/// Latest captures searched for titles and apps merely containing the
+/// typed text; those can't use an index. Their counts cover this window.
+const SUGGEST_SCAN: i64 = 10_000;
+
+/// Daemon events `find_gaps` reads per query.
+const DAEMON_EVENTS_PAGE: usize = 500;
+
+// The upper bound `:key || char(1114111)` sorts after every string that
+// starts with `:key`, so prefix matches are an index range scan. The
+// bare value column comes from the row holding MAX(ts), the latest one.
+const SUGGEST_TITLE_PREFIX: &str = "SELECT window_title, COUNT(*), MAX(ts) FROM captures
+     WHERE deleted = 0 AND title_key >= :key AND title_key < :key || char(1114111)
+     GROUP BY title_key ORDER BY MAX(ts) DESC LIMIT :candidates";
+const SUGGEST_TITLE_CONTAINS: &str = "SELECT window_title, COUNT(*), MAX(ts) FROM (
+         SELECT title_key, window_title, ts FROM captures
+         WHERE deleted = 0 AND title_key IS NOT NULL ORDER BY ts DESC LIMIT :scan)
+     WHERE instr(title_key, :key) > 1
+     GROUP BY title_key ORDER BY MAX(ts) DESC LIMIT :candidates";
+const SUGGEST_APP_PREFIX: &str = "SELECT app_name, COUNT(*), MAX(ts) FROM captures
+     WHERE deleted = 0 AND lower(app_name) >= :key AND lower(app_name) < :key || char(1114111)
+     GROUP BY lower(app_name) ORDER BY MAX(ts) DESC LIMIT :candidates";
+const SUGGEST_APP_CONTAINS: &str = "SELECT app_name, COUNT(*), MAX(ts) FROM (
+         SELECT app_name, ts FROM captures
+         WHERE deleted = 0 AND app_name IS NOT NULL ORDER BY ts DESC LIMIT :scan)
+     WHERE instr(lower(app_name), :key) > 1
+     GROUP BY lower(app_name) ORDER BY MAX(ts) DESC LIMIT :candidates";
+
+/// Narrows `list_recent`; `None` fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilter<'a> {
+    /// Taken at or after this, in Unix ms.
+    pub from: Option<i64>,
+    /// Taken before this, in Unix ms.
+    pub to: Option<i64>,
+    pub event_type: Option<&'a str>,
+    pub capture_method: Option<&'a str>,
+    pub mic_active: Option<bool>,
+    pub cam_active: Option<bool>,
+    pub workspace: Option<&'a str>,
+    pub contains_code: Option<bool>,
+    pub app_id: Option<&'a str>,
+    /// Frames of one burst, see `/control/burst`.
+    pub capture_group_id: Option<&'a str>,
+    /// At least this `activity_level`; captures without one never match.
+    pub min_activity: Option<u8>,
+    /// Also return captures flagged `blank`.
+    pub include_blank: bool,
+}
+
+impl From<&CaptureFilter<'_>> for QueryFilter {
+    fn from(filter: &CaptureFilter<'_>) -> Self {
+        let mut query = QueryFilter::new();
+        if let Some(from) = filter.from {
+            query = query.since(from);
+        }
+        if let Some(to) = filter.to {
+            query = query.before(to);
+        }
+        if let Some(event_type) = filter.event_type {
+            query = query.event_types([event_type]);
+        }
+        if let Some(method) = filter.capture_method {
+            query = query.capture_method(method);
+        }
+        if let Some(active) = filter.mic_active {
+            query = query.mic_active(active);
+        }
+        if let Some(active) = filter.cam_active {
+            query = query.cam_active(active);
+        }
+        if let Some(workspace) = filter.workspace {
+            query = query.workspace(workspace);
+        }
+        if let Some(code) = filter.contains_code {
+            query = query.contains_code(code);
+        }
+        if let Some(app_id) = filter.app_id {
+            query = query.app_id(app_id);
+        }
+        if let Some(group_id) = filter.capture_group_id {
+            query = query.capture_group(group_id);
+        }
+        if let Some(level) = filter.min_activity {
+            query = query.min_activity(level);
+        }
+        if !filter.include_blank {
+            query = query.exclude_blank();
+        }
+        query
+    }
+}
+
+/// Which captures a `QueryFilter` sees by their soft-delete flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeletedState {
+    #[default]
+    Live,
+    Deleted,
+    Any,
+}
+
+/// One typed condition of a `QueryFilter`. Column names come from this
+/// enum only; every value is bound as a parameter.
+#[derive(Debug, Clone)]
+enum Condition {
+    Since(i64),
+    Before(i64),
+    /// Case-insensitive for ASCII, like SQLite's `lower`.
+    TitleContains(String),
+    AppEquals(String),
+    AppId(String),
+    EventTypeIn(Vec<String>),
+    CaptureMethod(String),
+    MicActive(bool),
+    CamActive(bool),
+    Workspace(String),
+    ContainsCode(bool),
+    CaptureGroup(String),
+    MinActivity(u8),
+    ExcludeBlank,
+}
+
+/// Builds the WHERE clause shared by `list_filtered`, `count_filtered`
+/// and `delete_filtered` from typed conditions, all of which must hold.
+/// Values never become part of the SQL text, so quotes, `%` or NUL in a
+/// search string can't change what the statement does.
+#[derive(Debug, Clone, Default)]
+pub struct QueryFilter {
+    conditions: Vec<Condition>,
+    deleted: DeletedState,
+}
+
+impl QueryFilter {
+    /// Every live capture.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn deleted(mut self, state: DeletedState) -> Self {
+        self.deleted = state;
+        self
+    }
+
+    /// Captures at or after `ts_ms`.
+    pub fn since(self, ts_ms: i64) -> Self {
+        self.with(Condition::Since(ts_ms))
+    }
+
+    /// Captures strictly before `ts_ms`.
+    pub fn before(self, ts_ms: i64) -> Self {
+        self.with(Condition::Before(ts_ms))
+    }
+
+    pub fn title_contains(self, text: &str) -> Self {
+        self.with(Condition::TitleContains(text.to_string()))
+    }
+
+    pub fn app(self, app_name: &str) -> Self {
+        self.with(Condition::AppEquals(app_name.to_string()))
+    }
+
+    pub fn app_id(self, app_id: &str) -> Self {
+        self.with(Condition::AppId(app_id.to_string()))
+    }
+
+    /// Captures with any of `event_types`; an empty set matches nothing.
+    pub fn event_types<I, S>(self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.with(Condition::EventTypeIn(
+            event_types.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    pub fn capture_method(self, method: &str) -> Self {
+        self.with(Condition::CaptureMethod(method.to_string()))
+    }
+
+    pub fn mic_active(self, active: bool) -> Self {
+        self.with(Condition::MicActive(active))
+    }
+
+    pub fn cam_active(self, active: bool) -> Self {
+        self.with(Condition::CamActive(active))
+    }
+
+    pub fn workspace(self, workspace: &str) -> Self {
+        self.with(Condition::Workspace(workspace.to_string()))
+    }
+
+    /// Captures whose OCR text was (or wasn't) judged code; those without
+    /// OCR text match neither.
+    pub fn contains_code(self, code: bool) -> Self {
+        self.with(Condition::ContainsCode(code))
+    }
+
+    pub fn capture_group(self, group_id: &str) -> Self {
+        self.with(Condition::CaptureGroup(group_id.to_string()))
+    }
+
+    /// Captures with at least `level` of `activity_level`; untracked ones
+    /// match no level.
+    pub fn min_activity(self, level: u8) -> Self {
+        self.with(Condition::MinActivity(level))
+    }
+
+    /// Leaves out captures flagged `blank`.
+    pub fn exclude_blank(self) -> Self {
+        self.with(Condition::ExcludeBlank)
+    }
+
+    fn with(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// The WHERE clause, with one positional `?` per value, and the values
+    /// to bind in order.
+    pub fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut clauses = vec![match self.deleted {
+            DeletedState::Live => "deleted = 0".to_string(),
+            DeletedState::Deleted => "deleted = 1".to_string(),
+            DeletedState::Any => "1".to_string(),
+        }];
+        let mut values = Vec::new();
+        for condition in &self.conditions {
+            let text = |value: &String| Value::Text(value.clone());
+            let (clause, bound) = match condition {
+                Condition::Since(ts) => ("ts >= ?".to_string(), vec![Value::Integer(*ts)]),
+                Condition::Before(ts) => ("ts < ?".to_string(), vec![Value::Integer(*ts)]),
+                Condition::TitleContains(needle) => (
+                    "instr(lower(window_title), lower(?)) > 0".to_string(),
+                    vec![text(needle)],
+                ),
+                Condition::AppEquals(app) => ("app_name = ?".to_string(), vec![text(app)]),
+                Condition::AppId(app_id) => ("app_id = ?".to_string(), vec![text(app_id)]),
+                Condition::EventTypeIn(types) if types.is_empty() => ("0".to_string(), vec![]),
+                Condition::EventTypeIn(types) => (
+                    format!("event_type IN ({})", vec!["?"; types.len()].join(", ")),
+                    types.iter().map(text).collect(),
+                ),
+                Condition::CaptureMethod(method) => {
+                    ("capture_method = ?".to_string(), vec![text(method)])
+                }
+                Condition::MicActive(active) => {
+                    ("mic_active = ?".to_string(), vec![Value::Integer(*active as i64)])
+                }
+                Condition::CamActive(active) => {
+                    ("cam_active = ?".to_string(), vec![Value::Integer(*active as i64)])
+                }
+                Condition::Workspace(workspace) => {
+                    ("workspace = ?".to_string(), vec![text(workspace)])
+                }
+                Condition::ContainsCode(code) => {
+                    ("contains_code = ?".to_string(), vec![Value::Integer(*code as i64)])
+                }
+                Condition::CaptureGroup(group_id) => {
+                    ("capture_group_id = ?".to_string(), vec![text(group_id)])
+                }
+                Condition::MinActivity(level) => (
+                    "activity_level >= ?".to_string(),
+                    vec![Value::Integer(i64::from(*level))],
+                ),
+                Condition::ExcludeBlank => ("blank = 0".to_string(), vec![]),
+            };
+            clauses.push(clause);
+            values.extend(bound);
+        }
+        (clauses.join(" AND "), values)
+    }
+}
+
+const CAPTURE_COLUMNS: &str = "id, ts, window_title, app_name, event_type, path, width, height, \
+     monitor, hash, capture_group_id, rel_path, clipboard_text, prev_title, original_path, \
+     ocr_text, capture_method, mic_active, cam_active, codec, blank, importance, ocr_lines, \
+     trigger_path, doc_path, workspace, pack_path, pack_offset, pack_len, ax_text, \
+     contains_code, app_id, color_profile, keys_per_min, clicks_per_min, \
+     trigger_detail, activity_level";
+
+/// A capture as `Db::capture_apps` lists it, for usage statistics.
+#[derive(Debug, Clone)]
+pub struct CaptureApp {
+    pub id: String,
+    pub ts: i64,
+    pub app_name: Option<String>,
+    /// `None` where input counts weren't tracked.
+    pub input: Option<InputCounts>,
+}
+
+/// Reads a `CAPTURE_COLUMNS` row, opening sealed text with `key`.
+fn capture_from_row(row: &Row, key: Option<&TextKey>) -> rusqlite::Result<CaptureRecord> {
+    Ok(CaptureRecord {
+        id: row.get(0)?,
+        ts: DateTime::<Utc>::from_timestamp_millis(row.get::<_, i64>(1)?)
+            .unwrap_or_else(Utc::now),
+        window_title: row.get(2)?,
+        app_name: row.get(3)?,
+        event_type: row.get(4)?,
+        path: row.get(5)?,
+        width: row.get::<_, Option<i64>>(6)?.map(|v| v as u32),
+        height: row.get::<_, Option<i64>>(7)?.map(|v| v as u32),
+        monitor: row.get(8)?,
+        hash: row.get(9)?,
+        capture_group_id: row.get(10)?,
+        rel_path: row.get(11)?,
+        clipboard_text: crypto::open_text(row.get(12)?, key),
+        prev_title: row.get(13)?,
+        original_path: row.get(14)?,
+        ocr_text: crypto::open_text(row.get(15)?, key),
+        capture_method: row.get(16)?,
+        mic_active: row.get(17)?,
+        cam_active: row.get(18)?,
+        codec: row.get::<_, String>(19)?.parse().unwrap_or_default(),
+        blank: row.get(20)?,
+        importance: row.get(21)?,
+        ocr_lines: crypto::open_text(row.get(22)?, key)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        trigger_path: row.get(23)?,
+        doc_path: row.get(24)?,
+        workspace: row.get(25)?,
+        pack: match row.get::<_, Option<String>>(26)? {
+            Some(path) => Some(PackSlot {
+                path,
+                offset: row.get::<_, i64>(27)? as u64,
+                len: row.get::<_, i64>(28)? as u64,
+            }),
+            None => None,
+        },
+        ax_text: crypto::open_text(row.get(29)?, key),
+        contains_code: row.get(30)?,
+        app_id: row.get(31)?,
+        color_profile: row.get(32)?,
+        keys_per_min: row.get(33)?,
+        clicks_per_min: row.get(34)?,
+        trigger_detail: row.get(35)?,
+        activity_level: row.get(36)?,
+    })
+}
+
+/// `ocr_lines` column value: JSON, or NULL when there are none.
+fn ocr_lines_json(lines: &[OcrLine]) -> Option<String> {
+    if lines.is_empty() {
+        return None;
+    }
+    serde_json::to_string(lines).ok()
+}
+
+/// Appends to the `changes` feed; called inside the transaction making
+/// the change, so the feed never disagrees with the captures table.
+fn record_change(conn: &Connection, capture_id: &str, kind: ChangeKind) -> AppResult<()> {
+    conn.execute(
+        "INSERT INTO changes (capture_id, kind, ts) VALUES (?1, ?2, ?3)",
+        params![capture_id, kind.as_str(), Utc::now().timestamp_millis()],
+    )?;
+    Ok(())
+}
+
+/// What happened to a capture, as recorded in the `changes` feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    /// Soft-deleted; the row itself goes later, on `compact`.
+    Delete,
+    /// Dimensions, OCR or the code flag rewritten by a reprocess.
+    Update,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Insert => "insert",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Update => "update",
+        }
+    }
+}
+
+/// One entry of the `changes` feed. `seq` only ever grows, so sync tools
+/// can resume from the last one they saw.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChangeEntry {
+    pub seq: i64,
+    pub capture_id: String,
+    pub kind: String,
+    pub ts: DateTime<Utc>,
+}
+
+/// Daemon lifecycle marker (start, pause, lock, ...) used to explain gaps.
+#[derive(Debug, Clone)]
+pub struct DaemonEvent {
+    pub ts: i64,
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceOp {
+    Vacuum,
+    Analyze,
+}
+
+impl MaintenanceOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MaintenanceOp::Vacuum => "vacuum",
+            MaintenanceOp::Analyze => "analyze",
+        }
+    }
+}
+
+/// Outcome of a `VACUUM` or `ANALYZE` run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MaintenanceReport {
+    pub op: &'static str,
+    pub elapsed_ms: u64,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// Outcome of `Db::compact`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompactReport {
+    /// Soft-deleted rows removed for good.
+    pub purged: usize,
+    /// Soft-deleted rows kept because their image is still on disk.
+    pub kept: usize,
+    pub size_before: u64,
+    pub size_after: u64,
 }
 
 pub struct Db {
     path: PathBuf,
     conn: Connection,
+    /// Opens sealed text columns; set whenever a passphrase is configured.
+    text_key: Option<Arc<TextKey>>,
+    /// Seal text columns on write (`encrypt_text`).
+    seal_text: bool,
 }
 
 impl Db {
@@ -31,15 +654,146 @@ impl Db {
             std::fs::create_dir_all(parent)?;
         }
         let conn = Connection::open(path)?;
+        // Wait out short locks held by API readers or maintenance instead
+        // of failing with SQLITE_BUSY.
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
         let db = Self {
             path: path.to_path_buf(),
             conn,
+            text_key: None,
+            seal_text: false,
         };
         db.init()?;
         Ok(db)
     }
 
+    /// A second connection to the same database, with the same text key,
+    /// for use on another thread.
+    pub fn reopen(&self) -> AppResult<Self> {
+        let mut db = Self::new(&self.path)?;
+        db.text_key = self.text_key.clone();
+        db.seal_text = self.seal_text;
+        Ok(db)
+    }
+
+    /// `new` for `config.db_path`, with its text encryption settings.
+    pub fn open(config: &CaptureConfig) -> AppResult<Self> {
+        let mut db = Self::new(&config.db_path)?;
+        db.set_text_key(config.original_passphrase.as_deref(), config.encrypt_text)?;
+        Ok(db)
+    }
+
+    /// Derives the key for sealed text columns from `passphrase` and this
+    /// database's salt, created on first use. `seal` also encrypts text
+    /// written from now on; rows stored in plaintext stay readable.
+    pub fn set_text_key(&mut self, passphrase: Option<&str>, seal: bool) -> AppResult<()> {
+        self.text_key = match passphrase {
+            Some(passphrase) => Some(TextKey::derive(passphrase, &self.text_salt()?)),
+            None => None,
+        };
+        self.seal_text = seal && self.text_key.is_some();
+        Ok(())
+    }
+
+    pub fn text_key(&self) -> Option<&TextKey> {
+        self.text_key.as_deref()
+    }
+
+    /// Shared handle to the text key, for `SearchIndex`.
+    pub fn shared_text_key(&self) -> Option<Arc<TextKey>> {
+        self.text_key.clone()
+    }
+
+    fn text_salt(&self) -> AppResult<Vec<u8>> {
+        let select = "SELECT value FROM meta WHERE key = 'text_salt'";
+        if let Some(salt) = self.conn.query_row(select, [], |row| row.get(0)).optional()? {
+            return Ok(salt);
+        }
+        // Another process may have raced us to it; whichever salt landed
+        // first is the one everybody uses.
+        self.conn.execute(
+            "INSERT OR IGNORE INTO meta (key, value) VALUES ('text_salt', ?1)",
+            [&crypto::text_salt()[..]],
+        )?;
+        Ok(self.conn.query_row(select, [], |row| row.get(0))?)
+    }
+
+    /// Column value for sensitive text: sealed when `seal_text` is on.
+    fn sealed(&self, text: Option<&str>) -> Option<String> {
+        match (text, &self.text_key) {
+            (Some(text), Some(key)) if self.seal_text => Some(key.seal(text)),
+            (text, _) => text.map(str::to_string),
+        }
+    }
+
+    /// Refuses a database this binary can't use: another program's, or
+    /// one written by a newer veea. Returns the stored `schema_version`;
+    /// an empty database passes without one and is set up by `init`.
+    fn check_schema(&self) -> AppResult<Option<i64>> {
+        let path = self.path.display();
+        let incompatible = |message: String| {
+            AppError::Db(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_NOTADB),
+                Some(message),
+            ))
+        };
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name FROM sqlite_master
+                 WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+                 ORDER BY name",
+            )
+            .map_err(|e| incompatible(format!("{path} is not a veea database: {e}")))?;
+        let tables = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if tables.is_empty() {
+            return Ok(None);
+        }
+        if !tables.iter().any(|name| name == "captures") {
+            let shown: Vec<&str> = tables.iter().take(5).map(String::as_str).collect();
+            let more = if tables.len() > 5 { ", ..." } else { "" };
+            return Err(incompatible(format!(
+                "{path} is not a veea database: it has tables {}{more} but no captures \
+                 table; point db_path at a new file",
+                shown.join(", ")
+            )));
+        }
+        let mut stmt = self.conn.prepare("PRAGMA table_info(captures)")?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let missing: Vec<&str> = CORE_CAPTURE_COLUMNS
+            .iter()
+            .copied()
+            .filter(|column| !columns.iter().any(|c| c == column))
+            .collect();
+        if !missing.is_empty() {
+            return Err(incompatible(format!(
+                "{path} is not a veea database: its captures table lacks {}",
+                missing.join(", ")
+            )));
+        }
+        if !tables.iter().any(|name| name == "meta") {
+            return Ok(None);
+        }
+        let select = "SELECT value FROM meta WHERE key = 'schema_version'";
+        let version: Option<i64> = self
+            .conn
+            .query_row(select, [], |row| row.get(0))
+            .optional()?;
+        if let Some(version) = version.filter(|&v| v > SCHEMA_VERSION) {
+            return Err(incompatible(format!(
+                "{path} was created by a newer veea (v{version}, this build reads up to \
+                 v{SCHEMA_VERSION}); please upgrade"
+            )));
+        }
+        Ok(version)
+    }
+
     fn init(&self) -> AppResult<()> {
+        let version = self.check_schema()?;
         self.conn.execute_batch(
             r#"
             CREATE TABLE IF NOT EXISTS captures (
@@ -56,18 +810,135 @@ impl Db {
                 deleted INTEGER DEFAULT 0
             );
             CREATE INDEX IF NOT EXISTS captures_ts_idx ON captures(ts);
+            CREATE TABLE IF NOT EXISTS daemon_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                ts INTEGER NOT NULL,
+                kind TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS daemon_events_ts_idx ON daemon_events(ts);
+            CREATE TABLE IF NOT EXISTS capture_attempts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                ts INTEGER NOT NULL,
+                event_type TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                detail TEXT
+            );
+            CREATE INDEX IF NOT EXISTS capture_attempts_ts_idx ON capture_attempts(ts);
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS changes (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                capture_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                ts INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS changes_ts_idx ON changes(ts);
         "#,
         )?;
+        self.ensure_column("captures", "capture_group_id", "TEXT")?;
+        self.ensure_column("captures", "rel_path", "TEXT")?;
+        self.ensure_column("captures", "clipboard_text", "TEXT")?;
+        self.ensure_column("captures", "prev_title", "TEXT")?;
+        self.ensure_column("captures", "original_path", "TEXT")?;
+        self.ensure_column("captures", "ocr_text", "TEXT")?;
+        self.ensure_column("captures", "capture_method", "TEXT")?;
+        self.ensure_column("captures", "mic_active", "INTEGER")?;
+        self.ensure_column("captures", "cam_active", "INTEGER")?;
+        self.ensure_column("captures", "codec", "TEXT NOT NULL DEFAULT 'png'")?;
+        self.ensure_column("captures", "blank", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("captures", "importance", "REAL")?;
+        self.ensure_column("captures", "ocr_lines", "TEXT")?;
+        self.ensure_column("captures", "trigger_path", "TEXT")?;
+        self.ensure_column("captures", "doc_path", "TEXT")?;
+        self.ensure_column("captures", "workspace", "TEXT")?;
+        self.ensure_column("captures", "pack_path", "TEXT")?;
+        self.ensure_column("captures", "pack_offset", "INTEGER")?;
+        self.ensure_column("captures", "pack_len", "INTEGER")?;
+        self.ensure_column("captures", "ax_text", "TEXT")?;
+        self.ensure_column("captures", "contains_code", "INTEGER")?;
+        self.ensure_column("captures", "app_id", "TEXT")?;
+        self.ensure_column("captures", "color_profile", "TEXT")?;
+        self.ensure_column("captures", "keys_per_min", "INTEGER")?;
+        self.ensure_column("captures", "clicks_per_min", "INTEGER")?;
+        self.ensure_column("captures", "trigger_detail", "TEXT")?;
+        self.ensure_column("captures", "activity_level", "INTEGER")?;
+        if self.ensure_column("captures", "title_key", "TEXT")? {
+            self.backfill_title_keys()?;
+        }
+        if self.ensure_column("captures", "sync_seq", "INTEGER")? {
+            self.conn.execute_batch("UPDATE captures SET sync_seq = rowid")?;
+        }
+        self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS captures_title_key_idx ON captures(title_key, ts);
+             DROP INDEX IF EXISTS captures_id_order_idx;
+             CREATE UNIQUE INDEX IF NOT EXISTS captures_sync_seq_idx ON captures(sync_seq);
+             CREATE INDEX IF NOT EXISTS captures_app_key_idx ON captures(lower(app_name), ts);
+             CREATE INDEX IF NOT EXISTS captures_app_id_idx ON captures(app_id, ts);
+             CREATE INDEX IF NOT EXISTS captures_rel_path_idx ON captures(rel_path);
+             CREATE INDEX IF NOT EXISTS captures_group_idx ON captures(capture_group_id);",
+        )?;
+        if version != Some(SCHEMA_VERSION) {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('schema_version', ?1)",
+                [SCHEMA_VERSION],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fills `title_key` for captures stored before the column existed.
+    fn backfill_title_keys(&self) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut select = tx.prepare(
+                "SELECT id, window_title, app_name FROM captures WHERE window_title IS NOT NULL",
+            )?;
+            let mut update = tx.prepare("UPDATE captures SET title_key = ?1 WHERE id = ?2")?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let title: String = row.get(1)?;
+                let app: Option<String> = row.get(2)?;
+                update.execute(params![story::title_key(&title, app.as_deref()), id])?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Adds a column to an existing table if an older database lacks it.
+    /// Returns whether it had to be added.
+    fn ensure_column(&self, table: &str, column: &str, decl: &str) -> AppResult<bool> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(Result::ok)
+            .any(|name| name == column);
+        if !exists {
+            self.conn
+                .execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+        }
+        Ok(!exists)
+    }
+
     pub fn insert_capture(&self, record: &CaptureRecord) -> AppResult<()> {
-        self.conn.execute(
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
             r#"
             INSERT INTO captures (
                 id, ts, window_title, app_name, event_type, path,
-                width, height, monitor, hash, deleted
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 0)
+                width, height, monitor, hash, capture_group_id, rel_path,
+                clipboard_text, prev_title, original_path, ocr_text, title_key, capture_method,
+                mic_active, cam_active, codec, blank, importance, ocr_lines, trigger_path, doc_path,
+                workspace, pack_path, pack_offset, pack_len, ax_text, contains_code, app_id,
+                color_profile, keys_per_min, clicks_per_min, trigger_detail, activity_level,
+                deleted, sync_seq
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
+                ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, 0,
+                (SELECT COALESCE(MAX(sync_seq), 0) + 1 FROM captures))
             "#,
             params![
                 record.id,
@@ -80,8 +951,41 @@ impl Db {
                 record.height.map(|h| h as i64),
                 record.monitor,
                 record.hash,
+                record.capture_group_id,
+                record.rel_path,
+                self.sealed(record.clipboard_text.as_deref()),
+                record.prev_title,
+                record.original_path,
+                self.sealed(record.ocr_text.as_deref()),
+                record
+                    .window_title
+                    .as_deref()
+                    .and_then(|t| story::title_key(t, record.app_name.as_deref())),
+                record.capture_method,
+                record.mic_active,
+                record.cam_active,
+                record.codec.as_str(),
+                record.blank,
+                record.importance,
+                self.sealed(ocr_lines_json(&record.ocr_lines).as_deref()),
+                record.trigger_path,
+                record.doc_path,
+                record.workspace,
+                record.pack.as_ref().map(|slot| &slot.path),
+                record.pack.as_ref().map(|slot| slot.offset as i64),
+                record.pack.as_ref().map(|slot| slot.len as i64),
+                self.sealed(record.ax_text.as_deref()),
+                record.contains_code,
+                record.app_id,
+                record.color_profile,
+                record.keys_per_min,
+                record.clicks_per_min,
+                record.trigger_detail,
+                record.activity_level,
             ],
         )?;
+        record_change(&tx, &record.id, ChangeKind::Insert)?;
+        tx.commit()?;
         Ok(())
     }
 
@@ -90,33 +994,47 @@ impl Db {
     }
 
     pub fn open_reader(&self) -> AppResult<Connection> {
-        Connection::open(&self.path).map_err(Into::into)
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        Ok(conn)
     }
 
-    pub fn list_recent(&self, limit: usize) -> AppResult<Vec<CaptureRecord>> {
+    /// Newest captures matching `filter` first.
+    pub fn list_recent(
+        &self,
+        limit: usize,
+        offset: usize,
+        filter: &CaptureFilter,
+    ) -> AppResult<Vec<CaptureRecord>> {
+        self.list_filtered(&filter.into(), limit, offset)
+    }
+
+    /// Total number of rows `list_recent` pages over.
+    pub fn count_recent(&self, filter: &CaptureFilter) -> AppResult<usize> {
+        self.count_filtered(&filter.into())
+    }
+
+    /// Newest captures matching `filter` first.
+    pub fn list_filtered(
+        &self,
+        filter: &QueryFilter,
+        limit: usize,
+        offset: usize,
+    ) -> AppResult<Vec<CaptureRecord>> {
+        let (clause, mut values) = filter.to_sql();
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(offset as i64));
         let conn = self.open_reader()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, ts, window_title, app_name, event_type, path, width, height, monitor, hash
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
              FROM captures
-             WHERE deleted = 0
+             WHERE {clause}
              ORDER BY ts DESC
-             LIMIT ?1",
-        )?;
+             LIMIT ? OFFSET ?"
+        ))?;
 
-        let rows = stmt.query_map([limit as u32], |row| {
-            Ok(CaptureRecord {
-                id: row.get(0)?,
-                ts: DateTime::<Utc>::from_timestamp_millis(row.get::<_, i64>(1)?)
-                    .unwrap_or_else(Utc::now),
-                window_title: row.get(2)?,
-                app_name: row.get(3)?,
-                event_type: row.get(4)?,
-                path: row.get(5)?,
-                width: row.get::<_, Option<i64>>(6)?.map(|v| v as u32),
-                height: row.get::<_, Option<i64>>(7)?.map(|v| v as u32),
-                monitor: row.get(8)?,
-                hash: row.get(9)?,
-            })
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            capture_from_row(row, self.text_key())
         })?;
 
         let mut results = Vec::new();
@@ -126,56 +1044,1472 @@ impl Db {
         Ok(results)
     }
 
+    /// Up to `limit` live captures stored after capture `id`, for
+    /// consumers syncing incrementally: pass the last id seen, or "" to
+    /// start. Captures are paged in the order their rows were committed,
+    /// not by id or time, as the image writer may commit a capture after
+    /// newer ones; a capture committed after a page was read is always on
+    /// a later page. Fails for an id that was never stored or has been
+    /// purged by `compact`.
+    pub fn list_after_id(&self, id: &str, limit: usize) -> AppResult<Vec<CaptureRecord>> {
+        let conn = self.open_reader()?;
+        let after: i64 = if id.is_empty() {
+            0
+        } else {
+            conn.query_row("SELECT sync_seq FROM captures WHERE id = ?1", [id], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| AppError::Capture(format!("no capture {id} to page after")))?
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND sync_seq > ?1
+             ORDER BY sync_seq
+             LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(params![after, limit as i64], |row| {
+            capture_from_row(row, self.text_key())
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Total number of rows `list_filtered` pages over.
+    pub fn count_filtered(&self, filter: &QueryFilter) -> AppResult<usize> {
+        let (clause, values) = filter.to_sql();
+        let conn = self.open_reader()?;
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM captures WHERE {clause}"),
+            params_from_iter(values),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
     pub fn get_capture(&self, id: &str) -> AppResult<Option<CaptureRecord>> {
         let conn = self.open_reader()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, ts, window_title, app_name, event_type, path, width, height, monitor, hash
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
              FROM captures
              WHERE id = ?1 AND deleted = 0
-             LIMIT 1",
-        )?;
+             LIMIT 1"
+        ))?;
 
         let mut rows = stmt.query([id])?;
         if let Some(row) = rows.next()? {
-            let record = CaptureRecord {
-                id: row.get(0)?,
-                ts: DateTime::<Utc>::from_timestamp_millis(row.get::<_, i64>(1)?)
-                    .unwrap_or_else(Utc::now),
-                window_title: row.get(2)?,
-                app_name: row.get(3)?,
-                event_type: row.get(4)?,
-                path: row.get(5)?,
-                width: row.get::<_, Option<i64>>(6)?.map(|v| v as u32),
-                height: row.get::<_, Option<i64>>(7)?.map(|v| v as u32),
-                monitor: row.get(8)?,
-                hash: row.get(9)?,
-            };
-            return Ok(Some(record));
+            return Ok(Some(capture_from_row(row, self.text_key())?));
         }
 
         Ok(None)
     }
 
-    pub fn delete_recent(&self, minutes: i64) -> AppResult<usize> {
-        let conn = Connection::open(&self.path)?;
+    pub fn delete_recent(&self, minutes: i64, capture_dir: &Path) -> AppResult<Vec<String>> {
         let threshold = (Utc::now() - Duration::minutes(minutes)).timestamp_millis();
+        self.delete_filtered(&QueryFilter::new().since(threshold), capture_dir)
+    }
 
-        let mut stmt = conn.prepare(
-            "SELECT id, path FROM captures WHERE ts >= ?1 AND deleted = 0",
-        )?;
+    /// Soft-deletes the live captures matching `filter` and removes their
+    /// image files, except content-addressed ones other live captures
+    /// still share. Packed images stay in their pack as garbage until
+    /// `pack::compact` rewrites it. Returns the ids deleted.
+    pub fn delete_filtered(
+        &self,
+        filter: &QueryFilter,
+        capture_dir: &Path,
+    ) -> AppResult<Vec<String>> {
+        let (clause, values) = filter.clone().deleted(DeletedState::Live).to_sql();
+        let conn = Connection::open(&self.path)?;
+
+        // Rows and their change entries are committed together before any
+        // file goes, so a failure never leaves a capture without its image.
+        let tx = conn.unchecked_transaction()?;
+        let records = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT {CAPTURE_COLUMNS} FROM captures WHERE {clause}"
+            ))?;
+            let rows = stmt.query_map(params_from_iter(values), |row| {
+                capture_from_row(row, self.text_key())
+            })?;
+            let mut update = tx.prepare("UPDATE captures SET deleted = 1 WHERE id = ?1")?;
+            let mut records = Vec::new();
+            for row in rows {
+                let record = row?;
+                update.execute([&record.id])?;
+                record_change(&tx, &record.id, ChangeKind::Delete)?;
+                records.push(record);
+            }
+            records
+        };
+        tx.commit()?;
+
+        for record in &records {
+            let _refs = objects::lock_for(capture_dir, record)?;
+            if record.pack.is_none()
+                && !self.image_file_in_use(record)?
+                && let Some(path) = record.resolve_path(capture_dir)
+            {
+                let _ = std::fs::remove_file(path);
+            }
+            if let Some(original) = &record.original_path {
+                let _ = std::fs::remove_file(original);
+            }
+        }
+
+        Ok(records.into_iter().map(|record| record.id).collect())
+    }
 
-        let rows = stmt.query_map([threshold], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    /// Scored, non-blank live captures in `[from, to)`, highest score first,
+    /// at most `limit` of them when given.
+    pub fn scored_captures(
+        &self,
+        from: i64,
+        to: i64,
+        limit: Option<usize>,
+    ) -> AppResult<Vec<CaptureRecord>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND blank = 0 AND importance IS NOT NULL
+               AND ts >= ?1 AND ts < ?2
+             ORDER BY importance DESC, ts ASC
+             LIMIT ?3"
+        ))?;
+        // A negative LIMIT is none at all.
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let rows = stmt.query_map([from, to, limit], |row| {
+            capture_from_row(row, self.text_key())
         })?;
 
-        let mut deleted = 0;
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// Live captures stored as `codec`, oldest first.
+    pub fn captures_with_codec(&self, codec: ImageFormat) -> AppResult<Vec<CaptureRecord>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND codec = ?1
+             ORDER BY ts ASC"
+        ))?;
+        let rows =
+            stmt.query_map([codec.as_str()], |row| capture_from_row(row, self.text_key()))?;
+
+        let mut results = Vec::new();
         for row in rows {
-            let (id, path) = row?;
-            let _ = std::fs::remove_file(&path);
-            conn.execute("UPDATE captures SET deleted = 1 WHERE id = ?1", [id])?;
-            deleted += 1;
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// Points capture `id` at a re-encoded copy of its image.
+    pub fn set_image_file(
+        &self,
+        id: &str,
+        path: &Path,
+        capture_dir: &Path,
+        codec: ImageFormat,
+    ) -> AppResult<()> {
+        let rel_path = relative_capture_path(path, capture_dir);
+        self.conn.execute(
+            "UPDATE captures SET path = ?1, rel_path = ?2, codec = ?3 WHERE id = ?4",
+            params![
+                path.to_string_lossy(),
+                rel_path.map(|rel| rel.to_string_lossy().to_string()),
+                codec.as_str(),
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Whether a live capture other than `record` has its image in
+    /// `record`'s file, as content-addressed captures of identical images
+    /// do. The file must stay until the last of them is deleted.
+    pub fn image_file_in_use(&self, record: &CaptureRecord) -> AppResult<bool> {
+        let Some(rel_path) = &record.rel_path else {
+            return Ok(false);
+        };
+        let in_use = self.conn.query_row(
+            &format!(
+                "SELECT EXISTS(
+                     SELECT 1 FROM captures
+                     WHERE rel_path = ?1 AND id != ?2 AND pack_path IS NULL
+                       AND {LIVE_CAPTURES}
+                 )"
+            ),
+            params![rel_path, record.id],
+            |row| row.get(0),
+        )?;
+        Ok(in_use)
+    }
+
+    /// Live captures whose image is a file, oldest first. Content-addressed
+    /// captures may share theirs.
+    pub fn loose_captures(&self) -> AppResult<Vec<CaptureRecord>> {
+        self.captures_where("pack_path IS NULL", [])
+    }
+
+    /// Live packed captures, oldest first: all of them, or those in the
+    /// pack at `pack_path`.
+    pub fn packed_captures(&self, pack_path: Option<&str>) -> AppResult<Vec<CaptureRecord>> {
+        match pack_path {
+            Some(path) => self.captures_where("pack_path = ?1", [path]),
+            None => self.captures_where("pack_path IS NOT NULL", []),
         }
+    }
+
+    fn captures_where<P: rusqlite::Params>(
+        &self,
+        clause: &str,
+        params: P,
+    ) -> AppResult<Vec<CaptureRecord>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND {clause}
+             ORDER BY ts ASC"
+        ))?;
+        let rows = stmt.query_map(params, |row| capture_from_row(row, self.text_key()))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The newest live capture of each of `apps`, matched case-insensitively
+    /// by name, in the order asked; `None` for apps never captured.
+    pub fn latest_by_apps(&self, apps: &[String]) -> AppResult<Vec<Option<CaptureRecord>>> {
+        if apps.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = vec!["(?, lower(?))"; apps.len()].join(", ");
+        let values = apps
+            .iter()
+            .enumerate()
+            .flat_map(|(pos, app)| [Value::Integer(pos as i64), Value::Text(app.clone())]);
+        let mut latest = vec![None; apps.len()];
+        for (pos, record) in
+            self.latest_per_app(&format!("VALUES {rows}"), params_from_iter(values))?
+        {
+            latest[pos] = Some(record);
+        }
+        Ok(latest)
+    }
+
+    /// The newest live capture of each of the `limit` most recently seen
+    /// apps, newest first.
+    pub fn latest_of_each_app(&self, limit: usize) -> AppResult<Vec<CaptureRecord>> {
+        let apps = format!(
+            "SELECT ROW_NUMBER() OVER (ORDER BY MAX(ts) DESC) - 1, lower(app_name)
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND app_name IS NOT NULL
+             GROUP BY lower(app_name)
+             ORDER BY MAX(ts) DESC
+             LIMIT ?1"
+        );
+        let latest = self.latest_per_app(&apps, [limit as i64])?;
+        Ok(latest.into_iter().map(|(_, record)| record).collect())
+    }
+
+    /// Joins each `(pos, key)` row of `wanted` to the newest live capture
+    /// whose lowercased app name is `key`, in one query: every lookup is a
+    /// seek on `captures_app_key_idx` from its newest entry. Keys without a
+    /// capture are left out.
+    fn latest_per_app<P: rusqlite::Params>(
+        &self,
+        wanted: &str,
+        params: P,
+    ) -> AppResult<Vec<(usize, CaptureRecord)>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "WITH wanted(pos, key) AS ({wanted})
+             SELECT {CAPTURE_COLUMNS}, wanted.pos
+             FROM wanted
+             JOIN captures ON captures.rowid = (
+                 SELECT rowid FROM captures
+                 WHERE {LIVE_CAPTURES} AND lower(app_name) = wanted.key
+                 ORDER BY ts DESC
+                 LIMIT 1)
+             ORDER BY wanted.pos"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            let pos: i64 = row.get(37)?;
+            Ok((pos as usize, capture_from_row(row, self.text_key())?))
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Points capture `id` at `slot`, or back at its file with `None`, and
+    /// stores the image's `hash`.
+    pub fn set_pack_slot(&self, id: &str, slot: Option<&PackSlot>, hash: &str) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE captures SET pack_path = ?1, pack_offset = ?2, pack_len = ?3, hash = ?4
+             WHERE id = ?5",
+            params![
+                slot.map(|slot| &slot.path),
+                slot.map(|slot| slot.offset as i64),
+                slot.map(|slot| slot.len as i64),
+                hash,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Repoints captures at the slots a pack's compaction copied them to,
+    /// all in one transaction.
+    pub fn move_pack_slots(&self, moves: &[(String, PackSlot)]) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut update = tx.prepare(
+                "UPDATE captures SET pack_path = ?1, pack_offset = ?2, pack_len = ?3 WHERE id = ?4",
+            )?;
+            for (id, slot) in moves {
+                update.execute(params![slot.path, slot.offset as i64, slot.len as i64, id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Bytes of live images in each pack, keyed by its path relative to
+    /// `capture_dir`.
+    pub fn pack_usage(&self) -> AppResult<HashMap<String, u64>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT pack_path, SUM(pack_len) FROM captures
+             WHERE {LIVE_CAPTURES} AND pack_path IS NOT NULL
+             GROUP BY pack_path"
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        Ok(rows.collect::<Result<HashMap<_, _>, _>>()?)
+    }
+
+    /// Stores the fields `CaptureEngine::reprocess` recomputes.
+    pub fn update_reprocessed(&self, record: &CaptureRecord) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE captures SET width = ?1, height = ?2, ocr_text = ?3, ocr_lines = ?4,
+                 contains_code = ?5
+             WHERE id = ?6",
+            params![
+                record.width.map(|w| w as i64),
+                record.height.map(|h| h as i64),
+                self.sealed(record.ocr_text.as_deref()),
+                self.sealed(ocr_lines_json(&record.ocr_lines).as_deref()),
+                record.contains_code,
+                record.id
+            ],
+        )?;
+        record_change(&tx, &record.id, ChangeKind::Update)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Points every capture at `new_dir`, keeping its path relative to
+    /// `old_dir`. Checks that up to `sample` of the files already exist at
+    /// the new location first, and rewrites all rows in one transaction.
+    pub fn relocate(&mut self, old_dir: &Path, new_dir: &Path, sample: usize) -> AppResult<usize> {
+        let rows = {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT id, path, rel_path, original_path, pack_path FROM captures")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut moves = Vec::with_capacity(rows.len());
+        for (id, path, rel_path, original_path, pack_path) in rows {
+            let rel = match rel_path {
+                Some(rel) => PathBuf::from(rel),
+                None => relative_capture_path(Path::new(&path), old_dir).ok_or_else(|| {
+                    AppError::Relocate(format!("cannot place '{path}' under {}", old_dir.display()))
+                })?,
+            };
+            let new_path = new_dir.join(&rel);
+            // Encrypted originals always sit next to their served image.
+            let new_original = original_path.map(|_| {
+                let mut name = new_path.clone().into_os_string();
+                name.push(".orig.enc");
+                PathBuf::from(name)
+            });
+            // Packed images live in their pack, which moves with the rest
+            // of `capture_dir`.
+            let stored = pack_path.map_or_else(|| new_path.clone(), |pack| new_dir.join(pack));
+            moves.push((id, new_path, rel, new_original, stored));
+        }
+
+        let step = (moves.len() / sample.max(1)).max(1);
+        for (_, _, _, _, stored) in moves.iter().step_by(step).take(sample) {
+            if !stored.exists() {
+                return Err(AppError::Relocate(format!(
+                    "{} not found; move the files before relocating",
+                    stored.display()
+                )));
+            }
+        }
+
+        let tx = self.conn.transaction()?;
+        {
+            let mut update = tx.prepare(
+                "UPDATE captures SET path = ?1, rel_path = ?2, original_path = ?3 WHERE id = ?4",
+            )?;
+            for (id, new_path, rel, new_original, _) in &moves {
+                update.execute(params![
+                    new_path.to_string_lossy(),
+                    rel.to_string_lossy(),
+                    new_original.as_ref().map(|p| p.to_string_lossy()),
+                    id
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(moves.len())
+    }
+
+    /// Runs `VACUUM` or `ANALYZE` on this connection and records it as a
+    /// daemon event. The daemon only calls this from its capture loop, so
+    /// inserts queue up behind a vacuum instead of hitting SQLITE_BUSY.
+    pub fn run_maintenance(&self, op: MaintenanceOp) -> AppResult<MaintenanceReport> {
+        let size_before = self.file_size();
+        println!("Running {} on {}...", op.as_str(), self.path.display());
+        let started = Instant::now();
+        match op {
+            MaintenanceOp::Vacuum => self.conn.execute_batch("VACUUM")?,
+            MaintenanceOp::Analyze => self.conn.execute_batch("ANALYZE")?,
+        }
+        let report = MaintenanceReport {
+            op: op.as_str(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            size_before,
+            size_after: self.file_size(),
+        };
+        println!(
+            "{} finished in {}ms ({} -> {} bytes)",
+            op.as_str(),
+            report.elapsed_ms,
+            report.size_before,
+            report.size_after
+        );
+        self.insert_daemon_event(op.as_str())?;
+        Ok(report)
+    }
+
+    /// Feeds each line of `PRAGMA integrity_check` to `on_line` as SQLite
+    /// produces it ("ok" alone means no problems). Stops early once
+    /// `on_line` returns false.
+    pub fn integrity_check(&self, mut on_line: impl FnMut(String) -> bool) -> AppResult<()> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if !on_line(row.get(0)?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hard-deletes soft-deleted rows whose files are gone or belong to
+    /// live captures sharing them, then vacuums.
+    /// Refuses to touch a database that fails `PRAGMA quick_check`, so a
+    /// damaged file isn't rewritten into a worse one.
+    pub fn compact(&self, capture_dir: &Path) -> AppResult<CompactReport> {
+        let check: String = self.conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" {
+            return Err(AppError::Integrity(check));
+        }
+        let size_before = self.file_size();
+
+        let tx = self.conn.unchecked_transaction()?;
+        let (purged, kept) = {
+            let mut select = tx.prepare(&format!(
+                "SELECT {CAPTURE_COLUMNS} FROM captures WHERE deleted = 1"
+            ))?;
+            let mut delete = tx.prepare("DELETE FROM captures WHERE id = ?1")?;
+            let mut rows = select.query([])?;
+            let (mut purged, mut kept) = (0, 0);
+            while let Some(row) = rows.next()? {
+                let record = capture_from_row(row, self.text_key())?;
+                let original_exists = record
+                    .original_path
+                    .as_ref()
+                    .is_some_and(|p| Path::new(p).exists());
+                let image_kept = record.image_exists(capture_dir)
+                    && (record.pack.is_some() || !self.image_file_in_use(&record)?);
+                if image_kept || original_exists {
+                    kept += 1;
+                    continue;
+                }
+                delete.execute([&record.id])?;
+                purged += 1;
+            }
+            (purged, kept)
+        };
+        tx.commit()?;
+
+        self.run_maintenance(MaintenanceOp::Vacuum)?;
+        Ok(CompactReport {
+            purged,
+            kept,
+            size_before,
+            size_after: self.file_size(),
+        })
+    }
+
+    fn file_size(&self) -> u64 {
+        std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// Timestamp of the most recent daemon event of `kind`.
+    pub fn last_daemon_event(&self, kind: &str) -> AppResult<Option<i64>> {
+        let ts = self.conn.query_row(
+            "SELECT MAX(ts) FROM daemon_events WHERE kind = ?1",
+            [kind],
+            |row| row.get(0),
+        )?;
+        Ok(ts)
+    }
+
+    /// Logs a capture that didn't happen, with why. Titles are left out on
+    /// purpose since skipped windows are often the sensitive ones.
+    pub fn insert_attempt(
+        &self,
+        event_type: &str,
+        outcome: &str,
+        detail: Option<&str>,
+    ) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT INTO capture_attempts (ts, event_type, outcome, detail) VALUES (?1, ?2, ?3, ?4)",
+            params![Utc::now().timestamp_millis(), event_type, outcome, detail],
+        )?;
+        Ok(())
+    }
+
+    /// Up to `limit` entries of the `changes` feed after `since_seq`,
+    /// oldest first.
+    pub fn list_changes(&self, since_seq: i64, limit: usize) -> AppResult<Vec<ChangeEntry>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, capture_id, kind, ts FROM changes WHERE seq > ?1 ORDER BY seq ASC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![since_seq, limit as i64], |row| {
+            Ok(ChangeEntry {
+                seq: row.get(0)?,
+                capture_id: row.get(1)?,
+                kind: row.get(2)?,
+                ts: DateTime::from_timestamp_millis(row.get(3)?).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Highest `seq` ever written to the `changes` feed, 0 if none.
+    /// Survives pruning, as AUTOINCREMENT never reuses a number.
+    pub fn max_change_seq(&self) -> AppResult<i64> {
+        let select = "SELECT seq FROM sqlite_sequence WHERE name = 'changes'";
+        let seq = self
+            .conn
+            .query_row(select, [], |row| row.get(0))
+            .optional()?;
+        Ok(seq.unwrap_or(0))
+    }
+
+    /// Highest `seq` removed by `prune_changes`, 0 if nothing was. Readers
+    /// behind it have missed entries.
+    pub fn pruned_change_seq(&self) -> AppResult<i64> {
+        let select = "SELECT value FROM meta WHERE key = 'changes_pruned_seq'";
+        let seq = self
+            .conn
+            .query_row(select, [], |row| row.get(0))
+            .optional()?;
+        Ok(seq.unwrap_or(0))
+    }
+
+    /// Drops `changes` entries older than `before` (ms), remembering the
+    /// highest seq dropped. Returns how many went.
+    pub fn prune_changes(&self, before: i64) -> AppResult<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let select = "SELECT MAX(seq) FROM changes WHERE ts < ?1";
+        let last: Option<i64> = tx.query_row(select, [before], |row| row.get(0))?;
+        let Some(last) = last else {
+            return Ok(0);
+        };
+        let pruned = tx.execute("DELETE FROM changes WHERE seq <= ?1", [last])?;
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES ('changes_pruned_seq', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = MAX(value, excluded.value)",
+            [last],
+        )?;
+        tx.commit()?;
+        Ok(pruned)
+    }
+
+    pub fn insert_daemon_event(&self, kind: &str) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT INTO daemon_events (ts, kind) VALUES (?1, ?2)",
+            params![Utc::now().timestamp_millis(), kind],
+        )?;
+        Ok(())
+    }
+
+    /// Up to `limit` daemon events of `[from, until)` past the first
+    /// `offset`, oldest first.
+    pub fn list_daemon_events(
+        &self,
+        from: i64,
+        until: i64,
+        limit: usize,
+        offset: usize,
+    ) -> AppResult<Vec<DaemonEvent>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, kind FROM daemon_events WHERE ts >= ?1 AND ts < ?2
+             ORDER BY ts ASC, id ASC
+             LIMIT ?3 OFFSET ?4",
+        )?;
+        let rows = stmt.query_map(params![from, until, limit as i64, offset as i64], |row| {
+            Ok(DaemonEvent {
+                ts: row.get(0)?,
+                kind: row.get(1)?,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// The daemon events before `at` that still tell its state, oldest
+    /// first: the last `start` and, since then, the latest event of each
+    /// on/off pair. A start resets every state, so nothing older matters.
+    pub fn daemon_state_at(&self, at: i64) -> AppResult<Vec<DaemonEvent>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, kind FROM daemon_events WHERE id IN (
+                 SELECT MAX(id) FROM daemon_events
+                 WHERE ts < ?1 AND ts >= (
+                     SELECT COALESCE(MAX(ts), 0) FROM daemon_events
+                     WHERE kind = 'start' AND ts < ?1
+                 )
+                 GROUP BY CASE kind
+                     WHEN 'resume' THEN 'pause'
+                     WHEN 'unlock' THEN 'lock'
+                     WHEN 'display_wake' THEN 'display_sleep'
+                     WHEN 'schedule_resume' THEN 'schedule_pause'
+                     WHEN 'stop' THEN 'start'
+                     ELSE kind
+                 END
+             )
+             ORDER BY ts ASC, id ASC",
+        )?;
+        let rows = stmt.query_map([at], |row| {
+            Ok(DaemonEvent {
+                ts: row.get(0)?,
+                kind: row.get(1)?,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// Completions for `/search/suggest`: distinct titles and app names
+    /// starting with `prefix`, found through the title and app indexes,
+    /// then those containing it among the latest `SUGGEST_SCAN` captures.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> AppResult<Vec<Suggestion>> {
+        let conn = self.open_reader()?;
+        let mut found = Vec::new();
+        let title = story::title_key(prefix, None);
+        // SQLite's lower() only folds ASCII.
+        let app = Some(prefix.trim().to_ascii_lowercase()).filter(|a| !a.is_empty());
+        let queries = [
+            (SuggestionKind::Title, true, title.as_deref(), SUGGEST_TITLE_PREFIX),
+            (SuggestionKind::Title, false, title.as_deref(), SUGGEST_TITLE_CONTAINS),
+            (SuggestionKind::App, true, app.as_deref(), SUGGEST_APP_PREFIX),
+            (SuggestionKind::App, false, app.as_deref(), SUGGEST_APP_CONTAINS),
+        ];
+        for (kind, prefix_match, key, sql) in queries {
+            let Some(key) = key else { continue };
+            let to_suggestion = |row: &Row<'_>| {
+                Ok(Suggestion {
+                    kind,
+                    value: row.get(0)?,
+                    prefix_match,
+                    count: row.get::<_, i64>(1)? as usize,
+                    last_ts: row.get(2)?,
+                })
+            };
+            let mut stmt = conn.prepare_cached(sql)?;
+            let rows = if prefix_match {
+                stmt.query_map(
+                    named_params! { ":key": key, ":candidates": SUGGEST_CANDIDATES },
+                    to_suggestion,
+                )?
+            } else {
+                stmt.query_map(
+                    named_params! {
+                        ":key": key,
+                        ":candidates": SUGGEST_CANDIDATES,
+                        ":scan": SUGGEST_SCAN,
+                    },
+                    to_suggestion,
+                )?
+            };
+            for row in rows {
+                found.push(row?);
+            }
+        }
+        Ok(search::rank_suggestions(found, limit, Utc::now().timestamp_millis()))
+    }
+
+    /// Distinct title keys of live captures, for fuzzy story matching.
+    pub fn title_keys(&self) -> AppResult<Vec<String>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT DISTINCT title_key FROM captures
+             WHERE {LIVE_CAPTURES} AND title_key IS NOT NULL"
+        ))?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        let mut keys = Vec::new();
+        for row in rows {
+            keys.push(row?);
+        }
+        Ok(keys)
+    }
+
+    /// Live captures whose title normalizes to `key`, summarized in one
+    /// pass over their timestamps with days cut in `tz`.
+    pub fn story(&self, key: &str, tz: StorageTimezone) -> AppResult<Option<Story>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, ts, window_title FROM captures
+             WHERE {LIVE_CAPTURES} AND title_key = ?1
+             ORDER BY ts ASC"
+        ))?;
+        let mut rows = stmt.query([key])?;
+
+        let mut story: Option<Story> = None;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let ts: i64 = row.get(1)?;
+            let title: Option<String> = row.get(2)?;
+            let story = story.get_or_insert_with(|| Story {
+                story_id: id,
+                title_key: key.to_string(),
+                title: None,
+                similarity: 1.0,
+                capture_count: 0,
+                first_ts: ts,
+                last_ts: ts,
+                days: Vec::new(),
+            });
+            story.capture_count += 1;
+            story.last_ts = ts;
+            if title.is_some() {
+                story.title = title;
+            }
+            let date = DateTime::from_timestamp_millis(ts)
+                .map(|t| tz.date_of(t).to_string())
+                .unwrap_or_default();
+            match story.days.last_mut() {
+                Some(day) if day.date == date => day.count += 1,
+                _ => story.days.push(DayCount { date, count: 1 }),
+            }
+        }
+        Ok(story)
+    }
+
+    /// Title key of the story that capture `id` belongs to, deleted or not.
+    pub fn story_key(&self, id: &str) -> AppResult<Option<String>> {
+        let conn = self.open_reader()?;
+        let key = conn
+            .query_row("SELECT title_key FROM captures WHERE id = ?1", [id], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .optional()?;
+        Ok(key.flatten())
+    }
+
+    /// A story's live captures, oldest first.
+    pub fn list_story(&self, key: &str, limit: usize, offset: usize) -> AppResult<Vec<CaptureRecord>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS}
+             FROM captures
+             WHERE {LIVE_CAPTURES} AND title_key = ?1
+             ORDER BY ts ASC
+             LIMIT ?2 OFFSET ?3"
+        ))?;
+        let rows = stmt.query_map(params![key, limit as i64, offset as i64], |row| {
+            capture_from_row(row, self.text_key())
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// Total number of rows `list_story` pages over.
+    pub fn count_story(&self, key: &str) -> AppResult<usize> {
+        let conn = self.open_reader()?;
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM captures WHERE {LIVE_CAPTURES} AND title_key = ?1"),
+            [key],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Stretches of `[from, to)` longer than `min_gap_ms` without a capture,
+    /// each labelled with the daemon state that best explains it.
+    pub fn find_gaps(&self, from: i64, to: i64, min_gap_ms: i64) -> AppResult<Vec<Gap>> {
+        let captures = self.capture_timestamps(from, to)?;
+        let mut events = self.daemon_state_at(from)?;
+        let mut read = 0;
+        loop {
+            let page = self.list_daemon_events(from, to, DAEMON_EVENTS_PAGE, read)?;
+            read += page.len();
+            let last = page.len() < DAEMON_EVENTS_PAGE;
+            events.extend(page);
+            if last {
+                break;
+            }
+        }
+        let mut gaps = timeline::find_gaps(&captures, from, to, min_gap_ms);
+        timeline::attribute_causes(&mut gaps, &events);
+        Ok(gaps)
+    }
+
+    /// Live captures per `tz` day in `[from, to)`. Counted in quarter
+    /// hours first: every zone's offset is a whole number of them, so each
+    /// falls within one local day.
+    pub fn daily_counts(
+        &self,
+        from: i64,
+        to: i64,
+        tz: StorageTimezone,
+    ) -> AppResult<BTreeMap<NaiveDate, usize>> {
+        const QUARTER_HOUR_MS: i64 = 15 * 60 * 1000;
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT ts / {QUARTER_HOUR_MS}, COUNT(*) FROM captures
+             WHERE {LIVE_CAPTURES} AND ts >= ?1 AND ts < ?2
+             GROUP BY 1"
+        ))?;
+        let mut rows = stmt.query([from, to])?;
+
+        let mut days = BTreeMap::new();
+        while let Some(row) = rows.next()? {
+            let quarter: i64 = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            if let Some(ts) = DateTime::from_timestamp_millis(quarter * QUARTER_HOUR_MS) {
+                *days.entry(tz.date_of(ts)).or_insert(0) += count as usize;
+            }
+        }
+        Ok(days)
+    }
+
+    /// Filmstrip of `[from, to)` in `bucket_ms` slots. Each slot shows its
+    /// focus capture nearest the middle of the slot's captures, or the
+    /// median capture when it has no focus event.
+    pub fn timeline_strip(&self, from: i64, to: i64, bucket_ms: i64) -> AppResult<Vec<StripBucket>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "WITH bucketed AS (
+                 SELECT id, ts, event_type, (ts - ?1) / ?3 AS bucket
+                 FROM captures
+                 WHERE deleted = 0 AND ts >= ?1 AND ts < ?2
+             ),
+             counts AS (
+                 SELECT bucket, COUNT(*) AS n FROM bucketed GROUP BY bucket
+             ),
+             ranked AS (
+                 SELECT b.bucket, b.id, b.ts, b.event_type, c.n,
+                        ROW_NUMBER() OVER (PARTITION BY b.bucket ORDER BY b.ts, b.id) AS pos
+                 FROM bucketed b JOIN counts c ON c.bucket = b.bucket
+             ),
+             picked AS (
+                 SELECT bucket, id, n,
+                        ROW_NUMBER() OVER (
+                            PARTITION BY bucket
+                            ORDER BY event_type = 'focus' DESC, ABS(2 * pos - n - 1), ts
+                        ) AS choice
+                 FROM ranked
+             )
+             SELECT bucket, n, id FROM picked WHERE choice = 1 ORDER BY bucket",
+        )?;
+        let rows = stmt.query_map([from, to, bucket_ms], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as usize, row.get(2)?))
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(timeline::strip_buckets(results, from, to, bucket_ms))
+    }
+
+    /// The app of every live capture in `[from, to)`, oldest first.
+    pub fn capture_apps(&self, from: i64, to: i64) -> AppResult<Vec<CaptureApp>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, ts, app_name, keys_per_min, clicks_per_min FROM captures
+             WHERE deleted = 0 AND ts >= ?1 AND ts < ?2
+             ORDER BY ts ASC",
+        )?;
+        let rows = stmt.query_map([from, to], |row| {
+            let keys: Option<u32> = row.get(3)?;
+            let clicks: Option<u32> = row.get(4)?;
+            let input = keys
+                .zip(clicks)
+                .map(|(keys, clicks)| InputCounts { keys, clicks });
+            Ok(CaptureApp {
+                id: row.get(0)?,
+                ts: row.get(1)?,
+                app_name: row.get(2)?,
+                input,
+            })
+        })?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+
+    /// `(ts, id)` of every live capture in `[from, to)`, oldest first.
+    pub fn capture_timestamps(&self, from: i64, to: i64) -> AppResult<Vec<(i64, String)>> {
+        let conn = self.open_reader()?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, id FROM captures
+             WHERE deleted = 0 AND ts >= ?1 AND ts < ?2
+             ORDER BY ts ASC",
+        )?;
+        let rows = stmt.query_map([from, to], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            results.push(row?);
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open() -> (tempfile::TempDir, Db) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        (dir, db)
+    }
+
+    fn at(ms: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(ms).unwrap()
+    }
+
+    fn insert(db: &Db, id: &str, ts_ms: i64, adjust: impl FnOnce(&mut CaptureRecord)) {
+        let mut record = CaptureRecord::sample(id, at(ts_ms));
+        adjust(&mut record);
+        db.insert_capture(&record).unwrap();
+    }
+
+    fn ids(db: &Db, filter: &QueryFilter) -> Vec<String> {
+        let mut ids: Vec<String> = db
+            .list_filtered(filter, 100, 0)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn filter_values_are_bound_not_spliced() {
+        let hostile = "x' OR 1=1; DROP TABLE captures; --";
+        let (sql, values) = QueryFilter::new()
+            .title_contains(hostile)
+            .event_types([hostile])
+            .to_sql();
+        assert!(!sql.contains('\''), "{sql}");
+        assert!(!sql.contains("DROP"), "{sql}");
+        let hostile = Value::Text(hostile.to_string());
+        assert_eq!(values, [hostile.clone(), hostile]);
+    }
+
+    #[test]
+    fn title_search_takes_wildcards_literally() {
+        let (_dir, db) = open();
+        for (id, title) in [
+            ("quote", "it's done"),
+            ("percent", "100% done"),
+            ("underscore", "a_b done"),
+            ("nul", "before\0after"),
+            ("plain", "abc done"),
+        ] {
+            insert(&db, id, 1_000, |r| r.window_title = Some(title.to_string()));
+        }
+        let search = |text: &str| ids(&db, &QueryFilter::new().title_contains(text));
+        assert_eq!(search("'"), ["quote"]);
+        assert_eq!(search("%"), ["percent"]);
+        assert_eq!(search("_"), ["underscore"]);
+        assert_eq!(search("' OR '1'='1"), Vec::<String>::new());
+        assert_eq!(search("\0"), ["nul"]);
+        assert_eq!(search("DONE").len(), 4);
+        assert_eq!(db.count_filtered(&QueryFilter::new()).unwrap(), 5);
+    }
+
+    #[test]
+    fn event_types_match_exactly() {
+        let (_dir, db) = open();
+        for (id, event_type) in [("focus", "focus"), ("title", "title"), ("odd", "o'_%")] {
+            insert(&db, id, 1_000, |r| r.event_type = event_type.to_string());
+        }
+        let of = |types: &[&str]| ids(&db, &QueryFilter::new().event_types(types.iter().copied()));
+        assert_eq!(of(&["focus", "title"]), ["focus", "title"]);
+        assert_eq!(of(&["o'_%"]), ["odd"]);
+        assert_eq!(of(&["%"]), Vec::<String>::new());
+        assert_eq!(of(&["focus' OR '1'='1"]), Vec::<String>::new());
+        assert_eq!(of(&["focus\0"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn empty_event_type_set_matches_nothing() {
+        let (_dir, db) = open();
+        insert(&db, "focus", 1_000, |_| {});
+        let filter = QueryFilter::new().event_types(Vec::<String>::new());
+        assert!(filter.to_sql().1.is_empty());
+        assert_eq!(db.count_filtered(&filter).unwrap(), 0);
+        assert!(ids(&db, &filter).is_empty());
+    }
+
+    fn v4(n: u32) -> String {
+        format!("{n:08x}-0000-4000-8000-000000000000")
+    }
+
+    fn v7(n: u32) -> String {
+        format!("{n:08x}-0000-7000-8000-000000000000")
+    }
+
+    /// Every page of `list_after_id` from the start, `limit` at a time.
+    fn pages(db: &Db, limit: usize) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let page: Vec<String> = db
+                .list_after_id(&cursor, limit)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect();
+            let Some(last) = page.last() else {
+                return pages;
+            };
+            cursor = last.clone();
+            pages.push(page);
+        }
+    }
+
+    #[test]
+    fn paging_follows_commit_order() {
+        let (_dir, db) = open();
+        let committed = [v7(3), v4(2), v7(1), v4(0xffff_ffff), v7(2), v4(1)];
+        for id in &committed {
+            insert(&db, id, 1_000, |_| {});
+        }
+        for limit in [1, 2, 4, 6, 100] {
+            assert_eq!(pages(&db, limit).concat(), committed, "limit {limit}");
+        }
+    }
+
+    #[test]
+    fn cursor_sees_captures_committed_out_of_id_order() {
+        let (_dir, db) = open();
+        let after = |id: &str| -> Vec<String> {
+            db.list_after_id(id, 10)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect()
+        };
+        insert(&db, &v7(1), 1_000, |_| {});
+        insert(&db, &v7(3), 3_000, |_| {});
+        assert_eq!(after(""), [v7(1), v7(3)]);
+
+        // Taken before v7(3) but committed after the consumer read it.
+        insert(&db, &v7(2), 2_000, |_| {});
+        assert_eq!(after(&v7(3)), [v7(2)]);
+        assert!(after(&v7(2)).is_empty());
+        assert!(db.list_after_id(&v7(9), 10).is_err());
+    }
+
+    #[test]
+    fn paging_skips_deleted_captures() {
+        let (_dir, db) = open();
+        insert(&db, &v4(1), 1_000, |_| {});
+        insert(&db, &v4(2), 2_000, |_| {});
+        insert(&db, &v7(1), 3_000, |_| {});
+        let dir = tempfile::tempdir().unwrap();
+        let deleted =
+            db.delete_filtered(&QueryFilter::new().since(2_000).before(3_000), dir.path());
+        assert_eq!(deleted.unwrap(), [v4(2)]);
+        assert_eq!(pages(&db, 1).concat(), [v4(1), v7(1)]);
+        // A deleted capture still works as a cursor.
+        let rest: Vec<String> = db
+            .list_after_id(&v4(2), 10)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(rest, [v7(1)]);
+    }
+
+    fn schema_version(path: &Path) -> Option<i64> {
+        Connection::open(path)
+            .unwrap()
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'schema_version'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap()
+    }
+
+    fn refused(path: &Path) -> String {
+        match Db::new(path) {
+            Ok(_) => panic!("{} was accepted", path.display()),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn empty_database_is_set_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("veea.db");
+        std::fs::write(&path, b"").unwrap();
+        Db::new(&path).unwrap();
+        assert_eq!(schema_version(&path), Some(SCHEMA_VERSION));
+        // And opens again as it is.
+        Db::new(&path).unwrap();
+    }
+
+    #[test]
+    fn foreign_database_is_refused_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        let error = refused(&path);
+        assert!(error.contains("not a veea database"), "{error}");
+        assert!(error.contains("users"), "{error}");
+        let tables: i64 = Connection::open(&path)
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 1);
+    }
+
+    #[test]
+    fn foreign_captures_table_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE captures (id TEXT PRIMARY KEY, frame BLOB);")
+            .unwrap();
+        let error = refused(&path);
+        assert!(error.contains("lacks ts, event_type, path"), "{error}");
+    }
+
+    #[test]
+    fn non_sqlite_file_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.db");
+        std::fs::write(&path, "not a database, just some text\n".repeat(100)).unwrap();
+        let error = refused(&path);
+        assert!(error.contains("not a veea database"), "{error}");
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("veea.db");
+        Db::new(&path).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "UPDATE meta SET value = ?1 WHERE key = 'schema_version'",
+                [SCHEMA_VERSION + 1],
+            )
+            .unwrap();
+        let error = refused(&path);
+        assert!(error.contains("newer veea"), "{error}");
+        assert_eq!(schema_version(&path), Some(SCHEMA_VERSION + 1));
+    }
+
+    const MINUTE: i64 = 60_000;
+    /// 2024-06-05 00:00 UTC.
+    const DAY: i64 = 1_717_545_600_000;
+
+    fn strip_ids(strip: &[StripBucket]) -> Vec<Option<&str>> {
+        strip
+            .iter()
+            .map(|b| b.representative_id.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn range_filters_include_from_and_exclude_to() {
+        let (_dir, db) = open();
+        let (from, to) = (10_000, 20_000);
+        for (id, ts) in [
+            ("before", from - 1),
+            ("from", from),
+            ("inside", 15_000),
+            ("last", to - 1),
+            ("to", to),
+        ] {
+            insert(&db, id, ts, |_| {});
+        }
+        let range = QueryFilter::new().since(from).before(to);
+        assert_eq!(ids(&db, &range), ["from", "inside", "last"]);
+        assert_eq!(db.count_filtered(&range).unwrap(), 3);
+
+        let filter = CaptureFilter {
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        };
+        let listed: Vec<String> = db
+            .list_recent(100, 0, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(listed, ["last", "inside", "from"]);
+
+        let empty = QueryFilter::new().since(from).before(from);
+        assert_eq!(ids(&db, &empty), Vec::<String>::new());
+    }
+
+    #[test]
+    fn strip_buckets_start_inclusive_and_end_exclusive() {
+        let (_dir, db) = open();
+        let bucket = 10 * MINUTE;
+        let (from, to) = (DAY, DAY + 3 * bucket);
+        for (id, ts) in [
+            ("before", from - 1),
+            ("first", from),
+            ("end_of_first", from + bucket - 1),
+            ("second", from + bucket),
+            ("last", to - 1),
+            ("after", to),
+        ] {
+            insert(&db, id, ts, |_| {});
+        }
+        let strip = db.timeline_strip(from, to, bucket).unwrap();
+        let starts: Vec<i64> = strip.iter().map(|b| b.start).collect();
+        assert_eq!(starts, [from, from + bucket, from + 2 * bucket]);
+        let counts: Vec<usize> = strip.iter().map(|b| b.count).collect();
+        assert_eq!(counts, [2, 1, 1]);
+        assert_eq!(
+            strip_ids(&strip),
+            [Some("first"), Some("second"), Some("last")]
+        );
+    }
+
+    #[test]
+    fn strip_keeps_empty_and_short_buckets() {
+        let (_dir, db) = open();
+        let bucket = 10 * MINUTE;
+        let (from, to) = (DAY, DAY + 25 * MINUTE);
+        insert(&db, "late", from + 21 * MINUTE, |_| {});
+        let strip = db.timeline_strip(from, to, bucket).unwrap();
+        assert_eq!(strip_ids(&strip), [None, None, Some("late")]);
+        assert_eq!(strip[0].count, 0);
+        assert_eq!(strip[0].thumbnail_url, None);
+        assert_eq!(strip[2].start, from + 20 * MINUTE);
+        assert_eq!(
+            strip[2].thumbnail_url.as_deref(),
+            Some("/captures/late/thumbnail")
+        );
+    }
+
+    #[test]
+    fn strip_prefers_focus_captures_then_the_median() {
+        let (_dir, db) = open();
+        let bucket = 10 * MINUTE;
+        let title = |r: &mut CaptureRecord| r.event_type = "title".to_string();
+        insert(&db, "t1", DAY + MINUTE, title);
+        insert(&db, "t2", DAY + 2 * MINUTE, title);
+        insert(&db, "t3", DAY + 3 * MINUTE, title);
+        insert(&db, "u1", DAY + 11 * MINUTE, title);
+        insert(&db, "u2", DAY + 12 * MINUTE, title);
+        insert(&db, "focus", DAY + 19 * MINUTE, |_| {});
+        let strip = db.timeline_strip(DAY, DAY + 2 * bucket, bucket).unwrap();
+        assert_eq!(strip_ids(&strip), [Some("t2"), Some("focus")]);
+    }
+
+    fn changes(db: &Db, since_seq: i64) -> Vec<(i64, String, String)> {
+        let changes = db.list_changes(since_seq, 100).unwrap();
+        changes
+            .into_iter()
+            .map(|c| (c.seq, c.capture_id, c.kind))
+            .collect()
+    }
+
+    #[test]
+    fn changes_come_in_commit_order() {
+        let (dir, db) = open();
+        insert(&db, "b", 2_000, |_| {});
+        insert(&db, "a", 1_000, |_| {});
+        db.update_reprocessed(&CaptureRecord::sample("b", at(2_000)))
+            .unwrap();
+        let filter = QueryFilter::new().before(1_500);
+        db.delete_filtered(&filter, dir.path()).unwrap();
+        let expected = [
+            (1, "b", "insert"),
+            (2, "a", "insert"),
+            (3, "b", "update"),
+            (4, "a", "delete"),
+        ]
+        .map(|(seq, id, kind)| (seq, id.to_string(), kind.to_string()));
+        assert_eq!(changes(&db, 0), expected);
+        assert_eq!(changes(&db, 2), expected[2..]);
+        let first = db.list_changes(0, 2).unwrap();
+        assert_eq!(first.iter().map(|c| c.seq).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(db.max_change_seq().unwrap(), 4);
+    }
+
+    fn daemon_event(db: &Db, ts: i64, kind: &str) {
+        db.conn
+            .execute(
+                "INSERT INTO daemon_events (ts, kind) VALUES (?1, ?2)",
+                params![ts, kind],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn daemon_state_starts_at_the_last_start() {
+        let (_dir, db) = open();
+        for (ts, kind) in [
+            (100, "pause"),
+            (500, "start"),
+            (1_000, "lock"),
+            (2_000, "pause"),
+            (3_000, "unlock"),
+            (4_000, "on_battery"),
+            (20_000, "resume"),
+        ] {
+            daemon_event(&db, ts, kind);
+        }
+        let state: Vec<(i64, String)> = db
+            .daemon_state_at(10_000)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.ts, e.kind))
+            .collect();
+        let expected = [
+            (500, "start"),
+            (2_000, "pause"),
+            (3_000, "unlock"),
+            (4_000, "on_battery"),
+        ]
+        .map(|(ts, kind)| (ts, kind.to_string()));
+        assert_eq!(state, expected);
+    }
+
+    #[test]
+    fn gaps_read_daemon_events_a_page_at_a_time() {
+        let (_dir, db) = open();
+        daemon_event(&db, 0, "start");
+        daemon_event(&db, 1_000, "pause");
+        for i in 0..DAEMON_EVENTS_PAGE as i64 + 10 {
+            daemon_event(&db, 10_000 + i, "on_battery");
+        }
+        // Past the first page.
+        daemon_event(&db, 30_000, "resume");
+        daemon_event(&db, 31_000, "lock");
+        daemon_event(&db, 59_000, "unlock");
+        insert(&db, "a", 20_000, |_| {});
+        insert(&db, "b", 60_000, |_| {});
+
+        let causes: Vec<_> = db
+            .find_gaps(5_000, 60_001, 1_000)
+            .unwrap()
+            .into_iter()
+            .map(|gap| (gap.start, gap.cause))
+            .collect();
+        assert_eq!(
+            causes,
+            [
+                (5_000, timeline::GapCause::Paused),
+                (20_000, timeline::GapCause::Idle)
+            ]
+        );
+    }
+
+    #[test]
+    fn pruning_keeps_newer_changes_and_the_seq() {
+        let (_dir, db) = open();
+        assert_eq!(db.prune_changes(i64::MAX).unwrap(), 0);
+        assert_eq!(db.pruned_change_seq().unwrap(), 0);
+        for id in ["a", "b", "c"] {
+            insert(&db, id, 1_000, |_| {});
+        }
+        db.conn
+            .execute("UPDATE changes SET ts = seq * 1000", [])
+            .unwrap();
+        assert_eq!(db.prune_changes(2_500).unwrap(), 2);
+        assert_eq!(db.pruned_change_seq().unwrap(), 2);
+        assert_eq!(
+            changes(&db, 0),
+            [(3, "c".to_string(), "insert".to_string())]
+        );
+
+        // An older cutoff never moves the pruned seq back.
+        assert_eq!(db.prune_changes(1_500).unwrap(), 0);
+        assert_eq!(db.pruned_change_seq().unwrap(), 2);
 
-        Ok(deleted)
+        // Nor does emptying the feed reuse a seq.
+        assert_eq!(db.prune_changes(i64::MAX).unwrap(), 1);
+        assert_eq!(db.max_change_seq().unwrap(), 3);
+        insert(&db, "d", 1_000, |_| {});
+        assert_eq!(
+            changes(&db, 3),
+            [(4, "d".to_string(), "insert".to_string())]
+        );
     }
 }
@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+/// How long a device reading is reused. On Linux each one walks the open
+/// files of every process, too much to repeat for a burst of captures.
+const DEVICE_STATE_TTL: Duration = Duration::from_secs(2);
+
+/// Whether the microphone and camera are in use by any process. Only the
+/// open/in-use state is read; no audio or video is touched. Each field is
+/// `None` when the platform doesn't expose that state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceState {
+    pub mic_active: Option<bool>,
+    pub cam_active: Option<bool>,
+}
+
+pub fn device_state() -> DeviceState {
+    platform_device_state()
+}
+
+/// Recent `device_state` reading, so captures close together share one.
+#[derive(Debug, Default)]
+pub struct DeviceStateCache {
+    reading: Option<(Instant, DeviceState)>,
+}
+
+impl DeviceStateCache {
+    pub fn current(&mut self) -> DeviceState {
+        self.get(Instant::now(), device_state)
+    }
+
+    fn get(&mut self, now: Instant, read: impl FnOnce() -> DeviceState) -> DeviceState {
+        match self.reading {
+            Some((at, state)) if now.duration_since(at) < DEVICE_STATE_TTL => state,
+            _ => {
+                let state = read();
+                self.reading = Some((now, state));
+                state
+            }
+        }
+    }
+}
+
+/// A device counts as active while some process holds its node open:
+/// ALSA capture PCMs (`/dev/snd/pcmC*D*c`) for the mic, `/dev/video*` for
+/// the camera. Processes of other users can't be inspected, so an
+/// unprivileged daemon only sees its own user's calls.
+#[cfg(target_os = "linux")]
+fn platform_device_state() -> DeviceState {
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return DeviceState::default();
+    };
+    let (mut mic, mut cam) = (false, false);
+    for proc in procs.flatten() {
+        let Ok(fds) = std::fs::read_dir(proc.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let target = target.to_string_lossy();
+            if let Some(pcm) = target.strip_prefix("/dev/snd/pcm") {
+                mic |= pcm.ends_with('c');
+            } else {
+                cam |= target.starts_with("/dev/video");
+            }
+            if mic && cam {
+                break;
+            }
+        }
+    }
+    DeviceState {
+        mic_active: Some(mic),
+        cam_active: Some(cam),
+    }
+}
+
+/// Windows keeps per-app usage of privacy-guarded devices in the consent
+/// store; an app whose `LastUsedTimeStop` is 0 is using the device now.
+#[cfg(target_os = "windows")]
+fn platform_device_state() -> DeviceState {
+    const CONSENT_STORE: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore";
+
+    let in_use = |device: &str| -> Option<bool> {
+        let output = std::process::Command::new("reg")
+            .args(["query", &format!(r"{CONSENT_STORE}\{device}"), "/s", "/v", "LastUsedTimeStop"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&output.stdout);
+        Some(
+            text.lines()
+                .filter(|line| line.contains("LastUsedTimeStop"))
+                .any(|line| line.split_whitespace().last() == Some("0x0")),
+        )
+    };
+    DeviceState {
+        mic_active: in_use("microphone"),
+        cam_active: in_use("webcam"),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn platform_device_state() -> DeviceState {
+    DeviceState::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(mic: bool) -> DeviceState {
+        DeviceState {
+            mic_active: Some(mic),
+            cam_active: Some(false),
+        }
+    }
+
+    #[test]
+    fn readings_are_reused_for_a_short_while() {
+        let start = Instant::now();
+        let mut cache = DeviceStateCache::default();
+        let mut reads = 0;
+        let mut read = |mic| {
+            reads += 1;
+            state(mic)
+        };
+        assert_eq!(cache.get(start, || read(true)), state(true));
+        let soon = start + DEVICE_STATE_TTL - Duration::from_millis(1);
+        assert_eq!(cache.get(soon, || read(false)), state(true));
+        assert_eq!(
+            cache.get(start + DEVICE_STATE_TTL, || read(false)),
+            state(false)
+        );
+        assert_eq!(reads, 2);
+    }
+}
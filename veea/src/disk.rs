@@ -0,0 +1,50 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use sysinfo::{DiskRefreshKind, Disks};
+
+/// How long a free-space reading is reused before asking the OS again.
+const FREE_SPACE_TTL: Duration = Duration::from_secs(5);
+
+/// Free bytes on the filesystem holding `path`, or `None` when no mounted
+/// disk could be matched to it. Missing trailing components are fine; the
+/// nearest existing ancestor decides.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let path = existing.canonicalize().ok()?;
+    let disks =
+        Disks::new_with_refreshed_list_specifics(DiskRefreshKind::nothing().with_storage());
+    disks
+        .list()
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| d.available_space())
+}
+
+/// Recent `available_space` reading for one directory, so captures don't
+/// each pay for a disk listing.
+#[derive(Debug, Default)]
+pub struct FreeSpaceCache {
+    reading: Option<(Instant, Option<u64>)>,
+}
+
+impl FreeSpaceCache {
+    pub fn available(&mut self, path: &Path) -> Option<u64> {
+        match self.reading {
+            Some((at, bytes)) if at.elapsed() < FREE_SPACE_TTL => bytes,
+            _ => {
+                let bytes = available_space(path);
+                self.reading = Some((Instant::now(), bytes));
+                bytes
+            }
+        }
+    }
+
+    /// Forgets the reading, e.g. after the capture directory changed.
+    pub fn clear(&mut self) {
+        self.reading = None;
+    }
+}
@@ -0,0 +1,282 @@
+use std::sync::mpsc;
+
+use crate::focus::WindowEvent;
+
+#[cfg(not(target_os = "windows"))]
+const DISPLAY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Returns whether every display is powered down while the machine stays
+/// awake, or `None` when the platform doesn't let us tell.
+#[cfg(not(target_os = "windows"))]
+pub fn displays_asleep() -> Option<bool> {
+    platform_displays_asleep()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_displays_asleep() -> Option<bool> {
+    // The kernel's DPMS state covers X11 and Wayland alike; drivers that
+    // don't expose it still answer through the X server.
+    drm_displays_asleep().or_else(x11_displays_asleep)
+}
+
+/// Asleep when every connected, enabled DRM connector reports DPMS off.
+#[cfg(target_os = "linux")]
+fn drm_displays_asleep() -> Option<bool> {
+    let read = |path: std::path::PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+    let mut states = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/drm").ok()?.flatten() {
+        let path = entry.path();
+        if read(path.join("status")).trim() == "connected"
+            && read(path.join("enabled")).trim() == "enabled"
+        {
+            states.push(read(path.join("dpms")).trim().to_string());
+        }
+    }
+    if states.is_empty() {
+        return None;
+    }
+    Some(states.iter().all(|state| state != "On"))
+}
+
+#[cfg(target_os = "linux")]
+fn x11_displays_asleep() -> Option<bool> {
+    let output = std::process::Command::new("xset").arg("q").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Only printed while DPMS is enabled: "Monitor is On", "Monitor is
+    // Off", "Monitor is in Standby" or "Monitor is in Suspend".
+    let text = String::from_utf8_lossy(&output.stdout);
+    let state = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Monitor is "))?;
+    Some(state != "On")
+}
+
+#[cfg(target_os = "macos")]
+fn platform_displays_asleep() -> Option<bool> {
+    // The display wrangler's power state is 4 while the displays are on
+    // and 3 while dimmed; anything lower means they are off.
+    let output = std::process::Command::new("ioreg")
+        .args(["-n", "IODisplayWrangler", "-r", "-d", "1"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let state = text
+        .split("\"CurrentPowerState\"=")
+        .nth(1)
+        .and_then(|rest| {
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        });
+    if let Some(state) = state {
+        return Some(state < 3);
+    }
+
+    // Apple silicon Macs have no wrangler; ask CoreGraphics instead.
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGMainDisplayID() -> u32;
+        fn CGDisplayIsAsleep(display: u32) -> u32;
+    }
+    // SAFETY: both calls only read window server state.
+    Some(unsafe { CGDisplayIsAsleep(CGMainDisplayID()) } != 0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_displays_asleep() -> Option<bool> {
+    None
+}
+
+/// Polls the display power state and reports transitions to the event
+/// loop.
+#[cfg(not(target_os = "windows"))]
+pub fn monitor_displays(event_sender: mpsc::Sender<WindowEvent>) {
+    let mut last_asleep = false;
+
+    loop {
+        if let Some(asleep) = displays_asleep()
+            && asleep != last_asleep
+        {
+            let _ = event_sender.send(WindowEvent::DisplaySleepChanged { asleep });
+            last_asleep = asleep;
+        }
+        std::thread::sleep(DISPLAY_POLL_INTERVAL);
+    }
+}
+
+/// Reports display power transitions to the event loop. Windows has no
+/// call to query the state, so this registers a message-only window for
+/// `GUID_CONSOLE_DISPLAY_STATE` notifications and runs its message loop.
+#[cfg(target_os = "windows")]
+pub fn monitor_displays(event_sender: mpsc::Sender<WindowEvent>) {
+    use std::{cell::RefCell, ffi::c_void, ptr::null_mut};
+
+    #[repr(C)]
+    struct Guid {
+        data1: u32,
+        data2: u16,
+        data3: u16,
+        data4: [u8; 8],
+    }
+
+    #[repr(C)]
+    struct PowerBroadcastSetting {
+        power_setting: Guid,
+        data_length: u32,
+        data: [u8; 4],
+    }
+
+    #[repr(C)]
+    struct WndClass {
+        style: u32,
+        wnd_proc: unsafe extern "system" fn(*mut c_void, u32, usize, isize) -> isize,
+        cls_extra: i32,
+        wnd_extra: i32,
+        instance: *mut c_void,
+        icon: *mut c_void,
+        cursor: *mut c_void,
+        background: *mut c_void,
+        menu_name: *const u16,
+        class_name: *const u16,
+    }
+
+    #[repr(C)]
+    struct Msg {
+        hwnd: *mut c_void,
+        message: u32,
+        wparam: usize,
+        lparam: isize,
+        time: u32,
+        pt: [i32; 2],
+        private: u32,
+    }
+
+    const GUID_CONSOLE_DISPLAY_STATE: Guid = Guid {
+        data1: 0x6FE6_9556,
+        data2: 0x704A,
+        data3: 0x47A0,
+        data4: [0x8F, 0x24, 0xC2, 0x8D, 0x93, 0x6F, 0xDA, 0x47],
+    };
+    const WM_POWERBROADCAST: u32 = 0x0218;
+    const PBT_POWERSETTINGCHANGE: usize = 0x8013;
+    const HWND_MESSAGE: isize = -3;
+    const DEVICE_NOTIFY_WINDOW_HANDLE: u32 = 0;
+    /// `GUID_CONSOLE_DISPLAY_STATE` data: 0 off, 1 on, 2 dimmed.
+    const DISPLAY_OFF: u8 = 0;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetModuleHandleW(name: *const u16) -> *mut c_void;
+    }
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn RegisterClassW(class: *const WndClass) -> u16;
+        fn CreateWindowExW(
+            ex_style: u32,
+            class_name: *const u16,
+            window_name: *const u16,
+            style: u32,
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+            parent: *mut c_void,
+            menu: *mut c_void,
+            instance: *mut c_void,
+            param: *mut c_void,
+        ) -> *mut c_void;
+        fn RegisterPowerSettingNotification(
+            recipient: *mut c_void,
+            setting: *const Guid,
+            flags: u32,
+        ) -> *mut c_void;
+        fn GetMessageW(msg: *mut Msg, hwnd: *mut c_void, min: u32, max: u32) -> i32;
+        fn DispatchMessageW(msg: *const Msg) -> isize;
+        fn DefWindowProcW(hwnd: *mut c_void, msg: u32, wparam: usize, lparam: isize) -> isize;
+    }
+
+    thread_local! {
+        /// The window procedure runs on this thread, inside `GetMessageW`.
+        static SENDER: RefCell<Option<(mpsc::Sender<WindowEvent>, bool)>> =
+            const { RefCell::new(None) };
+    }
+
+    unsafe extern "system" fn wnd_proc(
+        hwnd: *mut c_void,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    ) -> isize {
+        if msg == WM_POWERBROADCAST && wparam == PBT_POWERSETTINGCHANGE && lparam != 0 {
+            // SAFETY: for PBT_POWERSETTINGCHANGE, lparam points at a
+            // POWERBROADCAST_SETTING valid for the duration of the message.
+            let asleep =
+                unsafe { (*(lparam as *const PowerBroadcastSetting)).data[0] } == DISPLAY_OFF;
+            SENDER.with_borrow_mut(|sender| {
+                if let Some((sender, last_asleep)) = sender
+                    && asleep != *last_asleep
+                {
+                    let _ = sender.send(WindowEvent::DisplaySleepChanged { asleep });
+                    *last_asleep = asleep;
+                }
+            });
+            return 1;
+        }
+        // SAFETY: unhandled messages go to the default procedure unchanged.
+        unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+    }
+
+    SENDER.set(Some((event_sender, false)));
+    let class_name: Vec<u16> = "VeeaDisplayWatcher\0".encode_utf16().collect();
+    // SAFETY: the class and window names outlive the window, which only
+    // lives on this thread; the message loop runs until the process exits.
+    unsafe {
+        let instance = GetModuleHandleW(null_mut());
+        let class = WndClass {
+            style: 0,
+            wnd_proc,
+            cls_extra: 0,
+            wnd_extra: 0,
+            instance,
+            icon: null_mut(),
+            cursor: null_mut(),
+            background: null_mut(),
+            menu_name: null_mut(),
+            class_name: class_name.as_ptr(),
+        };
+        if RegisterClassW(&class) == 0 {
+            eprintln!("Display sleep watcher unavailable: cannot register window class");
+            return;
+        }
+        let hwnd = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE as *mut c_void,
+            null_mut(),
+            instance,
+            null_mut(),
+        );
+        if hwnd.is_null()
+            || RegisterPowerSettingNotification(
+                hwnd,
+                &GUID_CONSOLE_DISPLAY_STATE,
+                DEVICE_NOTIFY_WINDOW_HANDLE,
+            )
+            .is_null()
+        {
+            eprintln!("Display sleep watcher unavailable: cannot register for notifications");
+            return;
+        }
+        let mut msg: Msg = std::mem::zeroed();
+        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+            DispatchMessageW(&msg);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Whether this platform has a document path probe.
+pub const SUPPORTED: bool = cfg!(target_os = "macos");
+
+/// Looks up the file shown by the focused window on a helper thread, so
+/// it can run while the screenshot is taken. Accessibility calls can hang
+/// when the target app is busy; the probe gives up after `timeout` and
+/// its answer is then dropped.
+pub struct DocProbe {
+    rx: mpsc::Receiver<Option<String>>,
+    deadline: Instant,
+}
+
+impl DocProbe {
+    pub fn start(timeout: Duration) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(platform_document_path(timeout));
+        });
+        Self {
+            rx,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// The path, waiting at most until the probe's deadline.
+    pub fn wait(self) -> Option<String> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        self.rx.recv_timeout(left).ok().flatten()
+    }
+}
+
+/// The focused window's `AXDocument`, read through System Events so no
+/// extra toolchain is needed. Needs the Accessibility permission; without
+/// it the script fails and there is no path.
+#[cfg(target_os = "macos")]
+const DOCUMENT_SCRIPT: &str = r#"
+function run() {
+    const se = Application('System Events');
+    const app = se.processes.whose({ frontmost: true })[0];
+    const win = app.attributes.byName('AXFocusedWindow').value();
+    const doc = win.attributes.byName('AXDocument').value();
+    return doc || '';
+}
+"#;
+
+#[cfg(target_os = "macos")]
+fn platform_document_path(timeout: Duration) -> Option<String> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("osascript")
+        .args(["-l", "JavaScript", "-e", DOCUMENT_SCRIPT])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+    let mut url = String::new();
+    std::io::Read::read_to_string(&mut child.stdout.take()?, &mut url).ok()?;
+    file_url_to_path(url.trim())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn platform_document_path(_timeout: Duration) -> Option<String> {
+    None
+}
+
+/// Local path of a `file://` URL, percent-decoded. Other schemes, such as
+/// documents in the cloud, have no path.
+#[cfg(target_os = "macos")]
+fn file_url_to_path(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let bytes = rest.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(out).ok()?;
+    (!path.is_empty()).then_some(path)
+}
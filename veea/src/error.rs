@@ -13,12 +13,66 @@ pub enum AppError {
     #[error("toml serialize error: {0}")]
     TomlSerialize(#[from] toml::ser::Error),
 
+    #[error("invalid config: {0}")]
+    Config(String),
+
     #[error("database error: {0}")]
     Db(#[from] rusqlite::Error),
 
     #[error("capture error: {0}")]
     Capture(String),
 
+    #[error("capture rate exceeded ({0} per minute)")]
+    RateLimited(u32),
+
+    #[error("monitor not found: {0}")]
+    MonitorNotFound(String),
+
+    #[error("capture paused")]
+    Paused,
+
+    #[error("invalid event type '{0}': use 1-32 of a-z, 0-9, '_' or '-'")]
+    InvalidEventType(String),
+
+    #[error("database integrity check failed: {0}")]
+    Integrity(String),
+
+    #[error("another instance is already running (lock held on {0})")]
+    InstanceLocked(String),
+
+    #[error("image unreadable: {0}")]
+    ImageUnreadable(String),
+
+    #[error("relocate failed: {0}")]
+    Relocate(String),
+
     #[error("channel error: {0}")]
     Channel(String),
+
+    #[error("low disk space: {available} bytes free, min_free_bytes is {required}")]
+    LowDiskSpace { available: u64, required: u64 },
+}
+
+impl AppError {
+    /// Stable category name, used to debounce error notifications.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Io(_) => "io",
+            AppError::TomlDeserialize(_) | AppError::TomlSerialize(_) | AppError::Config(_) => {
+                "config"
+            }
+            AppError::Db(_) => "db",
+            AppError::Capture(_) => "capture",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::MonitorNotFound(_) => "monitor_not_found",
+            AppError::Paused => "paused",
+            AppError::InvalidEventType(_) => "invalid_event_type",
+            AppError::Integrity(_) => "integrity",
+            AppError::InstanceLocked(_) => "instance_locked",
+            AppError::ImageUnreadable(_) => "image_unreadable",
+            AppError::Relocate(_) => "relocate",
+            AppError::Channel(_) => "channel",
+            AppError::LowDiskSpace { .. } => "low_disk_space",
+        }
+    }
 }
@@ -0,0 +1,308 @@
+//! Who may reach the API, from `api_expose`. Anything beyond this machine
+//! needs `api_token`, checked before the daemon starts; `proxy` mode adds
+//! what a reverse proxy such as nginx in front expects.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_error::ApiError,
+    base64,
+    config::CaptureConfig,
+    error::{AppError, AppResult},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiExpose {
+    /// Only this machine; `api_addr` must be a loopback address.
+    #[default]
+    Localhost,
+    /// Other machines connect directly, with `api_token`.
+    Lan,
+    /// Behind a reverse proxy: `X-Forwarded-For` names the client in logs,
+    /// request bodies are capped at `api_max_body_bytes`, and responses
+    /// forbid MIME sniffing and framing.
+    Proxy,
+}
+
+/// Refuses to serve captures without a token to anything but this
+/// machine.
+pub fn validate(config: &CaptureConfig) -> AppResult<()> {
+    let addr = config.api_addr;
+    let has_token = token(config).is_some();
+    let problem = match config.api_expose {
+        ApiExpose::Localhost if !addr.ip().is_loopback() => format!(
+            "api_addr {addr} is reachable from other machines, but api_expose is \"localhost\"; \
+             bind to 127.0.0.1, or set api_expose = \"lan\" and an api_token"
+        ),
+        ApiExpose::Lan if !has_token => {
+            "api_expose = \"lan\" needs an api_token, which clients then send as a bearer token"
+                .to_string()
+        }
+        _ if !addr.ip().is_loopback() && !has_token => format!(
+            "api_addr {addr} is reachable from other machines without authentication; \
+             set an api_token"
+        ),
+        _ => return Ok(()),
+    };
+    Err(AppError::Config(problem))
+}
+
+fn token(config: &CaptureConfig) -> Option<String> {
+    config
+        .api_token
+        .as_deref()
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// What `guard` enforces, fixed when the server starts.
+#[derive(Debug, Clone)]
+pub struct ExposePolicy {
+    mode: ApiExpose,
+    token: Option<String>,
+}
+
+impl ExposePolicy {
+    pub fn new(config: &CaptureConfig) -> Self {
+        Self {
+            mode: config.api_expose,
+            token: token(config),
+        }
+    }
+
+    pub fn mode(&self) -> ApiExpose {
+        self.mode
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Whether `request` carries the token: in its headers, or for
+    /// `/feed.atom` as `?token=`, since feed readers can't send headers.
+    fn admits(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        if request.uri().path() == "/feed.atom"
+            && let Ok(Query(query)) = Query::<TokenQuery>::try_from_uri(request.uri())
+            && let Some(given) = query.token
+        {
+            return same_bytes(given.as_bytes(), token.as_bytes());
+        }
+        self.authorized(request.headers())
+    }
+
+    /// Whether `headers` carry the token, as `Bearer <token>` or, so
+    /// browsers can open the web UI, as the password of HTTP basic auth.
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let Some(value) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return false;
+        };
+        let given = match value.split_once(' ') {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("bearer") => {
+                rest.trim().as_bytes().to_vec()
+            }
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("basic") => {
+                let Some(decoded) = base64::decode(rest.trim()) else {
+                    return false;
+                };
+                match decoded.iter().position(|&b| b == b':') {
+                    Some(colon) => decoded[colon + 1..].to_vec(),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+        same_bytes(&given, token.as_bytes())
+    }
+
+    /// The requesting client for logs: in proxy mode the first
+    /// `X-Forwarded-For` entry, as the socket peer is the proxy.
+    fn client(&self, request: &Request) -> String {
+        let forwarded = (self.mode == ApiExpose::Proxy)
+            .then(|| request.headers().get("x-forwarded-for")?.to_str().ok())
+            .flatten()
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+        if let Some(client) = forwarded {
+            return client.to_string();
+        }
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string())
+    }
+}
+
+/// Compares without stopping at the first difference, so response times
+/// don't give the token away byte by byte.
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Rejects requests without the token and, in proxy mode, adds the
+/// security headers to every response.
+pub async fn guard(
+    State(policy): State<Arc<ExposePolicy>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = if policy.admits(&request) {
+        next.run(request).await
+    } else {
+        eprintln!(
+            "Refused {} {} from {}: missing or wrong api_token",
+            request.method(),
+            request.uri().path(),
+            policy.client(&request)
+        );
+        let mut response = ApiError::unauthorized("api token required").into_response();
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"veea\""),
+        );
+        response
+    };
+    if policy.mode == ApiExpose::Proxy {
+        let headers = response.headers_mut();
+        let nosniff = HeaderValue::from_static("nosniff");
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, nosniff);
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(expose: ApiExpose, addr: &str, token: Option<&str>) -> CaptureConfig {
+        CaptureConfig {
+            api_expose: expose,
+            api_addr: addr.parse().unwrap(),
+            api_token: token.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn refusal(config: &CaptureConfig) -> String {
+        match validate(config) {
+            Ok(()) => panic!(
+                "{:?} on {} was accepted",
+                config.api_expose, config.api_addr
+            ),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn auth(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+        headers
+    }
+
+    fn basic(user_and_password: &str) -> HeaderMap {
+        auth(&format!(
+            "Basic {}",
+            base64::encode(user_and_password.as_bytes())
+        ))
+    }
+
+    #[test]
+    fn localhost_mode_needs_a_loopback_address() {
+        validate(&config(ApiExpose::Localhost, "127.0.0.1:8787", None)).unwrap();
+        validate(&config(ApiExpose::Localhost, "[::1]:8787", None)).unwrap();
+        for addr in ["0.0.0.0:8787", "192.168.1.20:8787", "[::]:8787"] {
+            let error = refusal(&config(ApiExpose::Localhost, addr, Some("secret")));
+            assert!(error.contains("api_expose is \"localhost\""), "{error}");
+        }
+    }
+
+    #[test]
+    fn lan_mode_needs_a_token() {
+        let error = refusal(&config(ApiExpose::Lan, "0.0.0.0:8787", None));
+        assert!(error.contains("needs an api_token"), "{error}");
+        // A blank token is no token.
+        refusal(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("  ")));
+        validate(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("secret"))).unwrap();
+    }
+
+    #[test]
+    fn proxy_mode_needs_a_token_off_loopback() {
+        validate(&config(ApiExpose::Proxy, "127.0.0.1:8787", None)).unwrap();
+        let error = refusal(&config(ApiExpose::Proxy, "10.0.0.5:8787", None));
+        assert!(error.contains("without authentication"), "{error}");
+        validate(&config(ApiExpose::Proxy, "10.0.0.5:8787", Some("secret"))).unwrap();
+    }
+
+    #[test]
+    fn bearer_token_is_accepted() {
+        let policy = ExposePolicy::new(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("secret")));
+        assert!(policy.authorized(&auth("Bearer secret")));
+        assert!(policy.authorized(&auth("bearer  secret ")));
+        assert!(!policy.authorized(&auth("Bearer Secret")));
+        assert!(!policy.authorized(&auth("Token secret")));
+        assert!(!policy.authorized(&auth("secret")));
+        assert!(!policy.authorized(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn basic_auth_password_is_accepted() {
+        let policy = ExposePolicy::new(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("secret")));
+        assert!(policy.authorized(&basic("veea:secret")));
+        assert!(policy.authorized(&basic(":secret")));
+        // The password may itself contain colons.
+        let policy = ExposePolicy::new(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("a:b")));
+        assert!(policy.authorized(&basic("me:a:b")));
+        assert!(!policy.authorized(&basic("a:b")));
+        assert!(!policy.authorized(&basic("a:bb")));
+        assert!(!policy.authorized(&auth("Basic not-base64!")));
+    }
+
+    #[test]
+    fn token_of_the_wrong_length_is_refused() {
+        let policy = ExposePolicy::new(&config(ApiExpose::Lan, "0.0.0.0:8787", Some("secret")));
+        for wrong in ["", "secre", "secret1", "secretsecret"] {
+            assert!(
+                !policy.authorized(&auth(&format!("Bearer {wrong}"))),
+                "{wrong:?}"
+            );
+            assert!(
+                !policy.authorized(&basic(&format!("veea:{wrong}"))),
+                "{wrong:?}"
+            );
+        }
+        assert!(!same_bytes(b"secret", b"secret\0"));
+        assert!(same_bytes(b"", b""));
+    }
+
+    #[test]
+    fn no_token_allows_everyone() {
+        let policy = ExposePolicy::new(&config(ApiExpose::Localhost, "127.0.0.1:8787", None));
+        assert!(!policy.has_token());
+        assert!(policy.authorized(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::Write as _;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::db::CaptureRecord;
+
+/// Atom document for `/feed.atom`: one entry per capture, newest first,
+/// linking the capture and its thumbnail under `base_url`.
+pub fn atom(records: &[CaptureRecord], base_url: &str) -> String {
+    let updated = records.first().map_or_else(Utc::now, |r| r.ts);
+    let mut xml = String::new();
+    let _ = write!(
+        xml,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>{base}/feed.atom</id>
+  <title>Veea captures</title>
+  <updated>{updated}</updated>
+  <author><name>Veea</name></author>
+  <link rel="self" type="application/atom+xml" href="{base}/feed.atom" />
+"#,
+        base = escape(base_url),
+        updated = timestamp(updated),
+    );
+    for record in records {
+        let title = record.window_title.as_deref().unwrap_or(&record.event_type);
+        let capture_url = escape(&format!("{base_url}/captures/{}", record.id));
+        let thumbnail_url = format!("{capture_url}/thumbnail");
+        let mut summary = format!("<p>{}", escape(&record.event_type));
+        if let Some(app) = &record.app_name {
+            summary.push_str(" · ");
+            summary.push_str(&escape(app));
+        }
+        summary.push_str(&format!("</p><img src=\"{thumbnail_url}\" />"));
+        let _ = write!(
+            xml,
+            r#"  <entry>
+    <id>urn:uuid:{id}</id>
+    <title>{title}</title>
+    <updated>{updated}</updated>
+    <link rel="alternate" type="application/json" href="{capture_url}" />
+    <link rel="enclosure" type="image/png" href="{thumbnail_url}" />
+    <content type="html">{content}</content>
+  </entry>
+"#,
+            id = escape(&record.id),
+            title = escape(title),
+            updated = timestamp(record.ts),
+            content = escape(&summary),
+        );
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn timestamp(ts: DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Escapes text for XML content and attributes, dropping control
+/// characters XML 1.0 can't represent at all; window titles may hold them.
+/// Also safe for HTML, see `report`.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use roxmltree::{Document, Node};
+
+    use super::*;
+
+    const ATOM: &str = "http://www.w3.org/2005/Atom";
+
+    fn record(id: &str, ts_ms: i64, title: Option<&str>, app: Option<&str>) -> CaptureRecord {
+        let mut record = CaptureRecord::sample(id, DateTime::from_timestamp_millis(ts_ms).unwrap());
+        record.window_title = title.map(str::to_string);
+        record.app_name = app.map(str::to_string);
+        record
+    }
+
+    /// Atom children of `node` named `name`.
+    fn children<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Vec<Node<'a, 'i>> {
+        node.children()
+            .filter(|c| c.tag_name().namespace() == Some(ATOM) && c.tag_name().name() == name)
+            .collect()
+    }
+
+    /// The text of the one `name` child of `node`, failing when there is
+    /// none or more than one, as RFC 4287 allows only one.
+    fn only<'a>(node: Node<'a, '_>, name: &str) -> &'a str {
+        let found = children(node, name);
+        assert_eq!(
+            found.len(),
+            1,
+            "<{}> needs one <{name}>",
+            node.tag_name().name()
+        );
+        found[0].text().unwrap_or("")
+    }
+
+    fn assert_timestamp(text: &str) {
+        DateTime::parse_from_rfc3339(text).unwrap_or_else(|e| panic!("{text:?}: {e}"));
+    }
+
+    /// Checks the parts of RFC 4287 the feed relies on and returns the
+    /// entries.
+    fn check_atom<'a, 'i>(doc: &'a Document<'i>) -> Vec<Node<'a, 'i>> {
+        let feed = doc.root_element();
+        assert_eq!(feed.tag_name().namespace(), Some(ATOM));
+        assert_eq!(feed.tag_name().name(), "feed");
+        assert!(!only(feed, "id").is_empty());
+        assert!(!only(feed, "title").is_empty());
+        assert_timestamp(only(feed, "updated"));
+        // A feed level author covers entries without their own.
+        let author = children(feed, "author");
+        assert_eq!(author.len(), 1);
+        assert!(!only(author[0], "name").is_empty());
+        let links = children(feed, "link");
+        assert!(links.iter().any(|l| l.attribute("rel") == Some("self")));
+
+        let entries = children(feed, "entry");
+        for entry in &entries {
+            assert!(only(*entry, "id").starts_with("urn:uuid:"));
+            only(*entry, "title");
+            assert_timestamp(only(*entry, "updated"));
+            let content = children(*entry, "content");
+            assert_eq!(content.len(), 1);
+            assert_eq!(content[0].attribute("type"), Some("html"));
+            for link in children(*entry, "link") {
+                assert!(link.attribute("href").is_some_and(|h| !h.is_empty()));
+            }
+        }
+        entries
+    }
+
+    #[test]
+    fn feed_is_valid_atom() {
+        let records = [
+            record("b", 1_717_588_800_000, Some("Inbox"), Some("mail")),
+            record("a", 1_717_588_700_000, None, None),
+        ];
+        let xml = atom(&records, "http://127.0.0.1:8787");
+        let doc = Document::parse(&xml).unwrap();
+        let entries = check_atom(&doc);
+        assert_eq!(entries.len(), 2);
+
+        let feed = doc.root_element();
+        assert_eq!(only(feed, "updated"), "2024-06-05T12:00:00.000Z");
+        assert_eq!(only(entries[0], "id"), "urn:uuid:b");
+        assert_eq!(only(entries[0], "title"), "Inbox");
+        // Without a window title, the event type stands in.
+        assert_eq!(only(entries[1], "title"), "focus");
+
+        let links = children(entries[0], "link");
+        let href = |rel: &str| {
+            links
+                .iter()
+                .find(|l| l.attribute("rel") == Some(rel))
+                .and_then(|l| l.attribute("href"))
+        };
+        assert_eq!(href("alternate"), Some("http://127.0.0.1:8787/captures/b"));
+        assert_eq!(
+            href("enclosure"),
+            Some("http://127.0.0.1:8787/captures/b/thumbnail")
+        );
+        assert_eq!(
+            only(entries[0], "content"),
+            "<p>focus · mail</p><img src=\"http://127.0.0.1:8787/captures/b/thumbnail\" />"
+        );
+    }
+
+    #[test]
+    fn empty_feed_is_valid_atom() {
+        let xml = atom(&[], "http://127.0.0.1:8787");
+        let doc = Document::parse(&xml).unwrap();
+        assert!(check_atom(&doc).is_empty());
+    }
+
+    #[test]
+    fn hostile_titles_stay_text() {
+        let title = "<script>alert('x')</script> & \"quotes\"\u{1b}[31m\0";
+        let records = [record("a", 1_717_588_800_000, Some(title), Some("a&b <c>"))];
+        let xml = atom(&records, "http://host/?a=1&b=2");
+        let doc = Document::parse(&xml).unwrap();
+        let entries = check_atom(&doc);
+        assert_eq!(
+            only(entries[0], "title"),
+            "<script>alert('x')</script> & \"quotes\"[31m"
+        );
+        assert_eq!(
+            only(doc.root_element(), "id"),
+            "http://host/?a=1&b=2/feed.atom"
+        );
+        let content = only(entries[0], "content");
+        assert!(
+            content.starts_with("<p>focus · a&amp;b &lt;c&gt;</p>"),
+            "{content}"
+        );
+        assert!(!xml.contains("<script>"));
+    }
+}
@@ -0,0 +1,787 @@
+//! Which window has focus, turned into `WindowEvent`s for the capture
+//! loop. Only X11 is event-driven: on Linux with an X server the watcher
+//! listens for focus and title changes. On macOS, Windows and Linux
+//! without an X server it polls the window list every 200ms instead.
+//! `monitor_periodic` polls on every platform, reading the focused
+//! window once per interval.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use xcap::Window;
+
+use crate::{
+    capture::SelfTestCheck,
+    config::{CaptureConfig, MemoryLimits},
+    db::{CaptureRecord, MaintenanceOp, MaintenanceReport},
+    error::AppResult,
+    memory::{BoundedMap, CollectionSize, MemoryUsage},
+};
+
+#[derive(Debug, Clone)]
+pub enum WindowEvent {
+    FocusChanged {
+        window_id: u32,
+        window_title: String,
+        app_name: Option<String>,
+        /// Window that held focus until now, if any.
+        previous: Option<FocusedWindow>,
+        /// Title the newly focused window had before its current one.
+        prev_title: Option<String>,
+    },
+    TitleChanged {
+        window_id: u32,
+        window_title: String,
+        app_name: Option<String>,
+        prev_title: Option<String>,
+    },
+    Periodic { window_title: String, app_name: Option<String> },
+    PrioritySettle { window_title: String, app_name: Option<String> },
+    SessionLockChanged { locked: bool },
+    /// Every display powered down, or one came back on.
+    DisplaySleepChanged { asleep: bool },
+    PowerChanged { on_battery: bool },
+    /// The machine resumed from a suspend of roughly `slept_ms`.
+    Woke { slept_ms: u64 },
+    /// `post_wake_delay_ms` after a wake, carrying the focused window.
+    WakeSettled { window_title: String, app_name: Option<String> },
+    /// The focused window moved or resized by more than
+    /// `GEOMETRY_THRESHOLD_PX` since focus or the last such event.
+    GeometryChanged { window_title: String, app_name: Option<String> },
+    /// Fired `geometry_debounce_ms` after a `GeometryChanged`; stale when a
+    /// newer change came in meanwhile.
+    GeometrySettled { generation: u64, window_title: String, app_name: Option<String> },
+    /// Fired `capture_delay_ms` after a focus or title change, to take
+    /// the `CaptureDelay` capture if it's due by now.
+    CaptureDue,
+    /// Database maintenance to run on the writer's connection; the result
+    /// goes to `reply` when someone is waiting for it.
+    Maintenance {
+        op: MaintenanceOp,
+        reply: Option<mpsc::Sender<AppResult<MaintenanceReport>>>,
+    },
+    /// Asks the loop for the sizes of its collections, see `/debug/memory`.
+    MemoryUsage { reply: mpsc::Sender<MemoryUsage> },
+    /// Re-reads one capture's image and optionally re-runs OCR on it; the
+    /// updated record, or `None` for an unknown id, goes to `reply`.
+    Reprocess {
+        id: String,
+        ocr: bool,
+        reply: mpsc::Sender<AppResult<Option<CaptureRecord>>>,
+    },
+    BurstFrame { label: String, group_id: String },
+    /// A file was created or changed in one of `watch_paths`; the last of
+    /// a burst within `fs_debounce_ms`.
+    FsTrigger { path: PathBuf },
+    /// Another app posted a desktop notification.
+    Notification { app_name: Option<String> },
+    /// Swaps in the config written by `POST /setup`, then runs the
+    /// capture self-test against it.
+    ApplyConfig {
+        config: Box<CaptureConfig>,
+        reply: mpsc::Sender<AppResult<Vec<SelfTestCheck>>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct FocusedWindow {
+    pub id: u32,
+    pub title: String,
+    pub app_name: Option<String>,
+    pub rect: Option<WindowRect>,
+}
+
+/// Smallest move or resize, in pixels along any edge, reported as a
+/// geometry change.
+const GEOMETRY_THRESHOLD_PX: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowRect {
+    fn differs_by_more_than(&self, other: &WindowRect, px: u32) -> bool {
+        self.x.abs_diff(other.x) > px
+            || self.y.abs_diff(other.y) > px
+            || self.width.abs_diff(other.width) > px
+            || self.height.abs_diff(other.height) > px
+    }
+}
+
+/// Source of focus/title change notifications for the capture loop.
+pub trait FocusWatcher: Send {
+    /// Short backend name, logged at startup and reported by `/status`.
+    fn backend(&self) -> &'static str;
+
+    /// Blocks forever, forwarding window events to `sender`.
+    fn run(self: Box<Self>, sender: mpsc::Sender<WindowEvent>);
+}
+
+/// Picks the X11 event watcher when an X server answers, and otherwise
+/// falls back to polling `Window::all()`. The watcher keeps its title
+/// history within `limits` and publishes its size to `usage`.
+pub fn select_watcher(limits: &MemoryLimits, usage: Arc<TrackerUsage>) -> Box<dyn FocusWatcher> {
+    let tracker = FocusTracker::new(limits, usage);
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(watcher) = x11::X11Watcher::connect(tracker.clone()) {
+            return Box::new(watcher);
+        }
+    }
+    Box::new(PollingWatcher { tracker })
+}
+
+/// Longest window title or app name kept, in characters. Filenames
+/// apply their own, shorter limit.
+pub const MAX_TITLE_CHARS: usize = 512;
+
+/// Cleans a title or app name as reported by the platform before it goes
+/// anywhere else: NULs are dropped, other control characters become
+/// spaces, surrounding whitespace is trimmed, and the result is capped at
+/// `MAX_TITLE_CHARS`. Invalid UTF-8 must already have been replaced, e.g.
+/// with `String::from_utf8_lossy`.
+pub fn sanitize_title(raw: &str) -> String {
+    raw.chars()
+        .filter(|c| *c != '\0')
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_TITLE_CHARS)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// `sanitize_title`, with `None` for names that end up empty.
+fn sanitize_name(raw: Option<String>) -> Option<String> {
+    raw.map(|name| sanitize_title(&name)).filter(|name| !name.is_empty())
+}
+
+pub fn get_focused_window() -> Option<FocusedWindow> {
+    if let Ok(windows) = Window::all() {
+        for window in windows {
+            if let Ok(false) = window.is_minimized()
+                && let Some(title) = sanitize_name(window.title().ok())
+                && let Ok(window_id) = window.id()
+            {
+                let app_name = sanitize_name(window.app_name().ok());
+                let rect = match (window.x(), window.y(), window.width(), window.height()) {
+                    (Ok(x), Ok(y), Ok(width), Ok(height)) => {
+                        Some(WindowRect { x, y, width, height })
+                    }
+                    _ => None,
+                };
+                return Some(FocusedWindow {
+                    id: window_id,
+                    title,
+                    app_name,
+                    rect,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Diagnostic view of one entry from `Window::all()`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WindowInfo {
+    pub id: Option<u32>,
+    pub title: Option<String>,
+    pub app_name: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub minimized: Option<bool>,
+    pub focused: Option<bool>,
+}
+
+/// Snapshot of every window xcap can see, in the order it reports them.
+/// Properties the platform refuses to report are left as `None`.
+pub fn list_windows() -> Result<Vec<WindowInfo>, xcap::XCapError> {
+    Ok(Window::all()?
+        .iter()
+        .map(|window| WindowInfo {
+            id: window.id().ok(),
+            title: window.title().ok(),
+            app_name: window.app_name().ok(),
+            x: window.x().ok(),
+            y: window.y().ok(),
+            width: window.width().ok(),
+            height: window.height().ok(),
+            minimized: window.is_minimized().ok(),
+            focused: window.is_focused().ok(),
+        })
+        .collect())
+}
+
+/// Recent titles per window id, oldest first, so captures can record what
+/// a window showed just before its current title.
+#[derive(Clone)]
+struct TitleHistory {
+    titles: BoundedMap<u32, VecDeque<String>>,
+    per_window: usize,
+}
+
+impl TitleHistory {
+    fn new(limits: &MemoryLimits) -> Self {
+        Self {
+            titles: BoundedMap::new(limits.title_history_windows),
+            per_window: limits.title_history_len.max(1),
+        }
+    }
+
+    /// Records `title` for window `id` unless it is already the latest one.
+    fn push(&mut self, id: u32, title: &str) {
+        let titles = self.titles.get_or_insert_with(id, VecDeque::new);
+        if titles.back().map(String::as_str) == Some(title) {
+            return;
+        }
+        if titles.len() >= self.per_window {
+            titles.pop_front();
+        }
+        titles.push_back(title.to_string());
+    }
+
+    /// The title window `id` had before `current`, if one was seen.
+    fn previous(&self, id: u32, current: &str) -> Option<String> {
+        self.titles
+            .get(&id)?
+            .iter()
+            .rev()
+            .find(|t| t.as_str() != current)
+            .cloned()
+    }
+
+    fn title_count(&self) -> usize {
+        self.titles.values().map(VecDeque::len).sum()
+    }
+}
+
+/// Title history sizes published by the watcher thread, which owns the
+/// history, for `/debug/memory`.
+#[derive(Debug, Default)]
+pub struct TrackerUsage {
+    windows: AtomicUsize,
+    titles: AtomicUsize,
+}
+
+impl TrackerUsage {
+    /// Fills the title history fields of `usage`.
+    pub fn report(&self, limits: &MemoryLimits, usage: &mut MemoryUsage) {
+        usage.title_history_windows = CollectionSize {
+            len: self.windows.load(Ordering::Relaxed),
+            cap: Some(limits.title_history_windows.max(1)),
+        };
+        usage.title_history_titles = CollectionSize {
+            len: self.titles.load(Ordering::Relaxed),
+            cap: Some(limits.title_history_windows.max(1) * limits.title_history_len.max(1)),
+        };
+    }
+}
+
+/// Tracks the last seen focused window and turns observations into events.
+/// Shared by every watcher so they report transitions identically.
+#[derive(Clone)]
+struct FocusTracker {
+    last_window: Option<FocusedWindow>,
+    history: TitleHistory,
+    usage: Arc<TrackerUsage>,
+    /// Geometry of the focused window when it gained focus or last
+    /// reported a geometry change.
+    geometry_baseline: Option<WindowRect>,
+}
+
+impl FocusTracker {
+    fn new(limits: &MemoryLimits, usage: Arc<TrackerUsage>) -> Self {
+        Self {
+            last_window: None,
+            history: TitleHistory::new(limits),
+            usage,
+            geometry_baseline: None,
+        }
+    }
+
+    fn observe(
+        &mut self,
+        focused: Option<FocusedWindow>,
+        event_sender: &mpsc::Sender<WindowEvent>,
+    ) {
+        if let Some(window) = focused {
+            let last = self.last_window.take();
+            let focus_changed = last.as_ref().map(|w| w.id) != Some(window.id);
+            let title_changed = last.as_ref().map(|w| &w.title) != Some(&window.title);
+            let prev_title = self.history.previous(window.id, &window.title);
+            self.history.push(window.id, &window.title);
+            self.usage.windows.store(self.history.titles.len(), Ordering::Relaxed);
+            self.usage.titles.store(self.history.title_count(), Ordering::Relaxed);
+
+            if focus_changed {
+                let _ = event_sender.send(WindowEvent::FocusChanged {
+                    window_id: window.id,
+                    window_title: window.title.clone(),
+                    app_name: window.app_name.clone(),
+                    previous: last,
+                    prev_title: prev_title.clone(),
+                });
+            }
+
+            if title_changed {
+                let _ = event_sender.send(WindowEvent::TitleChanged {
+                    window_id: window.id,
+                    window_title: window.title.clone(),
+                    app_name: window.app_name.clone(),
+                    prev_title,
+                });
+            }
+
+            if focus_changed {
+                self.geometry_baseline = window.rect;
+            } else if let Some(rect) = window.rect {
+                let moved = self
+                    .geometry_baseline
+                    .is_some_and(|b| rect.differs_by_more_than(&b, GEOMETRY_THRESHOLD_PX));
+                if moved {
+                    let _ = event_sender.send(WindowEvent::GeometryChanged {
+                        window_title: window.title.clone(),
+                        app_name: window.app_name.clone(),
+                    });
+                }
+                if moved || self.geometry_baseline.is_none() {
+                    self.geometry_baseline = Some(rect);
+                }
+            }
+
+            self.last_window = Some(window);
+        } else {
+            self.last_window = None;
+            self.geometry_baseline = None;
+        }
+    }
+}
+
+/// Fallback watcher that polls the window list every 200ms.
+pub struct PollingWatcher {
+    tracker: FocusTracker,
+}
+
+impl FocusWatcher for PollingWatcher {
+    fn backend(&self) -> &'static str {
+        "polling"
+    }
+
+    fn run(self: Box<Self>, sender: mpsc::Sender<WindowEvent>) {
+        monitor_window_events(self.tracker, sender);
+    }
+}
+
+fn monitor_window_events(mut tracker: FocusTracker, event_sender: mpsc::Sender<WindowEvent>) {
+    loop {
+        tracker.observe(get_focused_window(), &event_sender);
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Emits a periodic event every `interval_ms`, re-read each tick so power
+/// profile changes apply without a restart. An interval of 0 idles.
+pub fn monitor_periodic(event_sender: mpsc::Sender<WindowEvent>, interval_ms: Arc<AtomicU64>) {
+    loop {
+        let interval = interval_ms.load(Ordering::Relaxed);
+        if interval == 0 {
+            thread::sleep(Duration::from_secs(1));
+            continue;
+        }
+        if let Some(window) = get_focused_window() {
+            let _ = event_sender.send(WindowEvent::Periodic {
+                window_title: window.title,
+                app_name: window.app_name,
+            });
+        }
+        thread::sleep(Duration::from_millis(interval));
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::sync::mpsc;
+
+    use xcb::{x, Xid};
+
+    use super::{FocusTracker, FocusWatcher, FocusedWindow, WindowEvent, WindowRect};
+
+    xcb::atoms_struct! {
+        struct Atoms {
+            net_active_window => b"_NET_ACTIVE_WINDOW",
+            net_wm_name => b"_NET_WM_NAME",
+            utf8_string => b"UTF8_STRING",
+        }
+    }
+
+    /// Listens for `_NET_ACTIVE_WINDOW` changes on the root window and for
+    /// title changes on the active window, so nothing runs while idle.
+    pub struct X11Watcher {
+        conn: xcb::Connection,
+        root: x::Window,
+        atoms: Atoms,
+        tracker: FocusTracker,
+    }
+
+    impl X11Watcher {
+        pub fn connect(tracker: FocusTracker) -> Option<Self> {
+            let (conn, screen_num) = xcb::Connection::connect(None).ok()?;
+            let root = conn
+                .get_setup()
+                .roots()
+                .nth(screen_num as usize)?
+                .root();
+            let atoms = Atoms::intern_all(&conn).ok()?;
+            if atoms.net_active_window == x::ATOM_NONE {
+                return None;
+            }
+            let watcher = Self { conn, root, atoms, tracker };
+            // Make sure the window manager actually maintains the property.
+            watcher.active_window()?;
+            watcher.select_property_changes(root).ok()?;
+            Some(watcher)
+        }
+
+        fn select_property_changes(&self, window: x::Window) -> xcb::Result<()> {
+            self.select_events(window, x::EventMask::PROPERTY_CHANGE)
+        }
+
+        /// Title changes plus moves/resizes (ConfigureNotify) of a client.
+        fn select_window_changes(&self, window: x::Window) -> xcb::Result<()> {
+            self.select_events(
+                window,
+                x::EventMask::PROPERTY_CHANGE | x::EventMask::STRUCTURE_NOTIFY,
+            )
+        }
+
+        fn select_events(&self, window: x::Window, mask: x::EventMask) -> xcb::Result<()> {
+            self.conn.send_and_check_request(&x::ChangeWindowAttributes {
+                window,
+                value_list: &[x::Cw::EventMask(mask)],
+            })?;
+            Ok(())
+        }
+
+        /// Window rectangle in root coordinates.
+        fn geometry(&self, window: x::Window) -> Option<WindowRect> {
+            let geometry = self.conn.send_request(&x::GetGeometry {
+                drawable: x::Drawable::Window(window),
+            });
+            let origin = self.conn.send_request(&x::TranslateCoordinates {
+                src_window: window,
+                dst_window: self.root,
+                src_x: 0,
+                src_y: 0,
+            });
+            let geometry = self.conn.wait_for_reply(geometry).ok()?;
+            let origin = self.conn.wait_for_reply(origin).ok()?;
+            Some(WindowRect {
+                x: origin.dst_x().into(),
+                y: origin.dst_y().into(),
+                width: geometry.width().into(),
+                height: geometry.height().into(),
+            })
+        }
+
+        fn active_window(&self) -> Option<x::Window> {
+            let cookie = self.conn.send_request(&x::GetProperty {
+                delete: false,
+                window: self.root,
+                property: self.atoms.net_active_window,
+                r#type: x::ATOM_WINDOW,
+                long_offset: 0,
+                long_length: 1,
+            });
+            let reply = self.conn.wait_for_reply(cookie).ok()?;
+            reply.value::<x::Window>().first().copied()
+        }
+
+        fn window_title(&self, window: x::Window) -> Option<String> {
+            let cookie = self.conn.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property: self.atoms.net_wm_name,
+                r#type: self.atoms.utf8_string,
+                long_offset: 0,
+                long_length: 1024,
+            });
+            let reply = self.conn.wait_for_reply(cookie).ok()?;
+            let title = String::from_utf8_lossy(reply.value::<u8>()).to_string();
+            if !title.is_empty() {
+                return Some(title);
+            }
+
+            let cookie = self.conn.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property: x::ATOM_WM_NAME,
+                r#type: x::ATOM_ANY,
+                long_offset: 0,
+                long_length: 1024,
+            });
+            let reply = self.conn.wait_for_reply(cookie).ok()?;
+            // Plain STRING properties are Latin-1; decoding them as UTF-8
+            // turns accented titles into replacement characters.
+            let bytes = reply.value::<u8>();
+            let title = if reply.r#type() == x::ATOM_STRING {
+                bytes.iter().map(|&b| char::from(b)).collect()
+            } else {
+                String::from_utf8_lossy(bytes).to_string()
+            };
+            (!title.is_empty()).then_some(title)
+        }
+
+        /// Class name from WM_CLASS ("instance\0class\0"), matching what
+        /// xcap reports as the app name.
+        fn app_name(&self, window: x::Window) -> Option<String> {
+            let cookie = self.conn.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property: x::ATOM_WM_CLASS,
+                r#type: x::ATOM_STRING,
+                long_offset: 0,
+                long_length: 1024,
+            });
+            let reply = self.conn.wait_for_reply(cookie).ok()?;
+            String::from_utf8_lossy(reply.value::<u8>())
+                .split('\0')
+                .nth(1)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        }
+
+        fn focused(&self) -> Option<FocusedWindow> {
+            let window = self.active_window()?;
+            if window.is_none() {
+                return None;
+            }
+            let title = super::sanitize_name(self.window_title(window))?;
+            Some(FocusedWindow {
+                id: window.resource_id(),
+                title,
+                app_name: super::sanitize_name(self.app_name(window)),
+                rect: self.geometry(window),
+            })
+        }
+    }
+
+    impl FocusWatcher for X11Watcher {
+        fn backend(&self) -> &'static str {
+            "x11"
+        }
+
+        fn run(self: Box<Self>, sender: mpsc::Sender<WindowEvent>) {
+            let mut tracker = self.tracker.clone();
+            let mut watched = self.active_window();
+            if let Some(window) = watched {
+                let _ = self.select_window_changes(window);
+            }
+            tracker.observe(self.focused(), &sender);
+
+            loop {
+                let event = match self.conn.wait_for_event() {
+                    Ok(event) => event,
+                    Err(xcb::Error::Connection(e)) => {
+                        eprintln!("X11 focus watcher lost connection ({e}), falling back to polling");
+                        break;
+                    }
+                    Err(xcb::Error::Protocol(_)) => continue,
+                };
+
+                let ev = match event {
+                    xcb::Event::X(x::Event::PropertyNotify(ev)) => ev,
+                    xcb::Event::X(x::Event::ConfigureNotify(ev)) => {
+                        if Some(ev.window()) == watched {
+                            tracker.observe(self.focused(), &sender);
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                if ev.atom() == self.atoms.net_active_window {
+                    let active = self.active_window();
+                    if active != watched {
+                        if let Some(window) = active.filter(|w| !w.is_none()) {
+                            // The window may already be gone; the next notify recovers.
+                            let _ = self.select_window_changes(window);
+                        }
+                        watched = active;
+                    }
+                } else if ev.atom() != self.atoms.net_wm_name && ev.atom() != x::ATOM_WM_NAME {
+                    continue;
+                }
+
+                tracker.observe(self.focused(), &sender);
+            }
+
+            Box::new(super::PollingWatcher { tracker }).run(sender);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, title: &str) -> FocusedWindow {
+        FocusedWindow {
+            id,
+            title: title.to_string(),
+            app_name: Some("app".to_string()),
+            rect: None,
+        }
+    }
+
+    fn tracker() -> FocusTracker {
+        FocusTracker::new(&MemoryLimits::default(), Arc::new(TrackerUsage::default()))
+    }
+
+    fn kinds(rx: &mpsc::Receiver<WindowEvent>) -> Vec<&'static str> {
+        rx.try_iter()
+            .map(|event| match event {
+                WindowEvent::FocusChanged { .. } => "focus",
+                WindowEvent::TitleChanged { .. } => "title",
+                _ => "other",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn focus_switch_sends_focus_then_title() {
+        let (tx, rx) = mpsc::channel();
+        let mut tracker = tracker();
+        tracker.observe(Some(window(1, "Inbox")), &tx);
+        assert_eq!(kinds(&rx), ["focus", "title"]);
+        tracker.observe(Some(window(2, "main.rs")), &tx);
+        assert_eq!(kinds(&rx), ["focus", "title"]);
+        tracker.observe(Some(window(1, "Inbox")), &tx);
+        tracker.observe(Some(window(2, "main.rs")), &tx);
+        assert_eq!(kinds(&rx), ["focus", "title", "focus", "title"]);
+        tracker.observe(Some(window(2, "lib.rs")), &tx);
+        assert_eq!(kinds(&rx), ["title"]);
+        tracker.observe(Some(window(2, "lib.rs")), &tx);
+        assert_eq!(kinds(&rx), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn focus_switch_carries_the_previous_title() {
+        let (tx, rx) = mpsc::channel();
+        let mut tracker = tracker();
+        tracker.observe(Some(window(1, "draft")), &tx);
+        tracker.observe(Some(window(1, "final")), &tx);
+        tracker.observe(Some(window(2, "other")), &tx);
+        tracker.observe(Some(window(1, "final")), &tx);
+        let focus = rx
+            .try_iter()
+            .filter(|event| matches!(event, WindowEvent::FocusChanged { .. }))
+            .last();
+        let Some(WindowEvent::FocusChanged {
+            window_id,
+            window_title,
+            prev_title,
+            previous,
+            ..
+        }) = focus
+        else {
+            panic!("expected a focus change");
+        };
+        assert_eq!(window_id, 1);
+        assert_eq!(window_title, "final");
+        assert_eq!(prev_title.as_deref(), Some("draft"));
+        assert_eq!(previous.map(|w| w.id), Some(2));
+    }
+
+    #[test]
+    fn sanitize_title_drops_nuls_and_blanks_control_characters() {
+        assert_eq!(sanitize_title("Doc\0ument"), "Document");
+        assert_eq!(
+            sanitize_title("a\tb\nc\u{1b}d\u{7f}e\u{85}f"),
+            "a b c d e f"
+        );
+        assert_eq!(sanitize_title(" \r\n\0 main.rs \t\0"), "main.rs");
+        assert_eq!(sanitize_title("\0\0"), "");
+        // Not control characters, so kept.
+        assert_eq!(sanitize_title("a\u{200b}b ✓"), "a\u{200b}b ✓");
+        let lossy = String::from_utf8_lossy(b"Ti\xfftle");
+        assert_eq!(sanitize_title(&lossy), "Ti\u{fffd}tle");
+    }
+
+    #[test]
+    fn sanitize_title_truncates_on_a_char_boundary() {
+        let ascii = "a".repeat(MAX_TITLE_CHARS + 10);
+        assert_eq!(sanitize_title(&ascii), "a".repeat(MAX_TITLE_CHARS));
+
+        for wide in ["é", "漢", "🦀"] {
+            let title = sanitize_title(&wide.repeat(MAX_TITLE_CHARS + 1));
+            assert_eq!(title.chars().count(), MAX_TITLE_CHARS, "{wide}");
+            assert_eq!(title.len(), MAX_TITLE_CHARS * wide.len(), "{wide}");
+        }
+
+        // Exactly at the limit nothing is cut.
+        let exact = "🦀".repeat(MAX_TITLE_CHARS);
+        assert_eq!(sanitize_title(&exact), exact);
+    }
+
+    #[test]
+    fn sanitize_title_counts_what_is_left_after_cleaning() {
+        // Dropped NULs and trimmed whitespace don't use up the limit.
+        let padded = format!("{}  {}", "\0".repeat(100), "x".repeat(MAX_TITLE_CHARS));
+        assert_eq!(sanitize_title(&padded), "x".repeat(MAX_TITLE_CHARS));
+        // A cut that lands after a space leaves no trailing space.
+        let spaced = format!("{} {}", "a".repeat(MAX_TITLE_CHARS - 1), "b".repeat(10));
+        assert_eq!(sanitize_title(&spaced), "a".repeat(MAX_TITLE_CHARS - 1));
+    }
+
+    #[test]
+    fn names_that_clean_to_nothing_are_none() {
+        assert_eq!(sanitize_name(Some("\0\t \n".to_string())), None);
+        assert_eq!(sanitize_name(None), None);
+        assert_eq!(
+            sanitize_name(Some(" Firefox\0 ".to_string())).as_deref(),
+            Some("Firefox")
+        );
+    }
+
+    /// The title history side of the soak in `capture`: a million focus
+    /// and title changes across ever new windows. Run with
+    /// `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "soak test, takes a while"]
+    fn million_observations_keep_title_history_bounded() {
+        let limits = MemoryLimits::default();
+        let usage = Arc::new(TrackerUsage::default());
+        let mut tracker = FocusTracker::new(&limits, usage.clone());
+        let (tx, rx) = mpsc::channel();
+        for n in 0..1_000_000u32 {
+            // Each window goes through a few titles before the next opens.
+            tracker.observe(Some(window(n / 4, &format!("Title {n}"))), &tx);
+            if n % 10_000 == 0 {
+                rx.try_iter().for_each(drop);
+            }
+        }
+        let mut report = MemoryUsage::default();
+        usage.report(&limits, &mut report);
+        let (windows, titles) = (report.title_history_windows, report.title_history_titles);
+        assert_eq!(windows.len, limits.title_history_windows);
+        assert!(titles.len <= titles.cap.unwrap(), "{titles:?}");
+        assert_eq!(titles.len, limits.title_history_windows * 4);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use notify::{Event, RecursiveMode, Watcher};
+
+use crate::focus::WindowEvent;
+
+/// How long the loop waits for file events while nothing is pending.
+const IDLE_WAIT: Duration = Duration::from_secs(3600);
+
+/// Watches `paths`, not recursively, and sends a `FsTrigger` when files are
+/// created or modified in them. Events within `debounce` of the first one
+/// of a burst are folded into a single trigger carrying the latest path, so
+/// unpacking an archive yields one capture. Changes under `ignore`, the
+/// capture directory, are dropped so captures can't trigger themselves.
+/// Blocks until the capture loop goes away; returns early when no path
+/// could be watched.
+pub fn watch_paths(
+    paths: &[PathBuf],
+    ignore: &Path,
+    debounce: Duration,
+    event_sender: mpsc::Sender<WindowEvent>,
+) {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("File watcher unavailable: {e}");
+            return;
+        }
+    };
+    let mut watched = 0;
+    for path in paths {
+        match watcher.watch(path, RecursiveMode::NonRecursive) {
+            Ok(()) => watched += 1,
+            Err(e) => eprintln!("Cannot watch {}: {e}", path.display()),
+        }
+    }
+    if watched == 0 {
+        return;
+    }
+
+    let mut pending: Option<(PathBuf, Instant)> = None;
+    loop {
+        let wait = pending.as_ref().map_or(IDLE_WAIT, |(_, due)| {
+            due.saturating_duration_since(Instant::now())
+        });
+        match rx.recv_timeout(wait) {
+            Ok(Ok(event)) => {
+                if let Some(path) = trigger_path(&event, ignore) {
+                    let due = pending
+                        .take()
+                        .map_or_else(|| Instant::now() + debounce, |(_, due)| due);
+                    pending = Some((path, due));
+                }
+            }
+            Ok(Err(e)) => eprintln!("File watcher error: {e}"),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        // A steady stream of events never times out, so check here too.
+        if let Some((_, due)) = &pending
+            && *due <= Instant::now()
+        {
+            let (path, _) = pending.take().unwrap();
+            if event_sender.send(WindowEvent::FsTrigger { path }).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The file a create or modify event is about, unless it lies under
+/// `ignore`.
+fn trigger_path(event: &Event, ignore: &Path) -> Option<PathBuf> {
+    if !(event.kind.is_create() || event.kind.is_modify()) {
+        return None;
+    }
+    event
+        .paths
+        .iter()
+        .rev()
+        .find(|p| !p.starts_with(ignore))
+        .cloned()
+}
@@ -0,0 +1,55 @@
+//! Golden images for rendering tests, kept as PNGs under `tests/golden`.
+//! Run the tests with `VEEA_BLESS=1` to write the current output as the
+//! new golden, then look at it before committing.
+
+use std::path::{Path, PathBuf};
+
+use xcap::image::RgbaImage;
+
+use crate::codec::{self, ImageFormat};
+
+/// Largest per-channel difference still counted as equal, for rounding
+/// in glyph rasterization.
+const TOLERANCE: u8 = 2;
+
+/// Fails unless `image` matches `tests/golden/<name>.png`. A mismatch is
+/// saved to the temp directory for comparison.
+pub fn assert_matches(image: &RgbaImage, name: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    let png = codec::encode(image, ImageFormat::Png).unwrap();
+    if std::env::var_os("VEEA_BLESS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, png).unwrap();
+        return;
+    }
+    let golden = codec::open(&path, ImageFormat::Png).unwrap_or_else(|e| {
+        panic!(
+            "{}: {e}; run with VEEA_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    if golden.dimensions() != image.dimensions() {
+        fail(name, &path, &png, "its size".to_string());
+    }
+    let differing = image
+        .pixels()
+        .zip(golden.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0).any(|(x, y)| x.abs_diff(y) > TOLERANCE))
+        .count();
+    if differing > 0 {
+        fail(name, &path, &png, format!("{differing} pixels"));
+    }
+}
+
+/// Saves the mismatching `png` to the temp directory and panics.
+fn fail(name: &str, golden: &Path, png: &[u8], what: String) -> ! {
+    let actual = std::env::temp_dir().join(format!("veea-{name}.png"));
+    std::fs::write(&actual, png).unwrap();
+    panic!(
+        "{name} differs from {} in {what}; the output is at {}",
+        golden.display(),
+        actual.display()
+    );
+}
@@ -0,0 +1,324 @@
+//! `post_capture_hook`: a user command run after each stored capture, for
+//! feeding captures into pipelines of one's own. The capture is described
+//! only through environment variables, never spliced into the command,
+//! so a hostile window title can't inject anything. Hooks run on their
+//! own threads with a timeout and a cap on how many run at once, so a
+//! slow one can't hold up capturing; runs that fail, time out or are
+//! skipped at the cap are logged as capture attempts and counted for
+//! `/status`.
+//!
+//! With `storage_mode = "packs"` the image has no file of its own, so
+//! `VEEA_PATH` is empty and `VEEA_PACK_PATH`, `VEEA_PACK_OFFSET` and
+//! `VEEA_PACK_LEN` say where in its pack the encoded bytes lie. Copying
+//! them out for every hook would double the writes packs exist to save.
+
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::CaptureConfig,
+    db::{CaptureRecord, Db},
+};
+
+/// Bytes of a failed hook's stderr kept in its attempt.
+const STDERR_LIMIT: usize = 1024;
+/// How often a running hook is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Hooks that failed, timed out or were skipped since the daemon started.
+pub fn failures() -> u64 {
+    FAILURES.load(Ordering::Relaxed)
+}
+
+#[derive(Clone)]
+pub struct PostCaptureHook {
+    command: String,
+    timeout: Duration,
+    max_running: usize,
+    db_path: PathBuf,
+    capture_dir: PathBuf,
+    /// Where failures are recorded, opened on the first one and shared by
+    /// every run of this hook.
+    attempts: Arc<Mutex<Option<Db>>>,
+}
+
+impl PostCaptureHook {
+    /// The configured hook, if any.
+    pub fn from_config(config: &CaptureConfig) -> Option<Self> {
+        let command = config.post_capture_hook.as_deref()?.trim();
+        (!command.is_empty()).then(|| Self {
+            command: command.to_string(),
+            timeout: Duration::from_millis(config.hook_timeout_ms),
+            max_running: config.hook_max_running.max(1),
+            db_path: config.db_path.clone(),
+            capture_dir: config.capture_dir.clone(),
+            attempts: Arc::default(),
+        })
+    }
+
+    /// Starts the hook for `record` in the background, unless
+    /// `hook_max_running` hooks are still going.
+    pub fn run(&self, record: &CaptureRecord) {
+        let claimed = RUNNING.fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+            (running < self.max_running).then_some(running + 1)
+        });
+        if claimed.is_err() {
+            let detail = format!("hook_max_running ({}) reached", self.max_running);
+            self.failed(record, "hook_skipped", &detail);
+            return;
+        }
+        let hook = self.clone();
+        let record = record.clone();
+        thread::spawn(move || {
+            if let Err((outcome, detail)) = hook.execute(&record) {
+                hook.failed(&record, outcome, &detail);
+            }
+            RUNNING.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+
+    fn execute(&self, record: &CaptureRecord) -> Result<(), (&'static str, String)> {
+        let mut command = shell(&self.command);
+        match &record.pack {
+            Some(slot) => command
+                .env("VEEA_PATH", "")
+                .env("VEEA_PACK_PATH", self.capture_dir.join(&slot.path))
+                .env("VEEA_PACK_OFFSET", slot.offset.to_string())
+                .env("VEEA_PACK_LEN", slot.len.to_string()),
+            None => command.env("VEEA_PATH", &record.path),
+        };
+        let mut child = command
+            .env("VEEA_CAPTURE_ID", &record.id)
+            .env("VEEA_TITLE", record.window_title.as_deref().unwrap_or(""))
+            .env("VEEA_APP", record.app_name.as_deref().unwrap_or(""))
+            .env("VEEA_TS", record.ts.timestamp_millis().to_string())
+            .env("VEEA_EVENT_TYPE", &record.event_type)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ("hook_failed", format!("cannot start: {e}")))?;
+
+        // Read stderr alongside, so a chatty hook can't fill the pipe and
+        // stall.
+        let stderr = child.stderr.take().map(|mut pipe| {
+            thread::spawn(move || {
+                let mut kept = Vec::new();
+                let _ = (&mut pipe).take(STDERR_LIMIT as u64).read_to_end(&mut kept);
+                let _ = std::io::copy(&mut pipe, &mut std::io::sink());
+                kept
+            })
+        });
+        let Some(status) = wait(&mut child, self.timeout) else {
+            // Whatever the hook started may still hold stderr open, so it
+            // isn't waited for.
+            let detail = format!("killed after {} ms", self.timeout.as_millis());
+            return Err(("hook_timeout", detail));
+        };
+        if status.success() {
+            return Ok(());
+        }
+        let stderr = stderr
+            .and_then(|reader| reader.join().ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .unwrap_or_default();
+        let detail = if stderr.is_empty() {
+            status.to_string()
+        } else {
+            format!("{status}: {stderr}")
+        };
+        Err(("hook_failed", detail))
+    }
+
+    /// Logs a hook that didn't succeed as an attempt of `record`'s event
+    /// type; the detail names the capture but, like other attempts, not
+    /// its title.
+    fn failed(&self, record: &CaptureRecord, outcome: &str, detail: &str) {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+        eprintln!("post_capture_hook for {} {outcome}: {detail}", record.id);
+        let detail = format!("capture {}: {detail}", record.id);
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let recorded = match &mut *attempts {
+            Some(db) => db.insert_attempt(&record.event_type, outcome, Some(&detail)),
+            slot => Db::new(&self.db_path).and_then(|db| {
+                db.insert_attempt(&record.event_type, outcome, Some(&detail))?;
+                *slot = Some(db);
+                Ok(())
+            }),
+        };
+        if let Err(e) = recorded {
+            eprintln!("Recording the hook outcome failed: {e}");
+        }
+    }
+}
+
+/// `command` run through the platform shell, so hooks can use pipes and
+/// arguments as typed in the config. On Unix the shell leads a process
+/// group of its own, so a timeout can kill whatever it started too.
+fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut shell, 0);
+    shell.arg(command);
+    shell
+}
+
+/// Kills `child` and, on Unix, the rest of the process group `shell` put
+/// it at the head of.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        const SIGKILL: i32 = 9;
+
+        unsafe extern "C" {
+            fn kill(pid: i32, sig: i32) -> i32;
+        }
+
+        if let Ok(pid) = i32::try_from(child.id()) {
+            // SAFETY: plain syscall wrapper with no pointers involved; a
+            // negative pid addresses the group led by the child.
+            unsafe { kill(-pid, SIGKILL) };
+        }
+    }
+    let _ = child.kill();
+}
+
+/// The child's exit status, or `None` once it has been killed for running
+/// past `timeout`.
+fn wait(child: &mut Child, timeout: Duration) -> Option<std::process::ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            _ => {
+                kill(child);
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::pack::PackSlot;
+
+    /// Runs a hook for `record` that prints where the image is, and
+    /// returns what it printed.
+    #[cfg(unix)]
+    fn locate(record: &CaptureRecord) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.txt");
+        let config = CaptureConfig {
+            capture_dir: dir.path().join("captures"),
+            db_path: dir.path().join("veea.db"),
+            post_capture_hook: Some(format!(
+                "printf '%s|%s|%s|%s' \"$VEEA_PATH\" \"$VEEA_PACK_PATH\" \
+                 \"$VEEA_PACK_OFFSET\" \"$VEEA_PACK_LEN\" > '{}'",
+                out.display()
+            )),
+            ..Default::default()
+        };
+        PostCaptureHook::from_config(&config)
+            .unwrap()
+            .execute(record)
+            .unwrap();
+        std::fs::read_to_string(out)
+            .unwrap()
+            .replace(&*dir.path().to_string_lossy(), "<dir>")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_mode_passes_the_image_path() {
+        let mut record = CaptureRecord::sample("0001", Utc::now());
+        record.path = "/captures/0001.png".to_string();
+        assert_eq!(locate(&record), "/captures/0001.png|||");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn packs_mode_passes_the_slot_instead() {
+        let mut record = CaptureRecord::sample("0001", Utc::now());
+        record.pack = Some(PackSlot {
+            path: "packs/2024/06/05/12.vpack".to_string(),
+            offset: 4096,
+            len: 1234,
+        });
+        assert_eq!(
+            locate(&record),
+            "|<dir>/captures/packs/2024/06/05/12.vpack|4096|1234"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout_kills_what_the_hook_started() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+        let config = CaptureConfig {
+            db_path: dir.path().join("veea.db"),
+            post_capture_hook: Some(format!("(sleep 1; touch '{}') & sleep 5", marker.display())),
+            hook_timeout_ms: 200,
+            ..Default::default()
+        };
+        let hook = PostCaptureHook::from_config(&config).unwrap();
+        let record = CaptureRecord::sample("0001", Utc::now());
+        let (outcome, _) = hook.execute(&record).unwrap_err();
+        assert_eq!(outcome, "hook_timeout");
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn failures_share_one_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CaptureConfig {
+            db_path: dir.path().join("veea.db"),
+            post_capture_hook: Some("exit 1".to_string()),
+            ..Default::default()
+        };
+        let hook = PostCaptureHook::from_config(&config).unwrap();
+        let record = CaptureRecord::sample("0001", Utc::now());
+        hook.failed(&record, "hook_failed", "exit status: 1");
+        assert!(hook.attempts.lock().unwrap().is_some());
+        hook.clone()
+            .failed(&record, "hook_failed", "exit status: 1");
+
+        let db = Db::new(&config.db_path).unwrap();
+        let count: i64 = db
+            .open_reader()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM capture_attempts WHERE outcome = 'hook_failed'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
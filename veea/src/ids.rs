@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// UUID version of new capture ids. Both kinds are accepted wherever an
+/// id is looked up; only new captures are affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdVersion {
+    /// Random.
+    V4,
+    /// Starts with the creation time in milliseconds, so ids sort in
+    /// creation order and inserts land at the end of the primary key.
+    #[default]
+    V7,
+}
+
+impl IdVersion {
+    /// Version for configs written before the option existed, which keep
+    /// random ids until switched explicitly.
+    pub fn legacy() -> Self {
+        IdVersion::V4
+    }
+
+    pub fn new_id(self) -> String {
+        match self {
+            IdVersion::V4 => Uuid::new_v4(),
+            IdVersion::V7 => Uuid::now_v7(),
+        }
+        .to_string()
+    }
+}
+
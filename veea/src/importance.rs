@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How far back title changes count towards a capture's activity.
+const CHURN_WINDOW: Duration = Duration::minutes(10);
+/// Title changes within `CHURN_WINDOW` that earn the full churn weight.
+const CHURN_SATURATION: usize = 20;
+/// OCR characters that earn the full text weight.
+const OCR_SATURATION: usize = 2000;
+
+/// Weights of the heuristic importance score stored with each capture.
+/// Scores are only compared with each other, so the scale is arbitrary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportanceWeights {
+    /// Base score per event type; types not listed get `default_event`.
+    pub events: BTreeMap<String, f64>,
+    pub default_event: f64,
+    /// Added in proportion to how often titles changed in the last ten
+    /// minutes, as a sign of active work.
+    pub title_churn: f64,
+    /// Added in proportion to the amount of recognized text.
+    pub ocr_text: f64,
+}
+
+impl Default for ImportanceWeights {
+    fn default() -> Self {
+        let events = [
+            ("snapshot", 3.0),
+            ("priority_focus", 2.0),
+            ("focus", 1.5),
+            ("wake", 1.0),
+            ("title", 1.0),
+            ("focus_prev", 0.8),
+            ("fs", 1.0),
+            ("geometry", 0.5),
+            ("burst", 0.5),
+            ("interval", 0.2),
+        ];
+        Self {
+            events: events.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            default_event: 1.0,
+            title_churn: 1.0,
+            ocr_text: 1.0,
+        }
+    }
+}
+
+/// Recent title-change times, pruned to `CHURN_WINDOW`.
+#[derive(Debug, Default)]
+pub struct TitleChurn {
+    changes: VecDeque<DateTime<Utc>>,
+}
+
+impl TitleChurn {
+    pub fn record(&mut self, now: DateTime<Utc>) {
+        self.prune(now);
+        self.changes.push_back(now);
+        // Past saturation older entries add nothing; keep memory flat.
+        if self.changes.len() > CHURN_SATURATION {
+            self.changes.pop_front();
+        }
+    }
+
+    /// Share of `CHURN_SATURATION` reached within the window, 0 to 1.
+    pub fn level(&mut self, now: DateTime<Utc>) -> f64 {
+        self.prune(now);
+        self.changes.len() as f64 / CHURN_SATURATION as f64
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        while self.changes.front().is_some_and(|ts| now - *ts > CHURN_WINDOW) {
+            self.changes.pop_front();
+        }
+    }
+}
+
+impl ImportanceWeights {
+    /// Score of a capture of `event_type` taken at title churn `churn`
+    /// (0 to 1) with `ocr_chars` of recognized text.
+    pub fn score(&self, event_type: &str, churn: f64, ocr_chars: usize) -> f64 {
+        let base = self
+            .events
+            .get(event_type)
+            .copied()
+            .unwrap_or(self.default_event);
+        let text = ocr_chars.min(OCR_SATURATION) as f64 / OCR_SATURATION as f64;
+        base + self.title_churn * churn.clamp(0.0, 1.0) + self.ocr_text * text
+    }
+}
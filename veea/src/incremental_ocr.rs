@@ -0,0 +1,283 @@
+//! `incremental_ocr`: reads only the rows of a frame that changed since
+//! the last one OCR'd. The comparison is private to OCR, an exact row
+//! diff against one remembered frame. It never skips or deduplicates a
+//! capture, so it is not the change detection dedup statistics are still
+//! waiting for. Off by default until misreads at band edges have been
+//! measured on real screens.
+
+use std::ops::Range;
+
+use xcap::image::{imageops, RgbaImage};
+
+use crate::{
+    error::AppResult,
+    ocr::{Ocr, OcrLine, OcrResult},
+};
+
+/// Rows above and below a change that are read along with it, so glyphs
+/// that only partly changed are read whole.
+const BAND_MARGIN: u32 = 8;
+/// Past this share of the frame's height a change is read in full;
+/// cropping no longer saves much.
+const MAX_BAND_SHARE: f64 = 0.5;
+/// Incremental reads in a row before a full one, so misreads at band
+/// edges don't carry forward forever.
+const MAX_CHAIN: u32 = 20;
+
+/// Rows in which two frames of the same size differ, or `None` when they
+/// are identical.
+pub fn changed_rows(base: &RgbaImage, frame: &RgbaImage) -> Option<Range<u32>> {
+    let stride = frame.width() as usize * 4;
+    if stride == 0 || base.dimensions() != frame.dimensions() {
+        return None;
+    }
+    let mut rows = base
+        .as_raw()
+        .chunks_exact(stride)
+        .zip(frame.as_raw().chunks_exact(stride));
+    let top = rows.position(|(a, b)| a != b)?;
+    // `rows` now starts just after `top`.
+    let bottom = rows.rposition(|(a, b)| a != b).map_or(top, |i| top + 1 + i) + 1;
+    Some(top as u32..bottom as u32)
+}
+
+/// OCR state carried between captures: the last frame read and the lines
+/// found in it. The next frame of the same app and size is compared with
+/// it, and only the band of rows that changed goes through the backend;
+/// the other lines are carried forward.
+#[derive(Default)]
+pub struct IncrementalOcr {
+    base: Option<Base>,
+}
+
+struct Base {
+    app: Option<String>,
+    image: RgbaImage,
+    lines: Vec<OcrLine>,
+    /// Incremental reads since the last full one.
+    chain: u32,
+}
+
+impl IncrementalOcr {
+    pub fn recognize(
+        &mut self,
+        ocr: &dyn Ocr,
+        app: Option<&str>,
+        image: &RgbaImage,
+    ) -> AppResult<OcrResult> {
+        let base = self.base.take().filter(|b| {
+            b.app.as_deref() == app && b.image.dimensions() == image.dimensions() && b.chain < MAX_CHAIN
+        });
+        let mut chain = 0;
+        let mut result = None;
+        if let Some(base) = base {
+            match changed_rows(&base.image, image) {
+                None => {
+                    chain = base.chain;
+                    result = Some(OcrResult::from_lines(base.lines));
+                }
+                Some(rows) => {
+                    if let Some(band) = band(&base.lines, rows, image.height()) {
+                        chain = base.chain + 1;
+                        result = read_band(ocr, image, &base.lines, band)?;
+                    }
+                }
+            }
+        }
+        let result = match result {
+            Some(result) => result,
+            None => {
+                chain = 0;
+                ocr.extract(image)?
+            }
+        };
+
+        // Text without positions can't be carried forward.
+        if result.lines.is_empty() && !result.text.is_empty() {
+            return Ok(result);
+        }
+        self.base = Some(Base {
+            app: app.map(str::to_string),
+            image: image.clone(),
+            lines: result.lines.clone(),
+            chain,
+        });
+        Ok(result)
+    }
+}
+
+/// Full-width rows to read again for a change in `rows`, grown to cover
+/// every known line it touches since a crop through a line would split
+/// its words. `None` when that is most of the frame.
+fn band(lines: &[OcrLine], rows: Range<u32>, height: u32) -> Option<Range<u32>> {
+    let mut top = rows.start.saturating_sub(BAND_MARGIN);
+    let mut bottom = (rows.end + BAND_MARGIN).min(height);
+    loop {
+        let mut grown = false;
+        for line in lines {
+            let line_bottom = line.bottom().min(height);
+            if line.y < bottom && line_bottom > top && (line.y < top || line_bottom > bottom) {
+                top = top.min(line.y);
+                bottom = bottom.max(line_bottom);
+                grown = true;
+            }
+        }
+        if !grown {
+            break;
+        }
+    }
+    ((bottom - top) as f64 <= height as f64 * MAX_BAND_SHARE).then_some(top..bottom)
+}
+
+/// Reads `band` of `image` and merges it with the lines of `base_lines`
+/// outside it. `None` when the backend reports no positions.
+fn read_band(
+    ocr: &dyn Ocr,
+    image: &RgbaImage,
+    base_lines: &[OcrLine],
+    band: Range<u32>,
+) -> AppResult<Option<OcrResult>> {
+    let crop = imageops::crop_imm(image, 0, band.start, image.width(), band.end - band.start);
+    let fresh = ocr.extract(&crop.to_image())?;
+    if fresh.lines.is_empty() && !fresh.text.is_empty() {
+        return Ok(None);
+    }
+    let kept = base_lines
+        .iter()
+        .filter(|l| l.bottom() <= band.start || l.y >= band.end)
+        .cloned();
+    let read = fresh.lines.into_iter().map(|mut l| {
+        l.y += band.start;
+        l
+    });
+    Ok(Some(OcrResult::from_lines(kept.chain(read).collect())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use xcap::image::Rgba;
+
+    use super::*;
+
+    fn frame(height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(40, height, Rgba([255, 255, 255, 255]))
+    }
+
+    fn line(text: &str, y: u32, height: u32) -> OcrLine {
+        OcrLine {
+            text: text.to_string(),
+            x: 0,
+            y,
+            width: 40,
+            height,
+        }
+    }
+
+    #[test]
+    fn changed_rows_spans_the_first_to_last_difference() {
+        let base = frame(100);
+        assert_eq!(changed_rows(&base, &base.clone()), None);
+        let mut edited = base.clone();
+        edited.put_pixel(3, 20, Rgba([0, 0, 0, 255]));
+        assert_eq!(changed_rows(&base, &edited), Some(20..21));
+        edited.put_pixel(39, 61, Rgba([0, 0, 0, 255]));
+        assert_eq!(changed_rows(&base, &edited), Some(20..62));
+        edited.put_pixel(0, 99, Rgba([0, 0, 0, 255]));
+        assert_eq!(changed_rows(&base, &edited), Some(20..100));
+        // Frames of another size aren't compared.
+        assert_eq!(changed_rows(&base, &frame(99)), None);
+    }
+
+    #[test]
+    fn band_grows_to_whole_lines() {
+        let lines = [
+            line("title", 10, 12),
+            line("body", 40, 12),
+            line("footer", 80, 12),
+        ];
+        // The margin reaches into "body", so it is read whole.
+        assert_eq!(band(&lines, 55..56, 200), Some(40..64));
+        // A line pulled in can pull in the next one it overlaps.
+        let stacked = [line("a", 30, 12), line("b", 40, 12), line("c", 50, 12)];
+        assert_eq!(band(&stacked, 60..61, 200), Some(30..69));
+        // Clamped to the frame.
+        assert_eq!(band(&[], 195..200, 200), Some(187..200));
+        // Most of the frame is read in full.
+        assert_eq!(band(&lines, 10..90, 100), None);
+    }
+
+    /// Reads each row holding dark pixels as a line naming how many,
+    /// counting the reads and the rows it was given.
+    struct RowOcr {
+        reads: Cell<u32>,
+        rows: Cell<u32>,
+    }
+
+    impl Ocr for RowOcr {
+        fn name(&self) -> &'static str {
+            "rows"
+        }
+
+        fn extract(&self, img: &RgbaImage) -> AppResult<OcrResult> {
+            self.reads.set(self.reads.get() + 1);
+            self.rows.set(img.height());
+            let lines = (0..img.height())
+                .filter_map(|y| {
+                    let dark = (0..img.width())
+                        .filter(|&x| img.get_pixel(x, y)[0] == 0)
+                        .count();
+                    (dark > 0).then(|| line(&format!("{dark} dark"), y.saturating_sub(5), 10))
+                })
+                .collect();
+            Ok(OcrResult::from_lines(lines))
+        }
+    }
+
+    /// Darkens the first `count` pixels of row `y`.
+    fn mark(image: &mut RgbaImage, y: u32, count: u32) {
+        for x in 0..count {
+            image.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn only_the_changed_band_is_read() {
+        let ocr = RowOcr {
+            reads: Cell::new(0),
+            rows: Cell::new(0),
+        };
+        let mut incremental = IncrementalOcr::default();
+        // A line every 20 rows, the nth one n pixels long.
+        let mut base = frame(200);
+        for i in 0..10 {
+            mark(&mut base, i * 20 + 5, i + 1);
+        }
+        let first = incremental.recognize(&ocr, Some("editor"), &base).unwrap();
+        assert_eq!((ocr.reads.get(), ocr.rows.get()), (1, 200));
+        assert_eq!(first.lines.len(), 10);
+
+        // An unchanged frame isn't read again.
+        let same = incremental.recognize(&ocr, Some("editor"), &base).unwrap();
+        assert_eq!((ocr.reads.get(), same.text), (1, first.text.clone()));
+
+        let mut edited = base.clone();
+        mark(&mut edited, 105, 9);
+        let result = incremental
+            .recognize(&ocr, Some("editor"), &edited)
+            .unwrap();
+        assert_eq!(ocr.reads.get(), 2);
+        assert!(ocr.rows.get() < 40, "read {} rows", ocr.rows.get());
+        let texts: Vec<&str> = result.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts.len(), 10);
+        assert_eq!(texts[4..7], ["5 dark", "9 dark", "7 dark"]);
+        assert_eq!(result.lines[5].y, 100);
+
+        // Another app starts over with a full read.
+        incremental
+            .recognize(&ocr, Some("browser"), &edited)
+            .unwrap();
+        assert_eq!((ocr.reads.get(), ocr.rows.get()), (3, 200));
+    }
+}
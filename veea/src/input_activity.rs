@@ -0,0 +1,102 @@
+//! Coarse keyboard and mouse activity for `track_input_counts` and
+//! `track_activity_level`: how many keys were pressed and buttons clicked
+//! in the last minute, never which ones. Listening needs the `input_counts` feature; where the platform
+//! refuses the hook (no X server, or no accessibility permission on
+//! macOS) the counts just stay at zero.
+
+use std::{
+    sync::{Mutex, Once, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Whether this build can listen for input at all.
+pub const SUPPORTED: bool = cfg!(feature = "input_counts");
+
+/// Presses in the minute before a capture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputCounts {
+    pub keys: u32,
+    pub clicks: u32,
+}
+
+/// Presses per minute, keys and clicks together, from which a capture's
+/// `activity_level` is 1, 2 and 3: the odd click while reading, steady
+/// use, and typing.
+const ACTIVITY_THRESHOLDS: [u32; 3] = [1, 20, 80];
+
+/// One slot per second of the last minute, indexed by the Unix second
+/// modulo 60: the second it counts, then its key and click presses.
+static SECONDS: Mutex<[(i64, u32, u32); 60]> = Mutex::new([(0, 0, 0); 60]);
+static LISTENER: Once = Once::new();
+
+/// Starts the listener thread unless it already runs. It lives as long
+/// as the process, so turning tracking off only stops the counts being
+/// stored.
+pub fn start() {
+    LISTENER.call_once(platform_listen);
+}
+
+/// Presses counted over the last 60 seconds.
+pub fn last_minute() -> InputCounts {
+    let now = unix_second();
+    let seconds = SECONDS.lock().unwrap_or_else(PoisonError::into_inner);
+    seconds
+        .iter()
+        .filter(|(second, _, _)| now - second < 60)
+        .fold(InputCounts::default(), |total, &(_, keys, clicks)| {
+            InputCounts {
+                keys: total.keys + keys,
+                clicks: total.clicks + clicks,
+            }
+        })
+}
+
+/// 0 for no input at all up to 3 for typing, see `ACTIVITY_THRESHOLDS`.
+pub fn activity_level(counts: InputCounts) -> u8 {
+    let presses = counts.keys + counts.clicks;
+    ACTIVITY_THRESHOLDS
+        .iter()
+        .filter(|&&threshold| presses >= threshold)
+        .count() as u8
+}
+
+#[cfg_attr(not(feature = "input_counts"), allow(dead_code))]
+fn count(keys: u32, clicks: u32) {
+    let now = unix_second();
+    let mut seconds = SECONDS.lock().unwrap_or_else(PoisonError::into_inner);
+    let slot = &mut seconds[now.rem_euclid(60) as usize];
+    if slot.0 != now {
+        *slot = (now, 0, 0);
+    }
+    slot.1 += keys;
+    slot.2 += clicks;
+}
+
+fn unix_second() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(feature = "input_counts")]
+fn platform_listen() {
+    use rdev::EventType;
+
+    // `listen` blocks for good once the hook is in, so it gets a thread of
+    // its own; the event's key or button is dropped unread.
+    std::thread::spawn(|| {
+        let listened = rdev::listen(|event| match event.event_type {
+            EventType::KeyPress(_) => count(1, 0),
+            EventType::ButtonPress(_) => count(0, 1),
+            _ => {}
+        });
+        if let Err(e) = listened {
+            eprintln!("Input counting unavailable, storing zeros: {e:?}");
+        }
+    });
+}
+
+#[cfg(not(feature = "input_counts"))]
+fn platform_listen() {
+    eprintln!("Input tracking is on, but this build lacks the `input_counts` feature");
+}
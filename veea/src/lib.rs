@@ -0,0 +1,61 @@
+//! Veea's capture daemon, HTTP API, and (with the `client` feature) a
+//! typed client for that API.
+
+pub mod api;
+pub mod api_error;
+pub mod app_id;
+pub mod ax_text;
+pub mod base64;
+pub mod cache;
+pub mod capture;
+pub mod capture_delay;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod clipboard;
+pub mod code_text;
+pub mod codec;
+pub mod color_profile;
+pub mod config;
+pub mod cors;
+pub mod crypto;
+pub mod cursor;
+pub mod db;
+pub mod devices;
+pub mod disk;
+pub mod display;
+pub mod document;
+pub mod error;
+pub mod expose;
+pub mod feed;
+pub mod focus;
+pub mod fs_watch;
+#[cfg(test)]
+mod golden;
+pub mod hooks;
+pub mod ids;
+pub mod importance;
+pub mod incremental_ocr;
+pub mod input_activity;
+pub mod memory;
+pub mod notification_watch;
+pub mod notify;
+pub mod objects;
+pub mod ocr;
+pub mod overlay;
+pub mod pack;
+pub mod passphrase_backoff;
+pub mod power;
+pub mod priority;
+pub mod quality;
+pub mod report;
+pub mod schedule;
+pub mod search;
+pub mod session;
+pub mod story;
+pub mod thumbnail_cache;
+pub mod timeline;
+pub mod timezone;
+pub mod trigger;
+pub mod viz;
+pub mod workspace;
+pub mod writer;
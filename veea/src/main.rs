@@ -1,160 +1,451 @@
-mod api;
-mod capture;
-mod config;
-mod db;
-mod error;
-mod search;
+use veea::{
+    api, cache, db, display, expose, fs_watch, notification_watch, notify, objects, pack, power,
+    priority, report, session,
+};
 
 use std::{
     sync::mpsc,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-
-use capture::CaptureEngine;
-use config::{CaptureConfig, DEFAULT_CONFIG_PATH};
-use error::{AppError, AppResult};
-use std::net::SocketAddr;
-use xcap::Window;
-use std::path::Path;
-
-#[derive(Debug, Clone)]
-enum WindowEvent {
-    FocusChanged { window_title: String },
-    TitleChanged { window_title: String },
-    Periodic { window_title: String },
-}
-
-fn get_focused_window() -> Option<(u32, String)> {
-    if let Ok(windows) = Window::all() {
-        for window in windows {
-            if let Ok(is_minimized) = window.is_minimized() {
-                if !is_minimized {
-                    if let Ok(title) = window.title() {
-                        if !title.is_empty() {
-                            if let Ok(window_id) = window.id() {
-                                return Some((window_id, title));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-    None
-}
-
-fn monitor_window_events(event_sender: mpsc::Sender<WindowEvent>) {
-    let mut last_focused_window_id: Option<u32> = None;
-    let mut last_window_title: Option<String> = None;
-
-    loop {
-        if let Some((window_id, window_title)) = get_focused_window() {
-            if last_focused_window_id != Some(window_id) {
-                let _ = event_sender.send(WindowEvent::FocusChanged {
-                    window_title: window_title.clone(),
-                });
-                last_focused_window_id = Some(window_id);
-            }
-
-            if last_focused_window_id == Some(window_id) {
-                if last_window_title.as_ref() != Some(&window_title) {
-                    let _ = event_sender.send(WindowEvent::TitleChanged {
-                        window_title: window_title.clone(),
-                    });
-                }
-            }
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-            last_window_title = Some(window_title);
-        } else {
-            if last_focused_window_id.is_some() {
-                last_focused_window_id = None;
-                last_window_title = None;
-            }
-        }
-
-        thread::sleep(Duration::from_millis(200));
-    }
-}
-
-fn monitor_periodic(event_sender: mpsc::Sender<WindowEvent>, interval_ms: u64) {
-    loop {
-        if let Some((_id, title)) = get_focused_window() {
-            let _ = event_sender.send(WindowEvent::Periodic { window_title: title });
-        }
-        thread::sleep(Duration::from_millis(interval_ms));
-    }
-}
+use veea::capture::{self, CaptureEngine};
+use veea::capture_delay::{CaptureDelay, PendingCapture};
+use veea::codec::{self, ImageFormat};
+use veea::config::{self, CaptureConfig};
+use veea::error::{AppError, AppResult};
+use veea::focus::{self, monitor_periodic, WindowEvent};
+use veea::memory::CollectionSize;
+use veea::thumbnail_cache::ThumbnailCache;
+use veea::trigger::{TitleFilter, Trigger, TriggerPolicy};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
 
 fn run() -> AppResult<()> {
     println!("Starting capture daemon...");
-    let config = CaptureConfig::load_or_init(Path::new(DEFAULT_CONFIG_PATH))?;
-    let db = db::Db::new(&config.db_path)?;
-    let pause_flag = Arc::new(AtomicBool::new(false));
+    let config_path = config::default_config_path();
+    let _instance_lock = lock_instance(&config_path)?;
+    // Without a config the web UI walks through setup first; nothing is
+    // captured until it is done.
+    let first_run = !config_path.exists();
+    let mut config = CaptureConfig::load_or_default(&config_path)?;
+    expose::validate(&config)?;
+    let db = db::Db::open(&config)?;
+    db.insert_daemon_event("start")?;
+    let pause_flag = Arc::new(AtomicBool::new(first_run));
     let mut engine = CaptureEngine::new(config.clone(), db, pause_flag.clone())?;
+    let tracker_usage = Arc::new(focus::TrackerUsage::default());
+    let watcher = focus::select_watcher(&config.memory, tracker_usage.clone());
+    println!("Focus watcher backend: {}", watcher.backend());
+
+    let on_battery = Arc::new(AtomicBool::new(false));
+    let periodic_interval = Arc::new(AtomicU64::new(config.capture_interval_ms));
+
+    let (tx, rx) = mpsc::channel();
     let api_state = api::ApiState {
         db_path: engine.db_path(),
-        config: config.clone(),
+        config: Arc::new(RwLock::new(config.clone())),
+        config_path: config_path.clone(),
         search_index_path: config.search_index_path.clone(),
         pause_flag: pause_flag.clone(),
+        focus_backend: watcher.backend(),
+        events: tx.clone(),
+        bursts: Default::default(),
+        on_battery: on_battery.clone(),
+        image_reads: Arc::new(tokio::sync::Semaphore::new(
+            config.max_concurrent_image_reads.max(1),
+        )),
+        thumbnails: Arc::new(ThumbnailCache::new(config.thumb_cache_mb * 1024 * 1024)),
+        passphrase_backoff: Default::default(),
     };
 
-    let (tx, rx) = mpsc::channel();
-
     let watcher_tx = tx.clone();
     thread::spawn(move || {
-        monitor_window_events(watcher_tx);
+        watcher.run(watcher_tx);
     });
 
     // Start local API server
     let api_handle = api_state.clone();
+    let api_addr = config.api_addr;
+    let addr_file = config::api_addr_file(&config_path);
     thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().expect("failed to create tokio runtime");
-        let addr: SocketAddr = "127.0.0.1:8787"
-            .parse()
-            .expect("failed to parse default API address");
-        if let Err(e) = rt.block_on(api::serve(addr, api_handle)) {
+        let result = rt.block_on(async {
+            let listener = api::bind(api_addr).await?;
+            let bound = listener.local_addr()?;
+            println!("API listening on http://{bound}");
+            if first_run {
+                println!("No config yet; finish setup at http://{bound}/ to start capturing");
+            }
+            // Lets `veea status` and clients find this instance's port.
+            std::fs::write(&addr_file, bound.to_string())?;
+            api::serve(listener, api_handle).await
+        });
+        if let Err(e) = result {
             eprintln!("API server failed: {e}");
         }
     });
 
-    if config.capture_interval_ms > 0 {
+    if config.pause_when_locked {
+        let lock_tx = tx.clone();
+        thread::spawn(move || session::monitor_session_lock(lock_tx));
+    }
+
+    if config.pause_when_displays_off {
+        let display_tx = tx.clone();
+        thread::spawn(move || display::monitor_displays(display_tx));
+    }
+
+    let battery_overrides = config.battery_overrides();
+    if battery_overrides.is_some() {
+        let power_tx = tx.clone();
+        thread::spawn(move || power::monitor_power(power_tx));
+    }
+
+    if config.post_wake_delay_ms > 0 || config.capture_after_wake {
+        let wake_tx = tx.clone();
+        thread::spawn(move || power::monitor_wake(wake_tx));
+    }
+
+    let battery_interval = battery_overrides
+        .and_then(|p| p.capture_interval_ms)
+        .unwrap_or(0);
+    // Setup may turn on periodic capture later.
+    if first_run || config.capture_interval_ms > 0 || battery_interval > 0 {
         let periodic_tx = tx.clone();
-        let interval = config.capture_interval_ms;
+        let interval = periodic_interval.clone();
         thread::spawn(move || monitor_periodic(periodic_tx, interval));
     }
 
+    if !config.watch_paths.is_empty() {
+        let fs_tx = tx.clone();
+        let paths = config.watch_paths.clone();
+        let ignore = config.capture_dir.clone();
+        let debounce = Duration::from_millis(config.fs_debounce_ms);
+        thread::spawn(move || fs_watch::watch_paths(&paths, &ignore, debounce, fs_tx));
+    }
+
+    if config.capture_on_notification {
+        if notification_watch::SUPPORTED {
+            let notification_tx = tx.clone();
+            thread::spawn(move || notification_watch::watch_notifications(notification_tx));
+        } else {
+            eprintln!("capture_on_notification is not supported on this platform, ignoring it");
+        }
+    }
+
+    if config.daily_report {
+        let report_config = config.clone();
+        thread::spawn(move || schedule_reports(&report_config));
+    }
+
+    cache::init(&config);
+    let cache_config = config.clone();
+    thread::spawn(move || sweep_cache(&cache_config));
+
+    // Opened once here, so the hourly checks don't run the migrations each
+    // time.
+    let maintenance_tx = tx.clone();
+    let maintenance_db = db::Db::new(&config.db_path)?;
+    thread::spawn(move || schedule_analyze(maintenance_tx, &maintenance_db));
+
+    if config.changes_retention_days > 0 {
+        let changes_db = db::Db::new(&config.db_path)?;
+        let retention = chrono::Duration::days(config.changes_retention_days.into());
+        thread::spawn(move || prune_changes(&changes_db, retention));
+    }
+
     println!(
         "Monitoring window events... captures stored under {:?}",
         config.capture_dir
     );
 
+    // Only this thread and the image writer, which lowers itself, capture
+    // and run OCR; the API and watchers spawned above keep their normal
+    // priority.
+    if config.low_priority
+        && let Err(e) = priority::lower_current_thread()
+    {
+        eprintln!("Could not lower capture priority: {e}");
+    }
+
+    let mut notifier = notify::ErrorNotifier::new(&config);
+    let policy = TriggerPolicy::new(&config.triggers);
+    let mut title_filter = TitleFilter::new(config.title_min_change);
+    let mut geometry_generation = 0u64;
+    let mut capture_delay = CaptureDelay::new(config.capture_delay_ms);
     for event in rx {
         match event {
-            WindowEvent::FocusChanged { window_title }
-                if config.capture_on_focus =>
-            {
-                println!("Focus changed to: {}", window_title);
-                if let Err(e) = engine.capture_event(&window_title, "focus") {
-                    eprintln!("Capture failed: {}", e);
+            WindowEvent::FocusChanged {
+                window_id,
+                window_title,
+                app_name,
+                previous,
+                prev_title,
+            } => {
+                // A capture still pending would now find another window.
+                capture_delay.focus_moved(window_id);
+                title_filter.focused(&window_title);
+                if let Some(prev) = previous.filter(|_| config.capture_previous_on_focus)
+                    && let Err(e) =
+                        engine.capture_previous_window(&prev.title, prev.app_name.as_deref())
+                {
+                    eprintln!("Capture of previous window failed: {}", e);
+                    notifier.notify(&e);
+                }
+                match engine.capture_priority_focus(&window_title, app_name.as_deref()) {
+                    Ok(true) => {
+                        println!("Priority app focused: {}", window_title);
+                        if config.priority_settle_ms > 0 {
+                            let settle_tx = tx.clone();
+                            let delay = Duration::from_millis(config.priority_settle_ms);
+                            thread::spawn(move || {
+                                thread::sleep(delay);
+                                let _ = settle_tx.send(WindowEvent::PrioritySettle {
+                                    window_title,
+                                    app_name,
+                                });
+                            });
+                        }
+                    }
+                    Ok(false)
+                        if policy.allows(Trigger::Focus, &window_title, app_name.as_deref()) =>
+                    {
+                        println!("Focus changed to: {}", window_title);
+                        if capture_delay.is_set() {
+                            let pending = PendingCapture {
+                                window_id,
+                                window_title,
+                                app_name,
+                                prev_title,
+                                event_type: "focus",
+                            };
+                            if let Some(wait) = capture_delay.schedule(pending, Instant::now()) {
+                                send_after(&tx, wait, WindowEvent::CaptureDue);
+                            }
+                        } else if let Err(e) = engine.capture_event(
+                            &window_title,
+                            app_name.as_deref(),
+                            prev_title.as_deref(),
+                            "focus",
+                        ) {
+                            eprintln!("Capture failed: {}", e);
+                            notifier.notify(&e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        eprintln!("Capture failed: {}", e);
+                        notifier.notify(&e);
+                    }
                 }
             }
-            WindowEvent::TitleChanged { window_title }
-                if config.capture_on_title_change =>
+            WindowEvent::TitleChanged { window_id, window_title, app_name, prev_title }
+                if policy.allows(Trigger::Title, &window_title, app_name.as_deref()) =>
             {
+                if !title_filter.changed_enough(&window_title) {
+                    println!("Title changed too little to capture: {}", window_title);
+                    continue;
+                }
                 println!("Title changed to: {}", window_title);
-                if let Err(e) = engine.capture_event(&window_title, "title") {
+                if capture_delay.is_set() {
+                    let pending = PendingCapture {
+                        window_id,
+                        window_title,
+                        app_name,
+                        prev_title,
+                        event_type: "title",
+                    };
+                    if let Some(wait) = capture_delay.schedule(pending, Instant::now()) {
+                        send_after(&tx, wait, WindowEvent::CaptureDue);
+                    }
+                } else if let Err(e) = engine.capture_event(
+                    &window_title,
+                    app_name.as_deref(),
+                    prev_title.as_deref(),
+                    "title",
+                ) {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::CaptureDue => {
+                let Some(due) = capture_delay.take_due(Instant::now()) else {
+                    continue;
+                };
+                if let Err(e) = engine.capture_event(
+                    &due.window_title,
+                    due.app_name.as_deref(),
+                    due.prev_title.as_deref(),
+                    due.event_type,
+                ) {
                     eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
                 }
             }
-            WindowEvent::Periodic { window_title } => {
-                if let Err(e) = engine.capture_event(&window_title, "interval") {
-                    if !matches!(e, AppError::Capture(_)) {
+            WindowEvent::Periodic { window_title, app_name }
+                if policy.allows(Trigger::Interval, &window_title, app_name.as_deref()) =>
+            {
+                if let Err(e) = engine.capture_event(&window_title, app_name.as_deref(), None, "interval") {
+                    if !matches!(e, AppError::Capture(_) | AppError::RateLimited(_)) {
                         eprintln!("Capture failed: {}", e);
                     }
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::GeometryChanged { window_title, app_name }
+                if config.capture_on_geometry_change =>
+            {
+                geometry_generation += 1;
+                let generation = geometry_generation;
+                let settle_tx = tx.clone();
+                let delay = Duration::from_millis(config.geometry_debounce_ms);
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    let _ = settle_tx.send(WindowEvent::GeometrySettled {
+                        generation,
+                        window_title,
+                        app_name,
+                    });
+                });
+            }
+            WindowEvent::GeometrySettled { generation, window_title, app_name }
+                if generation == geometry_generation =>
+            {
+                println!("Window geometry changed: {}", window_title);
+                if let Err(e) =
+                    engine.capture_event(&window_title, app_name.as_deref(), None, "geometry")
+                {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::FsTrigger { path } => {
+                let Some(window) = focus::get_focused_window() else {
+                    continue;
+                };
+                println!("File changed: {}", path.display());
+                if let Err(e) =
+                    engine.capture_fs_event(&window.title, window.app_name.as_deref(), &path)
+                {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::Notification { app_name: source } => {
+                let Some(window) = focus::get_focused_window() else {
+                    continue;
+                };
+                println!("Notification from {}", source.as_deref().unwrap_or("an unknown app"));
+                if let Err(e) = engine.capture_event(
+                    &window.title,
+                    window.app_name.as_deref(),
+                    None,
+                    "notification",
+                ) {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::PrioritySettle { window_title, app_name } => {
+                if let Err(e) = engine.capture_priority_settle(&window_title, app_name.as_deref()) {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::BurstFrame { label, group_id } => {
+                if let Err(e) = engine.snapshot_burst_frame(&label, &group_id)
+                    && !matches!(e, AppError::Paused)
+                {
+                    eprintln!("Burst capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::SessionLockChanged { locked } => {
+                if locked {
+                    println!("Session locked, pausing capture");
+                } else {
+                    println!("Session unlocked, resuming capture");
+                }
+                engine.set_session_locked(locked);
+            }
+            WindowEvent::DisplaySleepChanged { asleep } => {
+                if asleep {
+                    println!("Displays asleep, pausing capture");
+                } else {
+                    println!("Displays awake, resuming capture");
+                }
+                engine.set_displays_asleep(asleep);
+            }
+            WindowEvent::Maintenance { op, reply } => {
+                let result = engine.run_maintenance(op);
+                if let Err(e) = &result {
+                    eprintln!("Database {} failed: {}", op.as_str(), e);
                 }
+                if let Some(reply) = reply {
+                    let _ = reply.send(result);
+                }
+            }
+            WindowEvent::Reprocess { id, ocr, reply } => {
+                let _ = reply.send(engine.reprocess(&id, ocr));
+            }
+            WindowEvent::ApplyConfig { config: new_config, reply } => {
+                let result = engine.apply_config((*new_config).clone());
+                if result.is_ok() {
+                    println!("Applied config from setup");
+                    periodic_interval.store(engine.capture_interval_ms(), Ordering::Relaxed);
+                    config = *new_config;
+                    notifier = notify::ErrorNotifier::new(&config);
+                    capture_delay.set_delay(config.capture_delay_ms);
+                }
+                let _ = reply.send(result.map(|_| engine.self_test()));
+            }
+            WindowEvent::MemoryUsage { reply } => {
+                let mut usage = engine.memory_usage();
+                tracker_usage.report(&config.memory, &mut usage);
+                usage.error_notifier = CollectionSize {
+                    len: notifier.tracked_kinds(),
+                    cap: None,
+                };
+                let _ = reply.send(usage);
+            }
+            WindowEvent::Woke { slept_ms } => {
+                println!("Woke from sleep after ~{}s", slept_ms / 1000);
+                engine.set_woke();
+                if config.capture_after_wake {
+                    let settle_tx = tx.clone();
+                    let delay = Duration::from_millis(config.post_wake_delay_ms);
+                    thread::spawn(move || {
+                        thread::sleep(delay);
+                        if let Some(window) = focus::get_focused_window() {
+                            let _ = settle_tx.send(WindowEvent::WakeSettled {
+                                window_title: window.title,
+                                app_name: window.app_name,
+                            });
+                        }
+                    });
+                }
+            }
+            WindowEvent::WakeSettled { window_title, app_name } => {
+                if let Err(e) =
+                    engine.capture_event(&window_title, app_name.as_deref(), None, "wake")
+                {
+                    eprintln!("Capture failed: {}", e);
+                    notifier.notify(&e);
+                }
+            }
+            WindowEvent::PowerChanged { on_battery: battery } => {
+                if battery {
+                    println!("Running on battery, applying battery profile");
+                } else {
+                    println!("Running on AC power, restoring default profile");
+                }
+                engine.set_on_battery(battery);
+                on_battery.store(battery, Ordering::Relaxed);
+                periodic_interval.store(engine.capture_interval_ms(), Ordering::Relaxed);
             }
             _ => {}
         }
@@ -163,23 +454,296 @@ fn run() -> AppResult<()> {
     Ok(())
 }
 
+/// Queues an `ANALYZE` whenever the last one is over a month old, so query
+/// plans keep up with the table as it grows.
+fn schedule_analyze(event_sender: mpsc::Sender<WindowEvent>, db: &db::Db) {
+    const ANALYZE_EVERY_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+    loop {
+        thread::sleep(Duration::from_secs(60 * 60));
+        let last = db.last_daemon_event("analyze");
+        let due = match last {
+            Ok(last) => last.is_none_or(|ts| {
+                chrono::Utc::now().timestamp_millis() - ts > ANALYZE_EVERY_MS
+            }),
+            Err(e) => {
+                eprintln!("Failed to check maintenance schedule: {e}");
+                false
+            }
+        };
+        if due {
+            let _ = event_sender.send(WindowEvent::Maintenance {
+                op: db::MaintenanceOp::Analyze,
+                reply: None,
+            });
+        }
+    }
+}
+
+/// Drops `changes` feed entries older than `retention`, hourly.
+fn prune_changes(db: &db::Db, retention: chrono::Duration) {
+    loop {
+        let before = (chrono::Utc::now() - retention).timestamp_millis();
+        if let Err(e) = db.prune_changes(before) {
+            eprintln!("Pruning the changes feed failed: {e}");
+        }
+        thread::sleep(Duration::from_secs(60 * 60));
+    }
+}
+
+/// Sends `event` back to the capture loop once `delay` has passed.
+fn send_after(tx: &mpsc::Sender<WindowEvent>, delay: Duration, event: WindowEvent) {
+    let tx = tx.clone();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        let _ = tx.send(event);
+    });
+}
+
+/// Sweeps the cache now and then once a day.
+fn sweep_cache(config: &CaptureConfig) {
+    loop {
+        match cache::sweep_with(config) {
+            Ok(report) if report.removed > 0 => println!(
+                "Swept {} cache files, freeing {} bytes",
+                report.removed, report.freed_bytes
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("Cache sweep failed: {e}"),
+        }
+        thread::sleep(Duration::from_secs(24 * 60 * 60));
+    }
+}
+
+/// Writes the previous day's report once it is missing, checking hourly
+/// so a day missed while the daemon was down is written on the next start.
+fn schedule_reports(config: &CaptureConfig) {
+    loop {
+        match db::Db::open(config).and_then(|db| report::write_due(&db, config)) {
+            Ok(Some(path)) => println!("Wrote daily report {}", path.display()),
+            Ok(None) => {}
+            Err(e) => eprintln!("Daily report failed: {e}"),
+        }
+        thread::sleep(Duration::from_secs(60 * 60));
+    }
+}
+
+/// `veea db vacuum|analyze|check`, for use while the daemon is stopped.
+fn db_command(command: &str) -> AppResult<()> {
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
+    match command {
+        "vacuum" => {
+            db.run_maintenance(db::MaintenanceOp::Vacuum)?;
+        }
+        "analyze" => {
+            db.run_maintenance(db::MaintenanceOp::Analyze)?;
+        }
+        _ => {
+            db.integrity_check(|line| {
+                println!("{line}");
+                true
+            })?;
+        }
+    }
+    Ok(())
+}
+
 fn test_capture() -> AppResult<()> {
     println!("=== Veea Capture Test Mode ===");
-    let config = CaptureConfig::load_or_init(Path::new(DEFAULT_CONFIG_PATH))?;
-    let db = db::Db::new(&config.db_path)?;
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
     let pause_flag = Arc::new(AtomicBool::new(false));
     let engine = CaptureEngine::new(config, db, pause_flag)?;
     engine.test_capture()
 }
 
-fn snapshot_once(label: &str) -> AppResult<()> {
+fn snapshot_once(
+    label: Option<&str>,
+    monitor: Option<&str>,
+    event_type: Option<&str>,
+) -> AppResult<()> {
     println!("=== Veea Snapshot Mode ===");
-    let config = CaptureConfig::load_or_init(Path::new(DEFAULT_CONFIG_PATH))?;
-    let db = db::Db::new(&config.db_path)?;
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let label = label.unwrap_or(&config.snapshot_label).to_string();
+    let event_type = event_type.unwrap_or(&config.snapshot_event_type).to_string();
+    let db = db::Db::open(&config)?;
     let pause_flag = Arc::new(AtomicBool::new(false));
     let mut engine = CaptureEngine::new(config, db, pause_flag)?;
-    let path = engine.snapshot_png(label)?;
-    println!("Snapshot saved: {}", path.display());
+    let record = engine.snapshot_png(&label, monitor, &event_type)?;
+    println!("Snapshot saved: {}", record.path);
+    Ok(())
+}
+
+/// Takes `veea-<user>.lock` next to the config for the daemon's lifetime,
+/// so a second daemon on the same data refuses to start while other
+/// users' instances are unaffected.
+fn lock_instance(config_path: &Path) -> AppResult<std::fs::File> {
+    let dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    let user: String = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "default".to_string())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect();
+    let path = dir.join(format!("veea-{user}.lock"));
+    let file = std::fs::File::create(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => {
+            Err(AppError::InstanceLocked(path.display().to_string()))
+        }
+        Err(std::fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// `veea status`: asks the current user's running daemon, located through
+/// its address file, for its status.
+fn status() -> AppResult<()> {
+    let config_path = config::default_config_path();
+    let addr = config::read_api_addr(&config_path).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!(
+                "no running instance found ({} missing)",
+                config::api_addr_file(&config_path).display()
+            ),
+        )
+    })?;
+    let auth = CaptureConfig::load_or_default(&config_path)?
+        .api_token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token.trim()))
+        .unwrap_or_default();
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write!(stream, "GET /status HTTP/1.0\r\nHost: {addr}\r\n{auth}\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+    println!("Instance at http://{addr}");
+    println!("{body}");
+    Ok(())
+}
+
+/// `veea compact`: rewrites packs that are mostly deleted captures, drops
+/// soft-deleted rows whose images are gone, vacuums, and deletes temp
+/// files left by interrupted image writes. For use while the daemon is
+/// stopped.
+fn compact() -> AppResult<()> {
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
+    let packs = pack::compact(&db, &config.capture_dir)?;
+    println!(
+        "Rewrote {} packs and removed {} empty ones, reclaiming {} bytes",
+        packs.rewritten, packs.removed, packs.reclaimed
+    );
+    let report = db.compact(&config.capture_dir)?;
+    println!(
+        "Purged {} deleted captures ({} kept, files still present)",
+        report.purged, report.kept
+    );
+    if config.capture_dir.exists() {
+        let removed = capture::remove_temp_files(&config.capture_dir, Duration::from_secs(60))?;
+        println!("Removed {removed} temp files left by interrupted writes");
+    }
+    println!(
+        "Reclaimed {} bytes ({} -> {} bytes)",
+        report.size_before.saturating_sub(report.size_after),
+        report.size_before,
+        report.size_after
+    );
+    Ok(())
+}
+
+/// `veea report [--date YYYY-MM-DD]`: writes the HTML report for a day,
+/// today by default, to `report_dir`.
+fn write_report(date: Option<&str>) -> AppResult<()> {
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let date = match date {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| AppError::Capture(format!("invalid date {date:?}, expected YYYY-MM-DD")))?,
+        None => config.storage_timezone.date_of(chrono::Utc::now()),
+    };
+    let db = db::Db::open(&config)?;
+    let path = report::write(&db, &config, date)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// `veea recompress-lossless`: rewrites every PNG capture as JPEG XL,
+/// deleting a PNG only once its copy decodes to the same pixels and no
+/// capture in the database points at it any more.
+fn recompress_lossless() -> AppResult<()> {
+    if !codec::JXL_AVAILABLE {
+        return Err(AppError::Capture("built without the `jxl` feature".to_string()));
+    }
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
+    let (mut converted, mut failed, mut saved) = (0, 0, 0i64);
+    for record in db.captures_with_codec(ImageFormat::Png)? {
+        // Packed images are left as they are.
+        if record.pack.is_some() {
+            continue;
+        }
+        let Some(png_path) = record.resolve_path(&config.capture_dir) else {
+            failed += 1;
+            eprintln!("Skipping {}: image file missing", record.id);
+            continue;
+        };
+        let jxl_path = match codec::recompress_lossless(&png_path) {
+            Ok(path) => path,
+            Err(e) => {
+                failed += 1;
+                eprintln!("Skipping {}: {e}", record.id);
+                continue;
+            }
+        };
+        db.set_image_file(&record.id, &jxl_path, &config.capture_dir, ImageFormat::Jxl)?;
+        let size = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len() as i64);
+        // Content-addressed captures of the same image share the PNG, and
+        // each gets pointed at the same copy in turn.
+        let _refs = objects::lock_for(&config.capture_dir, &record)?;
+        if db.image_file_in_use(&record)? {
+            converted += 1;
+            continue;
+        }
+        saved += size(&png_path) - size(&jxl_path);
+        std::fs::remove_file(&png_path)?;
+        converted += 1;
+    }
+    println!("Recompressed {converted} captures ({failed} skipped), saving {saved} bytes");
+    Ok(())
+}
+
+/// `veea pack`: moves captures stored as files into hourly packs. Safe to
+/// run beside the daemon, which locks a pack while appending to it.
+fn pack_captures() -> AppResult<()> {
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
+    let report = pack::pack_files(&db, &config.capture_dir, config.storage_timezone)?;
+    println!("Packed {} captures ({} skipped)", report.moved, report.skipped);
+    Ok(())
+}
+
+/// `veea unpack`: writes packed captures back out as files of their own.
+fn unpack_captures() -> AppResult<()> {
+    let config = CaptureConfig::load_or_default(&config::default_config_path())?;
+    let db = db::Db::open(&config)?;
+    let report = pack::unpack(&db, &config.capture_dir)?;
+    println!("Unpacked {} captures ({} skipped)", report.moved, report.skipped);
+    Ok(())
+}
+
+/// Repoints stored capture paths at a data directory the user has already
+/// moved, then saves the new `capture_dir` to the config.
+fn relocate(new_dir: &Path) -> AppResult<()> {
+    let config_path = config::default_config_path();
+    let mut config = CaptureConfig::load_or_init(&config_path)?;
+    let mut db = db::Db::open(&config)?;
+    let moved = db.relocate(&config.capture_dir, new_dir, 20)?;
+    config.capture_dir = new_dir.to_path_buf();
+    config.save(&config_path)?;
+    println!("Relocated {} captures to {}", moved, new_dir.display());
     Ok(())
 }
 
@@ -191,11 +755,74 @@ fn main() {
             std::process::exit(1);
         }
     } else if args.len() > 1 && args[1] == "snapshot" {
-        let label = args.get(2).map(String::as_str).unwrap_or("manual");
-        if let Err(e) = snapshot_once(label) {
+        let label = args.get(2).map(String::as_str);
+        let monitor = args.get(3).map(String::as_str).filter(|m| !m.is_empty());
+        let event_type = args.get(4).map(String::as_str);
+        if let Err(e) = snapshot_once(label, monitor, event_type) {
             eprintln!("Snapshot failed: {e}");
             std::process::exit(1);
         }
+    } else if args.len() > 1 && args[1] == "db" {
+        let command = args.get(2).map(String::as_str).unwrap_or("");
+        if !matches!(command, "vacuum" | "analyze" | "check") {
+            eprintln!("Usage: veea db vacuum|analyze|check");
+            std::process::exit(2);
+        }
+        if let Err(e) = db_command(command) {
+            eprintln!("Database {command} failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "status" {
+        if let Err(e) = status() {
+            eprintln!("Status failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "compact" {
+        if let Err(e) = compact() {
+            eprintln!("Compact failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "report" {
+        let date = args
+            .iter()
+            .position(|a| a == "--date")
+            .map(|i| args.get(i + 1).map(String::as_str));
+        if date == Some(None) {
+            eprintln!("Usage: veea report [--date YYYY-MM-DD]");
+            std::process::exit(2);
+        }
+        if let Err(e) = write_report(date.flatten()) {
+            eprintln!("Report failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "recompress-lossless" {
+        if let Err(e) = recompress_lossless() {
+            eprintln!("Recompress failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "pack" {
+        if let Err(e) = pack_captures() {
+            eprintln!("Pack failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "unpack" {
+        if let Err(e) = unpack_captures() {
+            eprintln!("Unpack failed: {e}");
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "relocate" {
+        let Some(new_dir) = args
+            .iter()
+            .position(|a| a == "--new-dir")
+            .and_then(|i| args.get(i + 1))
+        else {
+            eprintln!("Usage: veea relocate --new-dir PATH");
+            std::process::exit(2);
+        };
+        if let Err(e) = relocate(Path::new(new_dir)) {
+            eprintln!("Relocate failed: {e}");
+            std::process::exit(1);
+        }
     } else {
         if let Err(e) = run() {
             eprintln!("Fatal error: {e}");
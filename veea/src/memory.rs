@@ -0,0 +1,124 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::thumbnail_cache::ThumbnailCacheStats;
+
+/// Map that evicts its least recently used entry once it holds more than
+/// `cap` entries. Recency is kept in a plain queue, so updates are linear
+/// in `cap`; meant for the small caches of a long-running daemon.
+#[derive(Debug, Clone)]
+pub struct BoundedMap<K, V> {
+    cap: usize,
+    entries: HashMap<K, V>,
+    /// Keys from least to most recently used.
+    order: VecDeque<K>,
+}
+
+impl<K: Eq + Hash + Clone, V> BoundedMap<K, V> {
+    /// A `cap` of 0 is treated as 1.
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: cap.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Looks up `key` without counting it as a use.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.touch(&key);
+        let old = self.entries.insert(key, value);
+        self.evict();
+        old
+    }
+
+    /// Marks `key` as used, inserting `default()` first when it is missing.
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        self.touch(&key);
+        if !self.entries.contains_key(&key) {
+            self.entries.insert(key.clone(), default());
+            self.evict();
+        }
+        self.entries
+            .get_mut(&key)
+            .expect("just-used key is never evicted")
+    }
+
+    fn touch(&mut self, key: &K) {
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.cap {
+            let Some(stale) = self.order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&stale);
+        }
+    }
+}
+
+/// Entry count of one internal collection and its limit, if any.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CollectionSize {
+    pub len: usize,
+    pub cap: Option<usize>,
+}
+
+impl CollectionSize {
+    pub fn of<K: Eq + Hash + Clone, V>(map: &BoundedMap<K, V>) -> Self {
+        Self {
+            len: map.len(),
+            cap: Some(map.cap()),
+        }
+    }
+}
+
+/// Sizes of the daemon's long-lived collections, served by `/debug/memory`
+/// to spot growth over weeks of uptime.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Capture timestamps kept for the per-minute rate limit.
+    pub recent_captures: CollectionSize,
+    /// Priority apps already captured on first focus this session.
+    pub priority_seen: CollectionSize,
+    /// Windows with a remembered title history.
+    pub title_history_windows: CollectionSize,
+    /// Titles remembered across all of those windows.
+    pub title_history_titles: CollectionSize,
+    /// Error kinds with a pending notification debounce.
+    pub error_notifier: CollectionSize,
+    /// Bursts still running.
+    pub bursts: CollectionSize,
+    /// Thumbnails held by the API, with hit and miss counts.
+    pub thumbnail_cache: ThumbnailCacheStats,
+}
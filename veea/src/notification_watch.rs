@@ -0,0 +1,95 @@
+use std::sync::mpsc;
+
+use crate::focus::WindowEvent;
+
+/// Whether this platform lets other apps' notifications be observed.
+/// macOS and Windows only show them to the system or to packaged apps
+/// the user has granted access.
+pub const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// Notifications within this long of one that triggered are dropped, so
+/// a burst of messages makes one capture.
+#[cfg(target_os = "linux")]
+const NOTIFICATION_DEBOUNCE: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Sends a `Notification` event for each desktop notification another
+/// app posts. Veea's own are ignored, as a failed capture reported by
+/// notification must not trigger the next one. Returns early when
+/// notifications can't be observed.
+pub fn watch_notifications(event_sender: mpsc::Sender<WindowEvent>) {
+    platform_watch(event_sender);
+}
+
+#[cfg(target_os = "linux")]
+fn platform_watch(event_sender: mpsc::Sender<WindowEvent>) {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+        time::Instant,
+    };
+
+    use crate::notify;
+
+    // Notifications are `Notify` calls to the session bus's notification
+    // server; dbus-monitor prints each call followed by its arguments, the
+    // first being the sender's app name.
+    let child = Command::new("dbus-monitor")
+        .args([
+            "--session",
+            "--monitor",
+            "type='method_call',interface='org.freedesktop.Notifications',member='Notify'",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Notification watcher unavailable: cannot run dbus-monitor: {e}");
+            return;
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return;
+    };
+
+    let mut in_notify = false;
+    let mut last_sent: Option<Instant> = None;
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.starts_with("method call") {
+            in_notify = line.contains("member=Notify");
+            continue;
+        }
+        if !in_notify {
+            continue;
+        }
+        in_notify = false;
+        let app_name = line
+            .trim()
+            .strip_prefix("string \"")
+            .and_then(|rest| rest.strip_suffix('"'))
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+        if app_name.as_deref() == Some(notify::APP_NAME) {
+            continue;
+        }
+        if last_sent.is_some_and(|at| at.elapsed() < NOTIFICATION_DEBOUNCE) {
+            continue;
+        }
+        last_sent = Some(Instant::now());
+        if event_sender
+            .send(WindowEvent::Notification { app_name })
+            .is_err()
+        {
+            break;
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn platform_watch(_event_sender: mpsc::Sender<WindowEvent>) {}
@@ -0,0 +1,201 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, NaiveTime};
+
+use crate::{
+    config::{CaptureConfig, Notifications},
+    error::AppError,
+    trigger,
+};
+
+/// App name Veea's notifications are posted under.
+pub const APP_NAME: &str = "Veea";
+
+/// Minimum time between two notifications for the same error kind.
+const NOTIFY_DEBOUNCE: Duration = Duration::from_secs(60);
+/// Minimum time between two notifications for the same `DaemonEvent`.
+const EVENT_DEBOUNCE: Duration = Duration::from_secs(60 * 60);
+/// Span `failure_threshold` failures must fall in to count as repeated.
+const FAILURE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Trouble that stops captures until the user acts, from `notifications`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum DaemonEvent {
+    PermissionLost,
+    DiskFull,
+    RepeatedFailures,
+}
+
+/// Raises desktop notifications for capture errors: each failure when
+/// `notify_on_error` is set, at most once per minute per error kind, and
+/// the `notifications` events at most once an hour each. Nothing is shown
+/// during quiet hours.
+pub struct ErrorNotifier {
+    enabled: bool,
+    events: Notifications,
+    quiet_hours: Vec<(NaiveTime, NaiveTime)>,
+    last_sent: HashMap<&'static str, Instant>,
+    last_event: HashMap<DaemonEvent, Instant>,
+    /// Recent failures, at most `failure_threshold` of them.
+    failures: VecDeque<Instant>,
+}
+
+impl ErrorNotifier {
+    pub fn new(config: &CaptureConfig) -> Self {
+        let quiet_hours = config
+            .notifications
+            .quiet_hours
+            .iter()
+            .filter_map(|window| {
+                let parsed = trigger::parse_window(window);
+                if parsed.is_none() {
+                    eprintln!("Ignoring invalid quiet hours window '{window}'");
+                }
+                parsed
+            })
+            .collect();
+        Self {
+            enabled: config.notify_on_error,
+            events: config.notifications.clone(),
+            quiet_hours,
+            last_sent: HashMap::new(),
+            last_event: HashMap::new(),
+            failures: VecDeque::new(),
+        }
+    }
+
+    /// Error kinds and events currently tracked for debouncing; bounded by
+    /// the number of `AppError` and `DaemonEvent` variants.
+    pub fn tracked_kinds(&self) -> usize {
+        self.last_sent.len() + self.last_event.len()
+    }
+
+    pub fn notify(&mut self, err: &AppError) {
+        // Expected outcomes, not something the user needs to act on.
+        if matches!(err, AppError::Paused | AppError::RateLimited(_)) {
+            return;
+        }
+        let now = Instant::now();
+        // Quiet hours are checked before recording a send, so a problem
+        // that outlasts them is still reported afterwards.
+        if let Some((event, summary, body)) = self.event_for(err, now) {
+            if self.quiet() || !debounced(&mut self.last_event, event, now, EVENT_DEBOUNCE) {
+                return;
+            }
+            thread::spawn(move || show(summary, &body));
+            return;
+        }
+        if !self.enabled
+            || self.quiet()
+            || !debounced(&mut self.last_sent, err.kind(), now, NOTIFY_DEBOUNCE)
+        {
+            return;
+        }
+
+        let body = err.to_string();
+        // Delivery can block on the notification daemon; keep it off the
+        // capture loop.
+        thread::spawn(move || show("Veea capture failed", &body));
+    }
+
+    /// The `DaemonEvent` `err` amounts to, if its kind is switched on, with
+    /// the notification's summary and body.
+    fn event_for(
+        &mut self,
+        err: &AppError,
+        now: Instant,
+    ) -> Option<(DaemonEvent, &'static str, String)> {
+        if !self.events.enabled {
+            return None;
+        }
+        if let AppError::LowDiskSpace { .. } = err {
+            return self.events.disk_full.then(|| {
+                (
+                    DaemonEvent::DiskFull,
+                    "Veea paused: disk almost full",
+                    err.to_string(),
+                )
+            });
+        }
+        if self.events.permission_lost && is_permission_error(err) {
+            return Some((
+                DaemonEvent::PermissionLost,
+                "Veea lost screen capture permission",
+                format!("Check the screen recording permission for Veea. {err}"),
+            ));
+        }
+        if !self.events.capture_failures {
+            return None;
+        }
+        let threshold = self.events.failure_threshold.max(1) as usize;
+        self.failures.push_back(now);
+        while self.failures.len() > threshold
+            || self
+                .failures
+                .front()
+                .is_some_and(|at| now.duration_since(*at) > FAILURE_WINDOW)
+        {
+            self.failures.pop_front();
+        }
+        (self.failures.len() == threshold).then(|| {
+            (
+                DaemonEvent::RepeatedFailures,
+                "Veea captures keep failing",
+                format!("{threshold} captures failed in the last 10 minutes; latest: {err}"),
+            )
+        })
+    }
+
+    fn quiet(&self) -> bool {
+        let now = Local::now().time();
+        self.quiet_hours
+            .iter()
+            .any(|&window| trigger::in_window(now, window))
+    }
+}
+
+/// Records a send of `key` unless one happened within `interval`; returns
+/// whether to send.
+fn debounced<K: std::hash::Hash + Eq>(
+    last_sent: &mut HashMap<K, Instant>,
+    key: K,
+    now: Instant,
+    interval: Duration,
+) -> bool {
+    if let Some(last) = last_sent.get(&key)
+        && now.duration_since(*last) < interval
+    {
+        return false;
+    }
+    last_sent.insert(key, now);
+    true
+}
+
+/// Capture backends only report lost access in their error text, as the
+/// hints in `capture` already rely on.
+fn is_permission_error(err: &AppError) -> bool {
+    let AppError::Capture(message) = err else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    message.contains("permission") || message.contains("denied")
+}
+
+#[cfg(feature = "notifications")]
+fn show(summary: &str, body: &str) {
+    if let Err(e) = notify_rust::Notification::new()
+        .appname(APP_NAME)
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        eprintln!("Failed to show notification: {e}");
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+fn show(_summary: &str, _body: &str) {}
@@ -0,0 +1,183 @@
+//! Content-addressed capture files: each image is stored once, named by
+//! the SHA-256 of its encoded bytes, and every capture that encodes to
+//! the same bytes points at that one file. A file is only deleted with
+//! the last live capture referring to it, see `Db::image_file_in_use`;
+//! `lock` keeps a deletion from slipping in between a new capture
+//! sharing an object and its record being stored.
+//!
+//! Files live at `objects/ab/<hash>.<ext>` under `capture_dir`, sharded by
+//! the hash's first byte so no directory grows too large.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+use crate::{capture::write_atomic, codec::ImageFormat, db::CaptureRecord, error::AppResult, pack};
+
+/// Directory under `capture_dir` holding the objects.
+const OBJECT_DIR: &str = "objects";
+
+/// Object for an image with hex SHA-256 `hash`, relative to `capture_dir`.
+pub fn object_path(hash: &str, format: ImageFormat) -> PathBuf {
+    PathBuf::from(OBJECT_DIR)
+        .join(&hash[..2])
+        .join(format!("{hash}.{}", format.extension()))
+}
+
+/// Held while captures start or stop referring to objects.
+#[derive(Debug)]
+pub struct RefLock {
+    _file: File,
+}
+
+/// Waits for the lock on which captures refer to which objects. Storing
+/// a capture holds it from `store` until its record is inserted, and
+/// deleting one from checking `Db::image_file_in_use` until the file is
+/// gone. It is a file lock, so `veea pack` beside the daemon takes turns
+/// as well.
+pub fn lock(capture_dir: &Path) -> AppResult<RefLock> {
+    let dir = capture_dir.join(OBJECT_DIR);
+    fs::create_dir_all(&dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(".lock"))?;
+    file.lock()?;
+    Ok(RefLock { _file: file })
+}
+
+/// `lock`, when `record`'s image is an object other captures may share.
+pub fn lock_for(capture_dir: &Path, record: &CaptureRecord) -> AppResult<Option<RefLock>> {
+    let is_object = record.pack.is_none()
+        && record
+            .rel_path
+            .as_deref()
+            .is_some_and(|rel| Path::new(rel).starts_with(OBJECT_DIR));
+    if !is_object {
+        return Ok(None);
+    }
+    lock(capture_dir).map(Some)
+}
+
+/// Stores a new capture's encoded image under its hash, unless an
+/// identical one is already there, and points `record` at it. Call it
+/// holding `lock`.
+pub fn store(
+    capture_dir: &Path,
+    record: &mut CaptureRecord,
+    bytes: &[u8],
+    format: ImageFormat,
+) -> AppResult<()> {
+    let hash = pack::digest(bytes);
+    let rel = object_path(&hash, format);
+    let path = capture_dir.join(&rel);
+    // An object whose bytes don't match its name is left over from a
+    // damaged write, so it is replaced rather than shared.
+    let stored = fs::read(&path).is_ok_and(|stored| pack::digest(&stored) == hash);
+    if !stored {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&path, bytes)?;
+    }
+    record.path = path.to_string_lossy().into_owned();
+    record.rel_path = Some(rel.to_string_lossy().into_owned());
+    record.hash = Some(hash);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread,
+        time::Duration,
+    };
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::db::Db;
+
+    /// Stores `bytes` for a new capture `id` taken `minutes_ago` and
+    /// inserts its record.
+    fn capture(db: &Db, capture_dir: &Path, id: &str, minutes_ago: i64, bytes: &[u8]) -> PathBuf {
+        let ts = Utc::now() - chrono::Duration::minutes(minutes_ago);
+        let mut record = CaptureRecord::sample(id, ts);
+        let _refs = lock(capture_dir).unwrap();
+        store(capture_dir, &mut record, bytes, ImageFormat::Png).unwrap();
+        db.insert_capture(&record).unwrap();
+        PathBuf::from(record.path)
+    }
+
+    fn objects(capture_dir: &Path) -> usize {
+        fs::read_dir(capture_dir.join(OBJECT_DIR))
+            .unwrap()
+            .filter_map(|shard| fs::read_dir(shard.ok()?.path()).ok())
+            .flatten()
+            .count()
+    }
+
+    #[test]
+    fn identical_images_share_one_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        let a = capture(&db, dir.path(), "a", 10, b"same image");
+        let b = capture(&db, dir.path(), "b", 5, b"same image");
+        let c = capture(&db, dir.path(), "c", 1, b"another image");
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(objects(dir.path()), 2);
+        assert_eq!(fs::read(&a).unwrap(), b"same image");
+    }
+
+    #[test]
+    fn object_goes_with_its_last_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        let path = capture(&db, dir.path(), "old", 60, b"same image");
+        capture(&db, dir.path(), "new", 1, b"same image");
+
+        assert_eq!(db.delete_recent(5, dir.path()).unwrap().len(), 1);
+        assert_eq!(fs::read(&path).unwrap(), b"same image");
+        assert_eq!(db.delete_recent(120, dir.path()).unwrap().len(), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn damaged_object_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        let path = capture(&db, dir.path(), "a", 10, b"same image");
+        // Same length, different bytes: only the hash tells them apart.
+        fs::write(&path, b"same imagf").unwrap();
+
+        assert_eq!(capture(&db, dir.path(), "b", 5, b"same image"), path);
+        assert_eq!(fs::read(&path).unwrap(), b"same image");
+    }
+
+    #[test]
+    fn lock_holders_take_turns() {
+        let dir = tempfile::tempdir().unwrap();
+        let held = lock(dir.path()).unwrap();
+        let acquired = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let (capture_dir, acquired) = (dir.path().to_path_buf(), acquired.clone());
+            thread::spawn(move || {
+                let _refs = lock(&capture_dir).unwrap();
+                acquired.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(!acquired.load(Ordering::SeqCst));
+        drop(held);
+        waiter.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,242 @@
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use xcap::image::RgbaImage;
+
+use crate::{
+    cache::{CacheFile, Subsystem},
+    error::{AppError, AppResult},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OcrBackend {
+    Tesseract,
+    AppleVision,
+    #[default]
+    None,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OcrResult {
+    pub text: String,
+    /// Recognized lines with their position, for backends that report
+    /// one; empty otherwise.
+    pub lines: Vec<OcrLine>,
+}
+
+impl OcrResult {
+    /// Result whose text is `lines` in reading order, one per line.
+    pub fn from_lines(mut lines: Vec<OcrLine>) -> Self {
+        lines.sort_by_key(|l| (l.y, l.x));
+        let text = lines
+            .iter()
+            .map(|l| l.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self { text, lines }
+    }
+}
+
+/// A line of recognized text and its bounding box in image pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OcrLine {
+    pub text: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OcrLine {
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+}
+
+/// Text recognition engine run over each capture.
+pub trait Ocr: Send {
+    fn name(&self) -> &'static str;
+
+    fn extract(&self, img: &RgbaImage) -> AppResult<OcrResult>;
+}
+
+/// Builds the configured backend, or `None` when OCR is off or the
+/// backend isn't available on this platform.
+pub fn select_backend(backend: OcrBackend) -> Option<Box<dyn Ocr>> {
+    match backend {
+        OcrBackend::Tesseract => Some(Box::new(Tesseract)),
+        #[cfg(target_os = "macos")]
+        OcrBackend::AppleVision => Some(Box::new(AppleVision)),
+        #[cfg(not(target_os = "macos"))]
+        OcrBackend::AppleVision => {
+            eprintln!("AppleVision OCR is only available on macOS; OCR disabled");
+            None
+        }
+        OcrBackend::None => None,
+    }
+}
+
+/// Command-line OCR tools read from files, so the image takes a round
+/// trip through the cache, deleted again when `f` returns.
+fn with_temp_png<T>(
+    img: &RgbaImage,
+    f: impl FnOnce(&std::path::Path) -> AppResult<T>,
+) -> AppResult<T> {
+    let file = CacheFile::new(Subsystem::Ocr, "png")?;
+    img.save(file.path()).map_err(|e| AppError::Capture(e.to_string()))?;
+    f(file.path())
+}
+
+fn run_tool(command: &mut Command) -> AppResult<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(AppError::Capture(format!(
+            "OCR failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Lines from tesseract's TSV output, whose word rows (level 5) carry a
+/// box and the block, paragraph and line they belong to.
+fn parse_tsv(tsv: &str) -> Vec<OcrLine> {
+    let mut lines: Vec<((u32, u32, u32), OcrLine)> = Vec::new();
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        let [level, _page, block, par, line, _word, left, top, width, height, _conf, text] =
+            cols[..]
+        else {
+            continue;
+        };
+        let num = |s: &str| s.parse::<u32>().ok();
+        let text = text.trim();
+        if level != "5" || text.is_empty() {
+            continue;
+        }
+        let (Some(b), Some(p), Some(l), Some(x), Some(y), Some(w), Some(h)) = (
+            num(block),
+            num(par),
+            num(line),
+            num(left),
+            num(top),
+            num(width),
+            num(height),
+        ) else {
+            continue;
+        };
+        let key = (b, p, l);
+        match lines.last_mut() {
+            Some((last, current)) if *last == key => {
+                let right = (current.x + current.width).max(x + w);
+                let bottom = current.bottom().max(y + h);
+                current.x = current.x.min(x);
+                current.y = current.y.min(y);
+                current.width = right - current.x;
+                current.height = bottom - current.y;
+                current.text.push(' ');
+                current.text.push_str(text);
+            }
+            _ => lines.push((
+                key,
+                OcrLine { text: text.to_string(), x, y, width: w, height: h },
+            )),
+        }
+    }
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+/// The `tesseract` CLI, which must be on PATH.
+pub struct Tesseract;
+
+impl Ocr for Tesseract {
+    fn name(&self) -> &'static str {
+        "tesseract"
+    }
+
+    fn extract(&self, img: &RgbaImage) -> AppResult<OcrResult> {
+        with_temp_png(img, |path| {
+            let tsv = run_tool(Command::new("tesseract").arg(path).arg("stdout").arg("tsv"))?;
+            Ok(OcrResult::from_lines(parse_tsv(&tsv)))
+        })
+    }
+}
+
+/// Vision framework's text recognizer, driven through JavaScript for
+/// Automation so no extra toolchain is needed.
+#[cfg(target_os = "macos")]
+pub struct AppleVision;
+
+#[cfg(target_os = "macos")]
+const VISION_SCRIPT: &str = r#"
+ObjC.import('Vision');
+function run(argv) {
+    const url = $.NSURL.fileURLWithPath(argv[0]);
+    const handler = $.VNImageRequestHandler.alloc.initWithURLOptions(url, $({}));
+    const request = $.VNRecognizeTextRequest.alloc.init;
+    handler.performRequestsError($([request]), null);
+    const results = request.results;
+    const lines = [];
+    for (let i = 0; i < results.count; i++) {
+        lines.push(results.objectAtIndex(i).topCandidates(1).objectAtIndex(0).string.js);
+    }
+    return lines.join('\n');
+}
+"#;
+
+#[cfg(target_os = "macos")]
+impl Ocr for AppleVision {
+    fn name(&self) -> &'static str {
+        "apple_vision"
+    }
+
+    fn extract(&self, img: &RgbaImage) -> AppResult<OcrResult> {
+        let text = with_temp_png(img, |path| {
+            run_tool(
+                Command::new("osascript")
+                    .args(["-l", "JavaScript", "-e", VISION_SCRIPT])
+                    .arg(path),
+            )
+        })?;
+        Ok(OcrResult { text, lines: Vec::new() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tsv_words_are_grouped_into_lines() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t800\t600\t-1\t
+4\t1\t1\t1\t1\t0\t10\t20\t200\t18\t-1\t
+5\t1\t1\t1\t1\t1\t10\t22\t60\t16\t96\tQuarterly
+5\t1\t1\t1\t1\t2\t80\t20\t50\t18\t95\treport
+5\t1\t1\t1\t1\t3\t140\t20\t10\t18\t10\t\u{20}
+5\t1\t1\t1\t2\t1\t10\t50\t40\t16\t91\tdraft
+5\t1\t2\t1\t1\t1\tx\t90\t40\t16\t91\tbroken
+5\t1\t2\t1\t1\t1\t300\t90\t40\t16
+";
+        let lines = parse_tsv(tsv);
+        assert_eq!(
+            lines,
+            [
+                OcrLine {
+                    text: "Quarterly report".to_string(),
+                    x: 10,
+                    y: 20,
+                    width: 120,
+                    height: 18
+                },
+                OcrLine {
+                    text: "draft".to_string(),
+                    x: 10,
+                    y: 50,
+                    width: 40,
+                    height: 16
+                },
+            ]
+        );
+    }
+}
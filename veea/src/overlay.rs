@@ -0,0 +1,382 @@
+//! Label with the capture time and window burned into a corner of an
+//! image handed out of the archive, e.g. `/captures/:id/image?overlay=true`.
+//! Only copies are drawn on; the stored capture never is.
+
+use std::path::PathBuf;
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use serde::{Deserialize, Serialize};
+use xcap::image::RgbaImage;
+
+use crate::{db::CaptureRecord, timezone::StorageTimezone};
+
+/// DejaVu Sans, covering Latin, Greek, Cyrillic, Arabic and Hebrew. See
+/// `assets/fonts/LICENSE`.
+const BUILTIN_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+/// Fonts shipped with the OS that cover CJK, tried in order when the
+/// label has characters no configured font draws.
+#[cfg(target_os = "linux")]
+const SYSTEM_FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+#[cfg(target_os = "macos")]
+const SYSTEM_FALLBACK_FONTS: &[&str] = &[
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/AppleSDGothicNeo.ttc",
+];
+#[cfg(target_os = "windows")]
+const SYSTEM_FALLBACK_FONTS: &[&str] = &[
+    r"C:\Windows\Fonts\msyh.ttc",
+    r"C:\Windows\Fonts\YuGothM.ttc",
+    r"C:\Windows\Fonts\malgun.ttf",
+];
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const SYSTEM_FALLBACK_FONTS: &[&str] = &[];
+const ELLIPSIS: char = '…';
+/// Black at about 60% opacity behind white text.
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+const BACKGROUND_ALPHA: f32 = 0.6;
+const FOREGROUND: [u8; 3] = [255, 255, 255];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// One line of the label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayField {
+    /// Capture time in `storage_timezone`.
+    Timestamp,
+    Title,
+    App,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayConfig {
+    /// Label images that don't ask either way with `?overlay=`.
+    pub enabled: bool,
+    pub position: OverlayPosition,
+    /// Text height in pixels.
+    pub font_size: f32,
+    /// Lines of the label, top to bottom; fields a capture lacks are left
+    /// out.
+    pub fields: Vec<OverlayField>,
+    /// TrueType or OpenType font tried before the built-in one, for
+    /// scripts neither DejaVu Sans nor the OS's CJK fonts cover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_path: Option<PathBuf>,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: OverlayPosition::BottomRight,
+            font_size: 16.0,
+            fields: vec![OverlayField::Timestamp, OverlayField::Title],
+            font_path: None,
+        }
+    }
+}
+
+/// Draws the label for `record` onto `image`. Lines too wide for the
+/// image are cut short with an ellipsis.
+pub fn apply(
+    image: &mut RgbaImage,
+    record: &CaptureRecord,
+    config: &OverlayConfig,
+    timezone: StorageTimezone,
+) {
+    let lines = label_lines(record, &config.fields, timezone);
+    if lines.is_empty() || config.font_size <= 0.0 {
+        return;
+    }
+    let fonts = load_fonts(config.font_path.as_ref(), &lines);
+    let scale = PxScale::from(config.font_size);
+    let metrics = fonts[0].as_scaled(scale);
+    let line_height = metrics.height() + metrics.line_gap();
+    let padding = config.font_size * 0.4;
+    let margin = config.font_size * 0.5;
+
+    let (width, height) = image.dimensions();
+    let max_text_width = width as f32 - 2.0 * (margin + padding);
+    if max_text_width < config.font_size {
+        return;
+    }
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| elide(&fonts, scale, line, max_text_width))
+        .collect();
+    let text_width = lines
+        .iter()
+        .map(|line| text_width(&fonts, scale, line))
+        .fold(0.0, f32::max);
+    let box_width = text_width + 2.0 * padding;
+    let box_height = line_height * lines.len() as f32 + 2.0 * padding;
+    let left = match config.position {
+        OverlayPosition::TopLeft | OverlayPosition::BottomLeft => margin,
+        OverlayPosition::TopRight | OverlayPosition::BottomRight => {
+            width as f32 - margin - box_width
+        }
+    };
+    let top = match config.position {
+        OverlayPosition::TopLeft | OverlayPosition::TopRight => margin,
+        OverlayPosition::BottomLeft | OverlayPosition::BottomRight => {
+            (height as f32 - margin - box_height).max(0.0)
+        }
+    };
+
+    let (x0, y0) = (left.max(0.0) as u32, top as u32);
+    let x1 = ((left + box_width).ceil() as u32).min(width);
+    let y1 = ((top + box_height).ceil() as u32).min(height);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            blend(image, x, y, BACKGROUND, BACKGROUND_ALPHA);
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let baseline = top + padding + line_height * i as f32 + metrics.ascent();
+        draw_line(image, &fonts, scale, (left + padding, baseline), FOREGROUND, line);
+    }
+}
+
+fn label_lines(
+    record: &CaptureRecord,
+    fields: &[OverlayField],
+    timezone: StorageTimezone,
+) -> Vec<String> {
+    fields
+        .iter()
+        .filter_map(|field| match field {
+            OverlayField::Timestamp => Some(
+                timezone
+                    .local_time(record.ts)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+            ),
+            OverlayField::Title => record.window_title.clone(),
+            OverlayField::App => record.app_name.clone(),
+        })
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// `font_path`'s font if it loads, then the built-in one, then a system
+/// font for any character of `lines` neither has.
+fn load_fonts(font_path: Option<&PathBuf>, lines: &[String]) -> Vec<FontArc> {
+    let mut fonts = Vec::new();
+    if let Some(path) = font_path {
+        match std::fs::read(path).map(FontArc::try_from_vec) {
+            Ok(Ok(font)) => fonts.push(font),
+            Ok(Err(e)) => eprintln!("Overlay font {} unusable: {e}", path.display()),
+            Err(e) => eprintln!("Overlay font {} unreadable: {e}", path.display()),
+        }
+    }
+    fonts.push(builtin_font());
+    let missing = |fonts: &[FontArc]| {
+        lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .any(|c| fonts.iter().all(|font| font.glyph_id(c).0 == 0))
+    };
+    for path in SYSTEM_FALLBACK_FONTS {
+        if !missing(&fonts) {
+            break;
+        }
+        if let Ok(font) = std::fs::read(path).map(FontArc::try_from_vec) {
+            fonts.extend(font.ok());
+        }
+    }
+    fonts
+}
+
+/// The embedded DejaVu Sans, also used for labels of generated images.
+pub(crate) fn builtin_font() -> FontArc {
+    FontArc::try_from_slice(BUILTIN_FONT).expect("built-in font parses")
+}
+
+/// First of `fonts` with a glyph for `c`; the first one draws its
+/// missing-glyph box when none has.
+fn font_for(fonts: &[FontArc], c: char) -> &FontArc {
+    fonts
+        .iter()
+        .find(|font| font.glyph_id(c).0 != 0)
+        .unwrap_or(&fonts[0])
+}
+
+pub(crate) fn text_width(fonts: &[FontArc], scale: PxScale, text: &str) -> f32 {
+    text.chars()
+        .map(|c| {
+            let font = font_for(fonts, c).as_scaled(scale);
+            font.h_advance(font.glyph_id(c))
+        })
+        .sum()
+}
+
+/// `text`, or as much of it as fits in `max_width` followed by an
+/// ellipsis.
+fn elide(fonts: &[FontArc], scale: PxScale, text: &str, max_width: f32) -> String {
+    if text_width(fonts, scale, text) <= max_width {
+        return text.to_string();
+    }
+    let budget = max_width - text_width(fonts, scale, &ELLIPSIS.to_string());
+    let mut kept = String::new();
+    let mut width = 0.0;
+    for c in text.chars() {
+        let font = font_for(fonts, c).as_scaled(scale);
+        width += font.h_advance(font.glyph_id(c));
+        if width > budget {
+            break;
+        }
+        kept.push(c);
+    }
+    format!("{}{ELLIPSIS}", kept.trim_end())
+}
+
+/// Draws `text` in `color` starting at `left` on `baseline`, each
+/// character in the first of `fonts` that has it.
+pub(crate) fn draw_line(
+    image: &mut RgbaImage,
+    fonts: &[FontArc],
+    scale: PxScale,
+    (left, baseline): (f32, f32),
+    color: [u8; 3],
+    text: &str,
+) {
+    let mut caret = left;
+    for c in text.chars() {
+        let font = font_for(fonts, c);
+        let scaled = font.as_scaled(scale);
+        let glyph = scaled
+            .glyph_id(c)
+            .with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(glyph.id);
+        let Some(outline) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let x = bounds.min.x as i64 + x as i64;
+            let y = bounds.min.y as i64 + y as i64;
+            if x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64 {
+                blend(image, x as u32, y as u32, color, coverage);
+            }
+        });
+    }
+}
+
+/// Mixes `alpha` of `color` into the pixel at `(x, y)`.
+fn blend(image: &mut RgbaImage, x: u32, y: u32, color: [u8; 3], alpha: f32) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let pixel = image.get_pixel_mut(x, y);
+    for (channel, target) in pixel.0.iter_mut().zip(color) {
+        *channel = (*channel as f32 * (1.0 - alpha) + target as f32 * alpha).round() as u8;
+    }
+    pixel.0[3] = pixel.0[3].max((alpha * 255.0) as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use xcap::image::Rgba;
+
+    use super::*;
+    use crate::golden;
+
+    /// A busy background, so the label box and its blending show.
+    fn backdrop(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 255 / width) as u8, (y * 255 / height) as u8, 160, 255])
+        })
+    }
+
+    fn record(title: &str) -> CaptureRecord {
+        let mut record =
+            CaptureRecord::sample("c1", DateTime::from_timestamp(1_717_588_800, 0).unwrap());
+        record.window_title = Some(title.to_string());
+        record.app_name = Some("Editor".to_string());
+        record
+    }
+
+    fn config(position: OverlayPosition, fields: Vec<OverlayField>) -> OverlayConfig {
+        OverlayConfig {
+            enabled: true,
+            position,
+            font_size: 14.0,
+            fields,
+            font_path: None,
+        }
+    }
+
+    fn utc() -> StorageTimezone {
+        StorageTimezone::Named(chrono_tz::UTC)
+    }
+
+    #[test]
+    fn label_matches_golden() {
+        let mut image = backdrop(320, 120);
+        let config = config(
+            OverlayPosition::BottomRight,
+            vec![
+                OverlayField::Timestamp,
+                OverlayField::Title,
+                OverlayField::App,
+            ],
+        );
+        apply(
+            &mut image,
+            &record("Quarterly report — Ünïcode"),
+            &config,
+            utc(),
+        );
+        golden::assert_matches(&image, "overlay_bottom_right");
+    }
+
+    #[test]
+    fn elided_label_matches_golden() {
+        let mut image = backdrop(200, 60);
+        let title = "A window title far too long to fit across this small image";
+        let config = config(OverlayPosition::TopLeft, vec![OverlayField::Title]);
+        apply(&mut image, &record(title), &config, utc());
+        golden::assert_matches(&image, "overlay_top_left_elided");
+    }
+
+    #[test]
+    fn nothing_is_drawn_without_lines_or_room() {
+        let original = backdrop(320, 120);
+        let mut image = original.clone();
+        let mut empty = record("");
+        empty.app_name = None;
+        let fields = vec![OverlayField::Title, OverlayField::App];
+        apply(
+            &mut image,
+            &empty,
+            &config(OverlayPosition::TopLeft, fields.clone()),
+            utc(),
+        );
+        assert!(image == original);
+
+        let original = backdrop(20, 20);
+        let mut image = original.clone();
+        apply(
+            &mut image,
+            &record("Title"),
+            &config(OverlayPosition::TopLeft, fields),
+            utc(),
+        );
+        assert!(image == original);
+    }
+}
@@ -0,0 +1,504 @@
+//! Hourly pack files: captures appended to one archive per hour instead
+//! of one file each, for backup tools that slow down on many small files.
+//!
+//! A pack starts with `MAGIC`, followed by one slot per capture: the id's
+//! length as a little-endian u32, the id, the image's length as a
+//! little-endian u64 and the image bytes. Records point at the image bytes
+//! by offset and length, so reads seek straight to them; the ids keep a
+//! pack readable without the database. Deleting a capture leaves its slot
+//! behind as garbage until `compact` rewrites the pack.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    capture::write_atomic,
+    db::{CaptureRecord, Db},
+    error::{AppError, AppResult},
+    objects,
+    timezone::StorageTimezone,
+};
+
+const MAGIC: &[u8; 8] = b"VEEAPAK1";
+/// Directory under `capture_dir` holding the packs.
+const PACK_DIR: &str = "packs";
+const PACK_EXT: &str = "vpack";
+/// Share of a pack that must still be live data for `compact` to leave it.
+const MIN_LIVE_RATIO: f64 = 0.5;
+/// Packs written to this recently may still get appends, from a capture
+/// near the end of the hour, so `compact` leaves them alone.
+const SETTLE_TIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// How new event captures are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    /// One image file per capture.
+    #[default]
+    Files,
+    /// Appended to the pack for the hour they were taken in.
+    Packs,
+    /// One file per distinct image, named by its hash and shared by the
+    /// captures it was taken for, see `objects`.
+    ContentAddressed,
+}
+
+/// Where a packed capture's image is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackSlot {
+    /// Pack file, relative to `capture_dir`.
+    pub path: String,
+    /// Start of the image bytes in the pack.
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl PackSlot {
+    pub fn read(&self, capture_dir: &Path) -> std::io::Result<Vec<u8>> {
+        let mut file = File::open(capture_dir.join(&self.path))?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = vec![0; self.len as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Outcome of `pack_files` or `unpack`.
+#[derive(Debug, Default)]
+pub struct PackReport {
+    pub moved: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct CompactReport {
+    pub rewritten: usize,
+    /// Packs deleted because nothing in them was live.
+    pub removed: usize,
+    pub reclaimed: u64,
+}
+
+/// Hex SHA-256 of `bytes`. Packed captures store it as their `hash`, and
+/// it is checked whenever an image moves into, out of or between packs.
+pub fn digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn verify(id: &str, bytes: &[u8], hash: Option<&str>) -> AppResult<()> {
+    match hash {
+        Some(hash) if digest(bytes) != hash => Err(AppError::ImageUnreadable(format!(
+            "{id}: image does not match its stored hash"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A packed capture's image, checked against its hash.
+fn read_verified(record: &CaptureRecord, capture_dir: &Path) -> AppResult<Vec<u8>> {
+    let slot = record
+        .pack
+        .as_ref()
+        .ok_or_else(|| AppError::ImageUnreadable(format!("{} is not packed", record.id)))?;
+    let bytes = slot.read(capture_dir)?;
+    verify(&record.id, &bytes, record.hash.as_deref())?;
+    Ok(bytes)
+}
+
+/// Pack for captures taken at `ts`, relative to `capture_dir`:
+/// `packs/YYYY/MM/DD/HH.vpack` in `tz`, mirroring the capture folders.
+pub fn pack_path(ts: DateTime<Utc>, tz: StorageTimezone) -> PathBuf {
+    let local = tz.local_time(ts);
+    PathBuf::from(PACK_DIR)
+        .join(format!("{:04}", local.year()))
+        .join(format!("{:02}", local.month()))
+        .join(format!("{:02}", local.day()))
+        .join(format!("{:02}.{PACK_EXT}", local.hour()))
+}
+
+/// Appends `bytes` as capture `id`'s slot to the pack at `rel_path`,
+/// creating it if needed. The pack is locked while writing, so the daemon
+/// and `veea pack` can append to it at the same time.
+pub fn append(capture_dir: &Path, rel_path: &Path, id: &str, bytes: &[u8]) -> AppResult<PackSlot> {
+    let path = capture_dir.join(rel_path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    file.lock()?;
+    let start = file.metadata()?.len();
+    let mut slot = Vec::with_capacity(MAGIC.len() + 12 + id.len() + bytes.len());
+    if start == 0 {
+        slot.extend_from_slice(MAGIC);
+    }
+    slot.extend_from_slice(&(id.len() as u32).to_le_bytes());
+    slot.extend_from_slice(id.as_bytes());
+    slot.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    let offset = start + slot.len() as u64;
+    slot.extend_from_slice(bytes);
+    // A half-written slot would only be garbage, but trimming it keeps a
+    // failed write from growing the pack.
+    if let Err(e) = file.write_all(&slot).and_then(|_| file.sync_data()) {
+        let _ = file.set_len(start);
+        return Err(e.into());
+    }
+    Ok(PackSlot {
+        path: rel_path.to_string_lossy().into_owned(),
+        offset,
+        len: bytes.len() as u64,
+    })
+}
+
+/// Appends a new capture's encoded image to the pack for its hour and
+/// points `record` at it.
+pub fn store(
+    capture_dir: &Path,
+    tz: StorageTimezone,
+    record: &mut CaptureRecord,
+    bytes: &[u8],
+) -> AppResult<()> {
+    let slot = append(capture_dir, &pack_path(record.ts, tz), &record.id, bytes)?;
+    record.hash = Some(digest(bytes));
+    record.pack = Some(slot);
+    Ok(())
+}
+
+/// Moves live captures stored as files into packs. Each image is read
+/// back from its pack and checked against its hash before the record is
+/// repointed and the file deleted, once no unpacked capture shares it.
+pub fn pack_files(db: &Db, capture_dir: &Path, tz: StorageTimezone) -> AppResult<PackReport> {
+    let mut report = PackReport::default();
+    for record in db.loose_captures()? {
+        let Some(path) = record.resolve_path(capture_dir) else {
+            eprintln!("Skipping {}: image file missing", record.id);
+            report.skipped += 1;
+            continue;
+        };
+        let bytes = fs::read(&path)?;
+        let hash = digest(&bytes);
+        let slot = append(capture_dir, &pack_path(record.ts, tz), &record.id, &bytes)?;
+        if let Err(e) = verify(&record.id, &slot.read(capture_dir)?, Some(&hash)) {
+            eprintln!("Skipping {}: {e}", record.id);
+            report.skipped += 1;
+            continue;
+        }
+        db.set_pack_slot(&record.id, Some(&slot), &hash)?;
+        let _refs = objects::lock_for(capture_dir, &record)?;
+        if !db.image_file_in_use(&record)? {
+            fs::remove_file(&path)?;
+        }
+        report.moved += 1;
+    }
+    Ok(report)
+}
+
+/// Writes packed live captures back out as files at their recorded paths,
+/// checking each against its hash, then compacts the packs they leave
+/// empty.
+pub fn unpack(db: &Db, capture_dir: &Path) -> AppResult<PackReport> {
+    let mut report = PackReport::default();
+    for record in db.packed_captures(None)? {
+        let bytes = match read_verified(&record, capture_dir) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Skipping {}: {e}", record.id);
+                report.skipped += 1;
+                continue;
+            }
+        };
+        let path = match &record.rel_path {
+            Some(rel) => capture_dir.join(rel),
+            None => PathBuf::from(&record.path),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&path, &bytes)?;
+        db.set_pack_slot(&record.id, None, &digest(&bytes))?;
+        report.moved += 1;
+    }
+    compact(db, capture_dir)?;
+    Ok(report)
+}
+
+/// Rewrites packs whose live captures fill less than half the file,
+/// dropping deleted captures' slots, and deletes packs with nothing live
+/// left. Records of deleted captures keep pointing at the old pack, so
+/// `Db::compact` sees their images gone and purges them.
+pub fn compact(db: &Db, capture_dir: &Path) -> AppResult<CompactReport> {
+    let live = db.pack_usage()?;
+    let mut report = CompactReport::default();
+    for path in pack_files_under(&capture_dir.join(PACK_DIR))? {
+        let meta = fs::metadata(&path)?;
+        if !meta
+            .modified()?
+            .elapsed()
+            .is_ok_and(|age| age >= SETTLE_TIME)
+        {
+            continue;
+        }
+        let Some(rel) = path.strip_prefix(capture_dir).ok().map(Path::to_path_buf) else {
+            continue;
+        };
+        let live_bytes = live
+            .get(rel.to_string_lossy().as_ref())
+            .copied()
+            .unwrap_or(0);
+        if live_bytes as f64 >= meta.len() as f64 * MIN_LIVE_RATIO {
+            continue;
+        }
+        if live_bytes == 0 {
+            fs::remove_file(&path)?;
+            report.removed += 1;
+            report.reclaimed += meta.len();
+            continue;
+        }
+        let new_rel = compacted_path(&rel);
+        match rewrite(db, capture_dir, &rel, &new_rel) {
+            Ok(()) => {
+                fs::remove_file(&path)?;
+                let new_len = fs::metadata(capture_dir.join(&new_rel))?.len();
+                report.rewritten += 1;
+                report.reclaimed += meta.len().saturating_sub(new_len);
+            }
+            Err(e) => {
+                eprintln!("Leaving {} as it is: {e}", path.display());
+                let _ = fs::remove_file(capture_dir.join(&new_rel));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Copies the live slots of the pack at `rel` to a new pack at `new_rel`
+/// and repoints their records in one transaction.
+fn rewrite(db: &Db, capture_dir: &Path, rel: &Path, new_rel: &Path) -> AppResult<()> {
+    let mut moves = Vec::new();
+    for record in db.packed_captures(Some(&rel.to_string_lossy()))? {
+        let bytes = read_verified(&record, capture_dir)?;
+        moves.push((
+            record.id.clone(),
+            append(capture_dir, new_rel, &record.id, &bytes)?,
+        ));
+    }
+    db.move_pack_slots(&moves)
+}
+
+/// A fresh name beside `rel` for its compacted copy, keeping the hour:
+/// `HH.<unix ms>.vpack`.
+fn compacted_path(rel: &Path) -> PathBuf {
+    let name = rel.file_name().unwrap_or_default().to_string_lossy();
+    let hour = name.split('.').next().unwrap_or_default();
+    rel.with_file_name(format!(
+        "{hour}.{}.{PACK_EXT}",
+        Utc::now().timestamp_millis()
+    ))
+}
+
+fn pack_files_under(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut packs = Vec::new();
+    if !dir.exists() {
+        return Ok(packs);
+    }
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == PACK_EXT) {
+                packs.push(path);
+            }
+        }
+    }
+    Ok(packs)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::db::QueryFilter;
+
+    const UTC: StorageTimezone = StorageTimezone::Named(chrono_tz::UTC);
+
+    struct Archive {
+        dir: tempfile::TempDir,
+        db: Db,
+    }
+
+    impl Archive {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let db = Db::new(&dir.path().join("veea.db")).unwrap();
+            Self { dir, db }
+        }
+
+        fn capture_dir(&self) -> &Path {
+            self.dir.path()
+        }
+
+        /// A record for capture `id`, taken on 5 June 2024 at `hour`:00 UTC
+        /// and titled `title`.
+        fn record(&self, id: &str, hour: u32, title: &str) -> CaptureRecord {
+            let ts = Utc.with_ymd_and_hms(2024, 6, 5, hour, 0, 0).unwrap();
+            let mut record = CaptureRecord::sample(id, ts);
+            record.window_title = Some(title.to_string());
+            record.rel_path = Some(format!("2024/06/05/{id}.png"));
+            record.path = self
+                .capture_dir()
+                .join(record.rel_path.as_ref().unwrap())
+                .to_string_lossy()
+                .into_owned();
+            record
+        }
+
+        /// Stores `bytes` in its pack as the writer does.
+        fn packed(&self, id: &str, hour: u32, title: &str, bytes: &[u8]) -> CaptureRecord {
+            let mut record = self.record(id, hour, title);
+            store(self.capture_dir(), UTC, &mut record, bytes).unwrap();
+            self.db.insert_capture(&record).unwrap();
+            record
+        }
+
+        fn get(&self, id: &str) -> CaptureRecord {
+            self.db.get_capture(id).unwrap().unwrap()
+        }
+
+        fn delete(&self, title: &str) {
+            let filter = QueryFilter::new().title_contains(title);
+            self.db
+                .delete_filtered(&filter, self.capture_dir())
+                .unwrap();
+        }
+
+        fn pack(&self, hour: u32) -> PathBuf {
+            let ts = Utc.with_ymd_and_hms(2024, 6, 5, hour, 0, 0).unwrap();
+            self.capture_dir().join(pack_path(ts, UTC))
+        }
+
+        /// Makes every pack look last written before `SETTLE_TIME`.
+        fn settle(&self) {
+            let long_ago = std::time::SystemTime::now() - SETTLE_TIME - Duration::from_secs(60);
+            for path in pack_files_under(&self.capture_dir().join(PACK_DIR)).unwrap() {
+                let file = OpenOptions::new().write(true).open(path).unwrap();
+                file.set_modified(long_ago).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn slots_read_back_what_was_appended() {
+        let archive = Archive::new();
+        let first = archive.packed("a", 12, "Notes", b"first image");
+        let second = archive.packed("b", 12, "Notes", b"second image");
+
+        let (a, b) = (first.pack.unwrap(), second.pack.unwrap());
+        assert_eq!(a.path, "packs/2024/06/05/12.vpack");
+        assert_eq!(a.path, b.path);
+        assert!(b.offset >= a.offset + a.len);
+        assert_eq!(a.read(archive.capture_dir()).unwrap(), b"first image");
+        let bytes = read_verified(&archive.get("b"), archive.capture_dir()).unwrap();
+        assert_eq!(bytes, b"second image");
+        assert_eq!(
+            second.hash.as_deref(),
+            Some(digest(b"second image").as_str())
+        );
+        assert!(fs::read(archive.pack(12)).unwrap().starts_with(MAGIC));
+    }
+
+    #[test]
+    fn damaged_slot_is_rejected() {
+        let archive = Archive::new();
+        let record = archive.packed("a", 12, "Notes", b"first image");
+        let slot = record.pack.as_ref().unwrap();
+        let mut pack = fs::read(archive.pack(12)).unwrap();
+        pack[slot.offset as usize] ^= 0xff;
+        fs::write(archive.pack(12), pack).unwrap();
+
+        let error = read_verified(&record, archive.capture_dir()).unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{error}");
+        let report = unpack(&archive.db, archive.capture_dir()).unwrap();
+        assert_eq!((report.moved, report.skipped), (0, 1));
+        assert!(!Path::new(&record.path).exists());
+        assert!(archive.get("a").pack.is_some());
+    }
+
+    #[test]
+    fn unpack_restores_identical_files() {
+        let archive = Archive::new();
+        let a = archive.packed("a", 12, "Notes", b"first image");
+        let b = archive.packed("b", 13, "Notes", b"second image");
+
+        let report = unpack(&archive.db, archive.capture_dir()).unwrap();
+        assert_eq!((report.moved, report.skipped), (2, 0));
+        assert_eq!(fs::read(&a.path).unwrap(), b"first image");
+        assert_eq!(fs::read(&b.path).unwrap(), b"second image");
+        let a = archive.get("a");
+        assert_eq!(a.pack, None);
+        assert_eq!(a.hash.as_deref(), Some(digest(b"first image").as_str()));
+    }
+
+    #[test]
+    fn packing_removes_files_once_their_slot_is_recorded() {
+        let archive = Archive::new();
+        let loose = archive.record("a", 12, "Notes");
+        fs::create_dir_all(Path::new(&loose.path).parent().unwrap()).unwrap();
+        fs::write(&loose.path, b"loose image").unwrap();
+        archive.db.insert_capture(&loose).unwrap();
+        // Its file is gone, so there is nothing to pack.
+        archive
+            .db
+            .insert_capture(&archive.record("b", 12, "Notes"))
+            .unwrap();
+
+        let report = pack_files(&archive.db, archive.capture_dir(), UTC).unwrap();
+        assert_eq!((report.moved, report.skipped), (1, 1));
+        assert!(!Path::new(&loose.path).exists());
+        let packed = archive.get("a");
+        let bytes = read_verified(&packed, archive.capture_dir()).unwrap();
+        assert_eq!(bytes, b"loose image");
+        assert_eq!(archive.get("b").pack, None);
+    }
+
+    #[test]
+    fn compact_rewrites_sparse_settled_packs() {
+        let archive = Archive::new();
+        archive.packed("kept", 12, "keep", &[1; 1000]);
+        archive.packed("garbage", 12, "drop", &[2; 3000]);
+        archive.packed("dead", 13, "drop", &[3; 1000]);
+        archive.packed("full", 14, "keep", &[4; 1000]);
+        archive.delete("drop");
+
+        // Fresh packs may still be appended to.
+        let report = compact(&archive.db, archive.capture_dir()).unwrap();
+        assert_eq!((report.rewritten, report.removed), (0, 0));
+
+        archive.settle();
+        let full = fs::read(archive.pack(14)).unwrap();
+        let report = compact(&archive.db, archive.capture_dir()).unwrap();
+        assert_eq!((report.rewritten, report.removed), (1, 1));
+        assert!(!archive.pack(12).exists());
+        assert!(!archive.pack(13).exists());
+        // More than half of it is live.
+        assert_eq!(fs::read(archive.pack(14)).unwrap(), full);
+
+        let kept = archive.get("kept");
+        let slot = kept.pack.as_ref().unwrap();
+        assert_ne!(slot.path, "packs/2024/06/05/12.vpack");
+        assert!(slot.path.starts_with("packs/2024/06/05/12."));
+        let bytes = read_verified(&kept, archive.capture_dir()).unwrap();
+        assert_eq!(bytes, [1; 1000]);
+    }
+}
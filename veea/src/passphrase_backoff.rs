@@ -0,0 +1,98 @@
+//! Backoff for `/captures/:id/original`. Each try at `original_passphrase`
+//! costs one decryption, so without a limit the passphrase could be
+//! guessed as fast as the API answers. After `FREE_ATTEMPTS` wrong
+//! passphrases in a row every further one locks the endpoint out, for a
+//! second at first and twice as long each time after, up to
+//! `MAX_LOCKOUT`. The right passphrase resets the count.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Wrong passphrases allowed in a row before lockouts start.
+pub const FREE_ATTEMPTS: u32 = 3;
+const FIRST_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(300);
+
+/// Shared by every request. Times are passed in rather than read, so the
+/// lockouts can be tested without waiting.
+#[derive(Debug, Default)]
+pub struct PassphraseBackoff {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+impl PassphraseBackoff {
+    /// `Err` with the time left while locked out at `now`.
+    pub fn check(&self, now: Instant) -> Result<(), Duration> {
+        let inner = self.inner.lock().unwrap();
+        match inner.locked_until {
+            Some(until) if now < until => Err(until - now),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a wrong passphrase, returning the lockout it started, if
+    /// any.
+    pub fn failed(&self, now: Instant) -> Option<Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures = inner.failures.saturating_add(1);
+        let over = inner.failures.checked_sub(FREE_ATTEMPTS + 1)?;
+        let lockout = FIRST_LOCKOUT
+            .checked_mul(1 << over.min(16))
+            .map_or(MAX_LOCKOUT, |d| d.min(MAX_LOCKOUT));
+        inner.locked_until = Some(now + lockout);
+        Some(lockout)
+    }
+
+    /// Records the right passphrase.
+    pub fn succeeded(&self) {
+        *self.inner.lock().unwrap() = Inner::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn lockouts_start_after_the_free_attempts_and_double() {
+        let start = Instant::now();
+        let backoff = PassphraseBackoff::default();
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(backoff.failed(start), None);
+            assert_eq!(backoff.check(start), Ok(()));
+        }
+        assert_eq!(backoff.failed(start), Some(secs(1)));
+        assert_eq!(backoff.check(start), Err(secs(1)));
+        assert_eq!(backoff.check(start + secs(1)), Ok(()));
+        assert_eq!(backoff.failed(start + secs(1)), Some(secs(2)));
+        assert_eq!(backoff.failed(start + secs(3)), Some(secs(4)));
+        for _ in 0..40 {
+            backoff.failed(start);
+        }
+        assert_eq!(backoff.failed(start), Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn right_passphrase_resets_the_count() {
+        let start = Instant::now();
+        let backoff = PassphraseBackoff::default();
+        for _ in 0..FREE_ATTEMPTS {
+            backoff.failed(start);
+        }
+        backoff.succeeded();
+        assert_eq!(backoff.failed(start), None);
+        assert_eq!(backoff.check(start), Ok(()));
+    }
+}
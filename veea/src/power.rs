@@ -0,0 +1,249 @@
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::focus::WindowEvent;
+
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the wake watcher checks the clocks, and how much longer than
+/// that a check may take before it counts as a suspend.
+const WAKE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const WAKE_MIN_SLEEP: Duration = Duration::from_secs(30);
+
+/// Returns whether the machine is running on battery, or `None` when the
+/// power source can't be determined.
+pub fn on_battery() -> Option<bool> {
+    platform_on_battery()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_on_battery() -> Option<bool> {
+    let entries = std::fs::read_dir("/sys/class/power_supply").ok()?;
+    let mut has_battery = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let kind = std::fs::read_to_string(path.join("type")).unwrap_or_default();
+        match kind.trim() {
+            "Mains" | "USB" => {
+                let online = std::fs::read_to_string(path.join("online")).unwrap_or_default();
+                if online.trim() == "1" {
+                    return Some(false);
+                }
+            }
+            "Battery" => has_battery = true,
+            _ => {}
+        }
+    }
+    // No adapter online: on battery if there is one, otherwise a desktop.
+    Some(has_battery)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_on_battery() -> Option<bool> {
+    let output = std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Some(text.contains("'Battery Power'"))
+}
+
+#[cfg(target_os = "windows")]
+fn platform_on_battery() -> Option<bool> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    let mut status = SystemPowerStatus::default();
+    // SAFETY: the struct matches SYSTEM_POWER_STATUS and outlives the call.
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return None;
+    }
+    match status.ac_line_status {
+        0 => Some(true),
+        1 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_on_battery() -> Option<bool> {
+    None
+}
+
+/// Polls the power source and reports AC/battery transitions.
+pub fn monitor_power(event_sender: mpsc::Sender<WindowEvent>) {
+    let mut last = None;
+
+    loop {
+        if let Some(battery) = on_battery()
+            && last != Some(battery)
+        {
+            let _ = event_sender.send(WindowEvent::PowerChanged { on_battery: battery });
+            last = Some(battery);
+        }
+        thread::sleep(POWER_POLL_INTERVAL);
+    }
+}
+
+/// Reports wake-from-sleep as `WindowEvent::Woke`. On Linux that's
+/// logind's `PrepareForSleep` signal, read through `dbus-monitor`. macOS
+/// and Windows only announce sleep to a run loop or window of the app's
+/// own, so there, and on Linux when the system bus or `dbus-monitor` is
+/// missing or the monitor exits, the clocks are polled instead (see
+/// `poll_wake`).
+pub fn monitor_wake(event_sender: mpsc::Sender<WindowEvent>) {
+    #[cfg(target_os = "linux")]
+    if !watch_logind_sleep(&event_sender) {
+        return;
+    }
+    poll_wake(event_sender);
+}
+
+/// Sends `Woke` on each resume logind announces. Returns whether to fall
+/// back to polling: `false` only once nobody receives events any more.
+#[cfg(target_os = "linux")]
+fn watch_logind_sleep(event_sender: &mpsc::Sender<WindowEvent>) -> bool {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+
+    // Without logind nothing sends the signal and the monitor would wait
+    // forever.
+    if !std::path::Path::new("/run/systemd/seats").is_dir() {
+        return true;
+    }
+    let child = Command::new("dbus-monitor")
+        .args([
+            "--system",
+            "type='signal',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Wake watcher polling the clocks: cannot run dbus-monitor: {e}");
+            return true;
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return true;
+    };
+
+    let mut signals = SleepSignals::default();
+    let mut asleep_since = None;
+    let mut receiving = true;
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match signals.line(&line) {
+            Some(true) => asleep_since = Some(SystemTime::now()),
+            Some(false) => {
+                let slept = asleep_since
+                    .take()
+                    .and_then(|since| since.elapsed().ok())
+                    .unwrap_or_default();
+                let woke = WindowEvent::Woke {
+                    slept_ms: slept.as_millis() as u64,
+                };
+                if event_sender.send(woke).is_err() {
+                    receiving = false;
+                    break;
+                }
+            }
+            None => {}
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    if receiving {
+        eprintln!("Wake watcher polling the clocks: dbus-monitor exited");
+    }
+    receiving
+}
+
+/// Reads `PrepareForSleep` out of `dbus-monitor` output, which prints
+/// each signal's header line followed by its arguments: `true` as the
+/// machine suspends, `false` once it has resumed.
+#[derive(Debug, Default)]
+struct SleepSignals {
+    in_signal: bool,
+}
+
+impl SleepSignals {
+    fn line(&mut self, line: &str) -> Option<bool> {
+        if line.starts_with("signal ") {
+            self.in_signal = line.contains("member=PrepareForSleep");
+            return None;
+        }
+        if !std::mem::take(&mut self.in_signal) {
+            return None;
+        }
+        match line.trim() {
+            "boolean true" => Some(true),
+            "boolean false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// The fallback for `monitor_wake`. Nothing runs while the machine is
+/// suspended, so a wake shows up as a poll that took far longer than it
+/// slept: on the wall clock where the monotonic clock stops during suspend
+/// (Linux, macOS), on the monotonic clock where it keeps counting. Sleeps
+/// shorter than `WAKE_MIN_SLEEP` go unnoticed.
+fn poll_wake(event_sender: mpsc::Sender<WindowEvent>) {
+    loop {
+        let (wall, mono) = (SystemTime::now(), Instant::now());
+        thread::sleep(WAKE_POLL_INTERVAL);
+        let elapsed = wall.elapsed().unwrap_or_default().max(mono.elapsed());
+        if elapsed > WAKE_POLL_INTERVAL + WAKE_MIN_SLEEP {
+            let slept = elapsed - WAKE_POLL_INTERVAL;
+            let _ = event_sender.send(WindowEvent::Woke {
+                slept_ms: slept.as_millis() as u64,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_signals_are_read_from_monitor_output() {
+        let output = "\
+signal time=1718000000.1 sender=org.freedesktop.DBus -> destination=:1.90 serial=2 path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired
+   string \":1.90\"
+signal time=1718000100.2 sender=:1.3 -> destination=(null destination) serial=812 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep
+   boolean true
+   boolean false
+signal time=1718003600.7 sender=:1.3 -> destination=(null destination) serial=815 path=/org/freedesktop/login1; interface=org.freedesktop.login1.Manager; member=PrepareForSleep
+   boolean false
+";
+        let mut signals = SleepSignals::default();
+        let read: Vec<bool> = output.lines().filter_map(|line| signals.line(line)).collect();
+        assert_eq!(read, [true, false]);
+    }
+}
@@ -0,0 +1,68 @@
+/// Niceness given to the capture thread under `low_priority`.
+#[cfg(target_os = "linux")]
+const LOW_NICE: i32 = 10;
+
+/// Lowers the scheduling priority of the calling thread so capture, image
+/// encoding and OCR yield to foreground work. Threads it spawns afterwards,
+/// and OCR tools it runs, inherit the lower priority where the platform
+/// allows.
+pub fn lower_current_thread() -> Result<(), String> {
+    platform_lower_current_thread()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_lower_current_thread() -> Result<(), String> {
+    const PRIO_PROCESS: i32 = 0;
+
+    unsafe extern "C" {
+        fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+
+    // On Linux niceness is per thread and `who == 0` means the caller, so
+    // the API and watcher threads keep their priority.
+    // SAFETY: plain syscall wrapper with no pointers involved.
+    if unsafe { setpriority(PRIO_PROCESS, 0, LOW_NICE) } != 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn platform_lower_current_thread() -> Result<(), String> {
+    /// `QOS_CLASS_UTILITY`: long-running work the user isn't waiting on.
+    const QOS_CLASS_UTILITY: u32 = 0x11;
+
+    unsafe extern "C" {
+        fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+    }
+
+    // `setpriority` would apply to the whole process here; the QoS class
+    // only affects this thread.
+    // SAFETY: no pointers involved; returns an errno value on failure.
+    match unsafe { pthread_set_qos_class_self_np(QOS_CLASS_UTILITY, 0) } {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno).to_string()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_lower_current_thread() -> Result<(), String> {
+    const THREAD_PRIORITY_BELOW_NORMAL: i32 = -1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetCurrentThread() -> isize;
+        fn SetThreadPriority(thread: isize, priority: i32) -> i32;
+    }
+
+    // SAFETY: GetCurrentThread returns a pseudo-handle valid for the call.
+    if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_BELOW_NORMAL) } == 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_lower_current_thread() -> Result<(), String> {
+    Err("not supported on this platform".into())
+}
@@ -0,0 +1,157 @@
+use xcap::image::RgbaImage;
+
+/// Largest per-channel standard deviation of a blank frame. A bare prompt
+/// on an otherwise empty 1080p dark terminal already lifts it above 1.5,
+/// so only truly flat frames fall below.
+const BLANK_MAX_STDDEV: f64 = 1.0;
+/// Share of pixels that must equal the mean colour.
+const BLANK_MIN_UNIFORM: f64 = 0.99;
+
+/// Cheap per-image statistics for spotting failed captures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageStats {
+    /// Mean of R, G and B, 0-255.
+    pub mean: [f64; 3],
+    /// Standard deviation of R, G and B.
+    pub stddev: [f64; 3],
+    /// Share of pixels exactly matching the mean colour, rounded. For
+    /// near-flat frames that is the colour almost every pixel has.
+    pub uniform: f64,
+}
+
+impl ImageStats {
+    pub fn of(image: &RgbaImage) -> Self {
+        let count = (image.width() as f64 * image.height() as f64).max(1.0);
+        let mut sum = [0f64; 3];
+        let mut sum_sq = [0f64; 3];
+        for pixel in image.pixels() {
+            for c in 0..3 {
+                let v = pixel[c] as f64;
+                sum[c] += v;
+                sum_sq[c] += v * v;
+            }
+        }
+        let mean = sum.map(|s| s / count);
+        let mut stddev = [0f64; 3];
+        for c in 0..3 {
+            stddev[c] = (sum_sq[c] / count - mean[c] * mean[c]).max(0.0).sqrt();
+        }
+        let typical = mean.map(|m| m.round() as u8);
+        let matching = image
+            .pixels()
+            .filter(|p| [p[0], p[1], p[2]] == typical)
+            .count();
+        Self {
+            mean,
+            stddev,
+            uniform: matching as f64 / count,
+        }
+    }
+
+    /// Essentially one flat colour, like the solid black frames a missing
+    /// screen-recording permission produces. Dark screens with any content
+    /// on them have enough variance to pass.
+    pub fn is_blank(&self) -> bool {
+        self.stddev.iter().all(|s| *s < BLANK_MAX_STDDEV) && self.uniform >= BLANK_MIN_UNIFORM
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xcap::image::Rgba;
+
+    use super::*;
+
+    const BACKGROUND: Rgba<u8> = Rgba([30, 30, 30, 255]);
+    const TEXT: Rgba<u8> = Rgba([200, 200, 200, 255]);
+
+    fn blank(image: &RgbaImage) -> bool {
+        ImageStats::of(image).is_blank()
+    }
+
+    /// A 1080p dark terminal showing `lines`, each character an 8x16 cell
+    /// with a third of its inner pixels lit, and a block cursor after the
+    /// last line.
+    fn terminal(lines: &[&str]) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(1920, 1080, BACKGROUND);
+        let mut cell = |col: u32, row: u32, lit: &dyn Fn(u32, u32) -> bool| {
+            for y in 0..16 {
+                for x in 0..8 {
+                    if lit(x, y) {
+                        image.put_pixel(col * 8 + x, row * 16 + y, TEXT);
+                    }
+                }
+            }
+        };
+        for (row, line) in lines.iter().enumerate() {
+            for (col, ch) in line.chars().enumerate() {
+                if ch != ' ' {
+                    let seed = ch as u32;
+                    cell(col as u32, row as u32, &|x, y| {
+                        (2..14).contains(&y)
+                            && (1..7).contains(&x)
+                            && (x + y + seed).is_multiple_of(3)
+                    });
+                }
+            }
+        }
+        let last = lines.len().saturating_sub(1) as u32;
+        let col = lines.last().map_or(0, |l| l.chars().count()) as u32;
+        cell(col, last, &|_, _| true);
+        image
+    }
+
+    #[test]
+    fn solid_frames_are_blank() {
+        for colour in [[0, 0, 0], [255, 255, 255], [30, 30, 30], [12, 80, 200]] {
+            let [r, g, b] = colour;
+            let image = RgbaImage::from_pixel(320, 200, Rgba([r, g, b, 255]));
+            assert!(blank(&image), "{colour:?}");
+        }
+    }
+
+    #[test]
+    fn encoder_noise_on_a_black_frame_is_still_blank() {
+        let image = RgbaImage::from_fn(320, 200, |x, y| {
+            let v = u8::from((x * 7 + y * 13).is_multiple_of(250));
+            Rgba([v, v, v, 255])
+        });
+        let stats = ImageStats::of(&image);
+        assert!(stats.uniform < 1.0, "{stats:?}");
+        assert!(stats.is_blank(), "{stats:?}");
+    }
+
+    #[test]
+    fn dark_terminal_with_a_bare_prompt_is_not_blank() {
+        let stats = ImageStats::of(&terminal(&["user@host:~$ "]));
+        assert!(stats.stddev.iter().all(|s| *s > 1.5), "{stats:?}");
+        assert!(!stats.is_blank(), "{stats:?}");
+    }
+
+    #[test]
+    fn dark_terminal_with_output_is_not_blank() {
+        let image = terminal(&[
+            "user@host:~$ ls",
+            "Cargo.lock  Cargo.toml  src  target",
+            "user@host:~$ ",
+        ]);
+        assert!(!blank(&image));
+    }
+
+    #[test]
+    fn a_single_bright_pixel_breaks_a_flat_frame() {
+        let mut image = RgbaImage::from_pixel(320, 200, Rgba([0, 0, 0, 255]));
+        assert!(blank(&image));
+        image.put_pixel(10, 10, Rgba([255, 255, 255, 255]));
+        assert!(!blank(&image));
+    }
+
+    #[test]
+    fn dark_gradient_is_not_blank() {
+        let image = RgbaImage::from_fn(320, 200, |x, _| {
+            let v = (x / 20) as u8;
+            Rgba([v, v, v, 255])
+        });
+        assert!(!blank(&image));
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{NaiveDate, Utc};
+use xcap::image::DynamicImage;
+
+use crate::{
+    base64,
+    codec::{self, ImageFormat},
+    config::CaptureConfig,
+    db::{CaptureRecord, Db},
+    error::AppResult,
+    feed::escape,
+    timeline,
+};
+
+/// Stretches without a capture longer than this are idle: gaps, as
+/// `/gaps` reports them by default. The rest counts as active time.
+const IDLE_GAP_MS: i64 = 10 * 60 * 1000;
+const TOP_APPS: usize = 10;
+const HIGHLIGHTS: usize = 12;
+/// Longest edge of the thumbnails embedded in the report.
+const THUMBNAIL_SIZE: u32 = 320;
+const INPUT_HEADERS: &str = "<th>Keys/min</th><th>Clicks/min</th>";
+
+/// End-of-day overview of one `storage_timezone` day, written by
+/// `veea report` and the `daily_report` schedule.
+#[derive(Debug, Clone)]
+pub struct DailyReport {
+    pub date: NaiveDate,
+    pub capture_count: usize,
+    pub active_ms: i64,
+    /// Apps by active time, longest first.
+    pub top_apps: Vec<AppTime>,
+    /// Best scored captures of the day, as `/highlights` picks them.
+    pub highlights: Vec<CaptureRecord>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppTime {
+    pub app: String,
+    pub captures: usize,
+    pub active_ms: i64,
+    /// Mean key presses and clicks per minute over the captures that
+    /// tracked them, see `track_input_counts`.
+    pub keys_per_min: Option<f64>,
+    pub clicks_per_min: Option<f64>,
+}
+
+impl DailyReport {
+    pub fn build(db: &Db, config: &CaptureConfig, date: NaiveDate) -> AppResult<Self> {
+        let (start, end) = config.storage_timezone.day_bounds(date);
+        let (from, to) = (start.timestamp_millis(), end.timestamp_millis());
+        let captures = db.capture_apps(from, to)?;
+
+        // Each capture accounts for the time until the next one, unless a
+        // gap follows it.
+        let stamps: Vec<(i64, String)> = captures.iter().map(|c| (c.ts, c.id.clone())).collect();
+        let idle: HashSet<String> = timeline::find_gaps(&stamps, from, to, IDLE_GAP_MS)
+            .into_iter()
+            .filter_map(|gap| gap.before_id)
+            .collect();
+        let mut apps: HashMap<String, AppTime> = HashMap::new();
+        // Per app: captures with input counts, and their summed keys and
+        // clicks.
+        let mut input: HashMap<String, (u32, u64, u64)> = HashMap::new();
+        let mut active_ms = 0;
+        for (i, capture) in captures.iter().enumerate() {
+            let app = capture.app_name.clone().unwrap_or_else(|| "unknown".to_string());
+            if let Some(counts) = capture.input {
+                let sums = input.entry(app.clone()).or_default();
+                sums.0 += 1;
+                sums.1 += u64::from(counts.keys);
+                sums.2 += u64::from(counts.clicks);
+            }
+            let entry = apps.entry(app.clone()).or_insert(AppTime {
+                app,
+                captures: 0,
+                active_ms: 0,
+                keys_per_min: None,
+                clicks_per_min: None,
+            });
+            entry.captures += 1;
+            if let Some(next) = captures.get(i + 1)
+                && !idle.contains(&capture.id)
+            {
+                entry.active_ms += next.ts - capture.ts;
+                active_ms += next.ts - capture.ts;
+            }
+        }
+        for (app, (tracked, keys, clicks)) in input {
+            if let Some(entry) = apps.get_mut(&app) {
+                entry.keys_per_min = Some(keys as f64 / tracked as f64);
+                entry.clicks_per_min = Some(clicks as f64 / tracked as f64);
+            }
+        }
+        let mut top_apps: Vec<AppTime> = apps.into_values().collect();
+        top_apps.sort_by(|a, b| {
+            b.active_ms
+                .cmp(&a.active_ms)
+                .then(b.captures.cmp(&a.captures))
+                .then_with(|| a.app.cmp(&b.app))
+        });
+        top_apps.truncate(TOP_APPS);
+
+        let highlights = db.scored_captures(from, to, Some(HIGHLIGHTS))?;
+
+        Ok(Self {
+            date,
+            capture_count: captures.len(),
+            active_ms,
+            top_apps,
+            highlights,
+        })
+    }
+
+    /// Standalone HTML page with the highlights embedded as thumbnails, so
+    /// the file can be opened or moved without the archive.
+    pub fn to_html(&self, config: &CaptureConfig) -> String {
+        let mut html = String::new();
+        // Input columns only appear once some capture of the day has counts.
+        let tracked_input = self.top_apps.iter().any(|app| app.keys_per_min.is_some());
+        let _ = write!(
+            html,
+            r#"<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8" />
+    <title>Veea report {date}</title>
+    <style>
+      body {{ font-family: sans-serif; margin: 16px; }}
+      table {{ border-collapse: collapse; }}
+      td, th {{ padding: 4px 12px; text-align: left; }}
+      .grid {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(220px, 1fr)); gap: 12px; }}
+      .card {{ border: 1px solid #ccc; padding: 8px; border-radius: 6px; }}
+      img {{ max-width: 100%; }}
+    </style>
+  </head>
+  <body>
+    <h1>{date}</h1>
+    <p>{count} captures, {active} active</p>
+    <h2>Top apps</h2>
+    <table>
+      <tr><th>App</th><th>Active</th><th>Captures</th>{input_headers}</tr>
+"#,
+            date = self.date,
+            count = self.capture_count,
+            active = format_duration(self.active_ms),
+            input_headers = if tracked_input { INPUT_HEADERS } else { "" },
+        );
+        for app in &self.top_apps {
+            let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{r:.0}"));
+            let input = if tracked_input {
+                format!(
+                    "<td>{}</td><td>{}</td>",
+                    rate(app.keys_per_min),
+                    rate(app.clicks_per_min)
+                )
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                html,
+                "      <tr><td>{}</td><td>{}</td><td>{}</td>{input}</tr>",
+                escape(&app.app),
+                format_duration(app.active_ms),
+                app.captures
+            );
+        }
+        html.push_str("    </table>\n    <h2>Highlights</h2>\n    <div class=\"grid\">\n");
+        for record in &self.highlights {
+            let image = match thumbnail(record, &config.capture_dir) {
+                Some(png) => format!("<img src=\"data:image/png;base64,{}\" />", base64::encode(&png)),
+                None => "<p>image missing</p>".to_string(),
+            };
+            let _ = writeln!(
+                html,
+                "      <div class=\"card\"><div>{}</div><div><strong>{}</strong></div><div>{}</div>{image}</div>",
+                config.storage_timezone.local_time(record.ts).format("%H:%M"),
+                escape(record.app_name.as_deref().unwrap_or("")),
+                escape(record.window_title.as_deref().unwrap_or("")),
+            );
+        }
+        html.push_str("    </div>\n  </body>\n</html>\n");
+        html
+    }
+}
+
+/// Builds the report for `date` and writes it to `report_dir` as
+/// `YYYY-MM-DD.html`, replacing an earlier one. Returns the file written.
+pub fn write(db: &Db, config: &CaptureConfig, date: NaiveDate) -> AppResult<PathBuf> {
+    let report = DailyReport::build(db, config, date)?;
+    fs::create_dir_all(&config.report_dir)?;
+    let path = report_path(&config.report_dir, date);
+    fs::write(&path, report.to_html(config))?;
+    Ok(path)
+}
+
+pub fn report_path(report_dir: &Path, date: NaiveDate) -> PathBuf {
+    report_dir.join(format!("{date}.html"))
+}
+
+/// For the `daily_report` schedule: writes yesterday's report unless its
+/// file already exists. Returns the file when one was written.
+pub fn write_due(db: &Db, config: &CaptureConfig) -> AppResult<Option<PathBuf>> {
+    let today = config.storage_timezone.date_of(Utc::now());
+    let Some(yesterday) = today.pred_opt() else {
+        return Ok(None);
+    };
+    if report_path(&config.report_dir, yesterday).exists() {
+        return Ok(None);
+    }
+    write(db, config, yesterday).map(Some)
+}
+
+fn thumbnail(record: &CaptureRecord, capture_dir: &Path) -> Option<Vec<u8>> {
+    let bytes = record.read_image(capture_dir).ok()?;
+    let image = DynamicImage::from(codec::decode(&bytes, record.codec).ok()?);
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    codec::encode(&thumbnail.to_rgba8(), ImageFormat::Png).ok()
+}
+
+fn format_duration(ms: i64) -> String {
+    let minutes = ms / 60_000;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::timezone::StorageTimezone;
+
+    const MINUTE: i64 = 60_000;
+
+    fn config(dir: &Path) -> CaptureConfig {
+        CaptureConfig {
+            capture_dir: dir.to_path_buf(),
+            storage_timezone: "UTC".parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 6).unwrap()
+    }
+
+    /// Stores a capture of `app` `minutes` into the day.
+    fn capture(
+        db: &Db,
+        id: &str,
+        minutes: i64,
+        app: &str,
+        adjust: impl FnOnce(&mut CaptureRecord),
+    ) {
+        let (start, _) = StorageTimezone::Named(chrono_tz::UTC).day_bounds(date());
+        let ts = DateTime::from_timestamp_millis(start.timestamp_millis() + minutes * MINUTE);
+        let mut record = CaptureRecord::sample(id, ts.unwrap());
+        record.app_name = Some(app.to_string());
+        adjust(&mut record);
+        db.insert_capture(&record).unwrap();
+    }
+
+    #[test]
+    fn active_time_stops_at_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        capture(&db, "a", 60, "editor", |r| {
+            (r.keys_per_min, r.clicks_per_min) = (Some(90), Some(4))
+        });
+        capture(&db, "b", 65, "browser", |_| {});
+        capture(&db, "c", 70, "editor", |r| {
+            (r.keys_per_min, r.clicks_per_min) = (Some(30), Some(8))
+        });
+        // Ten minutes exactly is still active; past that it's a gap.
+        capture(&db, "d", 80, "editor", |_| {});
+        capture(&db, "e", 91, "browser", |_| {});
+        capture(&db, "f", 93, "browser", |_| {});
+
+        let report = DailyReport::build(&db, &config(dir.path()), date()).unwrap();
+        assert_eq!(report.capture_count, 6);
+        assert_eq!(report.active_ms, 22 * MINUTE);
+        let apps: Vec<(&str, usize, i64)> = report
+            .top_apps
+            .iter()
+            .map(|app| (app.app.as_str(), app.captures, app.active_ms / MINUTE))
+            .collect();
+        assert_eq!(apps, [("editor", 3, 15), ("browser", 3, 7)]);
+        let editor = &report.top_apps[0];
+        assert_eq!(
+            (editor.keys_per_min, editor.clicks_per_min),
+            (Some(60.0), Some(6.0))
+        );
+        assert_eq!(report.top_apps[1].keys_per_min, None);
+    }
+
+    #[test]
+    fn highlights_are_the_best_scored_captures() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Db::new(&dir.path().join("veea.db")).unwrap();
+        for i in 0..20 {
+            let id = format!("c{i:02}");
+            capture(&db, &id, i, "editor", |r| r.importance = Some(i as f64));
+        }
+        capture(&db, "blank", 30, "editor", |r| {
+            (r.importance, r.blank) = (Some(99.0), true)
+        });
+        capture(&db, "unscored", 31, "editor", |_| {});
+        capture(&db, "<b>", 32, "a & b", |r| {
+            r.window_title = Some("<script>\"x\"</script>".to_string());
+            r.importance = Some(50.0);
+        });
+
+        let config = config(dir.path());
+        let report = DailyReport::build(&db, &config, date()).unwrap();
+        let ids: Vec<&str> = report.highlights.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids.len(), HIGHLIGHTS);
+        assert_eq!(ids[..3], ["<b>", "c19", "c18"]);
+        assert_eq!(ids.last(), Some(&"c09"));
+
+        let html = report.to_html(&config);
+        assert!(html.contains("&lt;script&gt;&quot;x&quot;&lt;/script&gt;"));
+        assert!(html.contains("<td>a &amp; b</td>"));
+        assert!(!html.contains("<script>"));
+        // The sample captures have no image files.
+        assert_eq!(html.matches("image missing").count(), HIGHLIGHTS);
+    }
+}
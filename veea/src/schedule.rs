@@ -0,0 +1,171 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Weekday};
+
+use crate::{
+    config::ScheduleWindow,
+    error::{AppError, AppResult},
+    trigger,
+};
+
+/// When event captures are allowed, from the `schedule` config. Windows
+/// are parsed once, and a schedule with an invalid day or time window is
+/// refused as a whole: dropping the entry could leave a window without
+/// days or hours, which would allow capturing always.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    windows: Vec<Window>,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    days: Vec<Weekday>,
+    hours: Vec<(NaiveTime, NaiveTime)>,
+}
+
+impl Window {
+    fn allows_day(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// Hours past midnight in a window like "22:00-02:00" belong to the
+    /// day it started on.
+    fn allows(&self, time: NaiveTime, day: Weekday) -> bool {
+        if self.hours.is_empty() {
+            return self.allows_day(day);
+        }
+        self.hours.iter().any(|&(start, end)| {
+            if !trigger::in_window(time, (start, end)) {
+                false
+            } else if start > end && time < end {
+                self.allows_day(day.pred())
+            } else {
+                self.allows_day(day)
+            }
+        })
+    }
+}
+
+impl Schedule {
+    pub fn new(windows: &[ScheduleWindow]) -> AppResult<Self> {
+        let invalid = |what: &str| AppError::Config(format!("schedule: invalid {what}"));
+        let windows = windows
+            .iter()
+            .map(|window| {
+                let days = window
+                    .days
+                    .iter()
+                    .map(|day| {
+                        day.parse::<Weekday>()
+                            .map_err(|_| invalid(&format!("day '{day}'")))
+                    })
+                    .collect::<AppResult<_>>()?;
+                let hours = window
+                    .hours
+                    .iter()
+                    .map(|hours| {
+                        trigger::parse_window(hours)
+                            .ok_or_else(|| invalid(&format!("time window '{hours}'")))
+                    })
+                    .collect::<AppResult<_>>()?;
+                Ok(Window { days, hours })
+            })
+            .collect::<AppResult<_>>()?;
+        Ok(Self { windows })
+    }
+
+    /// Whether capturing is allowed now, in local time. An empty schedule
+    /// always allows it.
+    pub fn is_active(&self) -> bool {
+        self.is_active_at(&Local::now())
+    }
+
+    /// `is_active` against an explicit clock, in whatever zone `now` carries.
+    pub fn is_active_at<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        let (time, day) = (now.time(), now.weekday());
+        self.windows.is_empty() || self.windows.iter().any(|window| window.allows(time, day))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn schedule(windows: &[(&[&str], &[&str])]) -> AppResult<Schedule> {
+        let windows: Vec<_> = windows
+            .iter()
+            .map(|(days, hours)| ScheduleWindow {
+                days: days.iter().map(|d| d.to_string()).collect(),
+                hours: hours.iter().map(|h| h.to_string()).collect(),
+            })
+            .collect();
+        Schedule::new(&windows)
+    }
+
+    /// June 2024 at `day` `hh:mm` UTC; the 5th is a Wednesday.
+    fn at(day: u32, hh: u32, mm: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, day, hh, mm, 0).unwrap()
+    }
+
+    #[test]
+    fn invalid_entries_refuse_the_schedule() {
+        for (days, hours, named) in [
+            (&["mon", "mun"][..], &[][..], "day 'mun'"),
+            (&[], &["9-17"], "time window '9-17'"),
+            (&[], &["09:00"], "time window '09:00'"),
+            (&[], &["09:00-25:00"], "time window '09:00-25:00'"),
+            (&[], &["09:00-09:00"], "time window '09:00-09:00'"),
+        ] {
+            let error = schedule(&[(days, hours)]).unwrap_err().to_string();
+            assert!(error.contains(named), "{error}");
+        }
+        schedule(&[(&["Monday", "tue"], &["00:00-24:00"])]).unwrap();
+    }
+
+    #[test]
+    fn working_hours_match_weekdays_only() {
+        let weekdays = ["mon", "tue", "wed", "thu", "fri"];
+        let schedule = schedule(&[(&weekdays, &["09:00-17:30"])]).unwrap();
+        assert!(schedule.is_active_at(&at(5, 9, 0)));
+        assert!(schedule.is_active_at(&at(5, 17, 29)));
+        assert!(!schedule.is_active_at(&at(5, 17, 30)));
+        assert!(!schedule.is_active_at(&at(5, 8, 59)));
+        assert!(!schedule.is_active_at(&at(8, 12, 0)));
+    }
+
+    #[test]
+    fn window_past_midnight_belongs_to_the_day_it_starts() {
+        let schedule = schedule(&[(&["fri"], &["22:00-02:00"])]).unwrap();
+        assert!(schedule.is_active_at(&at(7, 23, 0)));
+        assert!(schedule.is_active_at(&at(8, 1, 59)));
+        assert!(!schedule.is_active_at(&at(8, 2, 0)));
+        assert!(!schedule.is_active_at(&at(8, 23, 0)));
+        // Thursday night's hours, and Thursday isn't listed.
+        assert!(!schedule.is_active_at(&at(7, 1, 0)));
+    }
+
+    #[test]
+    fn windows_may_end_at_midnight() {
+        let evening = schedule(&[(&[], &["18:00-24:00"])]).unwrap();
+        assert!(evening.is_active_at(&at(5, 23, 59)));
+        assert!(!evening.is_active_at(&at(5, 0, 0)));
+        assert!(!evening.is_active_at(&at(5, 17, 59)));
+
+        let all_day = schedule(&[(&["wed"], &["00:00-24:00"])]).unwrap();
+        assert!(all_day.is_active_at(&at(5, 0, 0)));
+        assert!(all_day.is_active_at(&at(5, 23, 59)));
+        assert!(!all_day.is_active_at(&at(6, 12, 0)));
+    }
+
+    #[test]
+    fn missing_days_or_hours_mean_all_of_them() {
+        assert!(schedule(&[]).unwrap().is_active_at(&at(8, 3, 0)));
+        let weekend = schedule(&[(&["sat", "sun"], &[])]).unwrap();
+        assert!(weekend.is_active_at(&at(8, 3, 0)));
+        assert!(!weekend.is_active_at(&at(7, 3, 0)));
+        let mornings = schedule(&[(&[], &["06:00-09:00"]), (&["sun"], &["20:00-21:00"])]).unwrap();
+        assert!(mornings.is_active_at(&at(8, 7, 0)));
+        assert!(mornings.is_active_at(&at(9, 20, 30)));
+        assert!(!mornings.is_active_at(&at(8, 20, 30)));
+    }
+}
@@ -1,62 +1,410 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use rusqlite::{params, Connection};
+use rusqlite::{
+    functions::{Context, FunctionFlags},
+    params_from_iter,
+    types::Value,
+    Connection,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    crypto::{self, TextKey},
     db::CaptureRecord,
     error::AppResult,
 };
 
+/// Shared by `search` and `count` so totals always match the hits; both
+/// add `SearchScope::range_clause`.
+/// Columns that may be sealed go through `veea_open`.
+const SUBSTRING_WHERE: &str = "deleted = 0 \
+     AND (window_title LIKE ?1 OR app_name LIKE ?1 OR app_id LIKE ?1 \
+          OR veea_open(clipboard_text) LIKE ?1 \
+          OR prev_title LIKE ?1 OR veea_open(ocr_text) LIKE ?1 OR doc_path LIKE ?1 \
+          OR veea_open(ax_text) LIKE ?1)";
+const TERMS_WHERE: &str = "deleted = 0 \
+     AND veea_terms_match(?1, window_title, app_name, app_id, veea_open(clipboard_text), \
+          prev_title, veea_open(ocr_text), doc_path, veea_open(ax_text))";
+const FUZZY_SCORE: &str = "veea_fuzzy_score(?1, window_title, app_name, app_id, \
+     veea_open(clipboard_text), prev_title, veea_open(ocr_text), doc_path, veea_open(ax_text))";
+/// What a `SearchHit` is read from, in order.
+const HIT_COLUMNS: &str =
+    "id, ts, window_title, app_name, event_type, path, width, height, monitor, app_id";
+/// Every searched field in one string, for the `fuzzy_prefilter` `LIKE`s.
+const FUZZY_HAYSTACK: &str = "(coalesce(window_title, '') || ' ' || coalesce(app_name, '') \
+     || ' ' || coalesce(app_id, '') || ' ' || coalesce(veea_open(clipboard_text), '') \
+     || ' ' || coalesce(prev_title, '') || ' ' || coalesce(veea_open(ocr_text), '') \
+     || ' ' || coalesce(doc_path, '') || ' ' || coalesce(veea_open(ax_text), ''))";
+
+/// How `/search` matches `q` against the searched fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// `q` anywhere, even inside a word: "cat" matches "category".
+    Substring,
+    /// Every word of `q` as a whole word, in any order; "quoted phrases"
+    /// must appear as written.
+    #[default]
+    Words,
+    /// All of `q` as one phrase of whole words.
+    Phrase,
+    /// Every word of `q`, forgiving typos and accepting word beginnings:
+    /// "screnshot" and "scree" both match "screenshot". Best matches first.
+    Fuzzy,
+}
+
+/// Which end of the matches `/search` lists first. Fuzzy searches rank
+/// by score before time either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchOrder {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+/// Narrows a search to captures taken in `[from, to)`, in Unix ms; open
+/// ends match any time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchScope {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub order: SearchOrder,
+}
+
+impl SearchScope {
+    /// `AND` conditions for the ends that are set, numbered from `?n`, and
+    /// the values they bind. Empty for an open range, so unbounded
+    /// searches stay as they were.
+    fn range_clause(&self, n: usize) -> (String, Vec<Value>) {
+        let mut clause = String::new();
+        let mut values = Vec::new();
+        for (end, op) in [(self.from, ">="), (self.to, "<")] {
+            if let Some(ts) = end {
+                clause.push_str(&format!(" AND ts {op} ?{}", n + values.len()));
+                values.push(Value::Integer(ts));
+            }
+        }
+        (clause, values)
+    }
+}
+
+/// Edits a query word of `len` characters may be away from a captured
+/// word: none for short words, which would otherwise match nearly anything.
+fn typo_allowance(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions,
+/// substitutions and swaps of neighbouring characters each cost one. Also
+/// measures title changes for `TitleFilter`, so only the last three rows
+/// of the table are kept.
+pub(crate) fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        row[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (previous[j] + 1)
+                .min(row[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(before[j - 2] + 1);
+            }
+            row[j] = best;
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut row);
+    }
+    previous[b.len()]
+}
+
+/// How closely `word` matches the query word `term`, from 1.0 for the same
+/// word down to 0.0 for no match.
+fn fuzzy_word_score(term: &[char], word: &str) -> f64 {
+    let word: Vec<char> = word.chars().collect();
+    if word == term {
+        return 1.0;
+    }
+    let allowance = typo_allowance(term.len());
+    // Partial words only count once they are long enough to mean something.
+    if term.len() >= 3 && word.starts_with(term) {
+        return 0.9;
+    }
+    if word.len().abs_diff(term.len()) <= allowance {
+        let distance = edit_distance(term, &word);
+        if distance <= allowance {
+            return 0.8 - 0.2 * distance as f64;
+        }
+    }
+    // A typo in a partial word: compare with the start of `word`.
+    if allowance > 0 && word.len() > term.len() {
+        let distance = edit_distance(term, &word[..term.len()]);
+        if distance <= allowance {
+            return 0.5 - 0.1 * distance as f64;
+        }
+    }
+    0.0
+}
+
+/// Pieces of the query word `term`, one of which every word it fuzzily
+/// matches contains as written; `None` when `LIKE` can't check that. An
+/// edit within `typo_allowance` touches one piece and a swap across two
+/// pieces touches both, so splitting into twice the allowance plus one
+/// leaves a piece intact. With no allowance the one piece is `term`.
+fn fuzzy_pieces(term: &[char]) -> Option<Vec<String>> {
+    // `LIKE` ignores case for ASCII letters only.
+    if !term.iter().all(char::is_ascii_alphanumeric) {
+        return None;
+    }
+    let count = 2 * typo_allowance(term.len()) + 1;
+    let size = term.len() / count;
+    let pieces = (0..count)
+        .map(|i| {
+            let end = if i + 1 == count { term.len() } else { (i + 1) * size };
+            term[i * size..end].iter().collect()
+        })
+        .collect();
+    Some(pieces)
+}
+
+/// `AND` conditions a capture must meet before `veea_fuzzy_score` is worth
+/// running on it: for every query word, a `fuzzy_pieces` piece somewhere
+/// in its fields. Pieces are ASCII letters and digits, so they go into the
+/// SQL as they are.
+fn fuzzy_prefilter(query: &str) -> String {
+    words(query)
+        .iter()
+        .filter_map(|word| fuzzy_pieces(&word.chars().collect::<Vec<_>>()))
+        .map(|pieces| {
+            let any: Vec<String> = pieces
+                .iter()
+                .map(|piece| format!("{FUZZY_HAYSTACK} LIKE '%{piece}%'"))
+                .collect();
+            format!(" AND ({})", any.join(" OR "))
+        })
+        .collect()
+}
+
+/// Average over the query words of their best match in `fields`, or 0.0
+/// when any of them matches nothing.
+fn fuzzy_score(terms: &[Vec<char>], fields: &[Vec<String>]) -> f64 {
+    let mut total = 0.0;
+    for term in terms {
+        let best = fields
+            .iter()
+            .flatten()
+            .map(|word| fuzzy_word_score(term, word))
+            .fold(0.0, f64::max);
+        if best == 0.0 {
+            return 0.0;
+        }
+        total += best;
+    }
+    if terms.is_empty() { 0.0 } else { total / terms.len() as f64 }
+}
+
+/// Lowercased runs of letters and digits; everything else separates words.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// What a capture must contain for `query` to match it: each term is a
+/// run of consecutive words.
+fn terms(query: &str, mode: SearchMode) -> Vec<Vec<String>> {
+    match mode {
+        SearchMode::Phrase => vec![words(query)],
+        // Segments alternate outside/inside double quotes; an unbalanced
+        // quote runs to the end of the query.
+        _ => query
+            .split('"')
+            .enumerate()
+            .flat_map(|(i, segment)| {
+                if i % 2 == 1 {
+                    vec![words(segment)]
+                } else {
+                    words(segment).into_iter().map(|w| vec![w]).collect()
+                }
+            })
+            .collect(),
+    }
+    .into_iter()
+    .filter(|term| !term.is_empty())
+    .collect()
+}
+
+/// Whether every term appears in one of `fields` on word boundaries.
+fn terms_match(terms: &[Vec<String>], fields: &[Vec<String>]) -> bool {
+    terms.iter().all(|term| {
+        fields
+            .iter()
+            .any(|field| field.windows(term.len()).any(|w| w == term.as_slice()))
+    })
+}
+
+/// What a `/search/suggest` completion is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    Title,
+    App,
+}
+
+/// A distinct window title or app name matching a typed prefix.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    /// The most recent title or app name with this normalized form.
+    pub value: String,
+    /// Whether `value` starts with the prefix rather than only containing it.
+    pub prefix_match: bool,
+    /// Live captures showing it.
+    pub count: usize,
+    pub last_ts: i64,
+}
+
+impl Suggestion {
+    /// How often it was seen, damped by how long ago: a title captured a
+    /// hundred times last month ranks near one seen a few times today.
+    fn score(&self, now_ms: i64) -> f64 {
+        let age_days = (now_ms - self.last_ts).max(0) as f64 / 86_400_000.0;
+        (1.0 + self.count as f64).ln() / (1.0 + age_days)
+    }
+}
+
+/// Prefix matches before substring matches, each best `score` first.
+pub fn rank_suggestions(mut items: Vec<Suggestion>, limit: usize, now_ms: i64) -> Vec<Suggestion> {
+    items.sort_by(|a, b| {
+        b.prefix_match
+            .cmp(&a.prefix_match)
+            .then(b.score(now_ms).total_cmp(&a.score(now_ms)))
+    });
+    items.truncate(limit);
+    items
+}
+
 #[derive(Clone)]
 pub struct SearchIndex {
     db_path: PathBuf,
+    text_key: Option<Arc<TextKey>>,
 }
 
-#[derive(serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SearchHit {
     pub id: String,
     pub ts: i64,
     pub window_title: Option<String>,
     pub app_name: Option<String>,
+    pub app_id: Option<String>,
     pub event_type: String,
-    pub path: String,
+    pub image_url: String,
+    pub thumbnail_url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub monitor: Option<String>,
+    /// Filesystem path; only sent to clients that ask for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
 }
 
 impl SearchIndex {
     pub fn new(db_path: &Path) -> AppResult<Self> {
         Ok(Self {
             db_path: db_path.to_path_buf(),
+            text_key: None,
         })
     }
 
+    /// Lets searches match sealed OCR and clipboard text. Without a key
+    /// those fields never match.
+    pub fn with_text_key(mut self, key: Option<Arc<TextKey>>) -> Self {
+        self.text_key = key;
+        self
+    }
+
     pub fn add_capture(&self, _record: &CaptureRecord, _ocr_text: Option<&str>) -> AppResult<()> {
         // With a SQLite-backed search, the primary table already stores the fields
         // we search on. OCR text can be added later via an auxiliary table.
         Ok(())
     }
 
-    pub fn search(&self, query: &str, limit: usize) -> AppResult<Vec<SearchHit>> {
-        let conn = Connection::open(&self.db_path)?;
-        let pattern = format!("%{}%", query);
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT id, ts, window_title, app_name, event_type, path
-            FROM captures
-            WHERE deleted = 0
-              AND (window_title LIKE ?1 OR app_name LIKE ?1)
-            ORDER BY ts DESC
-            LIMIT ?2
-            "#,
-        )?;
+    pub fn search(
+        &self,
+        query: &str,
+        mode: SearchMode,
+        scope: SearchScope,
+        limit: usize,
+        offset: usize,
+    ) -> AppResult<Vec<SearchHit>> {
+        let (conn, search_where, pattern) = self.open(query, mode)?;
+        let (range, range_values) = scope.range_clause(4);
+        let by_time = match scope.order {
+            SearchOrder::Newest => "ts DESC",
+            SearchOrder::Oldest => "ts ASC",
+        };
+        // Materialized so each candidate is scored once, not again for
+        // the filter and the ordering.
+        let sql = if mode == SearchMode::Fuzzy {
+            format!(
+                r#"
+                WITH scored AS MATERIALIZED (
+                    SELECT {HIT_COLUMNS}, {FUZZY_SCORE} AS score
+                    FROM captures
+                    WHERE {search_where}{range}
+                )
+                SELECT {HIT_COLUMNS}
+                FROM scored
+                WHERE score > 0
+                ORDER BY score DESC, {by_time}
+                LIMIT ?2 OFFSET ?3
+                "#
+            )
+        } else {
+            format!(
+                r#"
+                SELECT {HIT_COLUMNS}
+                FROM captures
+                WHERE {search_where}{range}
+                ORDER BY {by_time}
+                LIMIT ?2 OFFSET ?3
+                "#
+            )
+        };
+        let mut stmt = conn.prepare(&sql)?;
 
-        let rows = stmt.query_map(params![pattern, limit as i64], |row| {
+        let mut values = vec![
+            Value::Text(pattern),
+            Value::Integer(limit as i64),
+            Value::Integer(offset as i64),
+        ];
+        values.extend(range_values);
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            let id: String = row.get(0)?;
             Ok(SearchHit {
-                id: row.get(0)?,
+                image_url: format!("/captures/{id}/image"),
+                thumbnail_url: format!("/captures/{id}/thumbnail"),
+                id,
                 ts: row.get::<_, i64>(1)?,
                 window_title: row.get(2)?,
                 app_name: row.get(3)?,
                 event_type: row.get(4)?,
                 path: row.get(5)?,
+                width: row.get::<_, Option<i64>>(6)?.map(|v| v as u32),
+                height: row.get::<_, Option<i64>>(7)?.map(|v| v as u32),
+                monitor: row.get(8)?,
+                app_id: row.get(9)?,
             })
         })?;
 
@@ -67,7 +415,254 @@ impl SearchIndex {
         Ok(out)
     }
 
+    pub fn count(&self, query: &str, mode: SearchMode, scope: SearchScope) -> AppResult<usize> {
+        let (conn, mut search_where, pattern) = self.open(query, mode)?;
+        if mode == SearchMode::Fuzzy {
+            search_where.push_str(&format!(" AND {FUZZY_SCORE} > 0"));
+        }
+        let (range, range_values) = scope.range_clause(2);
+        let values = std::iter::once(Value::Text(pattern)).chain(range_values);
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM captures WHERE {search_where}{range}"),
+            params_from_iter(values),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Connection, WHERE clause and its `?1` for `mode`. Word modes filter
+    /// through `veea_terms_match`, which parses `?1` once per statement.
+    /// Fuzzy mode only narrows to the `fuzzy_prefilter` candidates; the
+    /// caller keeps those `veea_fuzzy_score` finds a match in.
+    fn open(&self, query: &str, mode: SearchMode) -> AppResult<(Connection, String, String)> {
+        let conn = Connection::open(&self.db_path)?;
+        let key = self.text_key.clone();
+        conn.create_scalar_function(
+            "veea_open",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx: &Context<'_>| Ok(crypto::open_text(ctx.get(0)?, key.as_deref())),
+        )?;
+        if mode == SearchMode::Substring {
+            return Ok((conn, SUBSTRING_WHERE.to_string(), format!("%{}%", query)));
+        }
+        if mode == SearchMode::Fuzzy {
+            conn.create_scalar_function(
+                "veea_fuzzy_score",
+                9,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                move |ctx: &Context<'_>| {
+                    let terms = ctx.get_or_create_aux(0, |query| {
+                        query.as_str().map(|q| {
+                            words(q)
+                                .iter()
+                                .map(|w| w.chars().collect::<Vec<_>>())
+                                .collect::<Vec<_>>()
+                        })
+                    })?;
+                    let fields = (1..ctx.len())
+                        .filter_map(|i| ctx.get::<Option<String>>(i).transpose())
+                        .map(|field| field.map(|f| words(&f)))
+                        .collect::<rusqlite::Result<Vec<_>>>()?;
+                    Ok(fuzzy_score(&terms, &fields))
+                },
+            )?;
+            let candidates = format!("deleted = 0{}", fuzzy_prefilter(query));
+            return Ok((conn, candidates, query.to_string()));
+        }
+        conn.create_scalar_function(
+            "veea_terms_match",
+            9,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx: &Context<'_>| {
+                let terms = ctx.get_or_create_aux(0, |query| query.as_str().map(|q| terms(q, mode)))?;
+                let fields = (1..ctx.len())
+                    .filter_map(|i| ctx.get::<Option<String>>(i).transpose())
+                    .map(|field| field.map(|f| words(&f)))
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(terms_match(&terms, &fields))
+            },
+        )?;
+        Ok((conn, TERMS_WHERE.to_string(), query.to_string()))
+    }
+
     pub fn index_path(&self) -> PathBuf {
         self.db_path.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+    use crate::db::Db;
+
+    fn chars(word: &str) -> Vec<char> {
+        word.chars().collect()
+    }
+
+    #[test]
+    fn short_words_allow_no_typos() {
+        let allowed: Vec<usize> = (0..=8).map(typo_allowance).collect();
+        assert_eq!(allowed, [0, 0, 0, 0, 1, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_once() {
+        let distance = |a: &str, b: &str| edit_distance(&chars(a), &chars(b));
+        assert_eq!(distance("screen", "screen"), 0);
+        assert_eq!(distance("screen", "scren"), 1);
+        assert_eq!(distance("screen", "screeen"), 1);
+        assert_eq!(distance("screen", "scraen"), 1);
+        assert_eq!(distance("screen", "sceren"), 1);
+        assert_eq!(distance("screen", "csreen"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn word_scores_rank_exact_over_prefix_over_typo() {
+        let score = |term: &str, word: &str| {
+            // Rounded, as scores are built from tenths.
+            (fuzzy_word_score(&chars(term), word) * 10.0).round() / 10.0
+        };
+        assert_eq!(score("screenshot", "screenshot"), 1.0);
+        assert_eq!(score("scree", "screenshot"), 0.9);
+        assert_eq!(score("screnshot", "screenshot"), 0.6);
+        assert_eq!(score("scrnshot", "screenshot"), 0.4);
+        // A typo in the part typed so far.
+        assert_eq!(score("scrre", "screenshot"), 0.4);
+        // Short words must match exactly.
+        assert_eq!(score("cat", "cat"), 1.0);
+        assert_eq!(score("cat", "cut"), 0.0);
+        assert_eq!(score("ca", "category"), 0.0);
+        assert_eq!(score("cat", "category"), 0.9);
+        assert_eq!(score("screenshot", "window"), 0.0);
+    }
+
+    #[test]
+    fn query_score_needs_every_word() {
+        let terms: Vec<Vec<char>> = ["quarterly", "reprot"].map(chars).to_vec();
+        let fields = vec![words("Quarterly report.pdf"), words("Preview")];
+        assert!((fuzzy_score(&terms, &fields) - (1.0 + 0.6) / 2.0).abs() < 1e-9);
+        let missing = vec![words("Quarterly numbers")];
+        assert_eq!(fuzzy_score(&terms, &missing), 0.0);
+        assert_eq!(fuzzy_score(&[], &fields), 0.0);
+    }
+
+    #[test]
+    fn every_fuzzy_match_keeps_a_piece() {
+        let typos = [
+            (
+                "test",
+                ["tset", "tent", "est", "tests", "testing"].as_slice(),
+            ),
+            ("abcd", &["acbd", "bacd", "abdc", "xbcd", "abcx"]),
+            (
+                "screen",
+                &["scrreen", "sreen", "csreen", "screne", "screenshot"],
+            ),
+            (
+                "screenshot",
+                &["screnshot", "scrnshot", "sreenshto", "scraenshoot"],
+            ),
+            ("cat", &["cat", "category"]),
+        ];
+        for (term, words) in typos {
+            let pieces = fuzzy_pieces(&chars(term)).unwrap();
+            for word in words {
+                assert!(fuzzy_word_score(&chars(term), word) > 0.0, "{term} {word}");
+                assert!(
+                    pieces.iter().any(|piece| word.contains(piece.as_str())),
+                    "{term} {word} {pieces:?}"
+                );
+            }
+        }
+        assert_eq!(fuzzy_pieces(&chars("cat")).unwrap(), ["cat"]);
+        assert_eq!(fuzzy_pieces(&chars("écran")), None);
+    }
+
+    #[test]
+    fn fuzzy_search_ranks_the_prefiltered_captures() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("veea.db");
+        let db = Db::new(&db_path).unwrap();
+        let titles = [
+            ("a", "Quarterly report"),
+            ("b", "Quarterly reprot draft"),
+            ("c", "Inbox"),
+            ("d", "Écran de veille"),
+        ];
+        for (n, (id, title)) in titles.into_iter().enumerate() {
+            let ts = DateTime::<Utc>::from_timestamp(1_700_000_000 + n as i64, 0).unwrap();
+            let mut record = CaptureRecord::sample(id, ts);
+            record.window_title = Some(title.to_string());
+            db.insert_capture(&record).unwrap();
+        }
+
+        let index = SearchIndex::new(&db_path).unwrap();
+        let scope = SearchScope::default();
+        let ids = |query: &str| -> Vec<String> {
+            index
+                .search(query, SearchMode::Fuzzy, scope, 10, 0)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.id)
+                .collect()
+        };
+        assert_eq!(ids("quartrly report"), ["a", "b"]);
+        assert_eq!(
+            index
+                .count("quartrly report", SearchMode::Fuzzy, scope)
+                .unwrap(),
+            2
+        );
+        assert_eq!(ids("reprot"), ["b", "a"]);
+        assert!(ids("spreadsheet").is_empty());
+        // Not prefiltered, but still scored.
+        assert_eq!(ids("écran"), ["d"]);
+    }
+
+    #[test]
+    fn range_binds_only_the_ends_given() {
+        let scope = |from, to| SearchScope {
+            from,
+            to,
+            order: SearchOrder::Newest,
+        };
+        assert_eq!(scope(None, None).range_clause(4), (String::new(), vec![]));
+        let (clause, values) = scope(None, Some(9)).range_clause(4);
+        assert_eq!(
+            (clause.as_str(), values),
+            (" AND ts < ?4", vec![Value::Integer(9)])
+        );
+        let (clause, values) = scope(Some(1), Some(9)).range_clause(2);
+        assert_eq!(clause, " AND ts >= ?2 AND ts < ?3");
+        assert_eq!(values, [Value::Integer(1), Value::Integer(9)]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("veea.db");
+        let db = Db::new(&db_path).unwrap();
+        for (id, secs) in [("old", 100), ("mid", 200), ("new", 300)] {
+            let ts = DateTime::<Utc>::from_timestamp(secs, 0).unwrap();
+            db.insert_capture(&CaptureRecord::sample(id, ts)).unwrap();
+        }
+        let index = SearchIndex::new(&db_path).unwrap();
+        for mode in [SearchMode::Substring, SearchMode::Fuzzy] {
+            let hits = |from, to| -> Vec<String> {
+                let hits = index
+                    .search("sample", mode, scope(from, to), 10, 0)
+                    .unwrap();
+                hits.into_iter().map(|hit| hit.id).collect()
+            };
+            assert_eq!(hits(None, None), ["new", "mid", "old"]);
+            assert_eq!(hits(Some(200_000), None), ["new", "mid"]);
+            assert_eq!(hits(None, Some(200_000)), ["old"]);
+            assert_eq!(hits(Some(150_000), Some(250_000)), ["mid"]);
+            let count = index.count("sample", mode, scope(Some(150_000), None));
+            assert_eq!(count.unwrap(), 2);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::{process::Command, sync::mpsc, thread, time::Duration};
+
+use crate::focus::WindowEvent;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Returns whether the desktop session is locked or showing the screensaver,
+/// or `None` when the platform doesn't let us tell.
+pub fn is_session_locked() -> Option<bool> {
+    platform_session_locked()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_session_locked() -> Option<bool> {
+    // logind tracks lock state for both X11 and Wayland sessions.
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+    let output = Command::new("loginctl")
+        .args(["show-session", &session, "-p", "LockedHint", "--value"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_session_locked() -> Option<bool> {
+    // The console user's session dictionary carries CGSSessionScreenIsLocked
+    // only while the lock screen (or screensaver with password) is up.
+    let output = Command::new("ioreg").args(["-n", "Root", "-d1"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Some(text.contains("\"CGSSessionScreenIsLocked\"=Yes"))
+}
+
+#[cfg(target_os = "windows")]
+fn platform_session_locked() -> Option<bool> {
+    // LogonUI owns the secure desktop while the workstation is locked.
+    let output = Command::new("tasklist")
+        .args(["/FI", "IMAGENAME eq LogonUI.exe", "/NH"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).contains("LogonUI.exe"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_session_locked() -> Option<bool> {
+    None
+}
+
+/// Polls the lock state and reports transitions to the event loop.
+pub fn monitor_session_lock(event_sender: mpsc::Sender<WindowEvent>) {
+    let mut last_locked = false;
+
+    loop {
+        if let Some(locked) = is_session_locked()
+            && locked != last_locked
+        {
+            let _ = event_sender.send(WindowEvent::SessionLockChanged { locked });
+            last_locked = locked;
+        }
+        thread::sleep(LOCK_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Title fragments that sit around the document name and change without
+/// the document changing: unsaved-change markers and the app suffix.
+const UNSAVED_MARKERS: &[char] = &['*', '●', '•', '◉'];
+const TITLE_SEPARATORS: &[&str] = &[" - ", " — ", " – ", " | "];
+
+/// Groups captures of one document: its normalized title. Lowercased,
+/// whitespace collapsed, unsaved markers dropped, and a trailing
+/// " - <app>" suffix removed, so "● Budget.xlsx - Excel" and
+/// "Budget.xlsx - Excel" share a story.
+pub fn title_key(window_title: &str, app_name: Option<&str>) -> Option<String> {
+    let mut title = window_title
+        .trim()
+        .trim_start_matches(UNSAVED_MARKERS)
+        .trim_end_matches(UNSAVED_MARKERS)
+        .trim()
+        .to_string();
+
+    if let Some(app) = app_name.map(str::trim).filter(|a| !a.is_empty()) {
+        for sep in TITLE_SEPARATORS {
+            if let Some((head, tail)) = title.rsplit_once(sep)
+                && tail.trim().eq_ignore_ascii_case(app)
+            {
+                title = head.trim_end_matches(UNSAVED_MARKERS).trim().to_string();
+                break;
+            }
+        }
+    }
+
+    let key = title.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (!key.is_empty()).then_some(key)
+}
+
+/// Jaccard similarity of the two keys' character trigrams, from 0 to 1.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let (a, b) = (trigrams(a), trigrams(b));
+    let shared = a.intersection(&b).count();
+    let total = a.len() + b.len() - shared;
+    if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    }
+}
+
+fn trigrams(key: &str) -> HashSet<[char; 3]> {
+    // Pad so short keys and word edges still yield trigrams.
+    let chars: Vec<char> = format!("  {key} ").chars().collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// All captures of one document over time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Story {
+    /// Id of the story's earliest live capture. Any capture id of the
+    /// story, deleted ones included, also works as `:story_id`.
+    pub story_id: String,
+    pub title_key: String,
+    /// Most recent raw title, for display.
+    pub title: Option<String>,
+    /// How closely `title_key` matched the query, 1.0 when exact.
+    pub similarity: f64,
+    pub capture_count: usize,
+    pub first_ts: i64,
+    pub last_ts: i64,
+    /// Captures per `storage_timezone` day, oldest first.
+    pub days: Vec<DayCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayCount {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub count: usize,
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use axum::body::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Rendered thumbnails kept in memory, least recently used evicted first
+/// once their total size passes the byte budget. Entries remember the
+/// source file's mtime and are only served while it is unchanged.
+#[derive(Debug)]
+pub struct ThumbnailCache {
+    cap_bytes: usize,
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Keys from least to most recently used.
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    mtime: SystemTime,
+    data: Bytes,
+}
+
+/// Counters served by `/debug/memory` and `/metrics`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ThumbnailCacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub cap_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ThumbnailCache {
+    /// A `cap_bytes` of 0 disables caching.
+    pub fn new(cap_bytes: usize) -> Self {
+        Self {
+            cap_bytes,
+            inner: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cached bytes for `key` rendered from a file last modified at
+    /// `mtime`. A stale entry is dropped and counts as a miss.
+    pub fn get(&self, key: &str, mtime: SystemTime) -> Option<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        let found = match inner.entries.get(key) {
+            Some(entry) if entry.mtime == mtime => Some(entry.data.clone()),
+            Some(_) => {
+                inner.remove(key);
+                None
+            }
+            None => None,
+        };
+        match &found {
+            Some(_) => {
+                inner.touch(key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        found
+    }
+
+    pub fn insert(&self, key: String, mtime: SystemTime, data: Bytes) {
+        if data.len() > self.cap_bytes {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+        inner.bytes += data.len();
+        inner.touch(&key);
+        inner.entries.insert(key, Entry { mtime, data });
+        while inner.bytes > self.cap_bytes {
+            let Some(stale) = inner.order.front().cloned() else {
+                break;
+            };
+            inner.remove(&stale);
+        }
+    }
+
+    /// Drops `key`, e.g. once its capture is deleted.
+    pub fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+
+    pub fn stats(&self) -> ThumbnailCacheStats {
+        let inner = self.inner.lock().unwrap();
+        ThumbnailCacheStats {
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            cap_bytes: self.cap_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Inner {
+    fn touch(&mut self, key: &str) {
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.to_string());
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.data.len();
+            self.order.retain(|k| k != key);
+        }
+    }
+}
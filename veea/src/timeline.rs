@@ -0,0 +1,311 @@
+use serde::Serialize;
+
+use crate::db::DaemonEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapCause {
+    Paused,
+    /// Outside the configured `schedule`.
+    Scheduled,
+    Idle,
+    Stopped,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Gap {
+    pub start: i64,
+    pub end: i64,
+    pub duration_ms: i64,
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+    pub cause: GapCause,
+}
+
+/// Finds intervals inside `[window_start, window_end)` longer than
+/// `min_gap_ms` with no capture. `captures` must be sorted by timestamp.
+/// The stretch before the first and after the last capture count as gaps
+/// too, with the missing neighbour left as `None`.
+pub fn find_gaps(
+    captures: &[(i64, String)],
+    window_start: i64,
+    window_end: i64,
+    min_gap_ms: i64,
+) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+    let mut prev: Option<&(i64, String)> = None;
+
+    for capture in captures {
+        let start = prev.map(|(ts, _)| *ts).unwrap_or(window_start);
+        if capture.0 - start > min_gap_ms {
+            gaps.push(Gap {
+                start,
+                end: capture.0,
+                duration_ms: capture.0 - start,
+                before_id: prev.map(|(_, id)| id.clone()),
+                after_id: Some(capture.1.clone()),
+                cause: GapCause::Unknown,
+            });
+        }
+        prev = Some(capture);
+    }
+
+    let start = prev.map(|(ts, _)| *ts).unwrap_or(window_start);
+    if window_end - start > min_gap_ms {
+        gaps.push(Gap {
+            start,
+            end: window_end,
+            duration_ms: window_end - start,
+            before_id: prev.map(|(_, id)| id.clone()),
+            after_id: None,
+            cause: GapCause::Unknown,
+        });
+    }
+
+    gaps
+}
+
+/// One slot of a day's filmstrip.
+#[derive(Debug, Clone, Serialize)]
+pub struct StripBucket {
+    pub start: i64,
+    pub count: usize,
+    /// `None` for buckets without captures.
+    pub representative_id: Option<String>,
+    pub thumbnail_url: Option<String>,
+}
+
+/// Lays `[window_start, window_end)` out as consecutive `bucket_ms` slots,
+/// filling in the `(bucket index, count, representative id)` rows that had
+/// captures and leaving the rest empty. The last slot may be shorter.
+pub fn strip_buckets(
+    rows: Vec<(i64, usize, String)>,
+    window_start: i64,
+    window_end: i64,
+    bucket_ms: i64,
+) -> Vec<StripBucket> {
+    let mut rows = rows.into_iter().peekable();
+    let mut buckets = Vec::new();
+    let mut start = window_start;
+    let mut index = 0;
+    while start < window_end {
+        let filled = rows.next_if(|(i, _, _)| *i == index);
+        let (count, representative_id) = match filled {
+            Some((_, count, id)) => (count, Some(id)),
+            None => (0, None),
+        };
+        buckets.push(StripBucket {
+            start,
+            count,
+            thumbnail_url: representative_id
+                .as_ref()
+                .map(|id| format!("/captures/{id}/thumbnail")),
+            representative_id,
+        });
+        start += bucket_ms;
+        index += 1;
+    }
+    buckets
+}
+
+/// Labels each gap with the daemon state that overlapped it the most.
+/// `events` must be sorted by timestamp and may start before the gaps.
+pub fn attribute_causes(gaps: &mut [Gap], events: &[DaemonEvent]) {
+    let (spans, restarts) = state_spans(events);
+    for gap in gaps.iter_mut() {
+        let mut best = (0, GapCause::Unknown);
+        for (start, end, cause) in &spans {
+            let overlap = end.min(&gap.end) - start.max(&gap.start);
+            if overlap > best.0 {
+                best = (overlap, *cause);
+            }
+        }
+        // A restart inside the gap means the daemon died somewhere in it.
+        if best.1 == GapCause::Unknown
+            && restarts.iter().any(|ts| *ts > gap.start && *ts <= gap.end)
+        {
+            best.1 = GapCause::Stopped;
+        }
+        gap.cause = best.1;
+    }
+}
+
+/// Turns the event log into `(start, end, cause)` spans, plus the
+/// timestamps of `start` events that had no matching `stop` (crashes).
+fn state_spans(events: &[DaemonEvent]) -> (Vec<(i64, i64, GapCause)>, Vec<i64>) {
+    let mut spans = Vec::new();
+    let mut restarts = Vec::new();
+    let mut paused_since = None;
+    let mut locked_since = None;
+    let mut asleep_since = None;
+    let mut scheduled_since = None;
+    let mut stopped_since = None;
+
+    for event in events {
+        match event.kind.as_str() {
+            "pause" => paused_since = paused_since.or(Some(event.ts)),
+            "resume" => {
+                if let Some(start) = paused_since.take() {
+                    spans.push((start, event.ts, GapCause::Paused));
+                }
+            }
+            "lock" => locked_since = locked_since.or(Some(event.ts)),
+            "unlock" => {
+                if let Some(start) = locked_since.take() {
+                    spans.push((start, event.ts, GapCause::Idle));
+                }
+            }
+            "display_sleep" => asleep_since = asleep_since.or(Some(event.ts)),
+            "display_wake" => {
+                if let Some(start) = asleep_since.take() {
+                    spans.push((start, event.ts, GapCause::Idle));
+                }
+            }
+            "schedule_pause" => scheduled_since = scheduled_since.or(Some(event.ts)),
+            "schedule_resume" => {
+                if let Some(start) = scheduled_since.take() {
+                    spans.push((start, event.ts, GapCause::Scheduled));
+                }
+            }
+            "stop" => stopped_since = Some(event.ts),
+            "start" => {
+                match stopped_since.take() {
+                    Some(start) => spans.push((start, event.ts, GapCause::Stopped)),
+                    None => restarts.push(event.ts),
+                }
+                // Pause and lock state do not survive a restart.
+                paused_since = None;
+                locked_since = None;
+                asleep_since = None;
+                scheduled_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    let open_end = i64::MAX;
+    if let Some(start) = paused_since {
+        spans.push((start, open_end, GapCause::Paused));
+    }
+    if let Some(start) = locked_since {
+        spans.push((start, open_end, GapCause::Idle));
+    }
+    if let Some(start) = asleep_since {
+        spans.push((start, open_end, GapCause::Idle));
+    }
+    if let Some(start) = scheduled_since {
+        spans.push((start, open_end, GapCause::Scheduled));
+    }
+    if let Some(start) = stopped_since {
+        spans.push((start, open_end, GapCause::Stopped));
+    }
+    (spans, restarts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60 * 1000;
+    const HOUR: i64 = 60 * MINUTE;
+    /// Midnight starting the day under test, in Unix ms.
+    const DAY: i64 = 1_717_545_600_000;
+    const NEXT_DAY: i64 = DAY + 24 * HOUR;
+
+    fn stamped(times: &[i64]) -> Vec<(i64, String)> {
+        times
+            .iter()
+            .map(|&ts| (ts, format!("c{}", (ts - DAY) / MINUTE)))
+            .collect()
+    }
+
+    fn spans(gaps: &[Gap]) -> Vec<(i64, i64)> {
+        gaps.iter()
+            .map(|g| ((g.start - DAY) / MINUTE, (g.end - DAY) / MINUTE))
+            .collect()
+    }
+
+    fn event(ts: i64, kind: &str) -> DaemonEvent {
+        DaemonEvent {
+            ts,
+            kind: kind.to_string(),
+        }
+    }
+
+    #[test]
+    fn gaps_at_both_ends_of_the_day() {
+        let captures = stamped(&[DAY + 8 * HOUR, DAY + 8 * HOUR + 5 * MINUTE, DAY + 17 * HOUR]);
+        let gaps = find_gaps(&captures, DAY, NEXT_DAY, 10 * MINUTE);
+        assert_eq!(
+            spans(&gaps),
+            [(0, 8 * 60), (8 * 60 + 5, 17 * 60), (17 * 60, 24 * 60)]
+        );
+
+        let first = &gaps[0];
+        assert_eq!(first.before_id, None);
+        assert_eq!(first.after_id.as_deref(), Some("c480"));
+        assert_eq!(first.duration_ms, 8 * HOUR);
+        let last = gaps.last().unwrap();
+        assert_eq!(last.before_id.as_deref(), Some("c1020"));
+        assert_eq!(last.after_id, None);
+        assert_eq!(last.duration_ms, 7 * HOUR);
+    }
+
+    #[test]
+    fn captures_at_the_day_edges_leave_no_edge_gaps() {
+        let captures = stamped(&[DAY, DAY + 12 * HOUR, NEXT_DAY - 1]);
+        let gaps = find_gaps(&captures, DAY, NEXT_DAY, 12 * HOUR);
+        assert!(gaps.is_empty(), "{:?}", spans(&gaps));
+        // Just under the threshold before the end of the day.
+        let captures = stamped(&[DAY + 5 * MINUTE, NEXT_DAY - 10 * MINUTE]);
+        let gaps = find_gaps(&captures, DAY, NEXT_DAY, 10 * MINUTE);
+        assert_eq!(spans(&gaps), [(5, 24 * 60 - 10)]);
+    }
+
+    #[test]
+    fn gap_of_exactly_the_threshold_is_not_reported() {
+        let captures = stamped(&[DAY + 10 * MINUTE, NEXT_DAY - 10 * MINUTE]);
+        let gaps = find_gaps(&captures, DAY, NEXT_DAY, 10 * MINUTE);
+        assert_eq!(spans(&gaps), [(10, 24 * 60 - 10)]);
+    }
+
+    #[test]
+    fn day_without_captures_is_one_gap() {
+        let gaps = find_gaps(&[], DAY, NEXT_DAY, 10 * MINUTE);
+        assert_eq!(spans(&gaps), [(0, 24 * 60)]);
+        assert_eq!(gaps[0].before_id, None);
+        assert_eq!(gaps[0].after_id, None);
+    }
+
+    #[test]
+    fn edge_gaps_take_causes_that_started_the_day_before() {
+        let captures = stamped(&[DAY + 9 * HOUR, DAY + 18 * HOUR]);
+        let mut gaps = find_gaps(&captures, DAY, NEXT_DAY, 10 * MINUTE);
+        attribute_causes(
+            &mut gaps,
+            &[
+                event(DAY - 2 * HOUR, "schedule_pause"),
+                event(DAY + 9 * HOUR, "schedule_resume"),
+                event(DAY + 18 * HOUR + MINUTE, "stop"),
+            ],
+        );
+        let causes: Vec<GapCause> = gaps.iter().map(|g| g.cause).collect();
+        assert_eq!(
+            causes,
+            [GapCause::Scheduled, GapCause::Unknown, GapCause::Stopped]
+        );
+    }
+
+    #[test]
+    fn restart_without_stop_marks_the_gap_stopped() {
+        let captures = stamped(&[DAY + 9 * HOUR, DAY + 12 * HOUR]);
+        let mut gaps = find_gaps(&captures, DAY, NEXT_DAY, 10 * MINUTE);
+        attribute_causes(&mut gaps, &[event(DAY + 11 * HOUR, "start")]);
+        let causes: Vec<GapCause> = gaps.iter().map(|g| g.cause).collect();
+        assert_eq!(
+            causes,
+            [GapCause::Unknown, GapCause::Stopped, GapCause::Unknown]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Time zone whose calendar days decide capture folders and day-based
+/// queries. Written in config as `"local"`, `"UTC"`, or an IANA name such
+/// as `"Asia/Tokyo"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum StorageTimezone {
+    /// The machine's zone, as the OS reports it at the time of use.
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl StorageTimezone {
+    /// Calendar day that `ts` falls on in this zone.
+    pub fn date_of(&self, ts: DateTime<Utc>) -> NaiveDate {
+        self.local_time(ts).date()
+    }
+
+    /// Wall-clock time of `ts` in this zone.
+    pub fn local_time(&self, ts: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => ts.with_timezone(&Local).naive_local(),
+            Self::Named(tz) => ts.with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// `[start, end)` of `date` in this zone. Days around DST changes are
+    /// 23 or 25 hours long rather than 24.
+    pub fn day_bounds(&self, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let next = date.succ_opt().unwrap_or(date);
+        match self {
+            Self::Local => (day_start(&Local, date), day_start(&Local, next)),
+            Self::Named(tz) => (day_start(tz, date), day_start(tz, next)),
+        }
+    }
+}
+
+/// First instant of `date` in `tz`. When a DST jump skips local midnight
+/// the day starts at the first local time that exists; when midnight
+/// happens twice the earlier one counts.
+fn day_start<T: TimeZone>(tz: &T, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    // Transitions shift the clock by at most a couple of hours; step in
+    // quarter hours to also cover zones with 30 or 45 minute offsets.
+    for step in 0..=12 {
+        let local = midnight + Duration::minutes(15 * step);
+        if let Some(start) = tz.from_local_datetime(&local).earliest() {
+            return start.with_timezone(&Utc);
+        }
+    }
+    midnight.and_utc()
+}
+
+impl FromStr for StorageTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        s.parse::<Tz>()
+            .map(Self::Named)
+            .map_err(|_| format!("unknown time zone '{s}', expected \"local\" or an IANA name"))
+    }
+}
+
+impl TryFrom<String> for StorageTimezone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<StorageTimezone> for String {
+    fn from(tz: StorageTimezone) -> Self {
+        tz.to_string()
+    }
+}
+
+impl fmt::Display for StorageTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => f.write_str("local"),
+            Self::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
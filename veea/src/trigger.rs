@@ -0,0 +1,475 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone, Weekday};
+
+use crate::{
+    app_id,
+    config::{TriggerRule, Triggers},
+    search,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Focus,
+    Title,
+    Interval,
+}
+
+/// Decides whether an event should lead to a capture, from the `triggers`
+/// config. Filters are lowercased and time windows parsed once up front.
+#[derive(Debug, Clone)]
+pub struct TriggerPolicy {
+    focus: Rule,
+    title: Rule,
+    interval: Rule,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    enabled: bool,
+    apps: Vec<String>,
+    exclude_apps: Vec<String>,
+    titles: Vec<String>,
+    exclude_titles: Vec<String>,
+    hours: Vec<(NaiveTime, NaiveTime)>,
+    days: Vec<Weekday>,
+}
+
+impl TriggerPolicy {
+    pub fn new(triggers: &Triggers) -> Self {
+        Self {
+            focus: Rule::new(&triggers.focus),
+            title: Rule::new(&triggers.title),
+            interval: Rule::new(&triggers.interval),
+        }
+    }
+
+    /// Checks the rule for `trigger` now. Rules that filter by app also
+    /// look up the focused app's id, so they can list it either by name
+    /// or by `app_id`.
+    pub fn allows(&self, trigger: Trigger, window_title: &str, app_name: Option<&str>) -> bool {
+        let app_id = self.rule(trigger).filters_apps().then(app_id::focused).flatten();
+        self.allows_at(trigger, window_title, app_name, app_id.as_deref(), &Local::now())
+    }
+
+    /// `allows` against an explicit clock, in whatever zone `now` carries,
+    /// and app id.
+    pub fn allows_at<Tz: TimeZone>(
+        &self,
+        trigger: Trigger,
+        window_title: &str,
+        app_name: Option<&str>,
+        app_id: Option<&str>,
+        now: &DateTime<Tz>,
+    ) -> bool {
+        let rule = self.rule(trigger);
+        rule.enabled
+            && rule.matches_window(window_title, &app_id::aliases(app_name, app_id))
+            && rule.matches_time(now.time(), now.weekday())
+    }
+
+    fn rule(&self, trigger: Trigger) -> &Rule {
+        match trigger {
+            Trigger::Focus => &self.focus,
+            Trigger::Title => &self.title,
+            Trigger::Interval => &self.interval,
+        }
+    }
+}
+
+impl Rule {
+    fn new(config: &TriggerRule) -> Self {
+        let lower = |list: &[String]| list.iter().map(|s| s.to_lowercase()).collect();
+        Self {
+            enabled: config.enabled,
+            apps: lower(&config.apps),
+            exclude_apps: lower(&config.exclude_apps),
+            titles: lower(&config.titles),
+            exclude_titles: lower(&config.exclude_titles),
+            hours: config
+                .hours
+                .iter()
+                .filter_map(|window| {
+                    let parsed = parse_window(window);
+                    if parsed.is_none() {
+                        eprintln!("Ignoring invalid trigger time window '{window}'");
+                    }
+                    parsed
+                })
+                .collect(),
+            days: config
+                .days
+                .iter()
+                .filter_map(|day| {
+                    let parsed = day.parse::<Weekday>().ok();
+                    if parsed.is_none() {
+                        eprintln!("Ignoring invalid trigger day '{day}'");
+                    }
+                    parsed
+                })
+                .collect(),
+        }
+    }
+
+    fn filters_apps(&self) -> bool {
+        self.enabled && !(self.apps.is_empty() && self.exclude_apps.is_empty())
+    }
+
+    /// `apps` are the lowercased names the window's app goes by.
+    fn matches_window(&self, window_title: &str, apps: &[String]) -> bool {
+        let title = window_title.to_lowercase();
+
+        if !self.apps.is_empty() && !apps.iter().any(|a| self.apps.contains(a)) {
+            return false;
+        }
+        if apps.iter().any(|a| self.exclude_apps.contains(a)) {
+            return false;
+        }
+        if !self.titles.is_empty() && !self.titles.iter().any(|t| title.contains(t.as_str())) {
+            return false;
+        }
+        !self.exclude_titles.iter().any(|t| title.contains(t.as_str()))
+    }
+
+    fn matches_time(&self, time: NaiveTime, day: Weekday) -> bool {
+        if !self.days.is_empty() && !self.days.contains(&day) {
+            return false;
+        }
+        self.hours.is_empty() || self.hours.iter().any(|&window| in_window(time, window))
+    }
+}
+
+/// Skips title changes too small to matter, like an unread counter going
+/// from "(5) Inbox" to "(6) Inbox", per `title_min_change`. Titles are
+/// compared with the last one let through, so a title creeping along a
+/// character at a time still gets captured once it has drifted far
+/// enough.
+#[derive(Debug, Clone, Default)]
+pub struct TitleFilter {
+    min_change: f64,
+    last: Option<String>,
+}
+
+impl TitleFilter {
+    pub fn new(min_change: f64) -> Self {
+        Self {
+            min_change,
+            last: None,
+        }
+    }
+
+    /// A newly focused window's title is what its changes are measured
+    /// against.
+    pub fn focused(&mut self, window_title: &str) {
+        self.last = Some(window_title.to_string());
+    }
+
+    /// Whether `window_title` differs enough from the last title let
+    /// through, which it then replaces.
+    pub fn changed_enough(&mut self, window_title: &str) -> bool {
+        let enough = self.min_change <= 0.0
+            || self
+                .last
+                .as_deref()
+                .is_none_or(|last| title_distance(last, window_title) >= self.min_change);
+        if enough {
+            self.last = Some(window_title.to_string());
+        }
+        enough
+    }
+}
+
+/// Edit distance between two titles, ignoring case, as a share of the
+/// longer one: 0 for the same title, 1 for nothing in common.
+pub fn title_distance(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.trim().to_lowercase().chars().collect();
+    let b: Vec<char> = b.trim().to_lowercase().chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    search::edit_distance(&a, &b) as f64 / longest as f64
+}
+
+/// Whether `time` falls in `window`, start inclusive. A window ending
+/// where it starts, "00:00-24:00", is the whole day.
+pub(crate) fn in_window(time: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start == end {
+        true
+    } else if start < end {
+        time >= start && time < end
+    } else {
+        // Wraps past midnight, e.g. "22:00-02:00".
+        time >= start || time < end
+    }
+}
+
+/// Parses "HH:MM-HH:MM", where the end may be "24:00" for midnight. A
+/// window ending at its start is only taken as "00:00-24:00"; anything
+/// else that empty is more likely a typo than the whole day.
+pub(crate) fn parse_window(window: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = window.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = match end.trim() {
+        "24:00" => NaiveTime::MIN,
+        end => NaiveTime::parse_from_str(end, "%H:%M").ok()?,
+    };
+    (start != end || (start == NaiveTime::MIN && window.trim().ends_with("24:00")))
+        .then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDate, Utc};
+
+    use super::*;
+    use crate::config::CaptureConfig;
+
+    const ALL: [Trigger; 3] = [Trigger::Focus, Trigger::Title, Trigger::Interval];
+
+    /// A Wednesday, 12:00 UTC.
+    fn noon() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_717_588_800, 0).unwrap()
+    }
+
+    fn policy(rule: TriggerRule) -> TriggerPolicy {
+        TriggerPolicy::new(&Triggers {
+            focus: rule.clone(),
+            title: rule.clone(),
+            interval: rule,
+        })
+    }
+
+    #[test]
+    fn excluded_app_is_refused_by_every_trigger() {
+        let policy = policy(TriggerRule {
+            exclude_apps: vec!["Slack".to_string()],
+            ..Default::default()
+        });
+        for trigger in ALL {
+            let allows = |app: Option<&str>, app_id: Option<&str>| {
+                policy.allows_at(trigger, "general", app, app_id, &noon())
+            };
+            assert!(!allows(Some("Slack"), None), "{trigger:?}");
+            assert!(!allows(Some("SLACK"), None), "{trigger:?}");
+            assert!(!allows(None, Some("/usr/lib/slack/slack")), "{trigger:?}");
+            assert!(allows(Some("Slackware Installer"), None), "{trigger:?}");
+            assert!(allows(Some("Firefox"), None), "{trigger:?}");
+            assert!(allows(None, None), "{trigger:?}");
+        }
+    }
+
+    #[test]
+    fn app_and_title_filters_apply_to_every_trigger() {
+        let policy = policy(TriggerRule {
+            apps: vec!["firefox".to_string(), "org.mozilla.firefox".to_string()],
+            exclude_titles: vec!["Private Browsing".to_string()],
+            ..Default::default()
+        });
+        for trigger in ALL {
+            let allows = |title: &str, app: Option<&str>, app_id: Option<&str>| {
+                policy.allows_at(trigger, title, app, app_id, &noon())
+            };
+            assert!(allows("Docs", Some("Firefox"), None), "{trigger:?}");
+            assert!(
+                allows("Docs", Some("Navigator"), Some("org.mozilla.firefox")),
+                "{trigger:?}"
+            );
+            assert!(
+                !allows("Docs — private browsing", Some("Firefox"), None),
+                "{trigger:?}"
+            );
+            assert!(!allows("Docs", Some("Terminal"), None), "{trigger:?}");
+            assert!(!allows("Docs", None, None), "{trigger:?}");
+        }
+    }
+
+    #[test]
+    fn triggers_are_configured_separately() {
+        let policy = TriggerPolicy::new(&Triggers {
+            focus: TriggerRule::default(),
+            title: TriggerRule {
+                exclude_apps: vec!["firefox".to_string()],
+                ..Default::default()
+            },
+            interval: TriggerRule {
+                enabled: false,
+                ..Default::default()
+            },
+        });
+        let allows = |trigger| policy.allows_at(trigger, "Docs", Some("Firefox"), None, &noon());
+        assert!(allows(Trigger::Focus));
+        assert!(!allows(Trigger::Title));
+        assert!(!allows(Trigger::Interval));
+    }
+
+    /// `hh:mm` UTC on `day` of the week of Monday 3 June 2024.
+    fn on(day: Weekday, hh_mm: &str) -> DateTime<Utc> {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 3 + day.num_days_from_monday()).unwrap();
+        let time = NaiveTime::parse_from_str(hh_mm, "%H:%M").unwrap();
+        date.and_time(time).and_utc()
+    }
+
+    fn timed(hours: &[&str], days: &[&str]) -> TriggerPolicy {
+        policy(TriggerRule {
+            hours: hours.iter().map(|h| h.to_string()).collect(),
+            days: days.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+
+    fn allows_at<Tz: TimeZone>(policy: &TriggerPolicy, now: &DateTime<Tz>) -> bool {
+        policy.allows_at(Trigger::Interval, "Docs", Some("Firefox"), None, now)
+    }
+
+    #[test]
+    fn time_window_includes_start_and_excludes_end() {
+        let policy = timed(&["09:00-17:30"], &[]);
+        for (time, allowed) in [
+            ("08:59", false),
+            ("09:00", true),
+            ("17:29", true),
+            ("17:30", false),
+        ] {
+            assert_eq!(
+                allows_at(&policy, &on(Weekday::Wed, time)),
+                allowed,
+                "{time}"
+            );
+        }
+    }
+
+    #[test]
+    fn time_window_wraps_past_midnight() {
+        let policy = timed(&["22:00-02:00"], &[]);
+        for (time, allowed) in [
+            ("21:59", false),
+            ("22:00", true),
+            ("23:59", true),
+            ("00:00", true),
+            ("01:59", true),
+            ("02:00", false),
+            ("12:00", false),
+        ] {
+            assert_eq!(
+                allows_at(&policy, &on(Weekday::Wed, time)),
+                allowed,
+                "{time}"
+            );
+        }
+    }
+
+    #[test]
+    fn any_of_several_windows_is_enough() {
+        let policy = timed(&["09:00-12:00", "13:00-17:00"], &[]);
+        assert!(allows_at(&policy, &on(Weekday::Wed, "11:00")));
+        assert!(!allows_at(&policy, &on(Weekday::Wed, "12:30")));
+        assert!(allows_at(&policy, &on(Weekday::Wed, "13:00")));
+    }
+
+    #[test]
+    fn day_filter_limits_the_weekdays() {
+        let policy = timed(&[], &["mon", "Fri"]);
+        assert!(allows_at(&policy, &on(Weekday::Mon, "03:00")));
+        assert!(allows_at(&policy, &on(Weekday::Fri, "23:59")));
+        assert!(!allows_at(&policy, &on(Weekday::Tue, "12:00")));
+        assert!(!allows_at(&policy, &on(Weekday::Sun, "12:00")));
+    }
+
+    #[test]
+    fn day_filter_judges_the_day_of_the_moment() {
+        // The early hours of Saturday belong to Saturday, even inside a
+        // window that started on Friday evening.
+        let policy = timed(&["22:00-02:00"], &["fri"]);
+        assert!(allows_at(&policy, &on(Weekday::Fri, "23:00")));
+        assert!(!allows_at(&policy, &on(Weekday::Sat, "01:00")));
+        assert!(allows_at(&policy, &on(Weekday::Fri, "01:00")));
+    }
+
+    #[test]
+    fn clock_is_read_in_its_own_zone() {
+        let policy = timed(&["09:00-17:30"], &["wed"]);
+        let berlin = FixedOffset::east_opt(2 * 60 * 60).unwrap();
+        // 08:00 UTC is 10:00 at +02:00.
+        assert!(!allows_at(&policy, &on(Weekday::Wed, "08:00")));
+        assert!(allows_at(
+            &policy,
+            &on(Weekday::Wed, "08:00").with_timezone(&berlin)
+        ));
+        // 23:00 UTC on Tuesday is already Wednesday at +02:00.
+        let late = on(Weekday::Tue, "23:00").with_timezone(&berlin);
+        assert!(!allows_at(&policy, &late));
+        assert!(allows_at(&timed(&[], &["wed"]), &late));
+    }
+
+    #[test]
+    fn invalid_windows_and_days_are_ignored() {
+        let policy = timed(&["9-5", "25:00-26:00", "10:00-11:00"], &["someday", "thu"]);
+        assert!(allows_at(&policy, &on(Weekday::Thu, "10:30")));
+        assert!(!allows_at(&policy, &on(Weekday::Thu, "12:00")));
+        assert!(!allows_at(&policy, &on(Weekday::Wed, "10:30")));
+        // With nothing valid left, the rule is unrestricted.
+        let policy = timed(&["9-5"], &["someday"]);
+        assert!(allows_at(&policy, &on(Weekday::Sun, "04:00")));
+    }
+
+    #[test]
+    fn legacy_switches_map_onto_trigger_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "capture_on_focus = true\ncapture_on_title_change = false\n",
+        )
+        .unwrap();
+        let config = CaptureConfig::load_or_default(&path).unwrap();
+        let policy = TriggerPolicy::new(&config.triggers);
+        let allows = |trigger| policy.allows_at(trigger, "Docs", None, None, &noon());
+        assert!(allows(Trigger::Focus));
+        assert!(!allows(Trigger::Title));
+        assert!(allows(Trigger::Interval));
+    }
+
+    #[test]
+    fn title_distance_is_a_share_of_the_longer_title() {
+        assert_eq!(title_distance("Inbox", "inbox "), 0.0);
+        assert_eq!(title_distance("", "  "), 0.0);
+        assert_eq!(title_distance("(5) Inbox", "(6) Inbox"), 1.0 / 9.0);
+        assert_eq!(title_distance("abcd", "abdc"), 0.25);
+        assert_eq!(title_distance("abc", "xyz"), 1.0);
+        assert_eq!(title_distance("", "abc"), 1.0);
+    }
+
+    #[test]
+    fn title_filter_skips_small_changes() {
+        let mut filter = TitleFilter::new(0.2);
+        filter.focused("(5) Inbox - Mail");
+        assert!(!filter.changed_enough("(6) Inbox - Mail"));
+        assert!(filter.changed_enough("Drafts - Mail"));
+        assert!(!filter.changed_enough("Drafts - Mail"));
+    }
+
+    #[test]
+    fn title_filter_measures_from_the_last_title_let_through() {
+        let mut filter = TitleFilter::new(0.3);
+        filter.focused("Uploading 10%");
+        // Each step is small, but they add up against the same baseline.
+        assert!(!filter.changed_enough("Uploading 20%"));
+        assert!(!filter.changed_enough("Uploading 30%"));
+        assert!(filter.changed_enough("Upload done 99%"));
+        assert!(!filter.changed_enough("Upload done 98%"));
+    }
+
+    #[test]
+    fn title_filter_without_a_threshold_or_baseline_lets_all_through() {
+        let mut off = TitleFilter::new(0.0);
+        off.focused("a");
+        assert!(off.changed_enough("a"));
+
+        let mut fresh = TitleFilter::new(0.5);
+        assert!(fresh.changed_enough("Inbox"));
+        assert!(!fresh.changed_enough("Inbox"));
+        // Focusing another window resets the baseline.
+        fresh.focused("Drafts");
+        assert!(!fresh.changed_enough("Drafts"));
+        assert!(fresh.changed_enough("Inbox"));
+    }
+}
@@ -0,0 +1,185 @@
+//! Charts rendered to images for clients that can only show pictures.
+
+use std::collections::BTreeMap;
+
+use ab_glyph::{Font, PxScale, ScaleFont};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use xcap::image::{Rgba, RgbaImage};
+
+use crate::overlay;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const LABEL: [u8; 3] = [87, 96, 106];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+/// Rows labelled down the left edge; rows start on Monday.
+const WEEKDAY_LABELS: [(u32, &str); 3] = [(0, "Mon"), (2, "Wed"), (4, "Fri")];
+
+/// Colours of a heatmap, from no captures to the busiest day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatmapScheme {
+    #[default]
+    Green,
+    Blue,
+    Orange,
+    Gray,
+}
+
+impl HeatmapScheme {
+    /// The empty-day colour followed by four levels of activity.
+    fn levels(self) -> [[u8; 3]; 5] {
+        const EMPTY: [u8; 3] = [235, 237, 240];
+        match self {
+            HeatmapScheme::Green => [
+                EMPTY,
+                [155, 233, 168],
+                [64, 196, 99],
+                [48, 161, 78],
+                [33, 110, 57],
+            ],
+            HeatmapScheme::Blue => [
+                EMPTY,
+                [198, 219, 239],
+                [107, 174, 214],
+                [33, 113, 181],
+                [8, 48, 107],
+            ],
+            HeatmapScheme::Orange => [
+                EMPTY,
+                [253, 208, 162],
+                [253, 141, 60],
+                [217, 72, 1],
+                [127, 39, 4],
+            ],
+            HeatmapScheme::Gray => [
+                EMPTY,
+                [189, 189, 189],
+                [150, 150, 150],
+                [99, 99, 99],
+                [37, 37, 37],
+            ],
+        }
+    }
+}
+
+/// Calendar heatmap of `year` in the style of a contribution graph: one
+/// column per week, one `cell` pixel square per day, shaded by its count
+/// in `counts` relative to the year's busiest day.
+pub fn heatmap(
+    year: i32,
+    counts: &BTreeMap<NaiveDate, usize>,
+    cell: u32,
+    scheme: HeatmapScheme,
+) -> Option<RgbaImage> {
+    let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+    let next = NaiveDate::from_ymd_opt(year + 1, 1, 1)?;
+    let lead = first.weekday().num_days_from_monday();
+    let column = |date: NaiveDate| (lead + (date - first).num_days() as u32) / 7;
+    let weeks = column(next - Duration::days(1)) + 1;
+
+    let gap = (cell / 6).max(1);
+    let pitch = cell + gap;
+    let font = overlay::builtin_font();
+    let fonts = [font];
+    let scale = PxScale::from((cell as f32 * 0.9).max(9.0));
+    let ascent = fonts[0].as_scaled(scale).ascent();
+    let label_width = WEEKDAY_LABELS
+        .iter()
+        .map(|(_, label)| overlay::text_width(&fonts, scale, label))
+        .fold(0.0, f32::max);
+    let left = label_width.ceil() as u32 + 2 * gap + pitch / 2;
+    let top = (scale.y * 1.4).ceil() as u32;
+    let width = left + weeks * pitch + pitch / 2;
+    let height = top + 7 * pitch + pitch / 2;
+
+    let [r, g, b] = BACKGROUND;
+    let mut image = RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255]));
+    let levels = scheme.levels();
+    let max = counts
+        .range(first..next)
+        .map(|(_, &count)| count)
+        .max()
+        .unwrap_or(0);
+    for date in first.iter_days().take_while(|&date| date < next) {
+        let count = counts.get(&date).copied().unwrap_or(0);
+        // Any activity at all shows as the first level, the busiest day
+        // as the last.
+        let level = match count {
+            0 => 0,
+            n => (n * 4).div_ceil(max).clamp(1, 4),
+        };
+        let x = left + column(date) * pitch;
+        let y = top + date.weekday().num_days_from_monday() * pitch;
+        let [r, g, b] = levels[level];
+        for py in y..y + cell {
+            for px in x..x + cell {
+                image.put_pixel(px, py, Rgba([r, g, b, 255]));
+            }
+        }
+    }
+
+    for (month, label) in MONTHS.iter().enumerate() {
+        let Some(start) = NaiveDate::from_ymd_opt(year, month as u32 + 1, 1) else {
+            continue;
+        };
+        // Label the first full week of the month, as its first column may
+        // mostly belong to the month before.
+        let week = column(start) + u32::from(start.weekday().num_days_from_monday() > 3);
+        if week >= weeks {
+            continue;
+        }
+        let x = (left + week * pitch) as f32;
+        overlay::draw_line(&mut image, &fonts, scale, (x, ascent + 1.0), LABEL, label);
+    }
+    for (row, label) in WEEKDAY_LABELS {
+        let y = (top + row * pitch) as f32 + (cell as f32 + ascent) / 2.0 - 1.0;
+        overlay::draw_line(&mut image, &fonts, scale, (gap as f32, y), LABEL, label);
+    }
+    Some(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden;
+
+    /// A year with a quiet start, a busy spring and a few single days.
+    fn counts(year: i32) -> BTreeMap<NaiveDate, usize> {
+        let day = |month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let mut counts = BTreeMap::new();
+        for (offset, date) in day(3, 1).iter_days().take(75).enumerate() {
+            counts.insert(date, offset % 11 * 3);
+        }
+        counts.insert(day(1, 1), 1);
+        counts.insert(day(7, 4), 120);
+        counts.insert(day(12, 31), 40);
+        // Outside the year, so it neither shows nor sets the scale.
+        counts.insert(NaiveDate::from_ymd_opt(year + 1, 1, 1).unwrap(), 10_000);
+        counts
+    }
+
+    #[test]
+    fn heatmap_matches_golden() {
+        let image = heatmap(2024, &counts(2024), 12, HeatmapScheme::Green).unwrap();
+        golden::assert_matches(&image, "heatmap_2024_green");
+    }
+
+    #[test]
+    fn small_cells_and_another_scheme_match_golden() {
+        let image = heatmap(2023, &counts(2023), 6, HeatmapScheme::Orange).unwrap();
+        golden::assert_matches(&image, "heatmap_2023_orange_small");
+    }
+
+    #[test]
+    fn every_week_of_the_year_gets_a_column() {
+        let size = |year, cell| heatmap(year, &BTreeMap::new(), cell, HeatmapScheme::Gray).unwrap();
+        // Most years span 53 week columns. 2012, a leap year starting on a
+        // Sunday, has 1 January alone in its first column and needs 54.
+        let (a, b) = (size(2024, 10), size(2012, 10));
+        assert_eq!(a.height(), b.height());
+        assert_eq!(b.width(), a.width() + 11);
+    }
+}
@@ -0,0 +1,183 @@
+/// Virtual desktop the focused window is on: the X11 desktop number, the
+/// macOS Space id or the Windows virtual desktop GUID. `None` where the
+/// platform doesn't expose it, e.g. on Wayland.
+pub fn current() -> Option<String> {
+    platform_current()
+}
+
+#[cfg(target_os = "linux")]
+fn platform_current() -> Option<String> {
+    use xcb::x;
+
+    xcb::atoms_struct! {
+        struct Atoms {
+            net_active_window => b"_NET_ACTIVE_WINDOW",
+            net_wm_desktop => b"_NET_WM_DESKTOP",
+            net_current_desktop => b"_NET_CURRENT_DESKTOP",
+        }
+    }
+
+    /// Sticky windows are on every desktop.
+    const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+    let (conn, screen_num) = xcb::Connection::connect(None).ok()?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)?
+        .root();
+    let atoms = Atoms::intern_all(&conn).ok()?;
+    let cardinal = |window: x::Window, property: x::Atom| -> Option<u32> {
+        let cookie = conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type: x::ATOM_CARDINAL,
+            long_offset: 0,
+            long_length: 1,
+        });
+        let reply = conn.wait_for_reply(cookie).ok()?;
+        reply.value::<u32>().first().copied()
+    };
+
+    let cookie = conn.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: atoms.net_active_window,
+        r#type: x::ATOM_WINDOW,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let active = conn
+        .wait_for_reply(cookie)
+        .ok()
+        .and_then(|reply| reply.value::<x::Window>().first().copied());
+    active
+        .and_then(|window| cardinal(window, atoms.net_wm_desktop))
+        .filter(|&desktop| desktop != ALL_DESKTOPS)
+        .or_else(|| cardinal(root, atoms.net_current_desktop))
+        .map(|desktop| desktop.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn platform_current() -> Option<String> {
+    // Private but long-stable SkyLight calls, re-exported by CoreGraphics;
+    // there is no public API for Spaces. The focused window is on the
+    // active Space.
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGSMainConnectionID() -> i32;
+        fn CGSGetActiveSpace(connection: i32) -> u64;
+    }
+
+    // SAFETY: both calls only read window server state.
+    let space = unsafe { CGSGetActiveSpace(CGSMainConnectionID()) };
+    (space != 0).then(|| space.to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn platform_current() -> Option<String> {
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Guid {
+        data1: u32,
+        data2: u16,
+        data3: u16,
+        data4: [u8; 8],
+    }
+
+    /// `IVirtualDesktopManager`; only the slots called here are typed.
+    #[repr(C)]
+    struct ManagerVtbl {
+        _query_interface: usize,
+        _add_ref: usize,
+        release: unsafe extern "system" fn(this: *mut c_void) -> u32,
+        _is_window_on_current_virtual_desktop: usize,
+        get_window_desktop_id:
+            unsafe extern "system" fn(this: *mut c_void, hwnd: *mut c_void, id: *mut Guid) -> i32,
+        _move_window_to_desktop: usize,
+    }
+
+    const CLSID_VIRTUAL_DESKTOP_MANAGER: Guid = Guid {
+        data1: 0xAA50_9086,
+        data2: 0x5CA9,
+        data3: 0x4C25,
+        data4: [0x8F, 0x95, 0x58, 0x9D, 0x3C, 0x07, 0xB4, 0x8A],
+    };
+    const IID_IVIRTUAL_DESKTOP_MANAGER: Guid = Guid {
+        data1: 0xA5CD_92FF,
+        data2: 0x29BE,
+        data3: 0x454C,
+        data4: [0x8D, 0x04, 0xD8, 0x28, 0x79, 0xFB, 0x3F, 0x1B],
+    };
+    const COINIT_MULTITHREADED: u32 = 0;
+    const CLSCTX_ALL: u32 = 0x17;
+
+    #[link(name = "ole32")]
+    unsafe extern "system" {
+        fn CoInitializeEx(reserved: *mut c_void, coinit: u32) -> i32;
+        fn CoUninitialize();
+        fn CoCreateInstance(
+            clsid: *const Guid,
+            outer: *mut c_void,
+            context: u32,
+            iid: *const Guid,
+            object: *mut *mut c_void,
+        ) -> i32;
+    }
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetForegroundWindow() -> *mut c_void;
+    }
+
+    // SAFETY: COM is initialised for the call and balanced afterwards; the
+    // manager pointer is only used while its reference is held, and `id`
+    // is a valid GUID for the method to fill.
+    let id = unsafe {
+        let initialized = CoInitializeEx(std::ptr::null_mut(), COINIT_MULTITHREADED) >= 0;
+        let mut id = Guid::default();
+        let mut manager: *mut c_void = std::ptr::null_mut();
+        let created = CoCreateInstance(
+            &CLSID_VIRTUAL_DESKTOP_MANAGER,
+            std::ptr::null_mut(),
+            CLSCTX_ALL,
+            &IID_IVIRTUAL_DESKTOP_MANAGER,
+            &mut manager,
+        ) >= 0
+            && !manager.is_null();
+        let window = GetForegroundWindow();
+        let found = created && !window.is_null() && {
+            let vtbl = *(manager as *const *const ManagerVtbl);
+            ((*vtbl).get_window_desktop_id)(manager, window, &mut id) >= 0
+        };
+        if created {
+            let vtbl = *(manager as *const *const ManagerVtbl);
+            ((*vtbl).release)(manager);
+        }
+        if initialized {
+            CoUninitialize();
+        }
+        found.then_some(id)?
+    };
+    // Windows that belong to no desktop, such as the taskbar, report the
+    // zero GUID.
+    if id.data1 == 0 && id.data2 == 0 && id.data3 == 0 && id.data4 == [0; 8] {
+        return None;
+    }
+    Some(format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}",
+        id.data1,
+        id.data2,
+        id.data3,
+        id.data4[0],
+        id.data4[1],
+        id.data4[2..].iter().map(|b| format!("{b:02X}")).collect::<String>()
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn platform_current() -> Option<String> {
+    None
+}
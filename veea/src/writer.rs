@@ -0,0 +1,252 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use xcap::image::RgbaImage;
+
+use crate::{
+    capture::save_image,
+    codec::{self, ImageFormat, PngCompression},
+    config::CaptureConfig,
+    db::{CaptureRecord, Db},
+    error::{AppError, AppResult},
+    hooks::PostCaptureHook,
+    notify::ErrorNotifier,
+    objects,
+    pack::{self, StorageMode},
+    priority,
+    timezone::StorageTimezone,
+};
+
+/// Captures waiting to be written before `ImageWriter::submit` blocks.
+/// Bounds the frames held in memory when the disk can't keep up.
+const QUEUE_LEN: usize = 8;
+
+struct WriteJob {
+    image: RgbaImage,
+    path: PathBuf,
+    format: ImageFormat,
+    compression: PngCompression,
+    /// ICC profile to embed, see `codec::encode_tagged`.
+    icc: Option<Vec<u8>>,
+    record: CaptureRecord,
+}
+
+/// Encodes and saves capture images on a background thread, then stores
+/// their records, so a slow disk doesn't hold up the event loop. Jobs run
+/// one at a time in the order they were submitted, and a record is only
+/// inserted once its image is on disk. With `storage_mode = "packs"` the
+/// images are appended to hourly packs instead of their own files, and
+/// with `"content_addressed"` they are named by their hash so identical
+/// ones are stored once. Under `low_priority` the worker lowers its own
+/// priority, and failed writes are reported through an `ErrorNotifier`.
+pub struct ImageWriter {
+    jobs: Option<SyncSender<WriteJob>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ImageWriter {
+    /// Starts the worker, which inserts records through `db`.
+    pub fn new(db: Db, config: &CaptureConfig) -> Self {
+        let store = Store {
+            mode: config.storage_mode,
+            capture_dir: config.capture_dir.clone(),
+            tz: config.storage_timezone,
+            hook: PostCaptureHook::from_config(config),
+        };
+        let low_priority = config.low_priority;
+        let mut notifier = ErrorNotifier::new(config);
+        let (jobs, rx) = mpsc::sync_channel::<WriteJob>(QUEUE_LEN);
+        let worker = thread::spawn(move || {
+            // Done here rather than relying on inheritance, as the engine
+            // starts its writer before the capture thread is lowered.
+            if low_priority && let Err(e) = priority::lower_current_thread() {
+                eprintln!("Could not lower image writer priority: {e}");
+            }
+            for job in rx {
+                if let Err(e) = write(&db, &store, job) {
+                    notifier.notify(&e);
+                }
+            }
+        });
+        Self {
+            jobs: Some(jobs),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues `image` to be saved at `path`, waiting only while the queue
+    /// is full.
+    pub fn submit(
+        &self,
+        image: RgbaImage,
+        path: PathBuf,
+        format: ImageFormat,
+        compression: PngCompression,
+        icc: Option<Vec<u8>>,
+        record: CaptureRecord,
+    ) -> AppResult<()> {
+        let job = WriteJob {
+            image,
+            path,
+            format,
+            compression,
+            icc,
+            record,
+        };
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or_else(|| AppError::Channel("image writer stopped".to_string()))
+    }
+}
+
+impl Drop for ImageWriter {
+    /// Finishes the queued writes before returning.
+    fn drop(&mut self) {
+        self.jobs.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Where the worker puts images: `capture_dir` and the zone naming the
+/// hourly packs matter only outside `StorageMode::Files`. `hook` runs once
+/// a capture is stored.
+struct Store {
+    mode: StorageMode,
+    capture_dir: PathBuf,
+    tz: StorageTimezone,
+    hook: Option<PostCaptureHook>,
+}
+
+/// Stores one job; a failure has already been logged and, if the image
+/// couldn't be saved, recorded as a `write_failed` attempt.
+fn write(db: &Db, store: &Store, job: WriteJob) -> AppResult<()> {
+    let WriteJob { image, path, format, compression, icc, mut record } = job;
+    let encoded = || codec::encode_tagged(&image, format, compression, icc.as_deref());
+    // Kept until the record is inserted, so the object it shares can't be
+    // deleted in between.
+    let mut refs = None;
+    let saved = match store.mode {
+        StorageMode::Files => save_image(&image, &path, format, compression, icc.as_deref()),
+        StorageMode::Packs => encoded()
+            .and_then(|bytes| pack::store(&store.capture_dir, store.tz, &mut record, &bytes)),
+        StorageMode::ContentAddressed => encoded().and_then(|bytes| {
+            refs = Some(objects::lock(&store.capture_dir)?);
+            objects::store(&store.capture_dir, &mut record, &bytes, format)
+        }),
+    };
+    if let Err(e) = saved {
+        eprintln!("Saving {} failed: {e}", path.display());
+        let _ = db.insert_attempt(&record.event_type, "write_failed", Some(&e.to_string()));
+        return Err(e);
+    }
+    let location = record
+        .pack
+        .as_ref()
+        .map_or_else(|| record.path.clone(), |slot| format!("{}@{}", slot.path, slot.offset));
+    println!("Saved screenshot: {location} ({}x{})", image.width(), image.height());
+    let inserted = db.insert_capture(&record);
+    drop(refs);
+    if let Err(e) = inserted {
+        eprintln!("Storing capture {} failed: {e}", record.id);
+        return Err(e);
+    }
+    if let Some(hook) = &store.hook {
+        hook.run(&record);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::db::CaptureFilter;
+
+    fn config(dir: &std::path::Path) -> CaptureConfig {
+        CaptureConfig {
+            capture_dir: dir.join("captures"),
+            db_path: dir.join("veea.db"),
+            ..Default::default()
+        }
+    }
+
+    /// Submits one capture to be saved at `path` and waits for the writer
+    /// to finish with it.
+    fn submit(config: &CaptureConfig, path: PathBuf) -> CaptureRecord {
+        let mut record = CaptureRecord::sample("0001", Utc::now());
+        record.path = path.to_string_lossy().into_owned();
+        let writer = ImageWriter::new(Db::new(&config.db_path).unwrap(), config);
+        writer
+            .submit(
+                RgbaImage::new(4, 4),
+                path,
+                ImageFormat::Png,
+                PngCompression::Default,
+                None,
+                record.clone(),
+            )
+            .unwrap();
+        drop(writer);
+        record
+    }
+
+    fn attempts(db: &Db) -> Vec<(String, String)> {
+        let conn = db.open_reader().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT event_type, outcome FROM capture_attempts ORDER BY id")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn stored(db: &Db) -> usize {
+        let filter = CaptureFilter {
+            include_blank: true,
+            ..Default::default()
+        };
+        db.count_recent(&filter).unwrap()
+    }
+
+    #[test]
+    fn saved_image_is_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        std::fs::create_dir_all(&config.capture_dir).unwrap();
+        let path = config.capture_dir.join("0001.png");
+        submit(&config, path.clone());
+
+        let db = Db::new(&config.db_path).unwrap();
+        assert!(path.is_file());
+        assert_eq!(stored(&db), 1);
+        assert!(db.get_capture("0001").unwrap().is_some());
+        assert!(attempts(&db).is_empty());
+    }
+
+    #[test]
+    fn failed_save_logs_an_attempt_and_stores_no_capture() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        // A file where the capture's folder should be fails the write.
+        std::fs::write(&config.capture_dir, b"").unwrap();
+        let path = config.capture_dir.join("0001.png");
+        submit(&config, path.clone());
+
+        let db = Db::new(&config.db_path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(stored(&db), 0);
+        assert!(db.get_capture("0001").unwrap().is_none());
+        assert_eq!(
+            attempts(&db),
+            [("focus".to_string(), "write_failed".to_string())]
+        );
+    }
+}
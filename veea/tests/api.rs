@@ -0,0 +1,522 @@
+//! The HTTP API driven in process: requests go straight into the axum
+//! `Router` through `tower::ServiceExt::oneshot`, the way `VeeaClient`
+//! sends them, and responses are decoded into the types the client
+//! shares with the server.
+
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, mpsc, Arc, RwLock},
+};
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    middleware, Router,
+};
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tempfile::TempDir;
+use tower::ServiceExt;
+use veea::{
+    api::{self, ApiState, CaptureSummary, EraseResponse, Page, SearchPage, Status},
+    codec::{self, ImageFormat},
+    config::CaptureConfig,
+    crypto,
+    db::{CaptureRecord, Db},
+    expose::{self, ApiExpose, ExposePolicy},
+    focus::WindowEvent,
+    passphrase_backoff,
+    thumbnail_cache::ThumbnailCache,
+};
+use xcap::image::{Rgba, RgbaImage};
+
+/// A scratch archive and the router serving it. Nothing runs the engine
+/// loop, so `events` only keeps its channel open.
+struct Archive {
+    dir: TempDir,
+    config: CaptureConfig,
+    _events: mpsc::Receiver<WindowEvent>,
+    app: Router,
+}
+
+impl Archive {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CaptureConfig {
+            capture_dir: dir.path().join("captures"),
+            db_path: dir.path().join("veea.db"),
+            // `/search` reads the captures table of this database.
+            search_index_path: dir.path().join("veea.db"),
+            enable_search_index: false,
+            image_format: ImageFormat::Png,
+            ..Default::default()
+        };
+        std::fs::create_dir_all(&config.capture_dir).unwrap();
+        Db::new(&config.db_path).unwrap();
+        let (events, events_rx) = mpsc::channel();
+        let state = ApiState {
+            db_path: config.db_path.clone(),
+            config: Arc::new(RwLock::new(config.clone())),
+            config_path: dir.path().join("config.toml"),
+            search_index_path: config.search_index_path.clone(),
+            pause_flag: Arc::new(AtomicBool::new(false)),
+            focus_backend: "none",
+            events,
+            bursts: Default::default(),
+            on_battery: Arc::new(AtomicBool::new(false)),
+            image_reads: Arc::new(tokio::sync::Semaphore::new(1)),
+            thumbnails: Arc::new(ThumbnailCache::new(1024 * 1024)),
+            passphrase_backoff: Default::default(),
+        };
+        Self {
+            dir,
+            app: api::router(state),
+            config,
+            _events: events_rx,
+        }
+    }
+
+    /// Stores a capture of `title` taken `minutes_ago`, with a small
+    /// generated PNG as its image.
+    fn capture(&self, id: &str, title: &str, minutes_ago: i64) -> CaptureRecord {
+        let image = RgbaImage::from_fn(8, 6, |x, y| Rgba([x as u8 * 30, y as u8 * 40, 90, 255]));
+        let rel_path = format!("{id}.png");
+        let path = self.config.capture_dir.join(&rel_path);
+        std::fs::write(&path, codec::encode(&image, ImageFormat::Png).unwrap()).unwrap();
+        let record = CaptureRecord {
+            id: id.to_string(),
+            ts: Utc::now() - Duration::minutes(minutes_ago),
+            window_title: Some(title.to_string()),
+            app_name: Some("editor".to_string()),
+            app_id: None,
+            event_type: "focus".to_string(),
+            path: path.to_string_lossy().to_string(),
+            width: Some(8),
+            height: Some(6),
+            monitor: None,
+            hash: None,
+            capture_group_id: None,
+            rel_path: Some(rel_path),
+            clipboard_text: None,
+            prev_title: None,
+            original_path: None,
+            ocr_text: None,
+            ax_text: None,
+            contains_code: None,
+            ocr_lines: Vec::new(),
+            trigger_path: None,
+            doc_path: None,
+            workspace: None,
+            color_profile: None,
+            keys_per_min: None,
+            clicks_per_min: None,
+            activity_level: None,
+            trigger_detail: None,
+            pack: None,
+            capture_method: None,
+            mic_active: None,
+            cam_active: None,
+            codec: ImageFormat::Png,
+            blank: false,
+            importance: None,
+        };
+        Db::new(&self.config.db_path)
+            .and_then(|db| db.insert_capture(&record))
+            .unwrap();
+        record
+    }
+
+    async fn send(&self, method: Method, uri: &str) -> (StatusCode, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, bytes.to_vec())
+    }
+
+    /// The JSON body of a successful request.
+    async fn json<T: DeserializeOwned>(&self, method: Method, uri: &str) -> T {
+        let (status, body) = self.send(method, uri).await;
+        assert!(
+            status.is_success(),
+            "{uri}: {status} {}",
+            String::from_utf8_lossy(&body)
+        );
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// The error code of a failed request, checking the error envelope.
+    async fn error(&self, method: Method, uri: &str) -> (StatusCode, String) {
+        let (status, body) = self.send(method, uri).await;
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert!(json["error"]["message"].is_string(), "{uri}: {json}");
+        (status, json["error"]["code"].as_str().unwrap().to_string())
+    }
+
+    /// The router behind the LAN mode token check, wanting `token`.
+    fn guarded(&self, token: &str) -> Router {
+        let policy = ExposePolicy::new(&CaptureConfig {
+            api_expose: ApiExpose::Lan,
+            api_addr: "0.0.0.0:8787".parse().unwrap(),
+            api_token: Some(token.to_string()),
+            ..Default::default()
+        });
+        let guard = middleware::from_fn_with_state(Arc::new(policy), expose::guard);
+        self.app.clone().layer(guard)
+    }
+
+    fn count(&self) -> usize {
+        Db::new(&self.config.db_path)
+            .and_then(|db| db.list_recent(100, 0, &Default::default()))
+            .unwrap()
+            .len()
+    }
+}
+
+fn ids<'a>(items: impl IntoIterator<Item = &'a CaptureSummary>) -> Vec<&'a str> {
+    items.into_iter().map(|c| c.id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn list_captures_pages_newest_first() {
+    let archive = Archive::new();
+    archive.capture("a", "Old", 30);
+    archive.capture("b", "Middle", 20);
+    archive.capture("c", "New", 10);
+
+    let first: Page<CaptureSummary> = archive.json(Method::GET, "/captures?limit=2").await;
+    assert_eq!((first.total, first.limit, first.offset), (3, 2, 0));
+    assert_eq!(ids(&first.items), ["c", "b"]);
+
+    let rest: Page<CaptureSummary> = archive
+        .json(Method::GET, "/captures?limit=2&offset=2")
+        .await;
+    assert_eq!(ids(&rest.items), ["a"]);
+
+    for uri in [
+        "/captures?offset=4294967296",
+        "/search?q=New&offset=4294967296",
+    ] {
+        let (status, code) = archive.error(Method::GET, uri).await;
+        assert_eq!(
+            (status, code.as_str()),
+            (StatusCode::BAD_REQUEST, "bad_request"),
+            "{uri}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn get_capture_returns_its_summary() {
+    let archive = Archive::new();
+    archive.capture("a", "Notes", 5);
+
+    let summary: CaptureSummary = archive.json(Method::GET, "/captures/a").await;
+    assert_eq!(summary.id, "a");
+    assert_eq!(summary.window_title.as_deref(), Some("Notes"));
+    assert_eq!((summary.width, summary.height), (Some(8), Some(6)));
+
+    let (status, code) = archive.error(Method::GET, "/captures/nope").await;
+    assert_eq!(
+        (status, code.as_str()),
+        (StatusCode::NOT_FOUND, "not_found")
+    );
+}
+
+#[tokio::test]
+async fn image_bytes_are_the_stored_file() {
+    let archive = Archive::new();
+    let record = archive.capture("a", "Notes", 5);
+
+    let (status, bytes) = archive.send(Method::GET, "/captures/a/image").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bytes, std::fs::read(&record.path).unwrap());
+
+    std::fs::remove_file(&record.path).unwrap();
+    let (status, code) = archive.error(Method::GET, "/captures/a/image").await;
+    assert_eq!(
+        (status, code.as_str()),
+        (StatusCode::NOT_FOUND, "not_found")
+    );
+}
+
+#[tokio::test]
+async fn search_matches_titles_and_hides_paths() {
+    let archive = Archive::new();
+    archive.capture("a", "Quarterly report", 10);
+    archive.capture("b", "Inbox", 5);
+
+    let page: SearchPage = archive
+        .json(Method::GET, "/search?q=report&mode=substring")
+        .await;
+    assert_eq!(page.total_matches, 1);
+    assert_eq!(page.items[0].id, "a");
+    assert_eq!(page.items[0].path, None);
+    assert_eq!(page.items[0].image_url, "/captures/a/image");
+}
+
+#[tokio::test]
+async fn pause_and_resume_show_in_status() {
+    let archive = Archive::new();
+    let status: Status = archive.json(Method::GET, "/status").await;
+    assert!(!status.paused);
+    assert_eq!(status.focus_backend, "none");
+
+    archive.send(Method::POST, "/control/pause").await;
+    let status: Status = archive.json(Method::GET, "/status").await;
+    assert!(status.paused);
+
+    archive.send(Method::POST, "/control/resume").await;
+    let status: Status = archive.json(Method::GET, "/status").await;
+    assert!(!status.paused);
+}
+
+#[tokio::test]
+async fn erase_deletes_only_recent_captures() {
+    let archive = Archive::new();
+    archive.capture("old", "Old", 60);
+    let recent = archive.capture("new", "New", 1);
+    for id in ["old", "new"] {
+        let uri = format!("/captures/{id}/thumbnail");
+        assert_eq!(archive.send(Method::GET, &uri).await.0, StatusCode::OK);
+    }
+
+    let erased: EraseResponse = archive
+        .json(Method::POST, "/control/erase?minutes=5&confirm=true")
+        .await;
+    assert_eq!(erased.deleted, 1);
+    assert_eq!(archive.count(), 1);
+    assert!(!Path::new(&recent.path).exists());
+    // The erased capture's thumbnail left the cache with it.
+    let text = archive.send(Method::GET, "/metrics").await.1;
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(metric(&text, "veea_thumbnail_cache_entries"), 1);
+}
+
+#[tokio::test]
+async fn snapshot_while_paused_is_a_conflict() {
+    let archive = Archive::new();
+    archive.send(Method::POST, "/control/pause").await;
+    let (status, code) = archive
+        .error(Method::POST, "/control/snapshot?label=test")
+        .await;
+    assert_eq!((status, code.as_str()), (StatusCode::CONFLICT, "paused"));
+    assert_eq!(archive.count(), 0);
+    assert!(
+        archive
+            .dir
+            .path()
+            .join("captures")
+            .read_dir()
+            .unwrap()
+            .next()
+            .is_none()
+    );
+}
+
+/// The value of `name` in a Prometheus text exposition.
+fn metric(text: &str, name: &str) -> u64 {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' ')?.parse().ok())
+        .unwrap_or_else(|| panic!("{name} missing from:\n{text}"))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn parallel_thumbnail_requests_are_counted_once_each() {
+    const CAPTURES: usize = 4;
+    const TASKS: usize = 16;
+    const ROUNDS: usize = 5;
+    let archive = Archive::new();
+    for n in 0..CAPTURES {
+        archive.capture(&format!("c{n}"), "Notes", 5);
+    }
+
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            let app = archive.app.clone();
+            tokio::spawn(async move {
+                let mut bodies = Vec::new();
+                for round in 0..ROUNDS {
+                    let n = (task + round) % CAPTURES;
+                    let request = Request::builder()
+                        .uri(format!("/captures/c{n}/thumbnail"))
+                        .body(Body::empty())
+                        .unwrap();
+                    let response = app.clone().oneshot(request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::OK);
+                    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                        .await
+                        .unwrap();
+                    bodies.push((n, body));
+                }
+                bodies
+            })
+        })
+        .collect();
+    let mut first = vec![None; CAPTURES];
+    for task in tasks {
+        for (n, body) in task.await.unwrap() {
+            // Rendered or cached, every response for a capture is the same.
+            assert_eq!(first[n].get_or_insert_with(|| body.clone()), &body);
+        }
+    }
+
+    // Every capture has been rendered by now, so these are all hits.
+    for n in 0..CAPTURES {
+        archive
+            .send(Method::GET, &format!("/captures/c{n}/thumbnail"))
+            .await;
+    }
+    let (status, text) = archive.send(Method::GET, "/metrics").await;
+    assert_eq!(status, StatusCode::OK);
+    let text = String::from_utf8(text).unwrap();
+    let hits = metric(&text, "veea_thumbnail_cache_hits_total");
+    let misses = metric(&text, "veea_thumbnail_cache_misses_total");
+    assert_eq!(hits + misses, (TASKS * ROUNDS + CAPTURES) as u64, "{text}");
+    // Tasks racing for the same capture may each miss before one stores it.
+    assert!(misses >= CAPTURES as u64, "{text}");
+    assert!(hits >= CAPTURES as u64, "{text}");
+    assert_eq!(
+        metric(&text, "veea_thumbnail_cache_entries"),
+        CAPTURES as u64
+    );
+}
+
+#[tokio::test]
+async fn originals_take_the_passphrase_beside_the_token() {
+    let archive = Archive::new();
+    let mut record = archive.capture("a", "Bank", 5);
+    let original = archive.dir.path().join("a.orig.enc");
+    std::fs::write(&original, crypto::encrypt("hunter2", b"unredacted")).unwrap();
+    record.id = "b".to_string();
+    record.original_path = Some(original.to_string_lossy().to_string());
+    Db::new(&archive.config.db_path)
+        .and_then(|db| db.insert_capture(&record))
+        .unwrap();
+    let app = archive.guarded("secret");
+    let send = |token: Option<&str>, passphrase: Option<&str>| {
+        let mut request = Request::builder().uri("/captures/b/original");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        if let Some(passphrase) = passphrase {
+            request = request.header(api::PASSPHRASE_HEADER, passphrase);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    let response = send(Some("secret"), Some("hunter2")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&bytes[..], b"unredacted");
+
+    for (token, passphrase) in [
+        (Some("secret"), None),
+        (Some("secret"), Some("wrong")),
+        (None, Some("hunter2")),
+        (Some("hunter2"), Some("hunter2")),
+    ] {
+        let response = send(token, passphrase).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{token:?} {passphrase:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn wrong_passphrases_lock_originals_out() {
+    let archive = Archive::new();
+    let mut record = archive.capture("a", "Bank", 5);
+    let original = archive.dir.path().join("a.orig.enc");
+    std::fs::write(&original, crypto::encrypt("hunter2", b"unredacted")).unwrap();
+    record.id = "b".to_string();
+    record.original_path = Some(original.to_string_lossy().to_string());
+    Db::new(&archive.config.db_path)
+        .and_then(|db| db.insert_capture(&record))
+        .unwrap();
+    let send = |passphrase: &str| {
+        let request = Request::builder()
+            .uri("/captures/b/original")
+            .header(api::PASSPHRASE_HEADER, passphrase)
+            .body(Body::empty())
+            .unwrap();
+        archive.app.clone().oneshot(request)
+    };
+
+    for _ in 0..=passphrase_backoff::FREE_ATTEMPTS {
+        let response = send("wrong").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    // Locked out, even for the right passphrase.
+    let response = send("hunter2").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+}
+
+#[tokio::test]
+async fn feed_alone_takes_the_token_as_a_parameter() {
+    let archive = Archive::new();
+    archive.capture("a", "Notes", 5);
+    let app = archive.guarded("secret");
+    for (uri, expected) in [
+        ("/feed.atom?token=secret", StatusCode::OK),
+        ("/feed.atom?limit=5&token=secret", StatusCode::OK),
+        ("/feed.atom?token=wrong", StatusCode::UNAUTHORIZED),
+        ("/feed.atom", StatusCode::UNAUTHORIZED),
+        ("/captures?token=secret", StatusCode::UNAUTHORIZED),
+    ] {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), expected, "{uri}");
+    }
+}
+
+/// A changes page's `max_seq` and `more`.
+fn cursor(page: &Value) -> (i64, bool) {
+    (
+        page["max_seq"].as_i64().unwrap(),
+        page["more"].as_bool().unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn changes_page_by_seq_and_refuse_pruned_cursors() {
+    let archive = Archive::new();
+    for id in ["a", "b", "c"] {
+        archive.capture(id, "Notes", 5);
+    }
+    let page: Value = archive.json(Method::GET, "/changes?limit=2").await;
+    let seqs: Vec<i64> = page["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["seq"].as_i64().unwrap())
+        .collect();
+    assert_eq!(seqs, [1, 2]);
+    assert_eq!(cursor(&page), (2, true));
+    let page: Value = archive.json(Method::GET, "/changes?since_seq=2").await;
+    assert_eq!(page["changes"][0]["capture_id"], "c");
+    assert_eq!(cursor(&page), (3, false));
+
+    let db = Db::new(&archive.config.db_path).unwrap();
+    db.prune_changes(Utc::now().timestamp_millis() + 1).unwrap();
+    let (status, code) = archive.error(Method::GET, "/changes?since_seq=1").await;
+    assert_eq!(
+        (status, code.as_str()),
+        (StatusCode::GONE, "changes_pruned")
+    );
+    // Caught up to the pruned seq, a reader carries on.
+    let page: Value = archive.json(Method::GET, "/changes?since_seq=3").await;
+    assert_eq!(cursor(&page), (3, false));
+}
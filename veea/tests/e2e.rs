@@ -0,0 +1,384 @@
+//! End-to-end smoke test of capture, storage and the API, run with
+//! `cargo test --features e2e`. A window with a known title is captured
+//! into a throwaway archive, the stored record and file are checked, and
+//! both are read back through the HTTP API. On Linux with Xvfb installed
+//! the engine captures a real window on a private display; the mock
+//! variant runs the same engine over a generated screen, so everything
+//! after grabbing the pixels is checked on every platform.
+
+#![cfg(feature = "e2e")]
+
+use std::{
+    path::Path,
+    sync::{atomic::AtomicBool, mpsc, Arc, RwLock},
+};
+
+use veea::{
+    api::{self, ApiState},
+    capture::{CaptureEngine, ScreenSource},
+    client::VeeaClient,
+    codec::{self, ImageFormat},
+    config::CaptureConfig,
+    db::{CaptureFilter, CaptureRecord, Db},
+    error::{AppError, AppResult},
+    ocr::OcrBackend,
+    thumbnail_cache::ThumbnailCache,
+};
+use xcap::image::{Rgba, RgbaImage};
+
+const SMOKE_TITLE: &str = "Veea smoke test window";
+const SMOKE_APP: &str = "veea-smoke";
+
+#[test]
+fn mock_capture_round_trips_through_the_api() {
+    let scratch = tempfile::tempdir().unwrap();
+    let config = smoke_config(scratch.path());
+    let record = capture_mock(&config);
+    check_record(&config, &record);
+    check_api(&config, &record);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn xvfb_capture_round_trips_through_the_api() {
+    if !xvfb_installed() {
+        eprintln!("Xvfb is not installed, skipping the real capture");
+        return;
+    }
+    let scratch = tempfile::tempdir().unwrap();
+    let config = smoke_config(scratch.path());
+    let record = capture_xvfb(&config);
+    check_record(&config, &record);
+    check_api(&config, &record);
+}
+
+fn smoke_config(dir: &Path) -> CaptureConfig {
+    CaptureConfig {
+        capture_dir: dir.join("captures"),
+        db_path: dir.join("veea.db"),
+        search_index_path: dir.join("search.db"),
+        enable_search_index: false,
+        ocr_backend: OcrBackend::None,
+        image_format: ImageFormat::Png,
+        max_captures_per_minute: 0,
+        skip_self: false,
+        ..Default::default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn xvfb_installed() -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join("Xvfb").is_file()))
+}
+
+#[cfg(target_os = "linux")]
+fn capture_xvfb(config: &CaptureConfig) -> CaptureRecord {
+    let xvfb = xvfb::Display::start().unwrap();
+    // SAFETY: nothing else in this test binary reads the environment.
+    unsafe {
+        std::env::set_var("DISPLAY", xvfb.name());
+        std::env::remove_var("WAYLAND_DISPLAY");
+    }
+    let _window = xvfb::TestWindow::open(xvfb.name(), SMOKE_TITLE).unwrap();
+
+    let db = Db::new(&config.db_path).unwrap();
+    let engine =
+        CaptureEngine::new(config.clone(), db, Arc::new(AtomicBool::new(false))).unwrap();
+    capture_with(config, engine)
+}
+
+/// A screen where every window shows the same generated gradient.
+struct GradientScreen;
+
+impl GradientScreen {
+    fn frame() -> RgbaImage {
+        let (width, height) = (320, 200);
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x ^ y) & 0xff) as u8,
+                255,
+            ])
+        })
+    }
+}
+
+impl ScreenSource for GradientScreen {
+    fn focused_window(&self) -> Option<RgbaImage> {
+        Some(Self::frame())
+    }
+
+    fn window(&self, _title: &str) -> Option<RgbaImage> {
+        Some(Self::frame())
+    }
+
+    fn monitor(&self) -> AppResult<(RgbaImage, Option<String>)> {
+        Err(AppError::MonitorNotFound("gradient".to_string()))
+    }
+}
+
+fn capture_mock(config: &CaptureConfig) -> CaptureRecord {
+    let db = Db::new(&config.db_path).unwrap();
+    let engine = CaptureEngine::new(config.clone(), db, Arc::new(AtomicBool::new(false)))
+        .unwrap()
+        .with_screen(GradientScreen);
+    capture_with(config, engine)
+}
+
+/// Snapshots the smoke window with `engine` and reads back what it stored.
+fn capture_with(config: &CaptureConfig, mut engine: CaptureEngine) -> CaptureRecord {
+    engine
+        .capture_event(SMOKE_TITLE, Some(SMOKE_APP), None, "snapshot")
+        .expect("capture failed");
+    // Dropping the engine waits for its writer to store the capture.
+    drop(engine);
+
+    let filter = CaptureFilter {
+        include_blank: true,
+        ..Default::default()
+    };
+    Db::new(&config.db_path)
+        .and_then(|db| db.list_recent(1, 0, &filter))
+        .unwrap()
+        .into_iter()
+        .next()
+        .expect("the engine stored no capture")
+}
+
+fn check_record(config: &CaptureConfig, record: &CaptureRecord) {
+    let (width, height) = (record.width.unwrap_or(0), record.height.unwrap_or(0));
+    assert!(
+        width > 0 && height > 0,
+        "capture {} has no dimensions",
+        record.id
+    );
+    assert_eq!(record.window_title.as_deref(), Some(SMOKE_TITLE));
+    let path = record
+        .resolve_path(&config.capture_dir)
+        .unwrap_or_else(|| panic!("image file of {} is missing", record.id));
+    let image = codec::open(&path, record.codec).unwrap();
+    assert_eq!(image.dimensions(), (width, height), "{}", path.display());
+}
+
+/// Serves the scratch archive on an ephemeral port and reads the capture
+/// back through list, get and image.
+fn check_api(config: &CaptureConfig, record: &CaptureRecord) {
+    // Nothing runs the engine loop; requests that need it would hang.
+    let (events, _events_rx) = mpsc::channel();
+    let state = ApiState {
+        db_path: config.db_path.clone(),
+        config: Arc::new(RwLock::new(config.clone())),
+        config_path: config.db_path.with_file_name("config.toml"),
+        search_index_path: config.search_index_path.clone(),
+        pause_flag: Arc::new(AtomicBool::new(false)),
+        focus_backend: "none",
+        events,
+        bursts: Default::default(),
+        on_battery: Arc::new(AtomicBool::new(false)),
+        image_reads: Arc::new(tokio::sync::Semaphore::new(
+            config.max_concurrent_image_reads.max(1),
+        )),
+        thumbnails: Arc::new(ThumbnailCache::new(0)),
+        passphrase_backoff: Default::default(),
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let listener = api::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(api::serve(listener, state));
+        let client = VeeaClient::new(format!("http://{addr}"));
+
+        let page = client
+            .list_captures(Some(10), None)
+            .await
+            .expect("GET /captures");
+        assert!(
+            page.items.iter().any(|c| c.id == record.id),
+            "GET /captures did not list {}",
+            record.id
+        );
+
+        let summary = client
+            .get_capture(&record.id)
+            .await
+            .expect("GET /captures/{id}");
+        assert_eq!(summary.window_title.as_deref(), Some(SMOKE_TITLE));
+
+        let bytes = client
+            .get_image_bytes(&record.id)
+            .await
+            .expect("GET /captures/{id}/image");
+        let image = codec::decode(&bytes, ImageFormat::Png).unwrap();
+        assert_eq!(Some(image.dimensions()), record.width.zip(record.height));
+
+        server.abort();
+    });
+}
+
+#[cfg(target_os = "linux")]
+mod xvfb {
+    use std::{
+        path::Path,
+        process::{Child, Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use xcb::x;
+
+    type SmokeResult<T> = Result<T, String>;
+
+    const SCREEN: &str = "1024x768x24";
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A private Xvfb server, killed on drop.
+    pub struct Display {
+        child: Child,
+        name: String,
+    }
+
+    impl Display {
+        /// Starts Xvfb on the first free display number from `:90`.
+        pub fn start() -> SmokeResult<Self> {
+            let number = (90..110)
+                .find(|n| !Path::new(&format!("/tmp/.X11-unix/X{n}")).exists())
+                .ok_or("no free X display number")?;
+            let name = format!(":{number}");
+            let child = Command::new("Xvfb")
+                .args([name.as_str(), "-screen", "0", SCREEN, "-nolisten", "tcp"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("cannot start Xvfb: {e}"))?;
+            let mut display = Self { child, name };
+
+            let socket = format!("/tmp/.X11-unix/X{number}");
+            let started = Instant::now();
+            while !Path::new(&socket).exists() {
+                if let Ok(Some(status)) = display.child.try_wait() {
+                    return Err(format!("Xvfb exited with {status}"));
+                }
+                if started.elapsed() > STARTUP_TIMEOUT {
+                    return Err("Xvfb did not come up".into());
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            Ok(display)
+        }
+
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+    }
+
+    impl Drop for Display {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    /// A mapped, titled window with a simple pattern drawn in it, so the
+    /// capture isn't a blank frame. Closed with its connection on drop.
+    pub struct TestWindow {
+        _conn: xcb::Connection,
+    }
+
+    impl TestWindow {
+        pub fn open(display: &str, title: &str) -> SmokeResult<Self> {
+            let x_err = |e: &dyn std::fmt::Display| format!("X11: {e}");
+            let (conn, screen_num) =
+                xcb::Connection::connect(Some(display)).map_err(|e| x_err(&e))?;
+            let screen = conn
+                .get_setup()
+                .roots()
+                .nth(screen_num as usize)
+                .ok_or("X11: no screen")?;
+
+            let window: x::Window = conn.generate_id();
+            conn.send_and_check_request(&x::CreateWindow {
+                depth: x::COPY_FROM_PARENT as u8,
+                wid: window,
+                parent: screen.root(),
+                x: 40,
+                y: 40,
+                width: 640,
+                height: 480,
+                border_width: 0,
+                class: x::WindowClass::InputOutput,
+                visual: screen.root_visual(),
+                value_list: &[
+                    x::Cw::BackPixel(screen.white_pixel()),
+                    x::Cw::EventMask(x::EventMask::EXPOSURE),
+                ],
+            })
+            .map_err(|e| x_err(&e))?;
+
+            let net_wm_name = conn.send_request(&x::InternAtom {
+                only_if_exists: false,
+                name: b"_NET_WM_NAME",
+            });
+            let utf8_string = conn.send_request(&x::InternAtom {
+                only_if_exists: false,
+                name: b"UTF8_STRING",
+            });
+            let net_wm_name = conn
+                .wait_for_reply(net_wm_name)
+                .map_err(|e| x_err(&e))?
+                .atom();
+            let utf8_string = conn
+                .wait_for_reply(utf8_string)
+                .map_err(|e| x_err(&e))?
+                .atom();
+            for (property, r#type) in [
+                (x::ATOM_WM_NAME, x::ATOM_STRING),
+                (net_wm_name, utf8_string),
+            ] {
+                conn.send_request(&x::ChangeProperty {
+                    mode: x::PropMode::Replace,
+                    window,
+                    property,
+                    r#type,
+                    data: title.as_bytes(),
+                });
+            }
+            conn.send_request(&x::MapWindow { window });
+            conn.flush().map_err(|e| x_err(&e))?;
+
+            // Draw only once the window is visible, or the server drops it.
+            loop {
+                if let xcb::Event::X(x::Event::Expose(_)) =
+                    conn.wait_for_event().map_err(|e| x_err(&e))?
+                {
+                    break;
+                }
+            }
+            let gc: x::Gcontext = conn.generate_id();
+            conn.send_request(&x::CreateGc {
+                cid: gc,
+                drawable: x::Drawable::Window(window),
+                value_list: &[x::Gc::Foreground(screen.black_pixel())],
+            });
+            let stripes: Vec<_> = (0..8)
+                .map(|i| x::Rectangle {
+                    x: i * 80,
+                    y: i * 60,
+                    width: 40,
+                    height: 480 - i as u16 * 60,
+                })
+                .collect();
+            conn.send_request(&x::PolyFillRectangle {
+                drawable: x::Drawable::Window(window),
+                gc,
+                rectangles: &stripes,
+            });
+            // A round trip makes sure the drawing reached the server.
+            conn.send_and_check_request(&x::FreeGc { gc })
+                .map_err(|e| x_err(&e))?;
+            Ok(Self { _conn: conn })
+        }
+    }
+}